@@ -0,0 +1,58 @@
+// build.rs
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs a command and returns its trimmed output, if it ran and succeeded.
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+/// Records how the binary was built, for its startup log line, `status` and instance reports.
+/// `PORKBUN_DDNS_GIT_HASH` overrides the commit for builds from a tree without `.git`, such
+/// as a Docker context that leaves it out.
+fn main() {
+    let git_hash = env::var("PORKBUN_DDNS_GIT_HASH")
+        .ok()
+        .filter(|hash| !hash.is_empty())
+        .or_else(|| output("git", &["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    let rustc = output(
+        &env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()),
+        &["--version"],
+    )
+    .unwrap_or_else(|| "unknown".to_string());
+    let target = env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(name, _)| {
+            name.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .filter(|feature| feature != "default")
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=PORKBUN_DDNS_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=PORKBUN_DDNS_RUSTC={}", rustc);
+    println!("cargo:rustc-env=PORKBUN_DDNS_TARGET={}", target);
+    println!(
+        "cargo:rustc-env=PORKBUN_DDNS_FEATURES={}",
+        features.join(",")
+    );
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=PORKBUN_DDNS_GIT_HASH");
+    // A new commit moves the branch HEAD points to; watching only paths that exist keeps
+    // Cargo from rerunning this on every build outside a checkout.
+    for path in [".git/HEAD", ".git/refs/heads", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+// src/access.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::porkbun::{Permission, Zone};
+use log::{error, info, warn};
+
+/// Checks each key pair with Porkbun's ping endpoint and logs the address Porkbun sees
+/// requests coming from. A refused key stops startup with a clear message instead of failing
+/// every record each cycle; a network failure is only logged, as the connection may still be
+/// coming up.
+pub async fn check_credentials(zones: &[Zone<'_>]) -> Result<()> {
+    // Extra zones share the primary zone's keys; only a mirror may bring its own.
+    let key_pairs = zones
+        .iter()
+        .enumerate()
+        .filter(|(i, zone)| *i == 0 || zone.mirror)
+        .map(|(_, zone)| zone);
+    for zone in key_pairs {
+        let domain = zone.client.domain;
+        let variables = if zone.mirror {
+            "PORKBUN_MIRROR_API_KEY and PORKBUN_MIRROR_SECRET_API_KEY"
+        } else {
+            "PORKBUN_API_KEY and PORKBUN_SECRET_API_KEY"
+        };
+        match zone.client.ping().await {
+            Ok(ip) => info!(
+                "Porkbun accepted the API key for {}; it sees requests coming from {}",
+                domain, ip
+            ),
+            Err(DdnsError::PorkbunApi { message, .. }) => {
+                return Err(DdnsError::Config(format!(
+                    "Porkbun refused the API key for {}: {}. Check {}.",
+                    domain, message, variables
+                )))
+            }
+            Err(e) => warn!(
+                "Could not check the API key for {} with Porkbun: {}",
+                domain, e
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Probes what the API keys may do in every managed zone and logs one line per zone, so a key
+/// without write access, or a domain whose API access is turned off, shows up when the daemon
+/// starts rather than at the first failed update. Nothing is changed by the probe.
+pub async fn report(zones: &[Zone<'_>]) {
+    info!("Checking API key access for {} zone(s)...", zones.len());
+    for zone in zones {
+        let access = zone.client.probe_access().await;
+        let line = format!(
+            "API key access for {}{}: read {}, write {}",
+            zone.client.domain,
+            if zone.mirror { " (mirror)" } else { "" },
+            access.read,
+            access.write
+        );
+        match (&access.read, &access.write) {
+            (Permission::Allowed, Permission::Allowed) => info!("{}", line),
+            (Permission::Denied(_), _) | (_, Permission::Denied(_)) => {
+                error!(
+                    "{}. Check that API access is turned on for the domain in Porkbun's dashboard and that the key pair is correct.",
+                    line
+                )
+            }
+            _ => warn!("{}", line),
+        }
+    }
+}
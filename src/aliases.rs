@@ -0,0 +1,258 @@
+// src/aliases.rs
+
+use crate::budget::WriteBudget;
+use crate::config::Config;
+use crate::decision;
+use crate::errors::{DdnsError, Result};
+use crate::explain;
+use crate::porkbun::{DnsRecord, Zone};
+use crate::record::Record;
+use crate::summary::CycleSummary;
+use log::{error, info, warn};
+
+/// Hostnames kept as CNAMEs of one canonical host, so only the canonical host's address
+/// records need dynamic updates.
+#[derive(Debug)]
+pub struct AliasGroup {
+    /// The subdomain the aliases point at, empty for the root domain.
+    pub canonical: String,
+    pub aliases: Vec<String>,
+}
+
+impl AliasGroup {
+    /// Parses `canonical=alias,alias;canonical=alias` groups. The canonical host may be `@` for
+    /// the root domain, but an alias can't: the root domain can't be a CNAME.
+    pub fn parse_groups(value: &str, var_name: &str) -> Result<Vec<AliasGroup>> {
+        let mut groups = Vec::new();
+        let mut seen: Vec<String> = Vec::new();
+        for entry in value.split(';').filter(|entry| !entry.trim().is_empty()) {
+            let Some((canonical, aliases)) = entry.split_once('=') else {
+                return Err(DdnsError::Config(format!(
+                    "{} entries must look like 'host=alias,alias', got '{}'.",
+                    var_name,
+                    entry.trim()
+                )));
+            };
+            let canonical = match canonical.trim() {
+                "@" => String::new(),
+                canonical => canonical.to_ascii_lowercase(),
+            };
+            let mut group = AliasGroup {
+                canonical,
+                aliases: Vec::new(),
+            };
+            for alias in aliases.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+                let alias = alias.to_ascii_lowercase();
+                if alias == "@" {
+                    return Err(DdnsError::Config(format!(
+                        "{} lists '@' as an alias, but the root domain can't be a CNAME.",
+                        var_name
+                    )));
+                }
+                if alias == group.canonical || seen.contains(&alias) {
+                    return Err(DdnsError::Config(format!(
+                        "{} lists '{}' more than once or as an alias of itself.",
+                        var_name, alias
+                    )));
+                }
+                seen.push(alias.clone());
+                group.aliases.push(alias);
+            }
+            groups.push(group);
+        }
+        Ok(groups)
+    }
+}
+
+/// Keeps the CNAMEs of every alias group whose canonical host is managed in this zone pointed
+/// at it: missing ones are created and ones pointing elsewhere are edited. An alias that
+/// already has other records is left alone and reported, as a CNAME can't sit next to them.
+pub async fn sync(
+    config: &Config,
+    budget: &WriteBudget,
+    zone: &Zone<'_>,
+    snapshot: Option<&[DnsRecord]>,
+    summary: &mut CycleSummary,
+) {
+    let porkbun_client = &zone.client;
+    let groups: Vec<&AliasGroup> = config
+        .aliases
+        .iter()
+        .filter(|group| {
+            zone.subdomains
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(&group.canonical))
+        })
+        .collect();
+    if groups.is_empty() {
+        return;
+    }
+
+    let retrieved;
+    let records = match snapshot {
+        Some(records) => records,
+        None => {
+            let reason = format!(
+                "reading the records of {} to keep its aliases' CNAMEs",
+                porkbun_client.domain
+            );
+            match explain::because(reason, porkbun_client.retrieve_records()).await {
+                Ok(records) => {
+                    retrieved = records;
+                    &retrieved
+                }
+                Err(e) => {
+                    error!(
+                        "Error retrieving records for zone {}, skipping its aliases: {}",
+                        porkbun_client.domain, e
+                    );
+                    for alias in groups.iter().flat_map(|group| &group.aliases) {
+                        summary.record_failure(&label(&porkbun_client.full_name(alias)), &e);
+                    }
+                    return;
+                }
+            }
+        }
+    };
+
+    for group in groups {
+        let target = porkbun_client.full_name(&group.canonical);
+        for alias in &group.aliases {
+            let name = label(&porkbun_client.full_name(alias));
+            match sync_alias(config, budget, zone, records, alias, &target).await {
+                Ok(true) => summary.updated.push(name),
+                Ok(false) => summary.unchanged.push(name),
+                Err(e) => {
+                    error!("Error processing alias '{}': {}", name, e);
+                    summary.record_failure(&name, e);
+                }
+            }
+        }
+    }
+}
+
+fn label(name: &str) -> String {
+    format!("{} (CNAME)", name)
+}
+
+/// Brings one alias's CNAME in line with `target`. Returns whether it was changed.
+async fn sync_alias(
+    config: &Config,
+    budget: &WriteBudget,
+    zone: &Zone<'_>,
+    records: &[DnsRecord],
+    alias: &str,
+    target: &str,
+) -> Result<bool> {
+    let porkbun_client = &zone.client;
+    let name = porkbun_client.full_name(alias);
+    let existing: Vec<&DnsRecord> = records
+        .iter()
+        .filter(|r| r.name.eq_ignore_ascii_case(&name))
+        .collect();
+    let cname = existing
+        .iter()
+        .find(|r| r.record_type.eq_ignore_ascii_case("CNAME"));
+    let record = Record::cname(alias).content(target.to_string());
+    let reason = porkbun_client.reason();
+    let decide = |remote: Option<&DnsRecord>| {
+        decision::log(&decision::Decision {
+            host: &name,
+            record_type: "CNAME",
+            detected_ip: target,
+            source: "alias",
+            cached_ip: None,
+            remote_ip: remote.map(|r| r.content.as_str()),
+            remote_ttl: remote.and_then(|r| r.ttl),
+            remote_from: "zone-snapshot",
+            ttl: match remote {
+                Some(r) => porkbun_client.update_ttl(r, alias),
+                None => porkbun_client.create_ttl(alias),
+            },
+            comparison: match remote {
+                Some(r) if r.has_content(target) => "ttl-differs",
+                Some(_) => "differs",
+                None => "missing",
+            },
+            action: if remote.is_some() { "update" } else { "create" },
+            policy: decision::Policy::new(config, reason),
+        })
+    };
+
+    match cname {
+        Some(cname) if cname.has_content(target) => {
+            let Some(ttl) = porkbun_client.ttl_drift(cname.ttl, alias) else {
+                info!("Alias {} already points to {}", name, target);
+                return Ok(false);
+            };
+            info!(
+                "Alias {} points to {} with TTL {} instead of the configured {}, updating it",
+                name,
+                target,
+                cname.ttl.unwrap_or_default(),
+                ttl
+            );
+            decide(Some(cname));
+            if config.dry_run {
+                info!(
+                    "Dry run: would change the TTL of CNAME record {} (ID {}) to {}",
+                    name, cname.id, ttl
+                );
+                return Ok(true);
+            }
+            budget.spend()?;
+            let reason = format!("alias {} has another TTL than configured", name);
+            explain::because(reason, porkbun_client.edit(cname, record)).await?;
+            Ok(true)
+        }
+        Some(cname) => {
+            info!(
+                "Alias {} points to {}, moving it to {}",
+                name, cname.content, target
+            );
+            decide(Some(cname));
+            if config.dry_run {
+                info!(
+                    "Dry run: would update CNAME record {} (ID {}) from {} to {}",
+                    name, cname.id, cname.content, target
+                );
+                return Ok(true);
+            }
+            budget.spend()?;
+            let reason = format!("alias {} points elsewhere, moving it", name);
+            explain::because(reason, porkbun_client.edit(cname, record)).await?;
+            Ok(true)
+        }
+        None if !existing.is_empty() => {
+            let types: Vec<&str> = existing.iter().map(|r| r.record_type.as_str()).collect();
+            Err(DdnsError::Config(format!(
+                "{} already has {} record(s), which a CNAME can't coexist with",
+                name,
+                types.join(", ")
+            )))
+        }
+        None if !config.auto_create => {
+            warn!(
+                "No CNAME record for alias {} and PORKBUN_AUTO_CREATE is disabled; not creating it",
+                name
+            );
+            Err(DdnsError::Config(
+                "no CNAME record exists and auto-create is disabled".to_string(),
+            ))
+        }
+        None => {
+            decide(None);
+            if config.dry_run {
+                info!(
+                    "Dry run: would create CNAME record {} pointing to {}",
+                    name, target
+                );
+                return Ok(true);
+            }
+            budget.spend()?;
+            let reason = format!("alias {} does not exist yet, creating it", name);
+            explain::because(reason, porkbun_client.create(record)).await?;
+            Ok(true)
+        }
+    }
+}
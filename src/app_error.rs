@@ -0,0 +1,33 @@
+// src/app_error.rs
+
+//! Errors of the updater's own subsystems. `DdnsError` only covers the API client,
+//! so these travel in its `Other` variant and the updater keeps a single `Result` type.
+
+use crate::errors::DdnsError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[cfg(feature = "verify")]
+    #[error("DNS query error: {0}")]
+    Dns(String),
+
+    #[error("Write limit reached: {0}")]
+    WriteBudget(String),
+
+    #[error("Hook error: {0}")]
+    Hook(String),
+
+    #[error("Router query error: {0}")]
+    Router(String),
+
+    #[cfg(feature = "remote-config")]
+    #[error("Remote configuration error: {0}")]
+    RemoteConfig(String),
+}
+
+impl From<AppError> for DdnsError {
+    fn from(e: AppError) -> Self {
+        DdnsError::Other(Box::new(e))
+    }
+}
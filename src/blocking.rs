@@ -0,0 +1,200 @@
+// src/blocking.rs
+
+//! A blocking version of the async [`PorkbunClient`](crate::porkbun::PorkbunClient), for
+//! scripts and plugins that don't run an async runtime of their own. Requests go through
+//! `reqwest::blocking` and retries wait with `std::thread::sleep`, so the caller's thread does
+//! all the waiting and no runtime has to be set up.
+
+use crate::clock::{self, Clock};
+use crate::errors::Result;
+use crate::porkbun::{self, DnsRecord, RecordType, API_BASE_URL};
+use crate::record::Record;
+use futures_executor::block_on;
+use std::future::{self, Future};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+
+/// Waits by blocking the calling thread, for retries of blocking requests.
+pub(crate) struct ThreadClock;
+
+impl Clock for ThreadClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn since_boot(&self) -> Option<Duration> {
+        clock::since_boot()
+    }
+
+    /// Sleeps before returning the future, which is then ready at once.
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        thread::sleep(duration);
+        future::ready(())
+    }
+}
+
+/// Owns its credentials and a blocking HTTP client. Each call returns once Porkbun has
+/// answered. Calls must not be made from inside an async runtime, where `reqwest::blocking`
+/// panics.
+pub struct PorkbunClient {
+    http: reqwest::blocking::Client,
+    base_url: String,
+    api_key: String,
+    secret_api_key: String,
+    domain: String,
+    ttl: Option<u32>,
+    journal: Option<String>,
+}
+
+impl PorkbunClient {
+    pub fn new(api_key: &str, secret_api_key: &str, domain: &str) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::blocking::Client::builder().build()?,
+            base_url: API_BASE_URL.to_string(),
+            api_key: api_key.to_string(),
+            secret_api_key: secret_api_key.to_string(),
+            domain: domain.to_string(),
+            ttl: None,
+            journal: None,
+        })
+    }
+
+    /// Sends requests to another API endpoint, such as a mock server in tests.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+
+    /// Sets the TTL written to created and updated records. Without one, updated records
+    /// keep their existing TTL and created records get `DEFAULT_TTL`.
+    pub fn with_ttl(mut self, ttl: Option<u32>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Records every write in the journal at `path` before it is sent. Writes are not
+    /// journaled by default.
+    pub fn with_journal(mut self, path: &str) -> Self {
+        self.journal = Some(path.to_string());
+        self
+    }
+
+    /// The client for one call, borrowing this one's settings. Its requests block, so the
+    /// futures it returns finish on the first poll.
+    fn client(&self) -> porkbun::PorkbunClient<'_> {
+        let client = porkbun::PorkbunClient::new_blocking(
+            &self.http,
+            &self.api_key,
+            &self.secret_api_key,
+            &self.domain,
+        )
+        .with_base_url(&self.base_url)
+        .with_ttl(self.ttl);
+        match &self.journal {
+            Some(path) => client.with_journal(path),
+            None => client,
+        }
+    }
+
+    /// Checks the credentials, returning the address Porkbun sees the request coming from.
+    pub fn ping(&self) -> Result<String> {
+        block_on(self.client().ping())
+    }
+
+    /// Retrieves every record in the zone.
+    pub fn retrieve_records(&self) -> Result<Vec<DnsRecord>> {
+        block_on(self.client().retrieve_records())
+    }
+
+    /// Looks up a subdomain's record of the given type.
+    pub fn get_record(
+        &self,
+        subdomain: &str,
+        record_type: RecordType,
+    ) -> Result<Option<DnsRecord>> {
+        block_on(self.client().get_record(subdomain, record_type))
+    }
+
+    /// Sets both the content and TTL of an existing record.
+    pub fn edit_record(
+        &self,
+        record: &DnsRecord,
+        subdomain: &str,
+        content: &str,
+        ttl: u32,
+    ) -> Result<()> {
+        block_on(self.client().edit_record(record, subdomain, content, ttl))
+    }
+
+    /// Creates a record for a subdomain.
+    pub fn create_record(
+        &self,
+        subdomain: &str,
+        record_type: RecordType,
+        content: &str,
+    ) -> Result<()> {
+        block_on(self.client().create_record(subdomain, record_type, content))
+    }
+
+    /// Creates a record built with `Record`'s constructors, returning its ID if Porkbun
+    /// reported one.
+    pub fn create(&self, record: impl Into<Record>) -> Result<Option<String>> {
+        block_on(self.client().create(record))
+    }
+
+    /// Replaces an existing record with one built with `Record`'s constructors.
+    pub fn edit(&self, existing: &DnsRecord, record: impl Into<Record>) -> Result<()> {
+        block_on(self.client().edit(existing, record))
+    }
+
+    /// Deletes an existing record.
+    pub fn delete_record(&self, record: &DnsRecord, subdomain: &str) -> Result<()> {
+        block_on(self.client().delete_record(record, subdomain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn calls_block_until_the_api_answers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/api/json/v3", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // Read the whole request, so closing the connection doesn't reset it.
+            let mut request = Vec::new();
+            let mut chunk = [0u8; 4096];
+            while !String::from_utf8_lossy(&request).contains("secretapikey") {
+                let n = stream.read(&mut chunk).unwrap();
+                assert!(n > 0, "connection closed mid-request");
+                request.extend_from_slice(&chunk[..n]);
+            }
+            assert!(String::from_utf8_lossy(&request).starts_with("POST /api/json/v3/ping "));
+            let body = r#"{"status":"SUCCESS","yourIp":"203.0.113.9"}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        let client = PorkbunClient::new("pk1_test", "sk1_test", "example.com")
+            .unwrap()
+            .with_base_url(&base_url);
+
+        assert_eq!(client.ping().unwrap(), "203.0.113.9");
+        server.join().unwrap();
+    }
+}
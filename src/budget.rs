@@ -0,0 +1,38 @@
+// src/budget.rs
+
+use crate::app_error::AppError;
+use crate::errors::Result;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Writes allowed per cycle unless configured otherwise.
+pub const DEFAULT_MAX_WRITES: u32 = 20;
+
+/// Caps the number of record writes in one cycle, so a bad host list or a misbehaving IP
+/// source can't rewrite a large zone in one go.
+#[derive(Debug)]
+pub struct WriteBudget {
+    limit: Option<u32>,
+    used: AtomicU32,
+}
+
+impl WriteBudget {
+    /// A budget of `limit` writes; `None` is unlimited.
+    pub fn new(limit: Option<u32>) -> Self {
+        Self {
+            limit,
+            used: AtomicU32::new(0),
+        }
+    }
+
+    /// Takes one write from the budget, failing once it is used up.
+    pub fn spend(&self) -> Result<()> {
+        let used = self.used.fetch_add(1, Ordering::Relaxed);
+        match self.limit {
+            Some(limit) if used >= limit => Err(AppError::WriteBudget(format!(
+                "already made {} write(s) this cycle; raise PORKBUN_MAX_WRITES_PER_CYCLE or run with --force",
+                limit
+            )).into()),
+            _ => Ok(()),
+        }
+    }
+}
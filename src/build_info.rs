@@ -0,0 +1,43 @@
+// src/build_info.rs
+
+use serde::Serialize;
+use std::fmt;
+
+/// What this binary is and how it was built, as recorded by `build.rs`, so operators of many
+/// instances can tell exactly what runs where.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub target: &'static str,
+    pub rustc: &'static str,
+    pub features: Vec<&'static str>,
+}
+
+pub fn get() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("PORKBUN_DDNS_GIT_HASH"),
+        target: env!("PORKBUN_DDNS_TARGET"),
+        rustc: env!("PORKBUN_DDNS_RUSTC"),
+        features: env!("PORKBUN_DDNS_FEATURES")
+            .split(',')
+            .filter(|feature| !feature.is_empty())
+            .collect(),
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let features = if self.features.is_empty() {
+            "none".to_string()
+        } else {
+            self.features.join(", ")
+        };
+        write!(
+            f,
+            "{} ({}, {}, {}; features: {})",
+            self.version, self.git_hash, self.target, self.rustc, features
+        )
+    }
+}
@@ -0,0 +1,181 @@
+// src/check.rs
+
+use crate::ip_fetcher::{self, IpDetector};
+use crate::last_success;
+use crate::porkbun::{RecordType, Zone};
+use crate::router::{self, RouterConfig};
+use serde_json::json;
+use std::time::Instant;
+
+/// Nagios plugin states and their exit codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// Compares every managed record with the current public IP and prints a single
+/// Nagios/Zabbix-compatible status line with perfdata, or with `json` one JSON object:
+///
+/// ```json
+/// {"status": "WARNING", "summary": "1 record(s) match 203.0.113.4; stale: ...",
+///  "matched": 1, "stale": [{"name": "example.com (A)", "content": "198.51.100.7"}],
+///  "missing": [], "failed": [{"name": "router", "error": "..."}], "double_nat": null,
+///  "seconds_since_last_success": 280, "time_seconds": 0.412}
+/// ```
+///
+/// Returns the plugin exit code.
+///
+/// A record pointing at an old address is a warning, since the next cycle should fix it. A
+/// missing record or a failed lookup is critical; failing to detect the IP at all is unknown.
+/// When a router source is configured, a router WAN address behind double or carrier-grade
+/// NAT is also a warning.
+pub async fn run(
+    client: &reqwest::Client,
+    zones: &[Zone<'_>],
+    record_types: &[RecordType],
+    router_config: Option<&RouterConfig>,
+    detector: &IpDetector<'_>,
+    json: bool,
+) -> i32 {
+    let started = Instant::now();
+    let mut addresses = Vec::with_capacity(record_types.len());
+    for &record_type in record_types {
+        match ip_fetcher::get_current_ip(client, detector, record_type).await {
+            Ok(ip) => addresses.push((record_type, ip)),
+            Err(e) => {
+                let summary = format!(
+                    "could not detect the public {}: {}",
+                    record_type.family(),
+                    e
+                );
+                if json {
+                    println!(
+                        "{}",
+                        json!({ "status": Status::Unknown.label(), "summary": summary })
+                    );
+                } else {
+                    println!("DDNS UNKNOWN - {}", summary);
+                }
+                return Status::Unknown as i32;
+            }
+        }
+    }
+
+    let mut matched = 0;
+    let mut stale = Vec::new();
+    let mut missing = Vec::new();
+    let mut failed = Vec::new();
+    for zone in zones {
+        for subdomain in zone.subdomains {
+            for (record_type, current_ip) in &addresses {
+                let name = record_type.label(&zone.client.full_name(subdomain));
+                match zone.client.get_record(subdomain, *record_type).await {
+                    Ok(Some(record)) if record.has_content(current_ip) => matched += 1,
+                    Ok(Some(record)) => stale.push((name, record.content)),
+                    Ok(None) => missing.push(name),
+                    Err(e) => failed.push((name, e.to_string())),
+                }
+            }
+        }
+    }
+
+    let mut nat = None;
+    if let Some(router_config) = router_config {
+        match router::router_ip(client, &router_config.source).await {
+            Ok(router_ip) => nat = router::upstream_nat(router_ip),
+            Err(e) => failed.push(("router".to_string(), e.to_string())),
+        }
+    }
+
+    let status = if !missing.is_empty() || !failed.is_empty() {
+        Status::Critical
+    } else if !stale.is_empty() || nat.is_some() {
+        Status::Warning
+    } else {
+        Status::Ok
+    };
+
+    let current_ips: Vec<&str> = addresses.iter().map(|(_, ip)| ip.as_str()).collect();
+    let mut details = vec![format!(
+        "{} record(s) match {}",
+        matched,
+        current_ips.join(", ")
+    )];
+    if !stale.is_empty() {
+        let stale: Vec<String> = stale
+            .iter()
+            .map(|(name, content)| format!("{}={}", name, content))
+            .collect();
+        details.push(format!("stale: {}", stale.join(", ")));
+    }
+    if !missing.is_empty() {
+        details.push(format!("missing: {}", missing.join(", ")));
+    }
+    if !failed.is_empty() {
+        let failed: Vec<String> = failed
+            .iter()
+            .map(|(name, e)| format!("{} ({})", name, e))
+            .collect();
+        details.push(format!("failed: {}", failed.join(", ")));
+    }
+    if let Some(kind) = nat {
+        details.push(format!("router is behind {}", kind));
+    }
+
+    // Read from the file the running updater keeps; `None` if no cycle ever synced everything.
+    let since_success = last_success::seconds_since();
+    let elapsed = started.elapsed().as_secs_f64();
+    if json {
+        let stale: Vec<_> = stale
+            .iter()
+            .map(|(name, content)| json!({ "name": name, "content": content }))
+            .collect();
+        let failed: Vec<_> = failed
+            .iter()
+            .map(|(name, e)| json!({ "name": name, "error": e }))
+            .collect();
+        println!(
+            "{}",
+            json!({
+                "status": status.label(),
+                "summary": details.join("; "),
+                "matched": matched,
+                "stale": stale,
+                "missing": missing,
+                "failed": failed,
+                "double_nat": nat,
+                "seconds_since_last_success": since_success,
+                "time_seconds": elapsed,
+            })
+        );
+        return status as i32;
+    }
+    println!(
+        "DDNS {} - {} | matched={} stale={} missing={} failed={} double_nat={} last_success={} time={:.3}s",
+        status.label(),
+        details.join("; "),
+        matched,
+        stale.len(),
+        missing.len(),
+        failed.len(),
+        u8::from(nat.is_some()),
+        // Nagios's "undetermined" value when no cycle ever synced everything.
+        since_success.map_or("U".to_string(), |seconds| format!("{}s", seconds)),
+        elapsed
+    );
+    status as i32
+}
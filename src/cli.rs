@@ -0,0 +1,193 @@
+// src/cli.rs
+
+use crate::errors::{DdnsError, Result};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::fs::{self, File};
+use std::path::Path;
+
+/// Keeps Porkbun DNS records pointed at this network's public IP address.
+///
+/// Settings come from `PORKBUN_*` environment variables, a `.env` file or `--config`.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Load settings from a TOML or YAML file; environment variables take precedence.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<String>,
+
+    /// Use the settings of one tenant from a config file with `[tenants]`. Without it, `run`
+    /// starts every tenant in a process of its own.
+    #[arg(long, global = true, value_name = "NAME")]
+    pub tenant: Option<String>,
+
+    /// Disable the per-cycle write limit (PORKBUN_MAX_WRITES_PER_CYCLE).
+    #[arg(long, global = true)]
+    pub force: bool,
+
+    /// Run a single check cycle and exit, for cron or systemd timers (same as `update`).
+    #[arg(long, global = true)]
+    pub once: bool,
+
+    /// Detect the IP and read the records, but only log the changes a single cycle would make.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Replace Porkbun and the IP services with in-memory fakes, to try out hooks and settings
+    /// without touching real records.
+    #[arg(long, global = true)]
+    pub simulate: bool,
+
+    /// Print the result as JSON on stdout: with `update` (or `--once`, `--dry-run`),
+    /// `status`, `check`, `discover`, `undo` and `validate`. `undo --json` only applies the
+    /// changes with `--yes`.
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Run a single check cycle, then print every network call it made and why, to help tune
+    /// intervals, strategies and caching to Porkbun's rate limits.
+    #[arg(long, global = true, conflicts_with = "json")]
+    pub explain: bool,
+
+    /// What to do; defaults to `run`.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Keep records up to date, checking on an interval or on SIGUSR2.
+    Run,
+    /// Run a single check cycle, apply any due scheduled changes and exit. Exits with 0 when
+    /// every change was applied, 1 when none could be, 2 when only some were and 3 when there
+    /// was nothing to do.
+    Update,
+    /// Show every managed record next to the current public IP.
+    Status {
+        /// Print every cell in full instead of fitting the table to the terminal.
+        #[arg(long)]
+        wide: bool,
+        /// Don't color record states (also turned off by NO_COLOR or output to a pipe).
+        #[arg(long)]
+        no_color: bool,
+    },
+    /// List unmanaged A/AAAA records that point at the current public IP, to add to the
+    /// host lists.
+    Discover,
+    /// Check the configuration and exit without contacting any API.
+    Validate,
+    /// Compare records with the public IP and print a Nagios/Zabbix status line.
+    Check,
+    /// Lower the TTL of every managed record ahead of a planned IP move.
+    PreChange {
+        /// TTL in seconds; defaults to Porkbun's minimum of 600.
+        ttl: Option<u32>,
+        /// Why the change is made; recorded in the journal.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Restore the TTLs saved by `pre-change`.
+    PostChange {
+        /// Why the change is made; recorded in the journal.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Point a host at an IP at a later time, applied by the running daemon.
+    Schedule {
+        /// `HH:MM`, `YYYY-MM-DD HH:MM` or an RFC 3339 timestamp, in local time.
+        when: String,
+        /// Full hostname, e.g. www.example.com.
+        host: String,
+        /// IPv4 or IPv6 address.
+        ip: String,
+        /// Why the change is made; recorded in the journal and passed to hooks as DDNS_REASON.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Revert the most recent journaled change set.
+    Undo {
+        /// Apply without asking for confirmation.
+        #[arg(long)]
+        yes: bool,
+        /// Why the change is undone; recorded in the journal.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Print a completion script for a shell to stdout, e.g. to
+    /// /usr/share/bash-completion/completions/porkbun_ddns.
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Write man pages for the program and each command into a directory, e.g.
+    /// /usr/share/man/man1.
+    Manpages { dir: String },
+}
+
+/// Prints the completion script for `shell` to stdout.
+pub fn print_completions(shell: Shell) {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+}
+
+/// Writes `porkbun_ddns.1`, and a `porkbun_ddns-<command>.1` for each command, into `dir`,
+/// creating it if needed.
+pub fn write_manpages(dir: &str) -> Result<()> {
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir).map_err(|e| {
+        DdnsError::file(
+            "Failed to create man page directory",
+            &dir.display().to_string(),
+            e,
+        )
+    })?;
+    let mut command = Cli::command();
+    // Built first so the global options show up on every command's page.
+    command.build();
+    let name = command.get_name().to_string();
+    let mut pages = vec![command.clone()];
+    pages.extend(
+        command
+            .get_subcommands()
+            .filter(|subcommand| subcommand.get_name() != "help")
+            .map(|subcommand| {
+                subcommand
+                    .clone()
+                    .name(format!("{}-{}", name, subcommand.get_name()))
+            }),
+    );
+    for page in pages {
+        let path = dir.join(format!("{}.1", page.get_name()));
+        let display = path.display().to_string();
+        let mut file = File::create(&path)
+            .map_err(|e| DdnsError::file("Failed to write man page", &display, e))?;
+        clap_mangen::Man::new(page)
+            .render(&mut file)
+            .map_err(|e| DdnsError::file("Failed to write man page", &display, e))?;
+        println!("Wrote {}", display);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn man_pages_cover_the_program_and_each_command() {
+        let dir = std::env::temp_dir().join(format!("porkbun-manpages-{}", std::process::id()));
+        write_manpages(dir.to_str().unwrap()).unwrap();
+        let page = |name: &str| fs::read_to_string(dir.join(name));
+        let status = page("porkbun_ddns-status.1");
+        let main = page("porkbun_ddns.1");
+        let help = page("porkbun_ddns-help.1");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(main.unwrap().contains("porkbun_ddns"));
+        let status = status.unwrap();
+        assert!(status.contains("wide"), "command options");
+        assert!(status.contains("dry"), "global options");
+        assert!(help.is_err(), "no page for clap's help command");
+    }
+}
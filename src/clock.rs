@@ -0,0 +1,209 @@
+// src/clock.rs
+
+//! The clocks the updater schedules by, behind the `Clock` trait so timing logic can run
+//! against `MockClock` in tests instead of waiting in real time. `MockClock` is only built
+//! for tests and with the `mock-clock` feature.
+
+use log::warn;
+use std::fs;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(any(test, feature = "mock-clock"))]
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+/// Wall-clock times before this (2024-01-01) mean the clock has not been set yet, as on
+/// devices whose RTC resets to the epoch or its build date on power loss.
+const EARLIEST_PLAUSIBLE: i64 = 1_704_067_200;
+
+static UNSET_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Time since boot, including time spent suspended. Unlike the wall clock this never jumps
+/// when NTP corrects the time, and unlike `Instant` it keeps counting through a suspend, so
+/// comparing the two tells a suspend apart from a clock correction. `None` where
+/// `/proc/uptime` is unavailable.
+pub fn since_boot() -> Option<Duration> {
+    let uptime = fs::read_to_string("/proc/uptime").ok()?;
+    let seconds: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+    Some(Duration::from_secs_f64(seconds))
+}
+
+/// Whether the wall clock looks set. Wall-clock deadlines such as scheduled changes are held
+/// back while it doesn't, rather than firing at once when the clock is far off.
+pub fn wall_clock_plausible(clock: &impl Clock) -> bool {
+    let now = clock.timestamp();
+    let plausible = now >= EARLIEST_PLAUSIBLE;
+    if !plausible && !UNSET_WARNED.swap(true, Ordering::Relaxed) {
+        warn!(
+            "The system clock reads {}, which looks unset; scheduled changes wait until it is corrected",
+            chrono::DateTime::from_timestamp(now, 0)
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(|| now.to_string())
+        );
+    } else if plausible {
+        UNSET_WARNED.store(false, Ordering::Relaxed);
+    }
+    plausible
+}
+
+/// The three clocks timing decisions are made with, and a way to wait on them.
+pub trait Clock {
+    /// The monotonic clock, which stops while the machine is suspended.
+    fn now(&self) -> Instant;
+    /// The wall clock, which NTP and the RTC may step in either direction.
+    fn wall(&self) -> SystemTime;
+    /// Time since boot, as `since_boot`.
+    fn since_boot(&self) -> Option<Duration>;
+    /// Waits for `duration` on the monotonic clock.
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send;
+
+    /// The wall clock as a Unix timestamp in seconds.
+    fn timestamp(&self) -> i64 {
+        match self.wall().duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        }
+    }
+}
+
+/// The real clocks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn since_boot(&self) -> Option<Duration> {
+        since_boot()
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(duration)
+    }
+}
+
+/// Something that happens to the clocks while a `MockClock` sleeps.
+#[cfg(any(test, feature = "mock-clock"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jump {
+    /// The machine is suspended: boot and wall time move on, the monotonic clock doesn't.
+    Suspend(Duration),
+    /// The wall clock is stepped forward, as by an NTP correction.
+    WallForward(Duration),
+    /// The wall clock is stepped backward.
+    WallBackward(Duration),
+}
+
+/// Clocks for tests, which only move when slept on. Each `sleep` returns at once after
+/// advancing every clock by its duration, plus any `Jump` set up for that sleep, and is
+/// recorded so tests can check how long code waited and in what steps.
+#[cfg(any(test, feature = "mock-clock"))]
+#[derive(Debug)]
+pub struct MockClock {
+    state: Mutex<MockState>,
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+#[derive(Debug)]
+struct MockState {
+    start: Instant,
+    monotonic: Duration,
+    boot: Option<Duration>,
+    wall: SystemTime,
+    sleeps: Vec<Duration>,
+    jumps: Vec<(usize, Jump)>,
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+impl MockClock {
+    /// Clocks an hour after boot, with the wall clock at 2025-01-01 00:00 UTC.
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(MockState {
+                start: Instant::now(),
+                monotonic: Duration::ZERO,
+                boot: Some(Duration::from_secs(3600)),
+                wall: SystemTime::UNIX_EPOCH + Duration::from_secs(1_735_689_600),
+                sleeps: Vec::new(),
+                jumps: Vec::new(),
+            }),
+        }
+    }
+
+    /// Makes time since boot unavailable, as without `/proc/uptime`.
+    pub fn without_boot_clock(self) -> Self {
+        self.state.lock().unwrap().boot = None;
+        self
+    }
+
+    /// Makes `jump` happen during the `sleep`th sleep, counting from 1.
+    pub fn jump_during(&self, sleep: usize, jump: Jump) {
+        self.state.lock().unwrap().jumps.push((sleep, jump));
+    }
+
+    /// The duration of every sleep so far, in order.
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state.lock().unwrap().sleeps.clone()
+    }
+
+    /// Monotonic time slept so far.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().monotonic
+    }
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        let state = self.state.lock().unwrap();
+        state.start + state.monotonic
+    }
+
+    fn wall(&self) -> SystemTime {
+        self.state.lock().unwrap().wall
+    }
+
+    fn since_boot(&self) -> Option<Duration> {
+        self.state.lock().unwrap().boot
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        let mut state = self.state.lock().unwrap();
+        state.sleeps.push(duration);
+        let sleep = state.sleeps.len();
+        state.monotonic += duration;
+        state.wall += duration;
+        state.boot = state.boot.map(|boot| boot + duration);
+        let jumps: Vec<Jump> = state
+            .jumps
+            .iter()
+            .filter(|(n, _)| *n == sleep)
+            .map(|(_, jump)| *jump)
+            .collect();
+        for jump in jumps {
+            match jump {
+                Jump::Suspend(duration) => {
+                    state.wall += duration;
+                    state.boot = state.boot.map(|boot| boot + duration);
+                }
+                Jump::WallForward(duration) => state.wall += duration,
+                Jump::WallBackward(duration) => state.wall -= duration,
+            }
+        }
+        std::future::ready(())
+    }
+}
@@ -1,40 +1,295 @@
 // src/config.rs
 
+use crate::aliases::AliasGroup;
+use crate::budget;
+use crate::discover::DiscoverMode;
+#[cfg(feature = "verify")]
+use crate::dns::{Resolver, Transport};
 use crate::errors::{DdnsError, Result};
-use std::env;
+use crate::expand::{self, HostList};
+#[cfg(feature = "geoip")]
+use crate::geoip::{GeoIp, MismatchAction, NetworkPolicy};
+use crate::hooks::HooksConfig;
+use crate::interface;
+use crate::ip_details::DetailsConfig;
+use crate::ip_fetcher::{Credentials, IpDetector, IpSource};
+use crate::porkbun::{self, ParsingMode, RecordType, RetrievalStrategy, UpdateMethod};
+use crate::port_check::{PortCheck, PortCheckConfig};
+use crate::probe::{ProbeConfig, ProbeTarget};
+use crate::psl;
+use crate::retry::RetryPolicy;
+use crate::router::{RouterConfig, RouterSource};
+use crate::simulate;
+use crate::stun;
+use crate::telemetry::{self, TelemetryConfig};
+use crate::template;
+use crate::vars::Vars;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::time::Duration;
 
 const DEFAULT_CHECK_INTERVAL: u64 = 300;
+/// How long a record confirmed to hold the detected address is trusted without a lookup.
+const DEFAULT_IP_STATE_MAX_AGE: u64 = 86400;
+const DEFAULT_API_CAPTURE_FILE: &str = "porkbun-api-capture.log";
+const DEFAULT_JOURNAL_FILE: &str = "porkbun-journal.jsonl";
+
+/// Credentials and zone for a secondary Porkbun account that mirrors the primary records.
+#[derive(Debug)]
+pub struct MirrorConfig {
+    pub api_key: String,
+    pub secret_api_key: String,
+    pub domain: String,
+}
+
+/// An additional zone managed with the primary credentials, from `PORKBUN_DOMAINS`.
+#[derive(Debug)]
+pub struct ZoneConfig {
+    pub domain: String,
+    pub subdomains: Vec<String>,
+    /// The `!` exclusion globs of the host list, which discovered hosts are checked against.
+    pub exclusions: Vec<String>,
+}
+
+/// Settings for checking that updated records are visible through a resolver.
+#[cfg(feature = "verify")]
+#[derive(Debug)]
+pub struct VerifyConfig {
+    pub resolvers: Vec<Resolver>,
+    pub delay_seconds: u64,
+}
 
 /// Holds the application's configuration.
 #[derive(Debug)]
 pub struct Config {
+    /// The tenant of a shared config file these settings belong to.
+    pub tenant: Option<String>,
     pub api_key: String,
     pub secret_api_key: String,
     pub domain: String,
     pub subdomains: Vec<String>,
+    /// The `!` exclusion globs of `PORKBUN_SUBDOMAIN`.
+    pub exclusions: Vec<String>,
+    pub extra_zones: Vec<ZoneConfig>,
+    pub record_types: Vec<RecordType>,
+    /// Services asked for the public address, in order until one answers.
+    pub ip_sources: Vec<IpSource>,
+    /// Sources that must agree on the address; `None` takes the first answer.
+    pub ip_quorum: Option<usize>,
+    /// `host:port` of the STUN server for the `stun` IP source.
+    pub stun_server: String,
+    /// Network interface for the `interface` IP source.
+    pub ip_interface: Option<String>,
+    /// Which IPv6 address the `interface` IP source picks when there are several.
+    pub ipv6_prefer: interface::Preference,
+    /// Publish this host's temporary IPv6 privacy address instead of its stable one.
+    pub ipv6_allow_temporary: bool,
+    /// TTL for created and updated records; `None` keeps an updated record's existing TTL.
+    pub ttl: Option<u32>,
+    /// TTLs for individual hosts, keyed by lowercase full hostname.
+    pub host_ttls: HashMap<String, u32>,
+    /// CNAMEs kept pointing at managed hosts, in every zone that manages the canonical host.
+    pub aliases: Vec<AliasGroup>,
+    pub auto_create: bool,
+    /// Whether the daemon checks what the API keys may do in each zone when it starts.
+    pub probe_access: bool,
+    /// Record writes allowed per cycle; `None` is unlimited.
+    pub max_writes_per_cycle: Option<u32>,
+    /// Log the writes a cycle would make instead of making them; set with `--dry-run`.
+    pub dry_run: bool,
+    /// Degrade instead of failing: broken optional settings are dropped at startup, and an
+    /// address family that can't be detected is left out of the cycles with one warning.
+    pub soft_fail: bool,
     pub check_interval_seconds: u64,
+    pub on_demand: bool,
+    pub startup_delay_seconds: u64,
+    pub startup_splay_seconds: u64,
+    pub settle_seconds: u64,
+    /// How long the IP state file may stand in for looking a record up; 0 always looks.
+    pub ip_state_max_age_seconds: u64,
+    /// How failed IP detection and Porkbun requests are retried.
+    pub retry: RetryPolicy,
+    pub retrieval_strategy: RetrievalStrategy,
+    pub update_method: UpdateMethod,
+    /// What the daemon does at startup with unmanaged records that point at this connection.
+    pub discover: DiscoverMode,
+    pub api_parsing: ParsingMode,
+    pub api_capture_file: String,
+    /// The journal every write is recorded in, unless `PORKBUN_JOURNAL_FILE` is empty.
+    pub journal_file: Option<String>,
+    pub mirror: Option<MirrorConfig>,
+    pub router: Option<RouterConfig>,
+    #[cfg(feature = "verify")]
+    pub verify: Option<VerifyConfig>,
+    pub hooks: HooksConfig,
+    pub ip_details: DetailsConfig,
+    #[cfg(feature = "geoip")]
+    pub network_policy: Option<NetworkPolicy>,
+    pub telemetry: Option<TelemetryConfig>,
+    /// Where to write Prometheus metrics after each cycle, for node_exporter's textfile collector.
+    pub textfile: Option<String>,
+    /// The Healthchecks.io (or compatible) check to ping after each cycle.
+    pub healthcheck_url: Option<String>,
+    pub probe: Option<ProbeConfig>,
+    pub port_checks: Option<PortCheckConfig>,
 }
 
 impl Config {
-    /// Loads configuration from environment variables.
-    pub fn from_env() -> Result<Self> {
-        let api_key = env::var("PORKBUN_API_KEY").map_err(|_| {
-            DdnsError::Config("PORKBUN_API_KEY environment variable not set.".to_string())
-        })?;
-        let secret_api_key = env::var("PORKBUN_SECRET_API_KEY").map_err(|_| {
-            DdnsError::Config("PORKBUN_SECRET_API_KEY environment variable not set.".to_string())
-        })?;
-        let domain = env::var("PORKBUN_DOMAIN").map_err(|_| {
-            DdnsError::Config("PORKBUN_DOMAIN environment variable not set.".to_string())
-        })?;
+    /// The IP sources, retry policy and credentials IP detection uses.
+    pub fn ip_detector(&self) -> IpDetector<'_> {
+        IpDetector {
+            sources: &self.ip_sources,
+            quorum: self.ip_quorum,
+            retry: &self.retry,
+            credentials: Credentials {
+                api_key: &self.api_key,
+                secret_api_key: &self.secret_api_key,
+            },
+            stun_server: &self.stun_server,
+            interface: self.ip_interface.as_deref(),
+            prefer: self.ipv6_prefer,
+            allow_temporary: self.ipv6_allow_temporary,
+        }
+    }
 
-        let subdomains_str = env::var("PORKBUN_SUBDOMAIN").unwrap_or_else(|_| "".to_string());
-        let subdomains: Vec<String> = subdomains_str
+    /// Loads the configuration from environment variables, as held by `vars`.
+    pub fn from_vars(vars: &Vars) -> Result<Self> {
+        let api_key = Self::credential_from_env(vars, "PORKBUN_API_KEY")?;
+        let secret_api_key = Self::credential_from_env(vars, "PORKBUN_SECRET_API_KEY")?;
+        // Accept a full hostname as a domain by splitting off the registrable domain and
+        // prefixing the extra labels onto every subdomain.
+        let extra_suffixes: Vec<String> = vars
+            .var("PORKBUN_PUBLIC_SUFFIXES")
+            .unwrap_or_default()
             .split(',')
-            .map(|s| s.trim().to_string())
+            .map(|s| s.trim().trim_start_matches('.').to_string())
+            .filter(|s| !s.is_empty())
             .collect();
 
-        let check_interval_seconds = env::var("PORKBUN_CHECK_INTERVAL_SECONDS")
+        let mut extra_zones = Vec::new();
+        if let Ok(value) = template::var(vars, "PORKBUN_DOMAINS") {
+            for entry in value.split(';').filter(|s| !s.trim().is_empty()) {
+                let (domain, subdomains) = entry.split_once(':').unwrap_or((entry, ""));
+                extra_zones.push(Self::zone(
+                    domain.trim(),
+                    &expand::expand_list(subdomains)?,
+                    &extra_suffixes,
+                )?);
+            }
+        }
+
+        let primary = match template::var(vars, "PORKBUN_DOMAIN") {
+            Ok(domain) => {
+                let subdomains_str =
+                    template::var(vars, "PORKBUN_SUBDOMAIN").unwrap_or_else(|_| "".to_string());
+                Self::zone(
+                    &domain,
+                    &expand::expand_list(&subdomains_str)?,
+                    &extra_suffixes,
+                )?
+            }
+            // Without PORKBUN_DOMAIN, the first zone in PORKBUN_DOMAINS is the primary one.
+            Err(_) if !extra_zones.is_empty() => extra_zones.remove(0),
+            Err(_) => {
+                return Err(DdnsError::Config(
+                    "PORKBUN_DOMAIN environment variable not set.".to_string(),
+                ))
+            }
+        };
+        let ZoneConfig {
+            domain,
+            subdomains,
+            exclusions,
+        } = primary;
+
+        let record_types = Self::record_types_from_env(vars)?;
+        let (ip_sources, ip_quorum) = if simulate::active() {
+            (vec![IpSource::Simulated], None)
+        } else {
+            let ip_sources = Self::ip_sources_from_env(vars)?;
+            let ip_quorum = Self::ip_quorum_from_env(vars, ip_sources.len())?;
+            (ip_sources, ip_quorum)
+        };
+        let stun_server = vars
+            .var("PORKBUN_STUN_SERVER")
+            .map(|server| {
+                let server = server.trim();
+                // A bare host or IPv4 address gets the standard STUN port.
+                if server.contains(':') {
+                    server.to_string()
+                } else {
+                    format!("{}:3478", server)
+                }
+            })
+            .unwrap_or_else(|_| stun::DEFAULT_SERVER.to_string());
+        let ip_interface = vars
+            .var("PORKBUN_IP_INTERFACE")
+            .map(|interface| interface.trim().to_string())
+            .ok()
+            .filter(|interface| !interface.is_empty());
+        if ip_sources.contains(&IpSource::Interface) && ip_interface.is_none() {
+            return Err(DdnsError::Config(
+                "The interface IP source needs PORKBUN_IP_INTERFACE, the network interface to read the address from."
+                    .to_string(),
+            ));
+        }
+        let ipv6_prefer = match vars.var("PORKBUN_IPV6_PREFER") {
+            Ok(value) => interface::Preference::parse(&value).ok_or_else(|| {
+                DdnsError::Config(format!(
+                    "PORKBUN_IPV6_PREFER '{}' must be first, eui64, newest or a prefix like 2001:db8::/48.",
+                    value.trim()
+                ))
+            })?,
+            Err(_) => interface::Preference::default(),
+        };
+
+        let ttl = match vars.var("PORKBUN_TTL") {
+            Ok(value) => Some(value.trim().parse::<u32>().map_err(|_| {
+                DdnsError::Config("PORKBUN_TTL must be a valid number.".to_string())
+            })?),
+            Err(_) => None,
+        };
+        if let Some(ttl) = ttl.filter(|&ttl| ttl < porkbun::MIN_TTL) {
+            return Err(DdnsError::Config(format!(
+                "PORKBUN_TTL {} is below Porkbun's minimum of {} seconds.",
+                ttl,
+                porkbun::MIN_TTL
+            )));
+        }
+
+        let host_ttls = Self::host_ttls_from_env(vars)?;
+        let aliases = match template::var(vars, "PORKBUN_ALIASES") {
+            Ok(value) => AliasGroup::parse_groups(&value, "PORKBUN_ALIASES")?,
+            Err(_) => Vec::new(),
+        };
+
+        // A safety net, so it fails fast like the other guards; 0 disables it.
+        let max_writes_per_cycle = match vars.var("PORKBUN_MAX_WRITES_PER_CYCLE") {
+            Ok(value) => match value.trim().parse::<u32>() {
+                Ok(0) => None,
+                Ok(limit) => Some(limit),
+                Err(_) => {
+                    return Err(DdnsError::Config(
+                        "PORKBUN_MAX_WRITES_PER_CYCLE must be a valid number.".to_string(),
+                    ))
+                }
+            },
+            Err(_) => Some(budget::DEFAULT_MAX_WRITES),
+        };
+
+        // Missing records are created by default; this allows turning that off.
+        let auto_create = vars
+            .var("PORKBUN_AUTO_CREATE")
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+            .unwrap_or(true);
+
+        let probe_access = vars
+            .var("PORKBUN_PROBE_ACCESS")
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+            .unwrap_or(false);
+
+        let check_interval_seconds = vars
+            .var("PORKBUN_CHECK_INTERVAL_SECONDS")
             .unwrap_or_else(|_| DEFAULT_CHECK_INTERVAL.to_string()) // Default check interval
             .parse::<u64>()
             .map_err(|_| {
@@ -43,12 +298,1029 @@ impl Config {
                 )
             })?;
 
-        Ok(Config {
+        let on_demand = vars
+            .var("PORKBUN_ON_DEMAND")
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+            .unwrap_or(false);
+
+        // Optional settings that fail to parse are dropped with a warning in soft-fail mode
+        // instead of stopping startup.
+        let soft_fail = vars
+            .var("PORKBUN_SOFT_FAIL")
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+            .unwrap_or(false);
+
+        let startup_delay_seconds = Self::optional(
+            soft_fail,
+            "a startup delay",
+            Self::seconds_from_env(vars, "PORKBUN_STARTUP_DELAY_SECONDS"),
+        )?;
+        let startup_splay_seconds = Self::optional(
+            soft_fail,
+            "a startup splay",
+            Self::seconds_from_env(vars, "PORKBUN_STARTUP_SPLAY_SECONDS"),
+        )?;
+
+        let retry = Self::optional(soft_fail, "retries", Self::retry_from_env(vars))?;
+
+        let settle_seconds = Self::optional(
+            soft_fail,
+            "a settle time",
+            Self::seconds_from_env(vars, "PORKBUN_SETTLE_SECONDS"),
+        )?;
+
+        let ip_state_max_age_seconds = match vars.var("PORKBUN_IP_STATE_MAX_AGE_SECONDS") {
+            Ok(_) => Self::optional(
+                soft_fail,
+                "the IP state file",
+                Self::seconds_from_env(vars, "PORKBUN_IP_STATE_MAX_AGE_SECONDS"),
+            )?,
+            Err(_) => DEFAULT_IP_STATE_MAX_AGE,
+        };
+
+        let retrieval_strategy = match vars.var("PORKBUN_RETRIEVAL_STRATEGY") {
+            Ok(value) => Self::optional(
+                soft_fail,
+                "the configured retrieval strategy",
+                RetrievalStrategy::parse(&value).map(Some),
+            )?
+            .unwrap_or(RetrievalStrategy::PerRecord),
+            Err(_) => RetrievalStrategy::PerRecord,
+        };
+
+        let update_method = match vars.var("PORKBUN_UPDATE_METHOD") {
+            Ok(value) => Self::optional(
+                soft_fail,
+                "the configured update method",
+                UpdateMethod::parse(&value).map(Some),
+            )?
+            .unwrap_or(UpdateMethod::ByNameType),
+            Err(_) => UpdateMethod::ByNameType,
+        };
+
+        let discover = match vars.var("PORKBUN_DISCOVER") {
+            Ok(value) => Self::optional(
+                soft_fail,
+                "the configured discovery mode",
+                DiscoverMode::parse(&value).map(Some),
+            )?
+            .unwrap_or(DiscoverMode::Off),
+            Err(_) => DiscoverMode::Off,
+        };
+
+        let api_parsing = match vars.var("PORKBUN_API_PARSING") {
+            Ok(value) => Self::optional(
+                soft_fail,
+                "the configured API parsing mode",
+                ParsingMode::parse(&value).map(Some),
+            )?
+            .unwrap_or(ParsingMode::Lenient),
+            Err(_) => ParsingMode::Lenient,
+        };
+        let api_capture_file = vars
+            .var("PORKBUN_API_CAPTURE_FILE")
+            .unwrap_or_else(|_| DEFAULT_API_CAPTURE_FILE.to_string());
+        let journal_file = Some(
+            vars.var("PORKBUN_JOURNAL_FILE")
+                .unwrap_or_else(|_| DEFAULT_JOURNAL_FILE.to_string()),
+        )
+        .filter(|path| !path.is_empty());
+
+        let mirror = Self::mirror_from_env(vars, &api_key, &secret_api_key, &domain);
+        let router = Self::optional(
+            soft_fail,
+            "router cross-checks",
+            Self::router_from_env(vars),
+        )?;
+        #[cfg(feature = "verify")]
+        let verify = Self::optional(soft_fail, "verification", Self::verify_from_env(vars))?;
+        #[cfg(not(feature = "verify"))]
+        if vars.var("PORKBUN_VERIFY").is_ok() {
+            warn!("PORKBUN_VERIFY is set, but this build does not include the `verify` feature; records will not be verified.");
+        }
+        let hooks = Self::optional(soft_fail, "hooks", Self::hooks_from_env(vars))?;
+        let ip_details = Self::optional(soft_fail, "IP details", Self::ip_details_from_env(vars))?;
+        // Expected-network checks are a safety guard, so they always fail fast.
+        #[cfg(feature = "geoip")]
+        let network_policy = Self::network_policy_from_env(vars, &ip_details)?;
+        #[cfg(not(feature = "geoip"))]
+        if vars.var("PORKBUN_EXPECTED_ASNS").is_ok()
+            || vars.var("PORKBUN_EXPECTED_COUNTRIES").is_ok()
+        {
+            warn!("PORKBUN_EXPECTED_ASNS/PORKBUN_EXPECTED_COUNTRIES are set, but this build does not include the `geoip` feature; the detected IP will not be checked.");
+        }
+        let telemetry = Self::optional(soft_fail, "telemetry", Self::telemetry_from_env(vars))?;
+        let healthcheck_url = Self::optional(
+            soft_fail,
+            "health check pings",
+            Self::healthcheck_url_from_env(vars),
+        )?;
+        let probe = Self::optional(soft_fail, "the latency probe", Self::probe_from_env(vars))?;
+        let port_checks =
+            Self::optional(soft_fail, "port checks", Self::port_checks_from_env(vars))?;
+
+        // Simulated records only exist in memory, so they are not checked against or reported
+        // to real services.
+        let router = router.filter(|_| !simulate::active());
+        #[cfg(feature = "verify")]
+        let verify = verify.filter(|_| !simulate::active());
+        let telemetry = telemetry.filter(|_| !simulate::active());
+        let port_checks = port_checks.filter(|_| !simulate::active());
+
+        let config = Config {
+            tenant: vars.var("PORKBUN_TENANT").ok(),
             api_key,
             secret_api_key,
             domain,
             subdomains,
+            exclusions,
+            extra_zones,
+            record_types,
+            ip_sources,
+            ip_quorum,
+            stun_server,
+            ip_interface,
+            ipv6_prefer,
+            ipv6_allow_temporary: vars
+                .var("PORKBUN_IPV6_ALLOW_TEMPORARY")
+                .is_ok_and(|v| v.eq_ignore_ascii_case("true") || v == "1"),
+            ttl,
+            host_ttls,
+            aliases,
+            auto_create,
+            probe_access,
+            max_writes_per_cycle,
+            dry_run: false,
+            soft_fail,
             check_interval_seconds,
+            on_demand,
+            startup_delay_seconds,
+            startup_splay_seconds,
+            settle_seconds,
+            ip_state_max_age_seconds,
+            retry,
+            retrieval_strategy,
+            update_method,
+            discover,
+            api_parsing,
+            api_capture_file,
+            journal_file,
+            mirror,
+            router,
+            #[cfg(feature = "verify")]
+            verify,
+            hooks,
+            ip_details,
+            #[cfg(feature = "geoip")]
+            network_policy,
+            telemetry,
+            textfile: vars
+                .var("PORKBUN_PROMETHEUS_TEXTFILE")
+                .ok()
+                .filter(|path| !path.is_empty()),
+            healthcheck_url,
+            probe,
+            port_checks,
+        };
+        config.lint()
+    }
+
+    /// Checks for host declarations that would manage the same record twice, before any API
+    /// call is made. Redundant duplicates are dropped with a warning; real conflicts are errors.
+    fn lint(mut self) -> Result<Self> {
+        let seen = Self::dedupe(&mut self.subdomains, "PORKBUN_SUBDOMAIN");
+        let mut domains = vec![self.domain.to_ascii_lowercase()];
+        for zone in &mut self.extra_zones {
+            Self::dedupe(&mut zone.subdomains, "PORKBUN_DOMAINS");
+            if domains.contains(&zone.domain.to_ascii_lowercase()) {
+                return Err(DdnsError::Config(format!(
+                    "Zone '{}' is configured more than once; list all of its subdomains in one entry.",
+                    zone.domain
+                )));
+            }
+            domains.push(zone.domain.to_ascii_lowercase());
+        }
+
+        for pattern in self.subdomains.iter().filter(|s| s.starts_with('*')) {
+            let suffix = pattern.trim_start_matches('*').trim_start_matches('.');
+            for host in self.subdomains.iter().filter(|s| !s.starts_with('*')) {
+                let covered = if suffix.is_empty() {
+                    !host.is_empty()
+                } else {
+                    host.ends_with(&format!(".{}", suffix))
+                };
+                if covered {
+                    warn!(
+                        "Wildcard '{}' overlaps explicit host '{}'; the explicit record takes precedence for that name.",
+                        pattern, host
+                    );
+                }
+            }
+        }
+
+        let all_hosts: Vec<String> = self
+            .extra_zones
+            .iter()
+            .flat_map(|zone| &zone.subdomains)
+            .map(|subdomain| subdomain.to_ascii_lowercase())
+            .chain(seen.iter().cloned())
+            .collect();
+        let mut zones = vec![(self.domain.as_str(), self.subdomains.as_slice())];
+        if let Some(mirror) = &self.mirror {
+            zones.push((&mirror.domain, &self.subdomains));
+        }
+        zones.extend(
+            self.extra_zones
+                .iter()
+                .map(|zone| (zone.domain.as_str(), zone.subdomains.as_slice())),
+        );
+        let full_names: Vec<String> = zones
+            .iter()
+            .flat_map(|(domain, subdomains)| {
+                subdomains
+                    .iter()
+                    .map(move |subdomain| match subdomain.as_str() {
+                        "" => domain.to_ascii_lowercase(),
+                        subdomain => format!("{}.{}", subdomain, domain).to_ascii_lowercase(),
+                    })
+            })
+            .collect();
+        for (var_name, hooks) in [
+            ("PORKBUN_HOST_PRE_UPDATE_HOOKS", &self.hooks.host_pre_update),
+            (
+                "PORKBUN_HOST_POST_UPDATE_HOOKS",
+                &self.hooks.host_post_update,
+            ),
+        ] {
+            for host in hooks.keys() {
+                if !full_names.contains(host) {
+                    warn!(
+                        "{} has a hook for '{}', which is not a managed host; give the full hostname, such as 'mail.{}'.",
+                        var_name, host, self.domain
+                    );
+                }
+            }
+        }
+        for host in self.host_ttls.keys() {
+            if !full_names.contains(host) {
+                warn!(
+                    "PORKBUN_HOST_TTLS has a TTL for '{}', which is not a managed host; give the full hostname, such as 'vpn.{}'.",
+                    host, self.domain
+                );
+            }
+        }
+
+        for group in &self.aliases {
+            if !all_hosts.contains(&group.canonical) {
+                warn!(
+                    "PORKBUN_ALIASES points aliases at '{}', which is not a managed subdomain; they will not be maintained.",
+                    if group.canonical.is_empty() { "@" } else { &group.canonical }
+                );
+            }
+            // The address record would be created next to the CNAME, which DNS doesn't allow.
+            if let Some(alias) = group.aliases.iter().find(|a| all_hosts.contains(a)) {
+                return Err(DdnsError::Config(format!(
+                    "'{}' is both a managed host and an alias in PORKBUN_ALIASES; list it in only one of them.",
+                    alias
+                )));
+            }
+        }
+
+        if let Some(mirror) = &self.mirror {
+            if mirror.domain.eq_ignore_ascii_case(&self.domain) && mirror.api_key == self.api_key {
+                return Err(DdnsError::Config(
+                    "The mirror zone is the same as the primary zone, so every record would be managed twice. Set PORKBUN_MIRROR_DOMAIN or PORKBUN_MIRROR_API_KEY to a different zone or account.".to_string(),
+                ));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Checks that a discovered host can be managed in `domain` as if it had been listed there:
+    /// the zone is configured, the host is neither managed already nor excluded with `!`, and
+    /// it isn't an alias, which would get an address record next to its CNAME. Returns why
+    /// not otherwise.
+    pub fn check_adoptable(
+        &self,
+        domain: &str,
+        subdomain: &str,
+    ) -> std::result::Result<(), String> {
+        let (subdomains, exclusions) = if domain.eq_ignore_ascii_case(&self.domain) {
+            (&self.subdomains, &self.exclusions)
+        } else {
+            match self
+                .extra_zones
+                .iter()
+                .find(|zone| zone.domain.eq_ignore_ascii_case(domain))
+            {
+                Some(zone) => (&zone.subdomains, &zone.exclusions),
+                None => return Err(format!("zone {} is not configured", domain)),
+            }
+        };
+        if subdomains.iter().any(|s| s.eq_ignore_ascii_case(subdomain)) {
+            return Err("it is managed already".to_string());
+        }
+        let key = subdomain.to_ascii_lowercase();
+        if let Some(exclusion) = exclusions
+            .iter()
+            .find(|exclusion| Self::matches_exclusion(exclusion, &key, domain))
+        {
+            return Err(format!("it matches !{}", exclusion));
+        }
+        if self
+            .aliases
+            .iter()
+            .any(|group| group.aliases.contains(&key))
+        {
+            return Err("it is an alias in PORKBUN_ALIASES".to_string());
+        }
+        Ok(())
+    }
+
+    /// Drops repeated subdomains with a warning, returning the lower-cased names kept.
+    fn dedupe(subdomains: &mut Vec<String>, var_name: &str) -> Vec<String> {
+        let mut seen: Vec<String> = Vec::new();
+        subdomains.retain(|subdomain| {
+            let key = subdomain.to_ascii_lowercase();
+            if seen.contains(&key) {
+                warn!(
+                    "Subdomain '{}' is listed more than once in {}; ignoring the duplicate.",
+                    subdomain, var_name
+                );
+                false
+            } else {
+                seen.push(key);
+                true
+            }
+        });
+        seen
+    }
+
+    /// Reads `PORKBUN_HOST_TTLS`, a `host=seconds;host=seconds` list of TTLs that replace
+    /// `PORKBUN_TTL` for individual hosts, each given by its full hostname.
+    fn host_ttls_from_env(vars: &Vars) -> Result<HashMap<String, u32>> {
+        let Ok(value) = template::var(vars, "PORKBUN_HOST_TTLS") else {
+            return Ok(HashMap::new());
+        };
+        value
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let parsed = entry
+                    .split_once('=')
+                    .and_then(|(host, ttl)| Some((host.trim(), ttl.trim().parse::<u32>().ok()?)));
+                let Some((host, ttl)) = parsed else {
+                    return Err(DdnsError::Config(format!(
+                        "PORKBUN_HOST_TTLS entries must look like 'host=seconds', got '{}'.",
+                        entry.trim()
+                    )));
+                };
+                if ttl < porkbun::MIN_TTL {
+                    return Err(DdnsError::Config(format!(
+                        "PORKBUN_HOST_TTLS sets {} for '{}', below Porkbun's minimum of {} seconds.",
+                        ttl,
+                        host,
+                        porkbun::MIN_TTL
+                    )));
+                }
+                Ok((host.trim_end_matches('.').to_ascii_lowercase(), ttl))
+            })
+            .collect()
+    }
+
+    /// Reads how transient failures are retried: `PORKBUN_RETRY_ATTEMPTS` in total (1 turns
+    /// retrying off), starting `PORKBUN_RETRY_DELAY_MS` apart and doubling each time.
+    fn retry_from_env(vars: &Vars) -> Result<RetryPolicy> {
+        let mut retry = RetryPolicy::default();
+        if let Ok(value) = vars.var("PORKBUN_RETRY_ATTEMPTS") {
+            retry.max_attempts = value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| {
+                    DdnsError::Config(
+                        "PORKBUN_RETRY_ATTEMPTS must be a positive number.".to_string(),
+                    )
+                })?;
+        }
+        if let Ok(value) = vars.var("PORKBUN_RETRY_DELAY_MS") {
+            let millis = value.trim().parse::<u64>().map_err(|_| {
+                DdnsError::Config("PORKBUN_RETRY_DELAY_MS must be a valid number.".to_string())
+            })?;
+            retry.base_delay = Duration::from_millis(millis);
+        }
+        Ok(retry)
+    }
+
+    /// Reads the global and per-host pre/post update hooks and the digest and failure hooks,
+    /// with the hourly limits of the notification hooks.
+    fn hooks_from_env(vars: &Vars) -> Result<HooksConfig> {
+        let host_hooks = |var_name: &str| match template::var(vars, var_name) {
+            Ok(value) => HooksConfig::parse_host_hooks(&value, var_name),
+            Err(_) => Ok(Default::default()),
+        };
+
+        Ok(HooksConfig {
+            pre_update: vars.var("PORKBUN_PRE_UPDATE_HOOK").ok(),
+            post_update: vars.var("PORKBUN_POST_UPDATE_HOOK").ok(),
+            host_pre_update: host_hooks("PORKBUN_HOST_PRE_UPDATE_HOOKS")?,
+            host_post_update: host_hooks("PORKBUN_HOST_POST_UPDATE_HOOKS")?,
+            digest: vars.var("PORKBUN_DIGEST_HOOK").ok(),
+            failure: vars.var("PORKBUN_FAILURE_HOOK").ok(),
+            post_update_max_per_hour: Self::max_per_hour(
+                vars,
+                "PORKBUN_POST_UPDATE_HOOK_MAX_PER_HOUR",
+            )?,
+            digest_max_per_hour: Self::max_per_hour(vars, "PORKBUN_DIGEST_HOOK_MAX_PER_HOUR")?,
+            failure_max_per_hour: Self::max_per_hour(vars, "PORKBUN_FAILURE_HOOK_MAX_PER_HOUR")?,
+        })
+    }
+
+    /// Reads how many times an hour a notification hook may run; unlimited when unset.
+    fn max_per_hour(vars: &Vars, var_name: &str) -> Result<Option<u32>> {
+        match vars.var(var_name) {
+            Ok(value) => value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .map(Some)
+                .ok_or_else(|| {
+                    DdnsError::Config(format!("{} must be a positive number.", var_name))
+                }),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Reads the optional reverse DNS and GeoIP sources used to describe changed addresses.
+    /// `PORKBUN_REVERSE_DNS` is either `true` (use the default resolver) or a resolver entry.
+    fn ip_details_from_env(vars: &Vars) -> Result<DetailsConfig> {
+        #[cfg_attr(not(any(feature = "verify", feature = "geoip")), allow(unused_mut))]
+        let mut details = DetailsConfig::default();
+
+        if let Ok(value) = vars.var("PORKBUN_REVERSE_DNS") {
+            #[cfg(feature = "verify")]
+            if value.eq_ignore_ascii_case("true") || value == "1" {
+                details.reverse_dns = Some(Resolver {
+                    transport: Transport::Udp,
+                    endpoint: Transport::Udp.default_endpoint().to_string(),
+                });
+            } else if !(value.eq_ignore_ascii_case("false") || value == "0") {
+                details.reverse_dns = Some(Resolver::parse(&value)?);
+            }
+            #[cfg(not(feature = "verify"))]
+            {
+                let _ = value;
+                warn!("PORKBUN_REVERSE_DNS is set, but this build does not include the `verify` feature; reverse lookups are disabled.");
+            }
+        }
+
+        if let Ok(value) = vars.var("PORKBUN_GEOIP_DATABASES") {
+            #[cfg(feature = "geoip")]
+            {
+                let paths: Vec<String> = value
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                if !paths.is_empty() {
+                    details.geoip = Some(GeoIp::open(&paths)?);
+                }
+            }
+            #[cfg(not(feature = "geoip"))]
+            {
+                let _ = value;
+                warn!("PORKBUN_GEOIP_DATABASES is set, but this build does not include the `geoip` feature; GeoIP lookups are disabled.");
+            }
+        }
+
+        Ok(details)
+    }
+
+    /// Reads the ASNs and countries the detected IP is expected to belong to. Checking them
+    /// needs the GeoIP databases from `PORKBUN_GEOIP_DATABASES`.
+    #[cfg(feature = "geoip")]
+    fn network_policy_from_env(
+        vars: &Vars,
+        details: &DetailsConfig,
+    ) -> Result<Option<NetworkPolicy>> {
+        let list = |var_name: &str| -> Vec<String> {
+            vars.var(var_name)
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+
+        let asns = list("PORKBUN_EXPECTED_ASNS")
+            .iter()
+            .map(|asn| {
+                let digits = asn.strip_prefix("AS").or_else(|| asn.strip_prefix("as"));
+                digits.unwrap_or(asn).parse::<u32>().map_err(|_| {
+                    DdnsError::Config(format!(
+                        "PORKBUN_EXPECTED_ASNS entry '{}' is not a valid ASN.",
+                        asn
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let countries = list("PORKBUN_EXPECTED_COUNTRIES");
+
+        if asns.is_empty() && countries.is_empty() {
+            return Ok(None);
+        }
+        if details.geoip.is_none() {
+            return Err(DdnsError::Config(
+                "PORKBUN_EXPECTED_ASNS/PORKBUN_EXPECTED_COUNTRIES need PORKBUN_GEOIP_DATABASES to be set.".to_string(),
+            ));
+        }
+
+        let action = match vars.var("PORKBUN_NETWORK_MISMATCH_ACTION") {
+            Ok(value) => MismatchAction::parse(&value)?,
+            Err(_) => MismatchAction::Refuse,
+        };
+
+        Ok(Some(NetworkPolicy {
+            asns,
+            countries,
+            action,
+        }))
+    }
+
+    /// Builds the optional post-update verification settings, enabled by `PORKBUN_VERIFY=true`.
+    #[cfg(feature = "verify")]
+    fn verify_from_env(vars: &Vars) -> Result<Option<VerifyConfig>> {
+        let enabled = vars
+            .var("PORKBUN_VERIFY")
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+            .unwrap_or(false);
+        if !enabled {
+            return Ok(None);
+        }
+
+        let resolvers = match vars.var("PORKBUN_VERIFY_RESOLVERS") {
+            Ok(list) => list
+                .split(',')
+                .filter(|entry| !entry.trim().is_empty())
+                .map(Resolver::parse)
+                .collect::<Result<Vec<_>>>()?,
+            Err(_) => {
+                let transport = match vars.var("PORKBUN_VERIFY_TRANSPORT") {
+                    Ok(value) => Transport::parse(&value)?,
+                    Err(_) => Transport::Udp,
+                };
+                let endpoint = vars
+                    .var("PORKBUN_VERIFY_ENDPOINT")
+                    .unwrap_or_else(|_| transport.default_endpoint().to_string());
+                vec![Resolver {
+                    transport,
+                    endpoint,
+                }]
+            }
+        };
+        if resolvers.is_empty() {
+            return Err(DdnsError::Config(
+                "PORKBUN_VERIFY_RESOLVERS must list at least one resolver.".to_string(),
+            ));
+        }
+
+        let delay_seconds = Self::seconds_from_env(vars, "PORKBUN_VERIFY_DELAY_SECONDS")?;
+
+        Ok(Some(VerifyConfig {
+            resolvers,
+            delay_seconds,
+        }))
+    }
+
+    /// In soft-fail mode, replaces an optional setting that failed to load with its default
+    /// and warns once at startup. Otherwise the error is passed through.
+    fn optional<T: Default>(soft_fail: bool, section: &str, result: Result<T>) -> Result<T> {
+        match result {
+            Err(e) if soft_fail => {
+                warn!("{} Continuing without {} (soft-fail mode).", e, section);
+                Ok(T::default())
+            }
+            other => other,
+        }
+    }
+
+    /// Reads a required credential. A credential typed at the startup prompt is among `vars`
+    /// like any other; see `reload::prompt_credentials`.
+    fn credential_from_env(vars: &Vars, var_name: &str) -> Result<String> {
+        vars.var(var_name)
+            .map_err(|_| DdnsError::Config(format!("{} environment variable not set.", var_name)))
+    }
+
+    /// Builds a zone from a domain and its host list. A full hostname such as `ddns.my.co.uk` is
+    /// split into the registrable domain and a prefix added to every subdomain, and `@` stands
+    /// for the domain itself.
+    fn zone(domain: &str, host_list: &HostList, extra_suffixes: &[String]) -> Result<ZoneConfig> {
+        let mut subdomains: Vec<String> = host_list
+            .names
+            .iter()
+            .map(|s| if s == "@" { "" } else { s.trim_matches('.') }.to_string())
+            .collect();
+
+        let (registrable, prefix) = psl::split(domain, extra_suffixes).ok_or_else(|| {
+            DdnsError::Config(format!(
+                "'{}' is a public suffix, not a registrable domain.",
+                domain
+            ))
+        })?;
+        if !prefix.is_empty() {
+            info!(
+                "Treating '{}' as domain '{}' with subdomain '{}'",
+                domain, registrable, prefix
+            );
+            subdomains = subdomains
+                .iter()
+                .map(|s| {
+                    if s.is_empty() {
+                        prefix.to_string()
+                    } else {
+                        format!("{}.{}", s, prefix)
+                    }
+                })
+                .collect();
+        }
+
+        let domain = registrable.to_string();
+        Self::apply_exclusions(&mut subdomains, &host_list.exclusions, &domain);
+        Ok(ZoneConfig {
+            domain,
+            subdomains,
+            exclusions: host_list.exclusions.clone(),
         })
     }
+
+    /// The hostname of a subdomain of `domain`, empty for the domain itself.
+    fn full_name(subdomain: &str, domain: &str) -> String {
+        if subdomain.is_empty() {
+            domain.to_string()
+        } else {
+            format!("{}.{}", subdomain, domain)
+        }
+    }
+
+    /// Whether a subdomain of `domain` matches an exclusion glob. A glob may be written against
+    /// the subdomain (`!*.internal`) or the full hostname (`!*.internal.example.com`).
+    fn matches_exclusion(exclusion: &str, subdomain: &str, domain: &str) -> bool {
+        expand::glob_match(exclusion, subdomain)
+            || expand::glob_match(exclusion, &Self::full_name(subdomain, domain))
+    }
+
+    /// Drops every subdomain matching a `!` exclusion glob.
+    fn apply_exclusions(subdomains: &mut Vec<String>, exclusions: &[String], domain: &str) {
+        for exclusion in exclusions {
+            let before = subdomains.len();
+            subdomains.retain(|subdomain| {
+                let excluded = Self::matches_exclusion(exclusion, subdomain, domain);
+                if excluded {
+                    info!(
+                        "Excluding {} (matches !{})",
+                        Self::full_name(subdomain, domain),
+                        exclusion
+                    );
+                }
+                !excluded
+            });
+            if subdomains.len() == before {
+                warn!("Exclusion '!{}' does not match any host", exclusion);
+            }
+        }
+    }
+
+    /// Reads the record types to manage from `PORKBUN_RECORD_TYPES`, defaulting to A only.
+    fn record_types_from_env(vars: &Vars) -> Result<Vec<RecordType>> {
+        let Ok(value) = vars.var("PORKBUN_RECORD_TYPES") else {
+            return Ok(vec![RecordType::A]);
+        };
+        let mut record_types = Vec::new();
+        for entry in value.split(',').filter(|s| !s.trim().is_empty()) {
+            let record_type = RecordType::parse(entry)?;
+            if !record_types.contains(&record_type) {
+                record_types.push(record_type);
+            }
+        }
+        if record_types.is_empty() {
+            return Err(DdnsError::Config(
+                "PORKBUN_RECORD_TYPES must list at least one of: A, AAAA.".to_string(),
+            ));
+        }
+        Ok(record_types)
+    }
+
+    /// Reads the IP sources to ask: the URLs in `PORKBUN_IP_URLS` first, then the services in
+    /// `PORKBUN_IP_SOURCES`. Custom URLs replace the default services rather than adding to
+    /// them, so setting only `PORKBUN_IP_URLS` keeps detection off third-party services.
+    fn ip_sources_from_env(vars: &Vars) -> Result<Vec<IpSource>> {
+        let mut sources = Vec::new();
+        if let Ok(value) = vars.var("PORKBUN_IP_URLS") {
+            for entry in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                let valid = reqwest::Url::parse(entry)
+                    .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host());
+                if !valid {
+                    return Err(DdnsError::Config(format!(
+                        "PORKBUN_IP_URLS entries must be http:// or https:// URLs, got '{}'.",
+                        entry
+                    )));
+                }
+                let source = IpSource::Url(entry.to_string());
+                if !sources.contains(&source) {
+                    sources.push(source);
+                }
+            }
+        }
+        let Ok(value) = vars.var("PORKBUN_IP_SOURCES") else {
+            if sources.is_empty() {
+                return Ok(IpSource::DEFAULT.to_vec());
+            }
+            return Ok(sources);
+        };
+        for entry in value.split(',').filter(|s| !s.trim().is_empty()) {
+            let source = IpSource::parse(entry)?;
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+        if sources.is_empty() {
+            return Err(DdnsError::Config(
+                "PORKBUN_IP_SOURCES must list at least one of: ipify, icanhazip, ifconfig.me, porkbun, stun, upnp, interface, opendns, cloudflare-dns."
+                    .to_string(),
+            ));
+        }
+        Ok(sources)
+    }
+
+    /// Reads the number of IP sources that must agree on the address. A safety guard, so it
+    /// fails fast rather than falling back to trusting a single source.
+    fn ip_quorum_from_env(vars: &Vars, source_count: usize) -> Result<Option<usize>> {
+        let Ok(value) = vars.var("PORKBUN_IP_QUORUM") else {
+            return Ok(None);
+        };
+        match value.trim().parse::<usize>() {
+            Ok(quorum) if quorum > 0 && quorum <= source_count => Ok(Some(quorum)),
+            _ => Err(DdnsError::Config(format!(
+                "PORKBUN_IP_QUORUM must be a number between 1 and the number of IP sources ({}).",
+                source_count
+            ))),
+        }
+    }
+
+    /// Reads an optional number of seconds, defaulting to zero when unset.
+    fn seconds_from_env(vars: &Vars, var_name: &str) -> Result<u64> {
+        match vars.var(var_name) {
+            Ok(value) => value
+                .parse::<u64>()
+                .map_err(|_| DdnsError::Config(format!("{} must be a valid number.", var_name))),
+            Err(_) => Ok(0),
+        }
+    }
+
+    /// Reads the optional inventory reporting settings; reporting is off unless
+    /// `PORKBUN_TELEMETRY_URL` is set.
+    fn telemetry_from_env(vars: &Vars) -> Result<Option<TelemetryConfig>> {
+        let Ok(url) = vars.var("PORKBUN_TELEMETRY_URL") else {
+            return Ok(None);
+        };
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(DdnsError::Config(
+                "PORKBUN_TELEMETRY_URL must be an http:// or https:// URL.".to_string(),
+            ));
+        }
+
+        let interval_seconds = match vars.var("PORKBUN_TELEMETRY_INTERVAL_SECONDS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| {
+                    DdnsError::Config(
+                        "PORKBUN_TELEMETRY_INTERVAL_SECONDS must be a positive number.".to_string(),
+                    )
+                })?,
+            Err(_) => telemetry::DEFAULT_INTERVAL_SECONDS,
+        };
+
+        Ok(Some(TelemetryConfig {
+            url,
+            token: vars.var("PORKBUN_TELEMETRY_TOKEN").ok(),
+            interval_seconds,
+            instance: vars
+                .var("PORKBUN_INSTANCE_NAME")
+                .unwrap_or_else(|_| telemetry::default_instance()),
+        }))
+    }
+
+    /// Reads the optional health check URL; pinging is off unless `PORKBUN_HEALTHCHECK_URL` is
+    /// set.
+    fn healthcheck_url_from_env(vars: &Vars) -> Result<Option<String>> {
+        let Ok(url) = vars.var("PORKBUN_HEALTHCHECK_URL") else {
+            return Ok(None);
+        };
+        let valid = reqwest::Url::parse(&url)
+            .is_ok_and(|parsed| matches!(parsed.scheme(), "http" | "https"));
+        if !valid {
+            return Err(DdnsError::Config(
+                "PORKBUN_HEALTHCHECK_URL must be an http:// or https:// URL.".to_string(),
+            ));
+        }
+        Ok(Some(url))
+    }
+
+    /// Reads the optional latency probe settings; the probe is off unless
+    /// `PORKBUN_PROBE_TARGET` is set.
+    fn probe_from_env(vars: &Vars) -> Result<Option<ProbeConfig>> {
+        let Ok(target) = vars.var("PORKBUN_PROBE_TARGET") else {
+            return Ok(None);
+        };
+        let target = ProbeTarget::parse(&target).ok_or_else(|| {
+            DdnsError::Config(
+                "PORKBUN_PROBE_TARGET must be an http:// or https:// URL or tcp://host:port."
+                    .to_string(),
+            )
+        })?;
+
+        let count = match vars.var("PORKBUN_PROBE_COUNT") {
+            Ok(value) => value
+                .parse::<u32>()
+                .ok()
+                .filter(|n| (1..=20).contains(n))
+                .ok_or_else(|| {
+                    DdnsError::Config(
+                        "PORKBUN_PROBE_COUNT must be a number from 1 to 20.".to_string(),
+                    )
+                })?,
+            Err(_) => 3,
+        };
+
+        Ok(Some(ProbeConfig { target, count }))
+    }
+
+    fn port_checks_from_env(vars: &Vars) -> Result<Option<PortCheckConfig>> {
+        let Ok(value) = template::var(vars, "PORKBUN_PORT_CHECKS") else {
+            return Ok(None);
+        };
+        let checks = value
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                PortCheck::parse(entry).ok_or_else(|| {
+                    DdnsError::Config(format!(
+                        "PORKBUN_PORT_CHECKS entries must look like 'host:port', got '{}'.",
+                        entry.trim()
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let service_url = vars.var("PORKBUN_PORT_CHECK_URL")
+            .ok()
+            .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+            .ok_or_else(|| {
+                DdnsError::Config(
+                    "PORKBUN_PORT_CHECKS needs PORKBUN_PORT_CHECK_URL, the http(s) URL of a port check service."
+                        .to_string(),
+                )
+            })?;
+        let expect = vars
+            .var("PORKBUN_PORT_CHECK_EXPECT")
+            .ok()
+            .filter(|expect| !expect.is_empty());
+
+        Ok(Some(PortCheckConfig {
+            checks,
+            service_url,
+            expect,
+        }))
+    }
+
+    /// Reads the optional router source of truth, given as either `PORKBUN_ROUTER_IP_URL` or
+    /// `PORKBUN_ROUTER_IP_COMMAND`.
+    fn router_from_env(vars: &Vars) -> Result<Option<RouterConfig>> {
+        let source = match (
+            vars.var("PORKBUN_ROUTER_IP_URL"),
+            vars.var("PORKBUN_ROUTER_IP_COMMAND"),
+        ) {
+            (Ok(_), Ok(_)) => {
+                return Err(DdnsError::Config(
+                    "Set only one of PORKBUN_ROUTER_IP_URL and PORKBUN_ROUTER_IP_COMMAND."
+                        .to_string(),
+                ))
+            }
+            (Ok(url), Err(_)) => RouterSource::Url(url),
+            (Err(_), Ok(command)) => RouterSource::Command(command),
+            (Err(_), Err(_)) => return Ok(None),
+        };
+
+        let mismatch_cycles = match vars.var("PORKBUN_ROUTER_MISMATCH_CYCLES") {
+            Ok(value) => value
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| {
+                    DdnsError::Config(
+                        "PORKBUN_ROUTER_MISMATCH_CYCLES must be a positive number.".to_string(),
+                    )
+                })?,
+            Err(_) => 3,
+        };
+
+        Ok(Some(RouterConfig {
+            source,
+            mismatch_cycles,
+        }))
+    }
+
+    /// Builds the optional mirror configuration. Any unset mirror variable falls back to the
+    /// primary value, so a second zone in the same account only needs `PORKBUN_MIRROR_DOMAIN`.
+    fn mirror_from_env(
+        vars: &Vars,
+        api_key: &str,
+        secret_api_key: &str,
+        domain: &str,
+    ) -> Option<MirrorConfig> {
+        let mirror_api_key = vars.var("PORKBUN_MIRROR_API_KEY").ok();
+        let mirror_secret_api_key = vars.var("PORKBUN_MIRROR_SECRET_API_KEY").ok();
+        let mirror_domain = template::var(vars, "PORKBUN_MIRROR_DOMAIN").ok();
+
+        if mirror_api_key.is_none() && mirror_secret_api_key.is_none() && mirror_domain.is_none() {
+            return None;
+        }
+
+        Some(MirrorConfig {
+            api_key: mirror_api_key.unwrap_or_else(|| api_key.to_string()),
+            secret_api_key: mirror_secret_api_key.unwrap_or_else(|| secret_api_key.to_string()),
+            domain: mirror_domain.unwrap_or_else(|| domain.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(subdomains: &str, domains: &str) -> Config {
+        let mut vars = Vars::default();
+        vars.set("PORKBUN_API_KEY", "pk1_test");
+        vars.set("PORKBUN_SECRET_API_KEY", "sk1_test");
+        vars.set("PORKBUN_DOMAIN", "example.com");
+        vars.set("PORKBUN_SUBDOMAIN", subdomains);
+        vars.set("PORKBUN_DOMAINS", domains);
+        vars.set("PORKBUN_ALIASES", "www=blog");
+        Config::from_vars(&vars).unwrap()
+    }
+
+    #[test]
+    fn adoptable_hosts() {
+        let config = config("www,!*.internal,!*.lab.example.com", "example.net:@,!old");
+        for (domain, subdomain, adoptable) in [
+            ("example.com", "api", true),
+            ("example.com", "", true),
+            ("example.com", "WWW", false),
+            ("example.com", "nas.internal", false),
+            ("example.com", "printer.lab", false),
+            ("example.com", "blog", false),
+            ("example.net", "api", true),
+            ("example.net", "", false),
+            ("example.net", "old", false),
+            ("example.org", "api", false),
+        ] {
+            assert_eq!(
+                config.check_adoptable(domain, subdomain).is_ok(),
+                adoptable,
+                "{} in {}",
+                subdomain,
+                domain
+            );
+        }
+    }
+
+    #[test]
+    fn simulation_leaves_real_services_and_files_alone() {
+        let mut vars = Vars::default();
+        vars.set("PORKBUN_DOMAIN", "example.com");
+        vars.set("PORKBUN_HEALTHCHECK_URL", "https://hc-ping.com/abc");
+        vars.set("PORKBUN_PROBE_TARGET", "https://example.net/");
+        vars.set(
+            "PORKBUN_PROMETHEUS_TEXTFILE",
+            "/var/lib/node_exporter/ddns.prom",
+        );
+        vars.set("PORKBUN_JOURNAL_FILE", "/data/journal.jsonl");
+        simulate::overrides(&mut vars);
+
+        let config = Config::from_vars(&vars).unwrap();
+        assert_eq!(config.healthcheck_url, None);
+        assert!(config.probe.is_none());
+        assert_eq!(
+            config.textfile.as_deref(),
+            Some("porkbun-simulate-metrics.prom")
+        );
+        assert_eq!(
+            config.journal_file.as_deref(),
+            Some("porkbun-simulate-journal.jsonl")
+        );
+    }
 }
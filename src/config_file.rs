@@ -0,0 +1,267 @@
+// src/config_file.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::template;
+use crate::vars::Vars;
+use log::info;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Credentials a tenant must not inherit from the shared environment.
+const CREDENTIAL_VARS: [&str; 4] = [
+    "PORKBUN_API_KEY",
+    "PORKBUN_SECRET_API_KEY",
+    "PORKBUN_MIRROR_API_KEY",
+    "PORKBUN_MIRROR_SECRET_API_KEY",
+];
+
+/// State files given a per-tenant default, so tenants never share a journal or plan.
+const TENANT_STATE_FILES: [(&str, &str); 6] = [
+    ("PORKBUN_JOURNAL_FILE", "journal.jsonl"),
+    ("PORKBUN_IP_STATE_FILE", "ip-state.json"),
+    ("PORKBUN_PLAN_FILE", "planned-changes.json"),
+    ("PORKBUN_TTL_BACKUP_FILE", "ttl-backup.json"),
+    (
+        "PORKBUN_REMOTE_CONFIG_STATE_FILE",
+        "remote-config-state.json",
+    ),
+    ("PORKBUN_LAST_SUCCESS_FILE", "last-success.json"),
+];
+
+/// The layout of a `--config` file, in TOML or YAML. Every value maps onto one of the `PORKBUN_*`
+/// environment variables; anything without a dedicated key can go under `[env]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    /// Numbers the revisions of a remote file; each change must raise it.
+    serial: Option<u64>,
+    credentials: Credentials,
+    domains: Vec<Domain>,
+    records: Records,
+    intervals: Intervals,
+    /// Site variables, substituted for `{name}` in hostnames.
+    vars: BTreeMap<String, String>,
+    env: BTreeMap<String, String>,
+    /// Customers with their own settings, each run in a process of its own. The settings
+    /// above are shared defaults for them, apart from credentials.
+    tenants: BTreeMap<String, FileConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Credentials {
+    api_key: Option<String>,
+    secret_api_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Domain {
+    domain: String,
+    /// Subdomains as in `PORKBUN_SUBDOMAIN`, with `@` for the domain itself.
+    #[serde(default)]
+    subdomains: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Records {
+    types: Option<Vec<String>>,
+    ttl: Option<u32>,
+    /// TTLs for individual hosts, keyed by full hostname as in `PORKBUN_HOST_TTLS`.
+    host_ttls: BTreeMap<String, u32>,
+    auto_create: Option<bool>,
+    max_writes_per_cycle: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Intervals {
+    check_seconds: Option<u64>,
+    settle_seconds: Option<u64>,
+    startup_delay_seconds: Option<u64>,
+    startup_splay_seconds: Option<u64>,
+}
+
+impl FileConfig {
+    /// Flattens the file into environment variable assignments.
+    fn into_vars(self) -> Vec<(String, String)> {
+        let mut vars = Vec::new();
+        let mut set = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                vars.push((key.to_string(), value));
+            }
+        };
+
+        set("PORKBUN_API_KEY", self.credentials.api_key);
+        set("PORKBUN_SECRET_API_KEY", self.credentials.secret_api_key);
+        if !self.domains.is_empty() {
+            let zones: Vec<String> = self
+                .domains
+                .iter()
+                .map(|zone| format!("{}:{}", zone.domain, zone.subdomains.join(",")))
+                .collect();
+            set("PORKBUN_DOMAINS", Some(zones.join(";")));
+        }
+        set(
+            "PORKBUN_RECORD_TYPES",
+            self.records.types.map(|types| types.join(",")),
+        );
+        set("PORKBUN_TTL", self.records.ttl.map(|v| v.to_string()));
+        if !self.records.host_ttls.is_empty() {
+            let host_ttls: Vec<String> = self
+                .records
+                .host_ttls
+                .iter()
+                .map(|(host, ttl)| format!("{}={}", host, ttl))
+                .collect();
+            set("PORKBUN_HOST_TTLS", Some(host_ttls.join(";")));
+        }
+        set(
+            "PORKBUN_AUTO_CREATE",
+            self.records.auto_create.map(|v| v.to_string()),
+        );
+        set(
+            "PORKBUN_MAX_WRITES_PER_CYCLE",
+            self.records.max_writes_per_cycle.map(|v| v.to_string()),
+        );
+        set(
+            "PORKBUN_CHECK_INTERVAL_SECONDS",
+            self.intervals.check_seconds.map(|v| v.to_string()),
+        );
+        set(
+            "PORKBUN_SETTLE_SECONDS",
+            self.intervals.settle_seconds.map(|v| v.to_string()),
+        );
+        set(
+            "PORKBUN_STARTUP_DELAY_SECONDS",
+            self.intervals.startup_delay_seconds.map(|v| v.to_string()),
+        );
+        set(
+            "PORKBUN_STARTUP_SPLAY_SECONDS",
+            self.intervals.startup_splay_seconds.map(|v| v.to_string()),
+        );
+        for (name, value) in self.vars {
+            set(&template::key(&name), Some(value));
+        }
+        for (key, value) in self.env {
+            set(&key, Some(value));
+        }
+        vars
+    }
+}
+
+/// Parses the file as YAML when its extension is `.yaml` or `.yml`, and as TOML otherwise.
+fn parse(path: &str, contents: &str) -> Result<FileConfig> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let parsed = match extension.as_deref() {
+        #[cfg(feature = "config-yaml")]
+        Some("yaml" | "yml") => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+        #[cfg(not(feature = "config-yaml"))]
+        Some("yaml" | "yml") => {
+            return Err(DdnsError::Config(format!(
+            "Config file '{}' is YAML, but this build does not include the `config-yaml` feature.",
+            path
+        )))
+        }
+        _ => toml::from_str(contents).map_err(|e| e.to_string()),
+    };
+    parsed.map_err(|e| DdnsError::Config(format!("Failed to parse config file '{}': {}", path, e)))
+}
+
+/// A remote configuration file: its serial and the environment variable assignments it holds.
+#[cfg(feature = "remote-config")]
+pub struct RemoteFile {
+    pub serial: Option<u64>,
+    pub vars: Vec<(String, String)>,
+}
+
+/// Parses remote configuration file contents. `path` only selects the format.
+#[cfg(feature = "remote-config")]
+pub fn remote(path: &str, contents: &str) -> Result<RemoteFile> {
+    let file = parse(path, contents)?;
+    Ok(RemoteFile {
+        serial: file.serial,
+        vars: file.into_vars(),
+    })
+}
+
+/// Reads a TOML or YAML configuration file and sets each value as its variable in `vars`,
+/// returning the names of the tenants it defines. Variables that are already set win, so the
+/// environment can override the file.
+///
+/// With `tenant`, that tenant's settings are loaded on top of the shared ones. They win over
+/// the environment too, and credentials it doesn't set are cleared, so a tenant never runs
+/// with another account's keys.
+pub fn load(path: &str, tenant: Option<&str>, vars: &mut Vars) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| DdnsError::Config(format!("Failed to read config file '{}': {}", path, e)))?;
+    let mut file = parse(path, &contents)?;
+    let mut tenants = std::mem::take(&mut file.tenants);
+    for (name, settings) in &tenants {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(DdnsError::Config(format!(
+                "Tenant name '{}' may only contain letters, digits, '-' and '_'.",
+                name
+            )));
+        }
+        if !settings.tenants.is_empty() {
+            return Err(DdnsError::Config(format!(
+                "Tenant '{}' defines tenants of its own; tenants can't be nested.",
+                name
+            )));
+        }
+    }
+    if !tenants.is_empty()
+        && (file.credentials.api_key.is_some() || file.credentials.secret_api_key.is_some())
+    {
+        return Err(DdnsError::Config(format!(
+            "Config file '{}' defines tenants, so credentials must be set for each tenant rather than shared.",
+            path
+        )));
+    }
+
+    let names: Vec<String> = tenants.keys().cloned().collect();
+    let mut loaded = 0;
+    if let Some(name) = tenant {
+        let Some(settings) = tenants.remove(name) else {
+            return Err(DdnsError::Config(format!(
+                "Config file '{}' has no tenant '{}'.",
+                path, name
+            )));
+        };
+        for var_name in CREDENTIAL_VARS {
+            vars.remove(var_name);
+        }
+        for (key, value) in settings.into_vars() {
+            vars.set(&key, &value);
+            loaded += 1;
+        }
+        vars.set("PORKBUN_TENANT", name);
+        for (var_name, file_name) in TENANT_STATE_FILES {
+            vars.set_default(var_name, &format!("porkbun-{}-{}", name, file_name));
+        }
+    }
+    for (key, value) in file.into_vars() {
+        if vars.set_default(&key, &value) {
+            loaded += 1;
+        }
+    }
+    match tenant {
+        Some(name) => info!(
+            "Loaded {} setting(s) for tenant {} from config file {}",
+            loaded, name, path
+        ),
+        None => info!("Loaded {} setting(s) from config file {}", loaded, path),
+    }
+    Ok(names)
+}
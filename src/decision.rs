@@ -0,0 +1,80 @@
+// src/decision.rs
+
+//! One structured log line per record write, holding everything that led to it, so a
+//! surprising update can be explained afterwards from the logs alone. The lines are logged
+//! under the `porkbun_ddns::decision` target, which `RUST_LOG` can select or silence.
+
+use crate::config::Config;
+use crate::porkbun::RecordType;
+use log::{info, warn};
+use serde::Serialize;
+
+/// The address a record should hold, and where it came from.
+#[derive(Debug, Clone, Copy)]
+pub struct Wanted<'a> {
+    pub record_type: RecordType,
+    pub ip: &'a str,
+    /// The service that detected the address; `None` for a scheduled change.
+    pub source: Option<&'a str>,
+    /// The address the IP state file says the record held, while its entry is fresh.
+    pub cached_ip: Option<&'a str>,
+}
+
+/// Why a record is about to be written.
+#[derive(Debug, Serialize)]
+pub struct Decision<'a> {
+    pub host: &'a str,
+    pub record_type: &'a str,
+    /// The address the record should hold, or the canonical host for an alias's CNAME.
+    pub detected_ip: &'a str,
+    /// The IP service that reported `detected_ip`, `scheduled` for a planned change or `alias`
+    /// for an alias's CNAME.
+    pub source: &'a str,
+    pub cached_ip: Option<&'a str>,
+    /// What the record held, or `None` when it doesn't exist.
+    pub remote_ip: Option<&'a str>,
+    /// The TTL the record held, when known.
+    pub remote_ttl: Option<u32>,
+    /// Where `remote_ip` was read: `lookup`, `zone-snapshot` or `ip-state`.
+    pub remote_from: &'a str,
+    /// The TTL being written.
+    pub ttl: u32,
+    /// `differs` when the record holds another address, `ttl-differs` when only its TTL isn't
+    /// the configured one, `missing` when there is none.
+    pub comparison: &'a str,
+    /// `update` or `create`.
+    pub action: &'a str,
+    pub policy: Policy<'a>,
+}
+
+/// The settings that shaped the write.
+#[derive(Debug, Serialize)]
+pub struct Policy<'a> {
+    pub dry_run: bool,
+    pub auto_create: bool,
+    pub update_method: &'a str,
+    pub max_writes_per_cycle: Option<u32>,
+    pub settle_seconds: u64,
+    pub reason: Option<&'a str>,
+}
+
+impl<'a> Policy<'a> {
+    pub fn new(config: &'a Config, reason: Option<&'a str>) -> Self {
+        Policy {
+            dry_run: config.dry_run,
+            auto_create: config.auto_create,
+            update_method: config.update_method.as_str(),
+            max_writes_per_cycle: config.max_writes_per_cycle,
+            settle_seconds: config.settle_seconds,
+            reason,
+        }
+    }
+}
+
+/// Logs the decision as a single JSON object.
+pub fn log(decision: &Decision<'_>) {
+    match serde_json::to_string(decision) {
+        Ok(line) => info!("{}", line),
+        Err(e) => warn!("Failed to encode the decision for {}: {}", decision.host, e),
+    }
+}
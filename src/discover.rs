@@ -0,0 +1,245 @@
+// src/discover.rs
+
+//! Finds records in the managed zones that already point at this connection but aren't
+//! managed yet, so moving an existing setup over doesn't mean typing every host in by hand.
+
+use crate::config::Config;
+use crate::errors::{DdnsError, Result};
+use crate::ip_fetcher;
+use crate::porkbun::{RecordType, Zone};
+use log::{debug, error, info, warn};
+use serde_json::json;
+
+/// What the daemon does with discovered hosts when it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoverMode {
+    Off,
+    /// Log the hosts, leaving the configuration alone.
+    Propose,
+    /// Manage the hosts from then on, as if they were configured.
+    Adopt,
+}
+
+impl DiscoverMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" | "false" => Ok(DiscoverMode::Off),
+            "propose" => Ok(DiscoverMode::Propose),
+            "adopt" => Ok(DiscoverMode::Adopt),
+            other => Err(DdnsError::Config(format!(
+                "Unknown discovery mode '{}'. Expected one of: off, propose, adopt.",
+                other
+            ))),
+        }
+    }
+}
+
+/// An unmanaged record holding the current public address.
+#[derive(Debug, Clone)]
+pub struct Found {
+    pub domain: String,
+    /// The subdomain, empty for the domain itself.
+    pub subdomain: String,
+    pub name: String,
+    pub record_type: RecordType,
+    pub ip: String,
+}
+
+/// Detects the public address of every configured record type, then reads each managed zone
+/// (mirrors aside, as they copy the primary zone) and returns the records of those types that
+/// hold it but aren't managed. Hosts excluded with `!` and aliases are left out. A zone that
+/// can't be read is logged and skipped.
+pub async fn find(client: &reqwest::Client, config: &Config, zones: &[Zone<'_>]) -> Vec<Found> {
+    let mut addresses = Vec::new();
+    for &record_type in &config.record_types {
+        match ip_fetcher::detect(client, &config.ip_detector(), record_type).await {
+            Ok((ip, _)) => addresses.push((record_type, ip)),
+            Err(e) => warn!(
+                "Could not detect the public {} address, so {} records are not discovered: {}",
+                record_type.family(),
+                record_type,
+                e
+            ),
+        }
+    }
+    if addresses.is_empty() {
+        return Vec::new();
+    }
+
+    let mut found = Vec::new();
+    for zone in zones.iter().filter(|zone| !zone.mirror) {
+        let domain = zone.client.domain;
+        let records = match zone.client.retrieve_records().await {
+            Ok(records) => records,
+            Err(e) => {
+                error!("Could not read zone {} to discover hosts: {}", domain, e);
+                continue;
+            }
+        };
+        for record in records {
+            let Some(&(record_type, ref ip)) = addresses
+                .iter()
+                .find(|(t, _)| record.record_type.eq_ignore_ascii_case(t.as_str()))
+            else {
+                continue;
+            };
+            if !record.has_content(ip) {
+                continue;
+            }
+            let name = record.name.trim_end_matches('.').to_ascii_lowercase();
+            let subdomain = if name.eq_ignore_ascii_case(domain) {
+                String::new()
+            } else {
+                match name.strip_suffix(&format!(".{}", domain.to_ascii_lowercase())) {
+                    Some(subdomain) => subdomain.to_string(),
+                    None => continue,
+                }
+            };
+            if let Err(reason) = config.check_adoptable(domain, &subdomain) {
+                debug!("Not proposing {}: {}", name, reason);
+                continue;
+            }
+            found.push(Found {
+                domain: domain.to_string(),
+                subdomain,
+                name,
+                record_type,
+                ip: ip.clone(),
+            });
+        }
+    }
+    found
+}
+
+/// The subdomains found in each zone, each listed once in the order found, with `@` for the
+/// domain itself as in the host lists.
+fn hosts_by_zone(found: &[Found]) -> Vec<(&str, Vec<&str>)> {
+    let mut zones: Vec<(&str, Vec<&str>)> = Vec::new();
+    for host in found {
+        let subdomain = if host.subdomain.is_empty() {
+            "@"
+        } else {
+            host.subdomain.as_str()
+        };
+        let index = match zones.iter().position(|(domain, _)| *domain == host.domain) {
+            Some(index) => index,
+            None => {
+                zones.push((&host.domain, Vec::new()));
+                zones.len() - 1
+            }
+        };
+        if !zones[index].1.contains(&subdomain) {
+            zones[index].1.push(subdomain);
+        }
+    }
+    zones
+}
+
+/// Logs the hosts that could be managed, with the lists to add them to.
+pub fn propose(found: &[Found]) {
+    if found.is_empty() {
+        info!("Discovery found no unmanaged records pointing at this connection");
+        return;
+    }
+    for host in found {
+        info!(
+            "Discovered {} {} pointing at this connection ({}); it is not managed",
+            host.name, host.record_type, host.ip
+        );
+    }
+    for (domain, subdomains) in hosts_by_zone(found) {
+        info!(
+            "To manage them, add to the hosts of {}: {}",
+            domain,
+            subdomains.join(",")
+        );
+    }
+}
+
+/// Adds the discovered hosts to the zones they were found in, so they are kept up to date
+/// like configured ones until the configuration is loaded again.
+pub fn adopt(config: &mut Config, found: &[Found]) {
+    if found.is_empty() {
+        info!("Discovery found no unmanaged records pointing at this connection");
+    }
+    for (domain, subdomains) in hosts_by_zone(found) {
+        let mut adopted = Vec::new();
+        for &host in &subdomains {
+            let subdomain = match host {
+                "@" => "",
+                subdomain => subdomain,
+            };
+            match config.check_adoptable(domain, subdomain) {
+                Ok(()) => adopted.push(host),
+                Err(reason) => warn!("Not adopting {} in {}: {}", host, domain, reason),
+            }
+        }
+        if adopted.is_empty() {
+            continue;
+        }
+        let list = if domain == config.domain {
+            &mut config.subdomains
+        } else {
+            match config
+                .extra_zones
+                .iter_mut()
+                .find(|zone| zone.domain == domain)
+            {
+                Some(zone) => &mut zone.subdomains,
+                None => continue,
+            }
+        };
+        list.extend(adopted.iter().map(|&subdomain| match subdomain {
+            "@" => String::new(),
+            subdomain => subdomain.to_string(),
+        }));
+        info!(
+            "Adopted {} discovered host(s) in {}: {}",
+            adopted.len(),
+            domain,
+            adopted.join(",")
+        );
+    }
+}
+
+/// Prints the hosts found for the `discover` command, with the lists to add them to.
+pub fn print(found: &[Found]) {
+    if found.is_empty() {
+        println!("No unmanaged records point at this connection.");
+        return;
+    }
+    println!(
+        "{} unmanaged record(s) point at this connection:",
+        found.len()
+    );
+    for host in found {
+        println!("  {} {} {}", host.name, host.record_type, host.ip);
+    }
+    println!();
+    println!("To manage them, add to the host lists:");
+    for (domain, subdomains) in hosts_by_zone(found) {
+        println!("  {}: {}", domain, subdomains.join(","));
+    }
+}
+
+/// The hosts found, for `discover --json`:
+///
+/// ```json
+/// {"records": [{"name": "nas.example.com", "domain": "example.com", "subdomain": "nas",
+///               "type": "A", "address": "203.0.113.4"}]}
+/// ```
+pub fn to_json(found: &[Found]) -> serde_json::Value {
+    let records: Vec<_> = found
+        .iter()
+        .map(|host| {
+            json!({
+                "name": host.name,
+                "domain": host.domain,
+                "subdomain": host.subdomain,
+                "type": host.record_type.as_str(),
+                "address": host.ip,
+            })
+        })
+        .collect();
+    json!({ "records": records })
+}
@@ -0,0 +1,398 @@
+// src/dns.rs
+
+use crate::app_error::AppError;
+use crate::errors::{DdnsError, Result};
+use crate::explain;
+use crate::random;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::{timeout, Duration};
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+pub const CLASS_IN: u16 = 1;
+/// The CHAOS class, used by resolvers to answer questions about themselves.
+pub const CLASS_CH: u16 = 3;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+
+/// How queries are carried to the resolver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    Https,
+    Tls,
+}
+
+impl Transport {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "udp" => Ok(Transport::Udp),
+            "doh" | "https" => Ok(Transport::Https),
+            "dot" | "tls" => Ok(Transport::Tls),
+            other => Err(DdnsError::Config(format!(
+                "Unknown DNS transport '{}'. Expected one of: udp, doh, dot.",
+                other
+            ))),
+        }
+    }
+
+    /// The endpoint used when none is configured.
+    pub fn default_endpoint(&self) -> &'static str {
+        match self {
+            Transport::Udp => "1.1.1.1:53",
+            Transport::Https => "https://cloudflare-dns.com/dns-query",
+            Transport::Tls => "cloudflare-dns.com:853",
+        }
+    }
+}
+
+/// A single resolver reachable over a given transport.
+///
+/// The endpoint is `host:port` for UDP and DoT, and a full URL for DoH.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    pub transport: Transport,
+    pub endpoint: String,
+}
+
+impl Resolver {
+    /// Parses a resolver entry such as `1.1.1.1`, `tls://dns.quad9.net` or
+    /// `https://dns.google/dns-query`. Entries without a scheme are plain UDP resolvers,
+    /// and a missing port defaults to 53 for UDP and 853 for DoT.
+    pub fn parse(entry: &str) -> Result<Self> {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return Err(DdnsError::Config("Empty DNS resolver entry.".to_string()));
+        }
+
+        if entry.starts_with("https://") {
+            return Ok(Resolver {
+                transport: Transport::Https,
+                endpoint: entry.to_string(),
+            });
+        }
+
+        let (transport, address) = match entry.split_once("://") {
+            Some(("tls", address)) => (Transport::Tls, address),
+            Some(("udp", address)) => (Transport::Udp, address),
+            Some((scheme, _)) => {
+                return Err(DdnsError::Config(format!(
+                    "Unknown DNS resolver scheme '{}' in '{}'.",
+                    scheme, entry
+                )))
+            }
+            None => (Transport::Udp, entry),
+        };
+        let default_port = if transport == Transport::Tls { 853 } else { 53 };
+
+        Ok(Resolver {
+            transport,
+            endpoint: with_default_port(address, default_port),
+        })
+    }
+
+    /// Queries the resolver and returns the textual value of every answer of type `qtype`.
+    pub async fn query(
+        &self,
+        client: &reqwest::Client,
+        name: &str,
+        qtype: u16,
+    ) -> Result<Vec<String>> {
+        self.query_class(client, name, qtype, CLASS_IN).await
+    }
+
+    /// Like `query`, for a question of another class.
+    pub async fn query_class(
+        &self,
+        client: &reqwest::Client,
+        name: &str,
+        qtype: u16,
+        qclass: u16,
+    ) -> Result<Vec<String>> {
+        let id = random::random_u64() as u16;
+        let message = build_query(id, name, qtype, qclass);
+        let via = match self.transport {
+            Transport::Udp => "UDP",
+            Transport::Https => "DoH",
+            Transport::Tls => "DoT",
+        };
+        explain::call(
+            "dns",
+            format!(
+                "{} query for {} type {} to {}",
+                via, name, qtype, self.endpoint
+            ),
+        );
+
+        let response = match self.transport {
+            Transport::Udp => timeout(QUERY_TIMEOUT, self.exchange_udp(&message))
+                .await
+                .map_err(|_| self.timeout_error())??,
+            Transport::Tls => timeout(QUERY_TIMEOUT, self.exchange_tls(&message))
+                .await
+                .map_err(|_| self.timeout_error())??,
+            Transport::Https => client
+                .post(&self.endpoint)
+                .header("content-type", "application/dns-message")
+                .header("accept", "application/dns-message")
+                .timeout(QUERY_TIMEOUT)
+                .body(message)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?
+                .to_vec(),
+        };
+
+        parse_answers(&response, id, qtype)
+    }
+
+    async fn exchange_udp(&self, message: &[u8]) -> Result<Vec<u8>> {
+        // An IPv6 resolver can only be reached from an IPv6 socket.
+        let local = match self.endpoint.parse::<SocketAddr>() {
+            Ok(SocketAddr::V6(_)) => "[::]:0",
+            _ => "0.0.0.0:0",
+        };
+        let socket = UdpSocket::bind(local).await.map_err(|e| self.io_error(e))?;
+        socket
+            .connect(&self.endpoint)
+            .await
+            .map_err(|e| self.io_error(e))?;
+        socket.send(message).await.map_err(|e| self.io_error(e))?;
+
+        let mut buf = vec![0u8; 4096];
+        let len = socket.recv(&mut buf).await.map_err(|e| self.io_error(e))?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    async fn exchange_tls(&self, message: &[u8]) -> Result<Vec<u8>> {
+        let host = self
+            .endpoint
+            .rsplit_once(':')
+            .map(|(host, _)| host)
+            .unwrap_or(&self.endpoint);
+
+        let stream = TcpStream::connect(&self.endpoint)
+            .await
+            .map_err(|e| self.io_error(e))?;
+        let connector = tokio_native_tls::native_tls::TlsConnector::new()
+            .map_err(|e| AppError::Dns(format!("Failed to set up TLS: {}", e)))?;
+        let mut stream = tokio_native_tls::TlsConnector::from(connector)
+            .connect(host, stream)
+            .await
+            .map_err(|e| AppError::Dns(format!("TLS handshake with {} failed: {}", host, e)))?;
+
+        // DNS over a stream is prefixed with a two-byte message length.
+        let mut framed = (message.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(message);
+        stream
+            .write_all(&framed)
+            .await
+            .map_err(|e| self.io_error(e))?;
+
+        let mut len = [0u8; 2];
+        stream
+            .read_exact(&mut len)
+            .await
+            .map_err(|e| self.io_error(e))?;
+        let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream
+            .read_exact(&mut buf)
+            .await
+            .map_err(|e| self.io_error(e))?;
+        Ok(buf)
+    }
+
+    fn io_error(&self, e: std::io::Error) -> DdnsError {
+        AppError::Dns(format!("Query to {} failed: {}", self.endpoint, e)).into()
+    }
+
+    fn timeout_error(&self) -> DdnsError {
+        AppError::Dns(format!("Query to {} timed out", self.endpoint)).into()
+    }
+}
+
+/// Returns the `in-addr.arpa` / `ip6.arpa` name used for reverse lookups of `ip`.
+pub fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(v6) => {
+            let mut name = String::with_capacity(72);
+            for byte in v6.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0x0F, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+/// Appends `port` unless the address already carries one. Bare IPv6 addresses are bracketed.
+fn with_default_port(address: &str, port: u16) -> String {
+    if let Ok(ip) = address.parse::<std::net::IpAddr>() {
+        return std::net::SocketAddr::new(ip, port).to_string();
+    }
+    if address.rsplit_once(':').is_some() {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, port)
+    }
+}
+
+/// Encodes a recursive query for `name` with a single question.
+fn build_query(id: u16, name: &str, qtype: u16, qclass: u16) -> Vec<u8> {
+    let mut message = Vec::with_capacity(32 + name.len());
+    message.extend_from_slice(&id.to_be_bytes());
+    message.extend_from_slice(&0x0100u16.to_be_bytes()); // Recursion desired
+    message.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    message.extend_from_slice(&[0, 0, 0, 0, 0, 0]); // ANCOUNT, NSCOUNT, ARCOUNT
+
+    for label in name.trim_end_matches('.').split('.') {
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    message.extend_from_slice(&qtype.to_be_bytes());
+    message.extend_from_slice(&qclass.to_be_bytes());
+    message
+}
+
+/// Decodes a response and returns the answers matching `qtype`.
+fn parse_answers(message: &[u8], id: u16, qtype: u16) -> Result<Vec<String>> {
+    let malformed = || AppError::Dns("Malformed DNS response".to_string()).into();
+    let read_u16 = |pos: usize| -> Result<u16> {
+        message
+            .get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(malformed)
+    };
+
+    if read_u16(0)? != id {
+        return Err(AppError::Dns("DNS response ID mismatch".to_string()).into());
+    }
+    let rcode = read_u16(2)? & 0x000F;
+    if rcode != 0 {
+        return Err(AppError::Dns(format!("Resolver returned error code {}", rcode)).into());
+    }
+
+    let question_count = read_u16(4)?;
+    let answer_count = read_u16(6)?;
+    let mut pos = 12;
+
+    for _ in 0..question_count {
+        pos = skip_name(message, pos).ok_or_else(malformed)? + 4;
+    }
+
+    let mut answers = Vec::new();
+    for _ in 0..answer_count {
+        pos = skip_name(message, pos).ok_or_else(malformed)?;
+        let record_type = read_u16(pos)?;
+        let data_len = read_u16(pos + 8)? as usize;
+        let data = message
+            .get(pos + 10..pos + 10 + data_len)
+            .ok_or_else(malformed)?;
+        pos += 10 + data_len;
+
+        if record_type != qtype {
+            continue;
+        }
+        match (record_type, data.len()) {
+            (TYPE_A, 4) => {
+                answers.push(Ipv4Addr::new(data[0], data[1], data[2], data[3]).to_string())
+            }
+            (TYPE_AAAA, 16) => {
+                let octets: [u8; 16] = data.try_into().map_err(|_| malformed())?;
+                answers.push(Ipv6Addr::from(octets).to_string())
+            }
+            (TYPE_PTR, _) => {
+                answers.push(read_name(message, pos - data_len).ok_or_else(malformed)?)
+            }
+            (TYPE_TXT, _) => answers.push(read_character_strings(data).ok_or_else(malformed)?),
+            _ => return Err(malformed()),
+        }
+    }
+    Ok(answers)
+}
+
+/// Decodes the (possibly compressed) name starting at `pos`.
+fn read_name(message: &[u8], mut pos: usize) -> Option<String> {
+    let mut labels = Vec::new();
+    // Bound the number of pointers followed so a looping message can't hang us.
+    for _ in 0..128 {
+        let len = *message.get(pos)?;
+        if len & 0xC0 == 0xC0 {
+            pos = (usize::from(len & 0x3F) << 8) | usize::from(*message.get(pos + 1)?);
+            continue;
+        }
+        if len == 0 {
+            return Some(labels.join("."));
+        }
+        let label = message.get(pos + 1..pos + 1 + len as usize)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        pos += 1 + len as usize;
+    }
+    None
+}
+
+/// Joins the length-prefixed character strings of a TXT record.
+fn read_character_strings(mut data: &[u8]) -> Option<String> {
+    let mut text = String::new();
+    while let Some((&len, rest)) = data.split_first() {
+        let chunk = rest.get(..usize::from(len))?;
+        text.push_str(&String::from_utf8_lossy(chunk));
+        data = &rest[usize::from(len)..];
+    }
+    Some(text)
+}
+
+/// Returns the position just past the (possibly compressed) name starting at `pos`.
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)?;
+        if len & 0xC0 == 0xC0 {
+            return Some(pos + 2);
+        }
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        pos += 1 + len as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_txt_character_strings() {
+        let cases: [(&str, &[u8], Option<&str>); 6] = [
+            ("one string", b"\x0b203.0.113.7", Some("203.0.113.7")),
+            (
+                "split across strings",
+                b"\x042001\x09:db8::7ab",
+                Some("2001:db8::7ab"),
+            ),
+            ("empty string", b"\x00", Some("")),
+            ("no strings", b"", Some("")),
+            ("length past the end", b"\x0c203.0.113.7", None),
+            ("second length past the end", b"\x03abc\x05de", None),
+        ];
+        for (case, data, expected) in cases {
+            assert_eq!(
+                read_character_strings(data).as_deref(),
+                expected,
+                "{}",
+                case
+            );
+        }
+    }
+}
@@ -2,17 +2,64 @@
 
 use thiserror::Error;
 
-/// A unified error type for the application.
+/// The error type of the library client. Callers branch on the variant rather than the
+/// message, e.g. with `is_transient` to decide whether to retry.
 #[derive(Error, Debug)]
 pub enum DdnsError {
     #[error("Configuration error: {0}")]
     Config(String),
 
+    /// The request never got a usable answer: DNS, connection or TLS failure, timeout or an
+    /// HTTP error status.
     #[error("HTTP request error: {0}")]
     Request(#[from] reqwest::Error),
 
-    #[error("Porkbun API error: {0}")]
-    PorkbunApi(String),
+    #[error("IP detection error: {0}")]
+    IpDetection(String),
+
+    /// Porkbun answered, but refused the request, with its error code when it gave one.
+    #[error("Porkbun API error: {message}{}", code.as_ref().map(|c| format!(" ({})", c)).unwrap_or_default())]
+    PorkbunApi {
+        code: Option<String>,
+        message: String,
+    },
+
+    /// Porkbun answered with something that could not be understood.
+    #[error("Unexpected Porkbun API response: {0}")]
+    UnexpectedResponse(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// An error from code built on the client, such as the updater's hooks.
+    #[error(transparent)]
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl DdnsError {
+    /// An I/O error on the file at `path`, with what was being done to it in the message.
+    pub fn file(action: &str, path: &str, e: std::io::Error) -> Self {
+        DdnsError::Io(std::io::Error::new(
+            e.kind(),
+            format!("{} '{}': {}", action, path, e),
+        ))
+    }
+
+    /// Whether trying again later may succeed: connection failures, timeouts, rate limiting
+    /// and server-side HTTP errors, and failed IP lookups. Anything else needs a
+    /// configuration change or Porkbun's attention first.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DdnsError::Request(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.status()
+                        .is_some_and(|status| status.is_server_error() || status.as_u16() == 429)
+            }
+            DdnsError::IpDetection(_) => true,
+            _ => false,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, DdnsError>;
@@ -0,0 +1,207 @@
+// src/events.rs
+
+//! A stream of what the client does (addresses seen, records written, requests that failed),
+//! so embedders can drive their own UIs and reactions without parsing logs.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use porkbun_ddns::events::{self, DdnsEvent};
+//! use std::future::poll_fn;
+//! use std::pin::Pin;
+//! use futures_core::Stream;
+//!
+//! let mut events = events::subscribe();
+//! while let Some(event) = poll_fn(|cx| Pin::new(&mut events).poll_next(cx)).await {
+//!     if let DdnsEvent::Updated { name, content, .. } = event {
+//!         println!("{} now points at {}", name, content);
+//!     }
+//! }
+//! # }
+//! ```
+
+use crate::errors::Result;
+use crate::journal::{Action, Operation};
+use futures_core::Stream;
+use log::debug;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// Events a subscriber may fall behind by before newer ones are dropped for it.
+const CAPACITY: usize = 256;
+
+static SUBSCRIBERS: Mutex<Vec<mpsc::Sender<DdnsEvent>>> = Mutex::new(Vec::new());
+
+/// How a write changed its record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Created,
+    Edited,
+    Deleted,
+}
+
+/// Something the client did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DdnsEvent {
+    /// `ping` found the address Porkbun sees requests coming from.
+    Detected { address: String },
+    /// A record was written. For a deleted record, `content` and `ttl` are what it held.
+    Updated {
+        change: Change,
+        name: String,
+        record_type: String,
+        content: String,
+        ttl: Option<u32>,
+    },
+    /// A request failed; `request` says which, such as "edit A record www.example.com".
+    Error { request: String, message: String },
+}
+
+/// The events of every client in the process from now on, as a `Stream` that ends only if
+/// dropped. A subscriber that falls `CAPACITY` events behind misses the newer ones until it
+/// catches up.
+pub fn subscribe() -> Events {
+    let (sender, receiver) = mpsc::channel(CAPACITY);
+    SUBSCRIBERS.lock().unwrap().push(sender);
+    Events { receiver }
+}
+
+/// Sends an event to every subscriber, forgetting those that went away.
+pub(crate) fn emit(event: DdnsEvent) {
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|sender| match sender.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                debug!("An event subscriber fell behind; dropping {:?}", event);
+                true
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        });
+}
+
+/// Reports the outcome of a write.
+pub(crate) fn written<T>(operation: &Operation, result: &Result<T>) {
+    let name = if operation.subdomain.is_empty() {
+        operation.domain.clone()
+    } else {
+        format!("{}.{}", operation.subdomain, operation.domain)
+    };
+    let (change, content, ttl) = match operation.action {
+        Action::Create => (
+            Change::Created,
+            &operation.new_content,
+            Some(operation.new_ttl),
+        ),
+        Action::Edit => (
+            Change::Edited,
+            &operation.new_content,
+            Some(operation.new_ttl),
+        ),
+        Action::Delete => (
+            Change::Deleted,
+            operation
+                .old_content
+                .as_ref()
+                .unwrap_or(&operation.new_content),
+            operation.old_ttl,
+        ),
+    };
+    emit(match result {
+        Ok(_) => DdnsEvent::Updated {
+            change,
+            name,
+            record_type: operation.record_type.clone(),
+            content: content.clone(),
+            ttl,
+        },
+        Err(e) => DdnsEvent::Error {
+            request: format!(
+                "{} {} record {}",
+                operation.action, operation.record_type, name
+            ),
+            message: e.to_string(),
+        },
+    });
+}
+
+/// The receiving end of `subscribe`.
+pub struct Events {
+    receiver: mpsc::Receiver<DdnsEvent>,
+}
+
+impl Stream for Events {
+    type Item = DdnsEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DdnsEvent>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::DdnsError;
+    use std::future::poll_fn;
+
+    async fn next(events: &mut Events) -> DdnsEvent {
+        poll_fn(|cx| Pin::new(&mut *events).poll_next(cx))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn subscribers_hear_about_writes_and_failures() {
+        let mut events = subscribe();
+        let operation = Operation {
+            id: 1,
+            at: 0,
+            batch: 1,
+            action: Action::Edit,
+            domain: "events.example.com".to_string(),
+            subdomain: "www".to_string(),
+            record_type: "A".to_string(),
+            record_id: Some("101".to_string()),
+            old_content: Some("198.51.100.1".to_string()),
+            old_ttl: Some(600),
+            new_content: "203.0.113.9".to_string(),
+            new_ttl: 900,
+            reason: None,
+        };
+        written(&operation, &Ok(()));
+        written::<()>(&operation, &Err(DdnsError::Config("refused".to_string())));
+
+        // Other tests' clients write at the same time, so only this test's events count.
+        let mut ours = Vec::new();
+        while ours.len() < 2 {
+            let event = next(&mut events).await;
+            let own = match &event {
+                DdnsEvent::Updated { name, .. } => name == "www.events.example.com",
+                DdnsEvent::Error { request, .. } => request.ends_with("www.events.example.com"),
+                _ => false,
+            };
+            if own {
+                ours.push(event);
+            }
+        }
+        assert_eq!(
+            ours,
+            vec![
+                DdnsEvent::Updated {
+                    change: Change::Edited,
+                    name: "www.events.example.com".to_string(),
+                    record_type: "A".to_string(),
+                    content: "203.0.113.9".to_string(),
+                    ttl: Some(900),
+                },
+                DdnsEvent::Error {
+                    request: "edit A record www.events.example.com".to_string(),
+                    message: "Configuration error: refused".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,273 @@
+// src/expand.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::template;
+
+/// Upper bound on the names one list may expand to, to catch runaway ranges early.
+const MAX_EXPANDED: usize = 1000;
+
+/// A host list split into the names it expands to and the exclusion globs listed with `!`.
+#[derive(Debug, Default)]
+pub struct HostList {
+    pub names: Vec<String>,
+    pub exclusions: Vec<String>,
+}
+
+/// Splits a comma-separated host list and expands shell-style brace patterns in each entry:
+/// numeric ranges such as `web{01..04}` (zero padding is kept) and alternatives such as
+/// `{www,api}.lab`. Commas inside braces belong to the pattern, not the list. Entries starting
+/// with `!` are exclusion globs, returned separately for the caller to apply.
+pub fn expand_list(list: &str) -> Result<HostList> {
+    let mut host_list = HostList::default();
+    for entry in split_top_level(list) {
+        let entry = entry.trim();
+        if let Some(exclusion) = entry.strip_prefix('!') {
+            host_list.exclusions.extend(expand(exclusion.trim())?);
+            continue;
+        }
+        let names = &mut host_list.names;
+        names.extend(expand(entry)?);
+        if names.len() > MAX_EXPANDED {
+            return Err(DdnsError::Config(format!(
+                "Host list expands to more than {} names.",
+                MAX_EXPANDED
+            )));
+        }
+    }
+    Ok(host_list)
+}
+
+/// Matches a name against a glob where `*` stands for any run of characters, including dots.
+/// Matching is case-insensitive, as DNS names are.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*` at all, so the whole name must have matched.
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+fn expand(pattern: &str) -> Result<Vec<String>> {
+    let Some(open) = pattern.find('{') else {
+        if pattern.contains('}') {
+            return Err(unbalanced(pattern));
+        }
+        return Ok(vec![pattern.to_string()]);
+    };
+    let close = matching_brace(pattern, open).ok_or_else(|| unbalanced(pattern))?;
+    let (prefix, inner, suffix) = (
+        &pattern[..open],
+        &pattern[open + 1..close],
+        &pattern[close + 1..],
+    );
+
+    let alternatives = match range(inner)? {
+        Some(values) => values,
+        None => {
+            let parts = split_top_level(inner);
+            if parts.len() < 2 && template::is_name(inner) {
+                return Err(DdnsError::Config(format!(
+                    "'{{{}}}' in '{}' is not a defined variable; set {} or use a range like 1..4 or alternatives like a,b.",
+                    inner,
+                    pattern,
+                    template::key(inner)
+                )));
+            }
+            if parts.len() < 2 {
+                return Err(DdnsError::Config(format!(
+                    "Brace group '{{{}}}' in '{}' needs a range like 1..4 or alternatives like a,b.",
+                    inner, pattern
+                )));
+            }
+            parts.into_iter().map(str::to_string).collect()
+        }
+    };
+
+    let suffixes = expand(suffix)?;
+    let mut names = Vec::new();
+    for alternative in alternatives {
+        for head in expand(&alternative)? {
+            for tail in &suffixes {
+                names.push(format!("{}{}{}", prefix, head, tail));
+                if names.len() > MAX_EXPANDED {
+                    return Err(DdnsError::Config(format!(
+                        "'{}' expands to more than {} names.",
+                        pattern, MAX_EXPANDED
+                    )));
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Parses `start..end` into its values, keeping zero padding when either bound has it.
+fn range(inner: &str) -> Result<Option<Vec<String>>> {
+    let Some((start, end)) = inner.split_once("..") else {
+        return Ok(None);
+    };
+    let (Ok(first), Ok(last)) = (start.parse::<u64>(), end.parse::<u64>()) else {
+        return Ok(None);
+    };
+    if last.abs_diff(first) as usize >= MAX_EXPANDED {
+        return Err(DdnsError::Config(format!(
+            "Range '{{{}}}' expands to more than {} names.",
+            inner, MAX_EXPANDED
+        )));
+    }
+
+    let padded = |bound: &str| bound.len() > 1 && bound.starts_with('0');
+    let width = if padded(start) || padded(end) {
+        start.len().max(end.len())
+    } else {
+        0
+    };
+    let values: Vec<u64> = if first <= last {
+        (first..=last).collect()
+    } else {
+        (last..=first).rev().collect()
+    };
+    Ok(Some(
+        values
+            .into_iter()
+            .map(|n| format!("{:0width$}", n, width = width))
+            .collect(),
+    ))
+}
+
+/// Splits on commas that are not inside braces.
+fn split_top_level(list: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&list[start..]);
+    parts
+}
+
+fn matching_brace(pattern: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in pattern[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unbalanced(pattern: &str) -> DdnsError {
+    DdnsError::Config(format!("Unbalanced braces in host pattern '{}'.", pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_brace_patterns() {
+        let cases: [(&str, &[&str], &[&str]); 9] = [
+            ("www,blog", &["www", "blog"], &[]),
+            (",www", &["", "www"], &[]),
+            ("web{1..3}", &["web1", "web2", "web3"], &[]),
+            ("web{08..10}", &["web08", "web09", "web10"], &[]),
+            ("node{3..1}", &["node3", "node2", "node1"], &[]),
+            ("{www,api}.lab", &["www.lab", "api.lab"], &[]),
+            (
+                "{a,b{1..2}}.{x,y}",
+                &["a.x", "a.y", "b1.x", "b1.y", "b2.x", "b2.y"],
+                &[],
+            ),
+            (
+                "web{01..03}, !web02",
+                &["web01", "web02", "web03"],
+                &["web02"],
+            ),
+            ("www,!{db,cache}.*", &["www"], &["db.*", "cache.*"]),
+        ];
+        for (list, names, exclusions) in cases {
+            let host_list = expand_list(list).unwrap();
+            assert_eq!(host_list.names, names, "{}", list);
+            assert_eq!(host_list.exclusions, exclusions, "{}", list);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_patterns() {
+        let cases = [
+            ("web{1..3", "Unbalanced"),
+            ("web}1", "Unbalanced"),
+            ("web{-}", "needs a range"),
+            ("web{site}", "not a defined variable"),
+            ("web{1..5000}", "more than"),
+            ("{a,b}{c,d}{0..499}", "more than"),
+        ];
+        for (list, message) in cases {
+            let error = expand_list(list).unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", list, error);
+        }
+    }
+
+    #[test]
+    fn glob_match_treats_star_as_any_run() {
+        let cases = [
+            ("web05", "web05", true),
+            ("WEB05", "web05", true),
+            ("web05", "web050", false),
+            ("web*", "web", true),
+            ("web*", "web12", true),
+            ("web*", "api", false),
+            ("*.internal", "db.internal", true),
+            ("*.internal", "db.lab.internal", true),
+            ("*.internal", "internal", false),
+            ("*.internal.example.com", "db.internal.example.com", true),
+            ("web*.lab", "web1.lab", true),
+            ("web*.lab", "web1.lab.old", false),
+            ("*a*a", "a", false),
+            ("*a*a", "aa", true),
+            ("a**b", "ab", true),
+            ("*", "", true),
+            ("", "", true),
+            ("", "www", false),
+        ];
+        for (pattern, name, matches) in cases {
+            assert_eq!(
+                glob_match(pattern, name),
+                matches,
+                "{} against {}",
+                pattern,
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+// src/explain.rs
+
+//! Records the network calls made during a check cycle, and why each was made, for the
+//! updater's `--explain` report. Recording and reasons are scoped to the future they are given
+//! with, like tracing spans: nothing is kept outside `record`, and a reason from `because`
+//! applies only to the calls made inside it, however other tasks interleave.
+
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+tokio::task_local! {
+    static CONTEXT: Context;
+}
+
+/// Where calls made in a scope are recorded, and why they are made.
+#[derive(Clone, Default)]
+struct Context {
+    /// `None` when not recording.
+    entries: Option<Arc<Mutex<Vec<Entry>>>>,
+    reason: String,
+}
+
+impl Context {
+    fn current() -> Self {
+        CONTEXT.try_with(Context::clone).unwrap_or_default()
+    }
+
+    fn push(entry: impl FnOnce(&str) -> Entry) {
+        let _ = CONTEXT.try_with(|context| {
+            if let Some(entries) = &context.entries {
+                entries.lock().unwrap().push(entry(&context.reason));
+            }
+        });
+    }
+}
+
+/// One line of the report.
+#[derive(Debug, Clone)]
+pub enum Entry {
+    /// A request that was sent: the service it went to, what it was and why it was needed.
+    Call {
+        service: String,
+        request: String,
+        reason: String,
+    },
+    /// A decision that caused or saved requests, like a cache hit or a retry.
+    Note(String),
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Call {
+                service,
+                request,
+                reason,
+            } => write!(f, "[{}] {} ({})", service, request, reason),
+            Entry::Note(note) => write!(f, "{}", note),
+        }
+    }
+}
+
+/// Runs `future`, recording the calls made inside it. Returns its output and what was
+/// recorded, in order.
+pub async fn record<F: Future>(future: F) -> (F::Output, Vec<Entry>) {
+    let entries = Arc::new(Mutex::new(Vec::new()));
+    let context = Context {
+        entries: Some(Arc::clone(&entries)),
+        reason: String::new(),
+    };
+    let output = CONTEXT.scope(context, future).await;
+    let entries = std::mem::take(&mut *entries.lock().unwrap());
+    (output, entries)
+}
+
+/// Runs `future` with `reason` given for the calls made inside it, in place of the reason of
+/// the enclosing scope.
+pub async fn because<F: Future>(reason: impl Into<String>, future: F) -> F::Output {
+    let context = Context {
+        reason: reason.into(),
+        ..Context::current()
+    };
+    CONTEXT.scope(context, future).await
+}
+
+/// Makes `future` record its calls, with the current reason, wherever it is polled. Spawned
+/// tasks don't inherit the scope they are spawned from, so a task spawned on behalf of an
+/// explained call is wrapped in this.
+pub fn carry<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    CONTEXT.scope(Context::current(), future)
+}
+
+/// Records a request about to be sent to `service`.
+pub fn call(service: &str, request: impl Into<String>) {
+    Context::push(|reason| Entry::Call {
+        service: service.to_string(),
+        request: request.into(),
+        reason: reason.to_string(),
+    });
+}
+
+/// Records a decision that caused or saved requests.
+pub fn note(note: impl Into<String>) {
+    Context::push(|_| Entry::Note(note.into()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reasons_apply_to_their_own_scope() {
+        call("outside", "GET /ignored");
+        let ((), entries) = record(async {
+            call("porkbun", "POST /ping");
+            because("first", async {
+                call("ipify", "GET /");
+                because("nested", async { call("stun", "binding request") }).await;
+                note("a note");
+                call("ipify", "GET /again");
+            })
+            .await;
+            let spawned = tokio::spawn(carry(because("spawned", async {
+                call("upnp", "GET /desc.xml");
+            })));
+            because("second", async {
+                tokio::task::yield_now().await;
+                call("porkbun", "POST /dns/retrieve");
+            })
+            .await;
+            spawned.await.unwrap();
+        })
+        .await;
+
+        let lines: Vec<String> = entries.iter().map(Entry::to_string).collect();
+        let (head, tail) = lines.split_at(5);
+        assert_eq!(
+            head,
+            [
+                "[porkbun] POST /ping ()",
+                "[ipify] GET / (first)",
+                "[stun] binding request (nested)",
+                "a note",
+                "[ipify] GET /again (first)",
+            ]
+        );
+        // The spawned task may run before or after the second scope's call.
+        let mut tail = tail.to_vec();
+        tail.sort();
+        assert_eq!(
+            tail,
+            [
+                "[porkbun] POST /dns/retrieve (second)",
+                "[upnp] GET /desc.xml (spawned)",
+            ]
+        );
+    }
+}
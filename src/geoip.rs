@@ -0,0 +1,165 @@
+// src/geoip.rs
+
+use crate::errors::{DdnsError, Result};
+use log::debug;
+use maxminddb::Reader;
+use serde::Deserialize;
+use std::fmt;
+use std::net::IpAddr;
+
+/// Network and location details for an address, as found in the local databases.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GeoInfo {
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub organization: Option<String>,
+}
+
+impl GeoInfo {
+    pub fn is_empty(&self) -> bool {
+        self.country.is_none() && self.asn.is_none() && self.organization.is_none()
+    }
+}
+
+impl fmt::Display for GeoInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        match (self.asn, &self.organization) {
+            (Some(asn), Some(org)) => parts.push(format!("AS{} {}", asn, org)),
+            (Some(asn), None) => parts.push(format!("AS{}", asn)),
+            (None, Some(org)) => parts.push(org.clone()),
+            (None, None) => {}
+        }
+        if let Some(country) = &self.country {
+            parts.push(country.clone());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// What to do when the detected address belongs to an unexpected network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MismatchAction {
+    /// Skip the cycle without touching any records.
+    Refuse,
+    /// Log a warning and update anyway.
+    Warn,
+}
+
+impl MismatchAction {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "refuse" => Ok(MismatchAction::Refuse),
+            "warn" => Ok(MismatchAction::Warn),
+            other => Err(DdnsError::Config(format!(
+                "Unknown network mismatch action '{}'. Expected one of: refuse, warn.",
+                other
+            ))),
+        }
+    }
+}
+
+/// The networks the detected address is expected to belong to. An empty list places no
+/// restriction on that attribute.
+#[derive(Debug)]
+pub struct NetworkPolicy {
+    pub asns: Vec<u32>,
+    pub countries: Vec<String>,
+    pub action: MismatchAction,
+}
+
+impl NetworkPolicy {
+    /// Returns why `info` falls outside the policy, or `None` if it matches. Addresses the
+    /// databases know nothing about count as a mismatch, since they can't be vouched for.
+    pub fn check(&self, info: &GeoInfo) -> Option<String> {
+        if !self.asns.is_empty() {
+            match info.asn {
+                Some(asn) if self.asns.contains(&asn) => {}
+                Some(asn) => return Some(format!("AS{} is not an expected ASN", asn)),
+                None => return Some("ASN is unknown".to_string()),
+            }
+        }
+        if !self.countries.is_empty() {
+            match &info.country {
+                Some(country)
+                    if self
+                        .countries
+                        .iter()
+                        .any(|c| c.eq_ignore_ascii_case(country)) => {}
+                Some(country) => return Some(format!("{} is not an expected country", country)),
+                None => return Some("country is unknown".to_string()),
+            }
+        }
+        None
+    }
+}
+
+/// The subset of GeoLite2/GeoIP2 Country, City and ASN records we read.
+#[derive(Deserialize)]
+struct Record<'a> {
+    #[serde(borrow)]
+    country: Option<Country<'a>>,
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Country<'a> {
+    iso_code: Option<&'a str>,
+}
+
+/// One or more MaxMind databases queried together, e.g. GeoLite2-Country plus GeoLite2-ASN.
+pub struct GeoIp {
+    databases: Vec<(String, Reader<Vec<u8>>)>,
+}
+
+impl fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.databases.iter().map(|(path, _)| path))
+            .finish()
+    }
+}
+
+impl GeoIp {
+    /// Loads every database into memory.
+    pub fn open(paths: &[String]) -> Result<Self> {
+        let databases = paths
+            .iter()
+            .map(|path| {
+                Reader::open_readfile(path)
+                    .map(|reader| (path.clone(), reader))
+                    .map_err(|e| {
+                        DdnsError::Config(format!(
+                            "Failed to open GeoIP database '{}': {}",
+                            path, e
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(GeoIp { databases })
+    }
+
+    /// Merges what each database knows about `ip`. Addresses missing from a database are
+    /// simply skipped.
+    pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        let mut info = GeoInfo::default();
+        for (path, reader) in &self.databases {
+            match reader.lookup::<Record>(ip) {
+                Ok(record) => {
+                    if let Some(code) = record.country.and_then(|c| c.iso_code) {
+                        info.country.get_or_insert_with(|| code.to_string());
+                    }
+                    if let Some(asn) = record.autonomous_system_number {
+                        info.asn.get_or_insert(asn);
+                    }
+                    if let Some(org) = record.autonomous_system_organization {
+                        info.organization.get_or_insert_with(|| org.to_string());
+                    }
+                }
+                Err(e) => debug!("No GeoIP entry for {} in {}: {}", ip, path, e),
+            }
+        }
+        info
+    }
+}
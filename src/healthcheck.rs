@@ -0,0 +1,83 @@
+// src/healthcheck.rs
+
+use crate::explain;
+use crate::summary::CycleSummary;
+use log::{info, warn};
+use reqwest::Url;
+use std::time::Duration;
+
+/// How long a ping may take; a monitoring service that is slow to answer must not hold up
+/// the next cycle.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pings a Healthchecks.io check, or a compatible dead man's switch, after a cycle: the URL
+/// itself when the cycle went through, and its `/fail` endpoint when a record failed or an
+/// address family was skipped. The cycle summary goes along as the body, so the check's log
+/// shows what happened. If the updater stops pinging, the service raises the alert. Dry runs
+/// don't ping, and failures are only logged.
+pub async fn ping(client: &reqwest::Client, url: Option<&str>, summary: &CycleSummary) {
+    let Some(url) = url else {
+        return;
+    };
+    if summary.dry_run {
+        return;
+    }
+    let Some(url) = ping_url(url, summary.degraded()) else {
+        warn!("Health check URL {} is not a valid URL; not pinging", url);
+        return;
+    };
+
+    explain::call("healthcheck", format!("POST {}", url));
+    let request = client
+        .post(url.clone())
+        .timeout(TIMEOUT)
+        .body(summary.to_json().to_string());
+    match request.send().await.and_then(|res| res.error_for_status()) {
+        Ok(_) => info!("Pinged health check {}", url),
+        Err(e) => warn!("Failed to ping health check {}: {}", url, e),
+    }
+}
+
+/// The URL to ping: the check's own, or with `/fail` added to its path, before any query
+/// string, when the cycle failed.
+fn ping_url(url: &str, failed: bool) -> Option<Url> {
+    let mut url = Url::parse(url).ok()?;
+    if failed {
+        let path = format!("{}/fail", url.path().trim_end_matches('/'));
+        url.set_path(&path);
+    }
+    Some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fail_goes_into_the_path() {
+        let cases = [
+            (
+                "https://hc-ping.com/0f1c3f9e",
+                "https://hc-ping.com/0f1c3f9e/fail",
+            ),
+            (
+                "https://hc-ping.com/0f1c3f9e/",
+                "https://hc-ping.com/0f1c3f9e/fail",
+            ),
+            (
+                "https://hc-ping.com/0f1c3f9e?rid=ddns",
+                "https://hc-ping.com/0f1c3f9e/fail?rid=ddns",
+            ),
+            ("http://monitor.lan:8000", "http://monitor.lan:8000/fail"),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(
+                ping_url(url, false).unwrap().as_str().trim_end_matches('/'),
+                url.trim_end_matches('/'),
+                "{}",
+                url
+            );
+            assert_eq!(ping_url(url, true).unwrap().as_str(), expected, "{}", url);
+        }
+    }
+}
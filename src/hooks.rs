@@ -0,0 +1,563 @@
+// src/hooks.rs
+
+use crate::app_error::AppError;
+use crate::clock::{Clock, SystemClock};
+use crate::errors::{DdnsError, Result};
+use crate::ip_details::IpDetails;
+use crate::porkbun::RecordType;
+use crate::probe::ProbeResult;
+use crate::summary::{CycleSummary, FamilyStatus};
+use crate::systemd;
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::process::{ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::Instant;
+
+/// How far back runs are counted against a notification hook's hourly limit.
+const RATE_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Shell commands run around record changes.
+///
+/// Global hooks run for every host; per-host hooks are keyed by lowercase full hostname and
+/// run after the global ones. The digest hook runs once for all the changes of a
+/// cycle, and the failure hook once for each cycle that didn't go cleanly.
+///
+/// The global post-update, digest and failure hooks are the notification channels, and each
+/// can be limited to a number of runs an hour. Pre-update and per-host hooks usually act on
+/// the change rather than report it, so they always run.
+#[derive(Debug, Default)]
+pub struct HooksConfig {
+    pub pre_update: Option<String>,
+    pub post_update: Option<String>,
+    pub host_pre_update: HashMap<String, String>,
+    pub host_post_update: HashMap<String, String>,
+    pub digest: Option<String>,
+    pub failure: Option<String>,
+    pub post_update_max_per_hour: Option<u32>,
+    pub digest_max_per_hour: Option<u32>,
+    pub failure_max_per_hour: Option<u32>,
+}
+
+impl HooksConfig {
+    /// Parses a `host=command;host=command` list of per-host hooks, each host given by its full
+    /// hostname.
+    pub fn parse_host_hooks(value: &str, var_name: &str) -> Result<HashMap<String, String>> {
+        value
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (host, command) = entry.split_once('=').ok_or_else(|| {
+                    DdnsError::Config(format!(
+                        "{} entries must look like 'host=command', got '{}'.",
+                        var_name,
+                        entry.trim()
+                    ))
+                })?;
+                let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+                Ok((host, command.trim().to_string()))
+            })
+            .collect()
+    }
+
+    /// The global hook, then the one for the host named `name`, if any.
+    fn commands<'a>(
+        global: &'a Option<String>,
+        per_host: &'a HashMap<String, String>,
+        name: &str,
+    ) -> Vec<&'a str> {
+        global
+            .iter()
+            .chain(per_host.get(&name.to_ascii_lowercase()))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// A notification hook's runs within the last hour, and what it was held back from reporting
+/// once it reached its limit.
+#[derive(Debug, Default)]
+struct Channel {
+    runs: VecDeque<Instant>,
+    held: Vec<String>,
+}
+
+/// Rate-limited notification hooks by command, so two hooks running the same command share a
+/// limit.
+static CHANNELS: Mutex<BTreeMap<String, Channel>> = Mutex::new(BTreeMap::new());
+
+impl Channel {
+    /// Drops the runs that no longer count against the limit at `now`, and returns whether
+    /// another run fits within it.
+    fn has_room(&mut self, now: Instant, max_per_hour: u32) -> bool {
+        while self
+            .runs
+            .front()
+            .is_some_and(|&run| now.duration_since(run) >= RATE_WINDOW)
+        {
+            self.runs.pop_front();
+        }
+        self.runs.len() < max_per_hour as usize
+    }
+}
+
+/// Counts a run of a notification hook against its hourly limit at the clock's time. Once the
+/// limit is reached the run is refused and `event` is kept for the overflow summary instead.
+fn admit(
+    clock: &impl Clock,
+    max_per_hour: Option<u32>,
+    command: &str,
+    event: impl FnOnce() -> String,
+) -> bool {
+    let Some(max_per_hour) = max_per_hour else {
+        return true;
+    };
+    let now = clock.now();
+    let mut channels = CHANNELS.lock().unwrap();
+    let channel = channels.entry(command.to_string()).or_default();
+    if channel.has_room(now, max_per_hour) {
+        channel.runs.push_back(now);
+        return true;
+    }
+    if channel.held.is_empty() {
+        warn!(
+            "Hook '{}' ran {} time(s) in the last hour; holding back its notifications and summarizing them once it may run again",
+            command, max_per_hour
+        );
+    }
+    channel.held.push(event());
+    false
+}
+
+/// Takes what a rate-limited notification hook held back, once it may run again at the
+/// clock's time, and counts the overflow run against its limit.
+fn take_held(clock: &impl Clock, command: &str, max_per_hour: u32) -> Option<Vec<String>> {
+    let now = clock.now();
+    let mut channels = CHANNELS.lock().unwrap();
+    let channel = channels.get_mut(command)?;
+    if channel.held.is_empty() || !channel.has_room(now, max_per_hour) {
+        return None;
+    }
+    channel.runs.push_back(now);
+    Some(std::mem::take(&mut channel.held))
+}
+
+/// Runs each rate-limited notification hook that held notifications back and may run again,
+/// once, with a summary of what it missed on its standard input, one line each. Called at the
+/// start of a cycle, so the summary arrives before the cycle's own notifications. Failures are
+/// logged and otherwise ignored.
+pub async fn run_overflow(config: &HooksConfig) {
+    let channels = [
+        (&config.post_update, config.post_update_max_per_hour),
+        (&config.digest, config.digest_max_per_hour),
+        (&config.failure, config.failure_max_per_hour),
+    ];
+    for (command, max_per_hour) in channels {
+        let (Some(command), Some(max_per_hour)) = (command, max_per_hour) else {
+            continue;
+        };
+        let Some(held) = take_held(&SystemClock, command, max_per_hour) else {
+            continue;
+        };
+        info!(
+            "Running hook '{}' with {} notification(s) it held back",
+            command,
+            held.len()
+        );
+        let mut input = held.join("\n");
+        input.push('\n');
+        let env = [
+            ("DDNS_HOOK_STAGE", "overflow".to_string()),
+            ("DDNS_SUPPRESSED_COUNT", held.len().to_string()),
+        ];
+        let status = shell(command, &env, Some(&input)).await;
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => warn!("Overflow run of hook '{}' exited with {}", command, status),
+            Err(e) => warn!("Failed to start hook '{}': {}", command, e),
+        }
+    }
+}
+
+/// Describes the change a hook is being run for. Exposed to hooks as `DDNS_*` variables.
+pub struct HookContext<'a> {
+    pub domain: &'a str,
+    pub subdomain: &'a str,
+    pub name: &'a str,
+    pub record_type: RecordType,
+    pub old_ip: Option<&'a str>,
+    pub new_ip: &'a str,
+    pub old_details: Option<&'a IpDetails>,
+    pub new_details: &'a IpDetails,
+    /// Why the change is made, for scheduled changes given a `--reason`.
+    pub reason: Option<&'a str>,
+    /// Latency measured after the change; only set for post-update hooks.
+    pub probe: Option<&'a ProbeResult>,
+    /// When every resolver should return the new address: once the answer the change replaces
+    /// has expired from caches. An RFC 3339 timestamp.
+    pub propagation_by: &'a str,
+}
+
+/// Runs the pre-update hooks for a host. A failing hook aborts the update for that host.
+pub async fn run_pre_update(config: &HooksConfig, ctx: &HookContext<'_>) -> Result<()> {
+    for command in HooksConfig::commands(&config.pre_update, &config.host_pre_update, ctx.name) {
+        run(command, "pre", ctx).await?;
+    }
+    Ok(())
+}
+
+/// Runs the post-update hooks for a host. Failures are logged and otherwise ignored.
+pub async fn run_post_update(config: &HooksConfig, ctx: &HookContext<'_>) {
+    for command in HooksConfig::commands(&config.post_update, &config.host_post_update, ctx.name) {
+        if config.post_update.as_deref() == Some(command)
+            && !admit(
+                &SystemClock,
+                config.post_update_max_per_hour,
+                command,
+                || {
+                    format!(
+                        "{} {}: {} -> {}",
+                        ctx.name,
+                        ctx.record_type,
+                        ctx.old_ip.unwrap_or("(new)"),
+                        ctx.new_ip
+                    )
+                },
+            )
+        {
+            continue;
+        }
+        if let Err(e) = run(command, "post", ctx).await {
+            warn!("{}", e);
+        }
+    }
+}
+
+/// Runs the digest hook once for every record changed in a cycle or batch of scheduled
+/// changes, so a whole zone moving to a new address makes one notification rather than one
+/// per record. Nothing runs when nothing changed or the changes were only a dry run. Failures
+/// are logged and otherwise ignored.
+pub async fn run_digest(config: &HooksConfig, summary: &CycleSummary) {
+    let Some(command) = &config.digest else {
+        return;
+    };
+    if summary.changes.is_empty() || summary.dry_run {
+        return;
+    }
+    let held = || {
+        let mut event = format!("digest of {} change(s)", summary.changes.len());
+        if !summary.failed.is_empty() {
+            event.push_str(&format!(" and {} failure(s)", summary.failed.len()));
+        }
+        event
+    };
+    if !admit(&SystemClock, config.digest_max_per_hour, command, held) {
+        return;
+    }
+    info!(
+        "Running digest hook for {} change(s): {}",
+        summary.changes.len(),
+        command
+    );
+
+    let lines: Vec<String> = summary.changes.iter().map(|c| c.to_string()).collect();
+    let changes: Vec<serde_json::Value> = summary
+        .changes
+        .iter()
+        .map(|change| {
+            serde_json::json!({
+                "name": change.name,
+                "record_type": change.record_type.as_str(),
+                "old_ip": change.old_ip,
+                "new_ip": change.new_ip,
+            })
+        })
+        .collect();
+    let failed: Vec<String> = summary
+        .failed
+        .iter()
+        .map(|(name, reason)| format!("{}: {}", name, reason))
+        .collect();
+    let env = [
+        ("DDNS_HOOK_STAGE", "digest".to_string()),
+        ("DDNS_CHANGE_COUNT", summary.changes.len().to_string()),
+        ("DDNS_CHANGES", lines.join("\n")),
+        (
+            "DDNS_CHANGES_JSON",
+            serde_json::Value::from(changes).to_string(),
+        ),
+        ("DDNS_FAILED_COUNT", summary.failed.len().to_string()),
+        ("DDNS_FAILED", failed.join("\n")),
+        ("DDNS_TENANT", tenant(summary)),
+    ];
+    let status = shell(command, &env, None).await;
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("Digest hook '{}' exited with {}", command, status),
+        Err(e) => warn!("Failed to start digest hook '{}': {}", command, e),
+    }
+}
+
+/// Runs the failure hook once for a cycle or batch of scheduled changes that failed to change
+/// a record or skipped an address family. Failures of the hook itself are logged and
+/// otherwise ignored.
+pub async fn run_failure(config: &HooksConfig, summary: &CycleSummary) {
+    let Some(command) = &config.failure else {
+        return;
+    };
+    if !summary.degraded() {
+        return;
+    }
+    let skipped: Vec<String> = summary
+        .families
+        .iter()
+        .filter(|(_, status)| !matches!(status, FamilyStatus::Synced(_)))
+        .map(|(record_type, status)| format!("{}: {}", record_type.family(), status))
+        .collect();
+    let held = || {
+        let mut event = format!(
+            "{} cycle with {} failure(s)",
+            summary.outcome().as_str(),
+            summary.failed.len()
+        );
+        if !skipped.is_empty() {
+            event.push_str(&format!(" ({})", skipped.join(", ")));
+        }
+        event
+    };
+    if !admit(&SystemClock, config.failure_max_per_hour, command, held) {
+        return;
+    }
+    info!(
+        "Running failure hook for {} failure(s): {}",
+        summary.failed.len(),
+        command
+    );
+
+    let failed: Vec<String> = summary
+        .failed
+        .iter()
+        .map(|(name, reason)| format!("{}: {}", name, reason))
+        .collect();
+    let env = [
+        ("DDNS_HOOK_STAGE", "failure".to_string()),
+        ("DDNS_OUTCOME", summary.outcome().as_str().to_string()),
+        ("DDNS_FAILED_COUNT", summary.failed.len().to_string()),
+        ("DDNS_FAILED", failed.join("\n")),
+        ("DDNS_SKIPPED", skipped.join("\n")),
+        ("DDNS_TENANT", tenant(summary)),
+    ];
+    let status = shell(command, &env, None).await;
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("Failure hook '{}' exited with {}", command, status),
+        Err(e) => warn!("Failed to start failure hook '{}': {}", command, e),
+    }
+}
+
+async fn run(command: &str, stage: &str, ctx: &HookContext<'_>) -> Result<()> {
+    info!(
+        "Running {}-update hook for {}: {}",
+        stage, ctx.name, command
+    );
+
+    let old_details = ctx.old_details;
+    let env = [
+        ("DDNS_HOOK_STAGE", stage.to_string()),
+        ("DDNS_DOMAIN", ctx.domain.to_string()),
+        ("DDNS_SUBDOMAIN", ctx.subdomain.to_string()),
+        ("DDNS_NAME", ctx.name.to_string()),
+        ("DDNS_RECORD_TYPE", ctx.record_type.as_str().to_string()),
+        ("DDNS_OLD_IP", ctx.old_ip.unwrap_or_default().to_string()),
+        ("DDNS_NEW_IP", ctx.new_ip.to_string()),
+        (
+            "DDNS_OLD_PTR",
+            old_details.and_then(|d| d.ptr.clone()).unwrap_or_default(),
+        ),
+        (
+            "DDNS_OLD_GEO",
+            old_details.and_then(|d| d.geo.clone()).unwrap_or_default(),
+        ),
+        (
+            "DDNS_NEW_PTR",
+            ctx.new_details.ptr.clone().unwrap_or_default(),
+        ),
+        (
+            "DDNS_NEW_GEO",
+            ctx.new_details.geo.clone().unwrap_or_default(),
+        ),
+        ("DDNS_REASON", ctx.reason.unwrap_or_default().to_string()),
+        ("DDNS_PROPAGATION_BY", ctx.propagation_by.to_string()),
+        (
+            "DDNS_PROBE",
+            ctx.probe.map(|p| p.to_string()).unwrap_or_default(),
+        ),
+        (
+            "DDNS_PROBE_AVG_MS",
+            ctx.probe
+                .and_then(|p| p.avg_ms)
+                .map(|ms| format!("{:.1}", ms))
+                .unwrap_or_default(),
+        ),
+        (
+            "DDNS_PROBE_LOSS_PERCENT",
+            ctx.probe
+                .map(|p| p.loss_percent().to_string())
+                .unwrap_or_default(),
+        ),
+    ];
+    let status = shell(command, &env, None)
+        .await
+        .map_err(|e| AppError::Hook(format!("Failed to start '{}': {}", command, e)))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(AppError::Hook(format!(
+            "{}-update hook '{}' for {} exited with {}",
+            stage, command, ctx.name, status
+        ))
+        .into())
+    }
+}
+
+/// The tenant a summary belongs to, for `DDNS_TENANT`; empty without tenants.
+fn tenant(summary: &CycleSummary) -> String {
+    summary.tenant.clone().unwrap_or_default()
+}
+
+/// Runs a hook command with the shell, kept away from systemd's notification socket, with
+/// `env` added to its environment and `input`, if any, on its standard input. Waits for it to exit.
+async fn shell(
+    command: &str,
+    env: &[(&str, String)],
+    input: Option<&str>,
+) -> io::Result<ExitStatus> {
+    let mut shell = Command::new("sh");
+    systemd::isolate(shell.arg("-c").arg(command))
+        .envs(env.iter().map(|(key, value)| (key, value)));
+    let Some(input) = input else {
+        return shell.status().await;
+    };
+    let mut child = shell.stdin(Stdio::piped()).spawn()?;
+    // A hook that exits without reading its input is fine, so a closed pipe is not an error.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(input.as_bytes()).await;
+    }
+    child.wait().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[tokio::test]
+    async fn shell_passes_the_environment_and_input() {
+        let env = [("DDNS_HOOK_STAGE", "test".to_string())];
+        let check = r#"test "$DDNS_HOOK_STAGE" = test && read line && test "$line" = held"#;
+        let cases = [
+            (check, Some("held\n"), true),
+            (check, Some("other\n"), false),
+            ("exit 0", Some("ignored\n"), true),
+        ];
+        for (command, input, success) in cases {
+            let status = shell(command, &env, input).await.unwrap();
+            assert_eq!(status.success(), success, "{} with {:?}", command, input);
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_runs_over_a_sliding_hour() {
+        let clock = MockClock::new();
+        let command = "notify --limit-test";
+        // (minutes since the previous step, whether the run is admitted)
+        let steps = [
+            (0, true),
+            (10, true),
+            (10, false),
+            (39, false),
+            (1, true),
+            (1, false),
+            (9, true),
+        ];
+        for (n, (wait, admitted)) in steps.into_iter().enumerate() {
+            clock.sleep(MINUTE * wait).await;
+            assert_eq!(
+                admit(&clock, Some(2), command, || format!("event {}", n)),
+                admitted,
+                "step {} at {:?}",
+                n,
+                clock.elapsed()
+            );
+        }
+        let held = CHANNELS.lock().unwrap()[command].held.clone();
+        assert_eq!(held, ["event 2", "event 3", "event 5"]);
+    }
+
+    #[tokio::test]
+    async fn held_notifications_go_out_once_there_is_room() {
+        let clock = MockClock::new();
+        let command = "notify --overflow-test";
+        assert!(take_held(&clock, command, 1).is_none(), "nothing held yet");
+        assert!(admit(&clock, Some(1), command, String::new));
+        assert!(!admit(&clock, Some(1), command, || "first".to_string()));
+        clock.sleep(MINUTE * 30).await;
+        assert!(!admit(&clock, Some(1), command, || "second".to_string()));
+        assert!(
+            take_held(&clock, command, 1).is_none(),
+            "still at the limit"
+        );
+
+        clock.sleep(MINUTE * 30).await;
+        assert_eq!(
+            take_held(&clock, command, 1).unwrap(),
+            ["first", "second"],
+            "the hour since the last run is over"
+        );
+        assert!(take_held(&clock, command, 1).is_none(), "taken once");
+        assert!(
+            !admit(&clock, Some(1), command, || "third".to_string()),
+            "the overflow run counts against the limit"
+        );
+    }
+
+    #[test]
+    fn unlimited_hooks_always_run() {
+        let clock = MockClock::new();
+        for _ in 0..100 {
+            assert!(admit(&clock, None, "notify --unlimited-test", String::new));
+        }
+    }
+
+    #[test]
+    fn host_hooks_are_keyed_by_full_hostname() {
+        let per_host = HooksConfig::parse_host_hooks(
+            "Mail.Example.com.=restart-mail; example.com=reload-nginx",
+            "PORKBUN_HOST_POST_UPDATE_HOOKS",
+        )
+        .unwrap();
+        let global = Some("notify".to_string());
+        let cases = [
+            ("mail.example.com", vec!["notify", "restart-mail"]),
+            ("MAIL.example.com", vec!["notify", "restart-mail"]),
+            ("example.com", vec!["notify", "reload-nginx"]),
+            ("mail.example.net", vec!["notify"]),
+            ("mail.mirror.example.org", vec!["notify"]),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(
+                HooksConfig::commands(&global, &per_host, name),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,251 @@
+// src/integrity.rs
+
+//! The updater's state files (IP state, plan, TTL backup, journal, last success and remote
+//! configuration serial) are sealed with an HMAC-SHA256 under a key of their own, kept in
+//! `PORKBUN_STATE_KEY_FILE`. A file that was edited or damaged fails its check and is set
+//! aside, so the updater starts over without it and re-syncs from Porkbun instead of acting on
+//! it. Until `init` loads the key, as in the library, files are written and read unsealed.
+
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use sha2::Sha256;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::OnceLock;
+
+const DEFAULT_KEY_FILE: &str = "porkbun-state.key";
+
+/// Starts the first line of a sealed file, followed by the contents' HMAC.
+const HEADER: &str = "#hmac-sha256 ";
+
+/// Separates a journal line from its HMAC. Encoded JSON never holds a bare tab.
+const LINE_SEPARATOR: char = '\t';
+
+static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Loads the key from `PORKBUN_STATE_KEY_FILE`, creating it on first use. An empty file name
+/// turns sealing off. A key file that can't be used is warned about, and an empty key is used
+/// instead, which still catches damaged files but not edited ones.
+pub fn init() {
+    let path = env::var("PORKBUN_STATE_KEY_FILE").unwrap_or_else(|_| DEFAULT_KEY_FILE.to_string());
+    if path.is_empty() {
+        return;
+    }
+    let key = load_or_create(&path).unwrap_or_else(|e| {
+        warn!(
+            "Can't use state key file '{}' ({}); state files are only checked for damage, not for tampering.",
+            path, e
+        );
+        Vec::new()
+    });
+    let _ = KEY.set(key);
+}
+
+fn load_or_create(path: &str) -> io::Result<Vec<u8>> {
+    match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+    {
+        Ok(mut file) => {
+            let mut key = [0u8; 32];
+            fs::File::open("/dev/urandom")?.read_exact(&mut key)?;
+            let key = hex(&key);
+            file.write_all(key.as_bytes())?;
+            info!("Created state key file '{}'", path);
+            Ok(key.into_bytes())
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            let key = fs::read_to_string(path)?.trim().to_string();
+            if key.len() < 32 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the key is shorter than 32 characters",
+                ));
+            }
+            Ok(key.into_bytes())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.is_ascii() || !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn hmac(key: &[u8], contents: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(contents.as_bytes());
+    mac
+}
+
+fn verify(key: &[u8], contents: &str, tag: &str) -> bool {
+    unhex(tag).is_some_and(|tag| hmac(key, contents).verify_slice(&tag).is_ok())
+}
+
+fn seal_with(key: Option<&[u8]>, contents: &str) -> String {
+    match key {
+        Some(key) => format!(
+            "{}{}\n{}",
+            HEADER,
+            hex(&hmac(key, contents).finalize().into_bytes()),
+            contents
+        ),
+        None => contents.to_string(),
+    }
+}
+
+/// The contents of a sealed file, if they pass the check. Without a key, the seal is only
+/// stripped.
+fn open_with<'a>(key: Option<&[u8]>, sealed: &'a str) -> Option<&'a str> {
+    let header = sealed
+        .strip_prefix(HEADER)
+        .and_then(|rest| rest.split_once('\n'));
+    match (key, header) {
+        (Some(key), Some((tag, contents))) => verify(key, contents, tag).then_some(contents),
+        (Some(_), None) => None,
+        (None, Some((_, contents))) => Some(contents),
+        (None, None) => Some(sealed),
+    }
+}
+
+fn seal_line_with(key: Option<&[u8]>, line: &str) -> String {
+    match key {
+        Some(key) => format!(
+            "{}{}{}",
+            line,
+            LINE_SEPARATOR,
+            hex(&hmac(key, line).finalize().into_bytes())
+        ),
+        None => line.to_string(),
+    }
+}
+
+fn open_line_with<'a>(key: Option<&[u8]>, sealed: &'a str) -> Option<&'a str> {
+    match (key, sealed.rsplit_once(LINE_SEPARATOR)) {
+        (Some(key), Some((line, tag))) => verify(key, line, tag).then_some(line),
+        (Some(_), None) => None,
+        (None, Some((line, _))) => Some(line),
+        (None, None) => Some(sealed),
+    }
+}
+
+/// Seals the contents of a state file.
+pub fn seal(contents: &str) -> String {
+    seal_with(KEY.get().map(Vec::as_slice), contents)
+}
+
+/// Reads a sealed state file. One that fails its check is warned about and moved aside to
+/// `<path>.rejected`, and then reads as `None`, like a missing one.
+pub fn read(path: &str) -> io::Result<Option<String>> {
+    read_with(KEY.get().map(Vec::as_slice), path)
+}
+
+fn read_with(key: Option<&[u8]>, path: &str) -> io::Result<Option<String>> {
+    let sealed = fs::read_to_string(path)?;
+    if let Some(contents) = open_with(key, &sealed) {
+        return Ok(Some(contents.to_string()));
+    }
+    let rejected = format!("{}.rejected", path);
+    match fs::rename(path, &rejected) {
+        Ok(()) => warn!(
+            "State file '{}' failed its integrity check, so it was edited or damaged; moved it to '{}' and starting over without it.",
+            path, rejected
+        ),
+        Err(e) => warn!(
+            "State file '{}' failed its integrity check, so it was edited or damaged; ignoring it (can't move it aside: {}).",
+            path, e
+        ),
+    }
+    Ok(None)
+}
+
+/// Seals one line of the journal.
+pub fn seal_line(line: &str) -> String {
+    seal_line_with(KEY.get().map(Vec::as_slice), line)
+}
+
+/// One line of the journal, if it passes its check.
+pub fn open_line(sealed: &str) -> Option<&str> {
+    open_line_with(KEY.get().map(Vec::as_slice), sealed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn sealed_files_open_only_unchanged_and_under_their_key() {
+        let sealed = seal_with(Some(KEY), "{\"serial\":7}");
+        assert_eq!(open_with(Some(KEY), &sealed), Some("{\"serial\":7}"));
+
+        let cases = [
+            ("edited", sealed.replace('7', "9")),
+            ("unsealed", "{\"serial\":9}".to_string()),
+            ("bad tag", format!("{}zz\n{{\"serial\":9}}", HEADER)),
+            ("truncated", sealed[..sealed.len() - 1].to_string()),
+        ];
+        for (case, contents) in cases {
+            assert_eq!(open_with(Some(KEY), &contents), None, "{}", case);
+        }
+        assert_eq!(
+            open_with(Some(b"another key another key another"), &sealed),
+            None,
+            "other key"
+        );
+        assert_eq!(
+            open_with(None, &sealed),
+            Some("{\"serial\":7}"),
+            "seal stripped without a key"
+        );
+    }
+
+    #[test]
+    fn sealed_journal_lines_open_only_unchanged() {
+        let line = r#"{"state":"done","id":1}"#;
+        let sealed = seal_line_with(Some(KEY), line);
+        assert_eq!(open_line_with(Some(KEY), &sealed), Some(line));
+        assert_eq!(
+            open_line_with(Some(KEY), &sealed.replace('1', "2")),
+            None,
+            "edited"
+        );
+        assert_eq!(open_line_with(Some(KEY), line), None, "unsealed");
+        assert_eq!(open_line_with(None, &sealed), Some(line), "no key");
+    }
+
+    #[test]
+    fn rejected_files_are_moved_aside() {
+        let path =
+            std::env::temp_dir().join(format!("porkbun-integrity-test-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let rejected = format!("{}.rejected", path);
+        fs::write(path, seal_with(Some(KEY), "[]")).unwrap();
+        let kept = read_with(Some(KEY), path).unwrap();
+        fs::write(path, "[{\"forged\":true}]").unwrap();
+        let forged = read_with(Some(KEY), path).unwrap();
+        let moved = fs::read_to_string(&rejected);
+        let left = fs::metadata(path).is_ok();
+        let _ = fs::remove_file(&rejected);
+
+        assert_eq!(kept.as_deref(), Some("[]"));
+        assert_eq!(forged, None);
+        assert_eq!(moved.unwrap(), "[{\"forged\":true}]");
+        assert!(!left, "moved, not copied");
+    }
+}
@@ -0,0 +1,417 @@
+// src/interface.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::ip_fetcher::is_public;
+use crate::porkbun::RecordType;
+use log::{debug, info};
+use std::net::{IpAddr, Ipv6Addr};
+use tokio::process::Command;
+
+/// Address flags that rule an IPv6 address out: temporary (privacy) addresses rotate, and the
+/// others are not, or no longer, usable.
+const UNUSABLE_FLAGS: [&str; 4] = ["temporary", "deprecated", "tentative", "dadfailed"];
+
+/// Which of several usable IPv6 addresses of an interface is published. Whatever the
+/// preference, the interface's first address is used when none matches it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Preference {
+    /// The first address listed, which is the primary address.
+    #[default]
+    First,
+    /// An address whose interface ID is derived from the MAC address (EUI-64), which stays the
+    /// same as long as the network card does.
+    Eui64,
+    /// The address with the longest remaining valid lifetime, which after a prefix change is
+    /// the address in the new prefix. Addresses valid forever are configured statically and
+    /// count as the oldest.
+    Newest,
+    /// An address in this prefix, such as the one a firewall rule is written for.
+    Prefix(Ipv6Addr, u8),
+}
+
+impl Preference {
+    /// Parses `first`, `eui64`, `newest` or a prefix like `2001:db8:1::/48`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "first" => Some(Preference::First),
+            "eui64" | "eui-64" => Some(Preference::Eui64),
+            "newest" => Some(Preference::Newest),
+            prefix => {
+                let (address, len) = prefix.split_once('/')?;
+                let len = len.parse::<u8>().ok().filter(|&len| len <= 128)?;
+                Some(Preference::Prefix(address.parse().ok()?, len))
+            }
+        }
+    }
+
+    fn matches(self, ip: Ipv6Addr) -> bool {
+        match self {
+            Preference::Eui64 => {
+                let octets = ip.octets();
+                octets[11] == 0xff && octets[12] == 0xfe
+            }
+            Preference::Prefix(prefix, len) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0);
+                u128::from(ip) & mask == u128::from(prefix) & mask
+            }
+            Preference::First | Preference::Newest => true,
+        }
+    }
+}
+
+/// A usable global address in an `ip addr` listing.
+struct Listed {
+    ip: IpAddr,
+    /// Seconds the address remains valid; `None` when it is valid forever or the listing
+    /// doesn't say.
+    valid_for: Option<u64>,
+}
+
+/// Reads the public address of a family assigned to a network interface, for hosts whose
+/// interface holds the public address itself (a VPS, or IPv6 behind most routers). The list
+/// comes from `ip addr show`, which both iproute2 and BusyBox provide. Only global-scope
+/// addresses count, and private, carrier-grade NAT and unique local ones are skipped; of the
+/// rest, the first listed is used, which is the primary address, unless `prefer` picks
+/// another IPv6 address.
+pub async fn global_address(
+    interface: &str,
+    record_type: RecordType,
+    prefer: Preference,
+) -> Result<IpAddr> {
+    let interface_error =
+        |message: String| DdnsError::IpDetection(format!("interface {}: {}", interface, message));
+
+    let output = Command::new("ip")
+        .args(["addr", "show", "dev", interface])
+        .output()
+        .await
+        .map_err(|e| interface_error(format!("could not run `ip`: {}", e)))?;
+    if !output.status.success() {
+        return Err(interface_error(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    select(
+        &String::from_utf8_lossy(&output.stdout),
+        record_type,
+        prefer,
+    )
+    .ok_or_else(|| interface_error(format!("no public {} address", record_type.family())))
+}
+
+/// Picks the address `global_address` publishes out of an `ip addr` listing.
+fn select(listing: &str, record_type: RecordType, prefer: Preference) -> Option<IpAddr> {
+    let listed = listed(listing, record_type);
+    let preferred = match (record_type, prefer) {
+        (RecordType::A, _) | (_, Preference::First) => None,
+        (_, Preference::Newest) => listed
+            .iter()
+            .rev()
+            .filter(|address| address.valid_for.is_some())
+            .max_by_key(|address| address.valid_for),
+        (_, prefer) => listed.iter().find(|address| match address.ip {
+            IpAddr::V6(ip) => prefer.matches(ip),
+            IpAddr::V4(_) => false,
+        }),
+    };
+    if preferred.is_none() && prefer != Preference::First && record_type == RecordType::Aaaa {
+        debug!("No IPv6 address matches {:?}; using the first", prefer);
+    }
+    preferred.or(listed.first()).map(|address| address.ip)
+}
+
+/// The public global-scope addresses of a family in an `ip addr` listing, in order.
+fn listed(listing: &str, record_type: RecordType) -> Vec<Listed> {
+    let family = match record_type {
+        RecordType::A => "inet",
+        RecordType::Aaaa => "inet6",
+    };
+    // Address lines look like `inet6 2001:db8::7/64 scope global dynamic mngtmpaddr`, and are
+    // followed by a line like `valid_lft 86321sec preferred_lft 14321sec`.
+    let mut listed = Vec::new();
+    let mut lines = listing.lines().peekable();
+    while let Some(line) = lines.next() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.first() != Some(&family)
+            || !fields.windows(2).any(|pair| pair == ["scope", "global"])
+            || fields.iter().any(|field| UNUSABLE_FLAGS.contains(field))
+        {
+            continue;
+        }
+        let Some(ip) = fields
+            .get(1)
+            .and_then(|field| field.split_once('/'))
+            .and_then(|(ip, _prefix)| ip.parse::<IpAddr>().ok())
+            .filter(|&ip| is_public(ip))
+        else {
+            continue;
+        };
+        let valid_for = lines.peek().and_then(|next| {
+            let next: Vec<&str> = next.split_whitespace().collect();
+            let (_, lifetime) = next.split_first().filter(|(key, _)| **key == "valid_lft")?;
+            lifetime.first()?.strip_suffix("sec")?.parse().ok()
+        });
+        listed.push(Listed { ip, valid_for });
+    }
+    listed
+}
+
+/// Replaces an IPv6 address seen by an outside service with the stable address of the same
+/// interface and prefix, when it is one of this host's temporary (privacy extension) addresses.
+/// Outgoing connections use those by default, and they rotate every few hours, which would
+/// otherwise move the AAAA records each time. A temporary address without a stable address
+/// next to it is refused rather than published. Addresses that aren't this host's own, such as
+/// a router's or one behind NPTv6, are returned as they are, as is everything when `ip` can't
+/// be run.
+pub async fn stable_instead_of_temporary(detected: String) -> Result<String> {
+    let Ok(address) = detected.parse::<Ipv6Addr>() else {
+        return Ok(detected);
+    };
+    let output = match Command::new("ip")
+        .args(["-6", "addr", "show"])
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            debug!(
+                "Could not list IPv6 addresses to check for a temporary address: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Ok(detected);
+        }
+        Err(e) => {
+            debug!("Could not run `ip` to check for a temporary address: {}", e);
+            return Ok(detected);
+        }
+    };
+    stable_in(&String::from_utf8_lossy(&output.stdout), address, detected)
+}
+
+/// Looks `address` up in an `ip -6 addr` listing, for `stable_instead_of_temporary`.
+fn stable_in(listing: &str, address: Ipv6Addr, detected: String) -> Result<String> {
+    // Interface blocks start with `2: eth0: <...>`, followed by their address lines.
+    let mut interface = "";
+    let mut found: Option<&str> = None;
+    let mut stable: Option<Ipv6Addr> = None;
+    for line in listing.lines() {
+        if !line.starts_with(' ') {
+            if found.is_some() {
+                break;
+            }
+            interface = line.split(':').nth(1).unwrap_or_default().trim();
+            stable = None;
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let Some(ip) = fields
+            .get(1)
+            .filter(|_| fields[0] == "inet6")
+            .and_then(|field| field.split_once('/'))
+            .and_then(|(ip, _prefix)| ip.parse::<Ipv6Addr>().ok())
+        else {
+            continue;
+        };
+        if ip == address {
+            if !fields.contains(&"temporary") {
+                return Ok(detected);
+            }
+            found = Some(interface);
+        } else if stable.is_none()
+            && ip.segments()[..4] == address.segments()[..4]
+            && fields.windows(2).any(|pair| pair == ["scope", "global"])
+            && !fields.iter().any(|field| UNUSABLE_FLAGS.contains(field))
+        {
+            stable = Some(ip);
+        }
+    }
+
+    match (found, stable) {
+        (None, _) => Ok(detected),
+        (Some(interface), Some(stable)) => {
+            info!(
+                "{} is a temporary privacy address of {}; using its stable address {}",
+                address, interface, stable
+            );
+            Ok(stable.to_string())
+        }
+        (Some(interface), None) => Err(DdnsError::IpDetection(format!(
+            "{} is a temporary privacy address of {}, which has no stable address in the same prefix; set PORKBUN_IPV6_ALLOW_TEMPORARY=true to publish it anyway",
+            address, interface
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `ip -6 addr show` on a laptop with privacy extensions enabled on wlan0.
+    const IPV6_LISTING: &str = "\
+1: lo: <LOOPBACK,UP,LOWER_UP> mtu 65536 state UNKNOWN qlen 1000
+    inet6 ::1/128 scope host noprefixroute
+       valid_lft forever preferred_lft forever
+2: wlan0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 state UP qlen 1000
+    inet6 2001:db8:1:0:a1b2:c3d4:e5f6:1/64 scope global temporary dynamic
+       valid_lft 86000sec preferred_lft 14000sec
+    inet6 2001:db8:1:0:a1b2:c3d4:e5f6:2/64 scope global temporary deprecated dynamic
+       valid_lft 80000sec preferred_lft 0sec
+    inet6 2001:db8:1:0:5054:ff:fe12:3456/64 scope global dynamic mngtmpaddr noprefixroute
+       valid_lft 86000sec preferred_lft 14000sec
+    inet6 2001:db8:9:0:a1b2:c3d4:e5f6:3/64 scope global temporary dynamic
+       valid_lft 86000sec preferred_lft 14000sec
+    inet6 2001:db8:9::5/64 scope global deprecated dynamic
+       valid_lft 600sec preferred_lft 0sec
+    inet6 fe80::5054:ff:fe12:3456/64 scope link
+       valid_lft forever preferred_lft forever
+";
+
+    #[test]
+    fn replaces_temporary_addresses_with_the_stable_one() {
+        let cases = [
+            (
+                "temporary",
+                "2001:db8:1:0:a1b2:c3d4:e5f6:1",
+                Some("2001:db8:1:0:5054:ff:fe12:3456"),
+            ),
+            (
+                "deprecated temporary",
+                "2001:db8:1:0:a1b2:c3d4:e5f6:2",
+                Some("2001:db8:1:0:5054:ff:fe12:3456"),
+            ),
+            (
+                "mngtmpaddr, the stable address itself",
+                "2001:db8:1:0:5054:ff:fe12:3456",
+                Some("2001:db8:1:0:5054:ff:fe12:3456"),
+            ),
+            (
+                "deprecated stable address, kept as detected",
+                "2001:db8:9::5",
+                Some("2001:db8:9::5"),
+            ),
+            (
+                "temporary, with only a deprecated address in its prefix",
+                "2001:db8:9:0:a1b2:c3d4:e5f6:3",
+                None,
+            ),
+            (
+                "not this host's, such as the router's",
+                "2001:db8:5::1",
+                Some("2001:db8:5::1"),
+            ),
+        ];
+        for (case, detected, expected) in cases {
+            let replaced = stable_in(
+                IPV6_LISTING,
+                detected.parse().unwrap(),
+                detected.to_string(),
+            );
+            assert_eq!(replaced.ok().as_deref(), expected, "{}", case);
+        }
+    }
+
+    /// `ip addr show dev eth0` on a host in the middle of a renumbering from 2001:db8:1::/64
+    /// to 2001:db8:2::/64.
+    const LISTING: &str = "\
+2: eth0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc fq_codel state UP group default qlen 1000
+    link/ether 52:54:00:12:34:56 brd ff:ff:ff:ff:ff:ff
+    inet 192.168.1.20/24 brd 192.168.1.255 scope global dynamic eth0
+       valid_lft 86000sec preferred_lft 86000sec
+    inet 203.0.113.7/24 brd 203.0.113.255 scope global eth0
+       valid_lft forever preferred_lft forever
+    inet6 2001:db8:1::1:5/64 scope global temporary dynamic
+       valid_lft 1200sec preferred_lft 0sec
+    inet6 fd00::5054:ff:fe12:3456/64 scope global dynamic mngtmpaddr
+       valid_lft 86000sec preferred_lft 14000sec
+    inet6 2001:db8:1::10/64 scope global
+       valid_lft forever preferred_lft forever
+    inet6 2001:db8:1:0:5054:ff:fe12:3456/64 scope global dynamic mngtmpaddr
+       valid_lft 1200sec preferred_lft 0sec
+    inet6 2001:db8:2:0:5054:ff:fe12:3456/64 scope global dynamic mngtmpaddr
+       valid_lft 86000sec preferred_lft 14000sec
+    inet6 2001:db8:2::99/64 scope global deprecated
+       valid_lft 90000sec preferred_lft 0sec
+    inet6 fe80::5054:ff:fe12:3456/64 scope link
+       valid_lft forever preferred_lft forever
+";
+
+    #[test]
+    fn picks_the_public_global_address_by_preference() {
+        let cases = [
+            (RecordType::A, Preference::First, Some("203.0.113.7")),
+            (RecordType::A, Preference::Eui64, Some("203.0.113.7")),
+            (RecordType::Aaaa, Preference::First, Some("2001:db8:1::10")),
+            (
+                RecordType::Aaaa,
+                Preference::Eui64,
+                Some("2001:db8:1:0:5054:ff:fe12:3456"),
+            ),
+            (
+                RecordType::Aaaa,
+                Preference::Newest,
+                Some("2001:db8:2:0:5054:ff:fe12:3456"),
+            ),
+            (
+                RecordType::Aaaa,
+                Preference::parse("2001:db8:2::/48").unwrap(),
+                Some("2001:db8:2:0:5054:ff:fe12:3456"),
+            ),
+            (
+                RecordType::Aaaa,
+                Preference::parse("2001:db8:3::/48").unwrap(),
+                Some("2001:db8:1::10"),
+            ),
+        ];
+        for (record_type, prefer, expected) in cases {
+            assert_eq!(
+                select(LISTING, record_type, prefer),
+                expected.map(|ip| ip.parse().unwrap()),
+                "{} with {:?}",
+                record_type,
+                prefer
+            );
+        }
+    }
+
+    #[test]
+    fn finds_nothing_without_a_public_global_address() {
+        let listing = "\
+2: wg0: <POINTOPOINT,NOARP,UP,LOWER_UP> mtu 1420 qdisc noqueue state UNKNOWN group default qlen 1000
+    inet 10.8.0.2/24 scope global wg0
+    inet6 fd42::2/64 scope global
+    inet6 2001:db8::5/64 scope global tentative
+    inet6 fe80::1/64 scope link
+";
+        for record_type in [RecordType::A, RecordType::Aaaa] {
+            assert_eq!(
+                select(listing, record_type, Preference::Newest),
+                None,
+                "{}",
+                record_type
+            );
+        }
+    }
+
+    #[test]
+    fn parses_preferences() {
+        let cases = [
+            ("first", Some(Preference::First)),
+            (" EUI64 ", Some(Preference::Eui64)),
+            ("eui-64", Some(Preference::Eui64)),
+            ("newest", Some(Preference::Newest)),
+            (
+                "2001:db8:1::/48",
+                Some(Preference::Prefix("2001:db8:1::".parse().unwrap(), 48)),
+            ),
+            ("::/0", Some(Preference::Prefix(Ipv6Addr::UNSPECIFIED, 0))),
+            ("2001:db8::/129", None),
+            ("2001:db8::", None),
+            ("203.0.113.0/24", None),
+            ("oldest", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(Preference::parse(value), expected, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// src/ip_details.rs
+
+#[cfg(feature = "verify")]
+use crate::dns::{self, Resolver};
+#[cfg(feature = "verify")]
+use crate::explain;
+#[cfg(feature = "geoip")]
+use crate::geoip::GeoIp;
+#[cfg(any(feature = "verify", feature = "geoip"))]
+use log::warn;
+use std::fmt;
+
+/// Where extra context about old and new addresses comes from. Both sources are optional.
+#[derive(Debug, Default)]
+pub struct DetailsConfig {
+    #[cfg(feature = "verify")]
+    pub reverse_dns: Option<Resolver>,
+    #[cfg(feature = "geoip")]
+    pub geoip: Option<GeoIp>,
+}
+
+/// Reverse DNS name and network/location of an address, so an unexpected change can be
+/// judged at a glance.
+#[derive(Debug, Default)]
+pub struct IpDetails {
+    pub ptr: Option<String>,
+    pub geo: Option<String>,
+}
+
+impl fmt::Display for IpDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<&str> = self
+            .ptr
+            .iter()
+            .chain(self.geo.iter())
+            .map(String::as_str)
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Looks up whatever details are configured for `ip`. Lookup failures are logged and leave
+/// the corresponding field empty.
+#[cfg_attr(not(feature = "verify"), allow(unused_variables, unused_mut))]
+pub async fn describe(client: &reqwest::Client, config: &DetailsConfig, ip: &str) -> IpDetails {
+    let mut details = IpDetails::default();
+    #[cfg(any(feature = "verify", feature = "geoip"))]
+    let addr = match ip.parse::<std::net::IpAddr>() {
+        Ok(addr) => addr,
+        Err(_) => {
+            warn!("Cannot look up details for '{}': not an IP address", ip);
+            return details;
+        }
+    };
+
+    #[cfg(feature = "verify")]
+    if let Some(resolver) = &config.reverse_dns {
+        let reason = format!("describing {} for logs and hooks (PORKBUN_REVERSE_DNS)", ip);
+        let name = dns::reverse_name(addr);
+        let query = resolver.query(client, &name, dns::TYPE_PTR);
+        match explain::because(reason, query).await {
+            Ok(names) => details.ptr = names.into_iter().next(),
+            Err(e) => warn!("Reverse DNS lookup for {} failed: {}", ip, e),
+        }
+    }
+
+    #[cfg(feature = "geoip")]
+    if let Some(geoip) = &config.geoip {
+        let info = geoip.lookup(addr);
+        if !info.is_empty() {
+            details.geo = Some(info.to_string());
+        }
+    }
+
+    details
+}
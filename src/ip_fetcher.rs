@@ -1,17 +1,631 @@
 // src/ip_fetcher.rs
 
-use crate::errors::Result;
-use log::info;
-
-/// Asynchronous function to get the current public IPv4 address from an external service.
-pub async fn get_current_ipv4(client: &reqwest::Client) -> Result<String> {
-    info!("Attempting to retrieve current public IPv4 address from api.ipify.org...");
-    let ip = client
-        .get("https://api.ipify.org")
-        .send()
+#[cfg(feature = "verify")]
+use crate::dns::{self, Resolver};
+use crate::errors::{DdnsError, Result};
+use crate::explain;
+use crate::interface;
+use crate::porkbun::{PorkbunClient, RecordType, API_BASE_URL};
+use crate::retry::{self, RetryPolicy};
+use crate::simulate;
+use crate::stun;
+use crate::upnp;
+use log::{error, info, warn};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tokio::task::JoinSet;
+
+/// Porkbun's ping endpoint on a host that only listens on IPv4.
+const PORKBUN_IPV4_BASE_URL: &str = "https://api-ipv4.porkbun.com/api/json/v3";
+
+/// resolver1.opendns.com, which answers `myip.opendns.com` with the address asking.
+#[cfg(feature = "verify")]
+const OPENDNS_RESOLVERS: [&str; 2] = ["208.67.222.222:53", "[2620:119:35::35]:53"];
+
+/// Cloudflare's resolver, which answers `whoami.cloudflare` with the address asking.
+#[cfg(feature = "verify")]
+const CLOUDFLARE_RESOLVERS: [&str; 2] = ["1.1.1.1:53", "[2606:4700:4700::1111]:53"];
+
+/// A service that reports the public address requests come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpSource {
+    Ipify,
+    Icanhazip,
+    IfconfigMe,
+    /// Porkbun's own `ping` endpoint, using the configured API keys.
+    Porkbun,
+    /// A STUN binding request over UDP to the configured STUN server.
+    Stun,
+    /// The router's own view of its external address, asked over UPnP IGD; IPv4 only.
+    Upnp,
+    /// The public address assigned to the configured network interface.
+    Interface,
+    /// A DNS query for `myip.opendns.com` to OpenDNS's resolver.
+    OpenDns,
+    /// A CHAOS-class TXT query for `whoami.cloudflare` to Cloudflare's resolver.
+    CloudflareDns,
+    /// A plain-text IP echo endpoint of the user's own, from `PORKBUN_IP_URLS`.
+    Url(String),
+    /// The scripted addresses of `--simulate`; not available otherwise.
+    Simulated,
+}
+
+impl IpSource {
+    /// The sources tried when `PORKBUN_IP_SOURCES` is not set, in order.
+    pub const DEFAULT: [IpSource; 4] = [
+        IpSource::Ipify,
+        IpSource::Icanhazip,
+        IpSource::IfconfigMe,
+        IpSource::Porkbun,
+    ];
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ipify" => Ok(IpSource::Ipify),
+            "icanhazip" => Ok(IpSource::Icanhazip),
+            "ifconfig.me" => Ok(IpSource::IfconfigMe),
+            "porkbun" => Ok(IpSource::Porkbun),
+            "stun" => Ok(IpSource::Stun),
+            "upnp" => Ok(IpSource::Upnp),
+            "interface" => Ok(IpSource::Interface),
+            "opendns" | "cloudflare-dns" if !cfg!(feature = "verify") => {
+                Err(DdnsError::Config(format!(
+                    "The {} IP source needs a build with the `verify` feature, which includes the DNS client.",
+                    value.trim()
+                )))
+            }
+            "opendns" => Ok(IpSource::OpenDns),
+            "cloudflare-dns" => Ok(IpSource::CloudflareDns),
+            other => Err(DdnsError::Config(format!(
+                "Unknown IP source '{}'. Expected one of: ipify, icanhazip, ifconfig.me, porkbun, stun, upnp, interface, opendns, cloudflare-dns.",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            IpSource::Ipify => "ipify",
+            IpSource::Icanhazip => "icanhazip",
+            IpSource::IfconfigMe => "ifconfig.me",
+            IpSource::Porkbun => "porkbun",
+            IpSource::Stun => "stun",
+            IpSource::Upnp => "upnp",
+            IpSource::Interface => "interface",
+            IpSource::OpenDns => "opendns",
+            IpSource::CloudflareDns => "cloudflare-dns",
+            IpSource::Url(url) => url,
+            IpSource::Simulated => "simulated",
+        }
+    }
+
+    /// The endpoint for an address family. ifconfig.me, Porkbun's IPv6 endpoint and custom URLs
+    /// answer over either family, so what they return is checked to be of the right one. The
+    /// sources that don't use HTTP have none.
+    fn url(&self, record_type: RecordType) -> Option<&str> {
+        match (self, record_type) {
+            (IpSource::Ipify, RecordType::A) => Some("https://api.ipify.org"),
+            (IpSource::Ipify, RecordType::Aaaa) => Some("https://api6.ipify.org"),
+            (IpSource::Icanhazip, RecordType::A) => Some("https://ipv4.icanhazip.com"),
+            (IpSource::Icanhazip, RecordType::Aaaa) => Some("https://ipv6.icanhazip.com"),
+            (IpSource::IfconfigMe, _) => Some("https://ifconfig.me/ip"),
+            (IpSource::Porkbun, RecordType::A) => Some(PORKBUN_IPV4_BASE_URL),
+            (IpSource::Porkbun, RecordType::Aaaa) => Some(API_BASE_URL),
+            (IpSource::Url(url), _) => Some(url),
+            (
+                IpSource::Stun
+                | IpSource::Upnp
+                | IpSource::Interface
+                | IpSource::OpenDns
+                | IpSource::CloudflareDns
+                | IpSource::Simulated,
+                _,
+            ) => None,
+        }
+    }
+}
+
+impl fmt::Display for IpSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The API keys Porkbun's ping is sent with.
+#[derive(Debug, Clone, Copy)]
+pub struct Credentials<'a> {
+    pub api_key: &'a str,
+    pub secret_api_key: &'a str,
+}
+
+/// Everything IP detection needs: the sources to try in order, how to retry when all of them
+/// fail, the credentials for Porkbun's ping, the STUN server and the network interface.
+pub struct IpDetector<'a> {
+    pub sources: &'a [IpSource],
+    /// When set, every source is asked at once and an address is only accepted when at least
+    /// this many of them agree on it.
+    pub quorum: Option<usize>,
+    pub retry: &'a RetryPolicy,
+    pub credentials: Credentials<'a>,
+    pub stun_server: &'a str,
+    pub interface: Option<&'a str>,
+    /// Which IPv6 address of the interface is used when it has several.
+    pub prefer: interface::Preference,
+    /// Whether a temporary IPv6 privacy address of this host may be published, instead of
+    /// being replaced with the stable address next to it.
+    pub allow_temporary: bool,
+}
+
+/// Asks one source for the public address of the given family.
+async fn fetch(
+    client: &reqwest::Client,
+    detector: &IpDetector<'_>,
+    source: &IpSource,
+    record_type: RecordType,
+) -> Result<String> {
+    let ip = match (source, source.url(record_type)) {
+        (IpSource::Porkbun, Some(url)) => {
+            let Credentials {
+                api_key,
+                secret_api_key,
+            } = detector.credentials;
+            PorkbunClient::new(client, api_key, secret_api_key, "")
+                .with_base_url(url)
+                .with_retry(RetryPolicy::NONE)
+                .ping()
+                .await?
+        }
+        (_, Some(url)) => {
+            let service = match source {
+                IpSource::Url(_) => "ip url",
+                _ => source.as_str(),
+            };
+            explain::call(service, format!("GET {}", url));
+            client
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?
+        }
+        (IpSource::Stun, None) => stun::mapped_address(detector.stun_server, record_type)
+            .await?
+            .to_string(),
+        (IpSource::Upnp, None) => upnp::external_address(client, record_type)
+            .await?
+            .to_string(),
+        (IpSource::Interface, None) => {
+            let interface = detector.interface.ok_or_else(|| {
+                DdnsError::IpDetection("no network interface is configured".to_string())
+            })?;
+            explain::note(format!(
+                "Read the {} address of interface {} locally; no network call",
+                record_type.family(),
+                interface
+            ));
+            interface::global_address(interface, record_type, detector.prefer)
+                .await?
+                .to_string()
+        }
+        #[cfg(feature = "verify")]
+        (IpSource::OpenDns | IpSource::CloudflareDns, None) => {
+            dns_echo(client, source, record_type).await?
+        }
+        #[cfg(not(feature = "verify"))]
+        (IpSource::OpenDns | IpSource::CloudflareDns, None) => {
+            return Err(DdnsError::IpDetection(format!(
+                "{} needs the `verify` feature",
+                source
+            )))
+        }
+        (_, None) => return simulate::address(record_type),
+    };
+    // Normalise to the canonical form, which is what Porkbun and resolvers return.
+    let ip = ip.trim();
+    let parsed = match record_type {
+        RecordType::A => ip.parse::<Ipv4Addr>().map(IpAddr::V4).ok(),
+        RecordType::Aaaa => ip.parse::<Ipv6Addr>().map(IpAddr::V6).ok(),
+    };
+    let Some(parsed) = parsed else {
+        // A proxy or captive portal may answer with a whole page; don't log all of it.
+        let answer = if ip.len() > 64 {
+            format!("A {}-byte answer", ip.len())
+        } else {
+            format!("'{}'", ip)
+        };
+        return Err(DdnsError::IpDetection(format!(
+            "{} is not an {} address",
+            answer,
+            record_type.family()
+        )));
+    };
+    // An echo service inside the network sees the address requests leave the client with,
+    // which is only the public one when the service is reached through the internet uplink.
+    if matches!(source, IpSource::Url(_)) && !is_public(parsed) {
+        return Err(DdnsError::IpDetection(format!(
+            "{} answered {}, which is not a public address",
+            source, parsed
+        )));
+    }
+    Ok(parsed.to_string())
+}
+
+/// Asks a resolver that answers with the address the query came from. A single UDP round trip,
+/// much lighter than HTTPS; the query goes to the service's resolver of the record's family,
+/// so the answer is of that family too.
+#[cfg(feature = "verify")]
+async fn dns_echo(
+    client: &reqwest::Client,
+    source: &IpSource,
+    record_type: RecordType,
+) -> Result<String> {
+    let (resolvers, name, qtype, qclass) = match (source, record_type) {
+        (IpSource::CloudflareDns, _) => (
+            CLOUDFLARE_RESOLVERS,
+            "whoami.cloudflare",
+            dns::TYPE_TXT,
+            dns::CLASS_CH,
+        ),
+        (_, RecordType::A) => (
+            OPENDNS_RESOLVERS,
+            "myip.opendns.com",
+            dns::TYPE_A,
+            dns::CLASS_IN,
+        ),
+        (_, RecordType::Aaaa) => (
+            OPENDNS_RESOLVERS,
+            "myip.opendns.com",
+            dns::TYPE_AAAA,
+            dns::CLASS_IN,
+        ),
+    };
+    let endpoint = match record_type {
+        RecordType::A => resolvers[0],
+        RecordType::Aaaa => resolvers[1],
+    };
+    let resolver = Resolver::parse(endpoint)?;
+    resolver
+        .query_class(client, name, qtype, qclass)
         .await?
-        .text()
-        .await?;
-    info!("Successfully retrieved current public IPv4: {}", ip);
-    Ok(ip.trim().to_string())
+        .into_iter()
+        .next()
+        .ok_or_else(|| DdnsError::IpDetection(format!("{} returned no answer", source)))
+}
+
+/// Whether an address can be reached from the internet, i.e. isn't private, carrier-grade NAT
+/// (100.64.0.0/10), unique local, loopback, link-local, multicast or unset.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            let shared = a == 100 && (64..128).contains(&b);
+            !(ip.is_private()
+                || shared
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast())
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            // fc00::/7 is unique local, fe80::/10 link-local.
+            !(first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                || ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast())
+        }
+    }
+}
+
+/// Tries each source in order until one returns an address of the right family.
+async fn first_answer(
+    client: &reqwest::Client,
+    detector: &IpDetector<'_>,
+    record_type: RecordType,
+) -> Result<(String, IpSource)> {
+    let mut failures = Vec::new();
+    for (position, source) in detector.sources.iter().enumerate() {
+        let reason = if position == 0 {
+            format!(
+                "public {}: first of {} IP source(s), later ones are only asked if it fails",
+                record_type.family(),
+                detector.sources.len()
+            )
+        } else {
+            format!(
+                "public {}: IP source {} of {}, as the ones before it failed",
+                record_type.family(),
+                position + 1,
+                detector.sources.len()
+            )
+        };
+        info!(
+            "Attempting to retrieve current public {} address from {}...",
+            record_type.family(),
+            source
+        );
+        match explain::because(reason, fetch(client, detector, source, record_type)).await {
+            Ok(ip) => return Ok((ip, source.clone())),
+            Err(e) => {
+                warn!("IP source {} failed: {}", source, e);
+                failures.push(format!("{}: {}", source, e));
+            }
+        }
+    }
+    Err(DdnsError::IpDetection(format!(
+        "no IP source answered ({})",
+        failures.join("; ")
+    )))
+}
+
+/// Asks every source at once and accepts the address at least `quorum` of them agree on, so a
+/// single broken or compromised service can't redirect the records. Fails when no address, or
+/// more than one, reaches the quorum.
+async fn consensus(
+    client: &reqwest::Client,
+    detector: &IpDetector<'_>,
+    record_type: RecordType,
+    quorum: usize,
+) -> Result<(String, IpSource)> {
+    info!(
+        "Asking {} IP sources for the public {} address (quorum {})...",
+        detector.sources.len(),
+        record_type.family(),
+        quorum
+    );
+    let reason = format!(
+        "public {}: every IP source is asked, for a quorum of {}",
+        record_type.family(),
+        quorum
+    );
+    let mut queries = JoinSet::new();
+    for source in detector.sources.iter().cloned() {
+        let client = client.clone();
+        let api_key = detector.credentials.api_key.to_string();
+        let secret_api_key = detector.credentials.secret_api_key.to_string();
+        let stun_server = detector.stun_server.to_string();
+        let interface = detector.interface.map(str::to_string);
+        let prefer = detector.prefer;
+        let reason = reason.clone();
+        queries.spawn(explain::carry(explain::because(reason, async move {
+            // Each query runs on its own task, so it gets its own copy of the settings.
+            let detector = IpDetector {
+                sources: &[],
+                quorum: None,
+                retry: &RetryPolicy::NONE,
+                credentials: Credentials {
+                    api_key: &api_key,
+                    secret_api_key: &secret_api_key,
+                },
+                stun_server: &stun_server,
+                interface: interface.as_deref(),
+                prefer,
+                allow_temporary: true,
+            };
+            let answer = fetch(&client, &detector, &source, record_type).await;
+            (source, answer)
+        })));
+    }
+    let mut answers = Vec::new();
+    while let Some(joined) = queries.join_next().await {
+        match joined {
+            Ok(answer) => answers.push(answer),
+            Err(e) => warn!("IP source query did not finish: {}", e),
+        }
+    }
+    answers.sort_by_key(|(source, _)| detector.sources.iter().position(|s| s == source));
+    tally(&answers, record_type, quorum)
+}
+
+/// Picks the address at least `quorum` of `answers` agree on, reporting the first source that
+/// gave it. Fails when no address, or more than one, reaches the quorum.
+fn tally(
+    answers: &[(IpSource, Result<String>)],
+    record_type: RecordType,
+    quorum: usize,
+) -> Result<(String, IpSource)> {
+    // Each address with the sources that reported it, in configuration order.
+    let mut votes: Vec<(&str, Vec<IpSource>)> = Vec::new();
+    for (source, answer) in answers {
+        match answer {
+            Ok(ip) => match votes.iter_mut().find(|(voted, _)| voted == ip) {
+                Some((_, sources)) => sources.push(source.clone()),
+                None => votes.push((ip, vec![source.clone()])),
+            },
+            Err(e) => warn!("IP source {} failed: {}", source, e),
+        }
+    }
+    let described: Vec<String> = answers
+        .iter()
+        .map(|(source, answer)| match answer {
+            Ok(ip) => format!("{}: {}", source, ip),
+            Err(_) => format!("{}: failed", source),
+        })
+        .collect();
+
+    let agreed: Vec<&(&str, Vec<IpSource>)> = votes
+        .iter()
+        .filter(|(_, sources)| sources.len() >= quorum)
+        .collect();
+    match agreed.as_slice() {
+        [(ip, sources)] => {
+            info!(
+                "{} of {} IP sources agree on {} ({})",
+                sources.len(),
+                answers.len(),
+                ip,
+                described.join(", ")
+            );
+            Ok((ip.to_string(), sources[0].clone()))
+        }
+        _ => {
+            error!(
+                "IP sources did not reach a quorum of {} for the public {}: {}",
+                quorum,
+                record_type.family(),
+                described.join(", ")
+            );
+            Err(DdnsError::IpDetection(format!(
+                "no address reached a quorum of {} ({})",
+                quorum,
+                described.join(", ")
+            )))
+        }
+    }
+}
+
+/// Detects the public address of a family, returning it with the source that gave it (in
+/// consensus mode, the first source that agreed). A whole round of failures is retried as a
+/// transient error.
+pub async fn detect(
+    client: &reqwest::Client,
+    detector: &IpDetector<'_>,
+    record_type: RecordType,
+) -> Result<(String, IpSource)> {
+    let what = format!("Detecting the public {}", record_type.family());
+    let (ip, source) = retry::retry(detector.retry, &what, || async {
+        match detector.quorum {
+            Some(quorum) => consensus(client, detector, record_type, quorum).await,
+            None => first_answer(client, detector, record_type).await,
+        }
+    })
+    .await?;
+    let ip = match record_type {
+        RecordType::Aaaa if !detector.allow_temporary => {
+            interface::stable_instead_of_temporary(ip).await?
+        }
+        _ => ip,
+    };
+
+    info!(
+        "Successfully retrieved current public {} from {}: {}",
+        record_type.family(),
+        source,
+        ip
+    );
+    Ok((ip, source))
+}
+
+/// Gets the current public address for the given record type.
+pub async fn get_current_ip(
+    client: &reqwest::Client,
+    detector: &IpDetector<'_>,
+    record_type: RecordType,
+) -> Result<String> {
+    detect(client, detector, record_type)
+        .await
+        .map(|(ip, _)| ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(source: IpSource, ip: &str) -> (IpSource, Result<String>) {
+        (source, Ok(ip.to_string()))
+    }
+
+    fn failure(source: IpSource) -> (IpSource, Result<String>) {
+        (source, Err(DdnsError::IpDetection("timed out".to_string())))
+    }
+
+    #[test]
+    fn tells_public_addresses_apart() {
+        let cases = [
+            ("203.0.113.7", true),
+            ("2001:db8::7", true),
+            ("192.168.1.20", false),
+            ("100.64.0.1", false),
+            ("127.0.0.1", false),
+            ("169.254.1.1", false),
+            ("0.0.0.0", false),
+            ("fd00::1", false),
+            ("fe80::1", false),
+            ("::1", false),
+            ("ff02::1", false),
+        ];
+        for (ip, public) in cases {
+            assert_eq!(is_public(ip.parse().unwrap()), public, "{}", ip);
+        }
+    }
+
+    #[test]
+    fn tally_accepts_only_a_single_address_that_reaches_the_quorum() {
+        let cases = [
+            (
+                "two of three agree",
+                vec![
+                    answer(IpSource::Ipify, "203.0.113.7"),
+                    answer(IpSource::Icanhazip, "198.51.100.1"),
+                    answer(IpSource::IfconfigMe, "203.0.113.7"),
+                ],
+                2,
+                Some(("203.0.113.7", IpSource::Ipify)),
+            ),
+            (
+                "a failure doesn't count against the agreeing sources",
+                vec![
+                    failure(IpSource::Ipify),
+                    answer(IpSource::Icanhazip, "203.0.113.7"),
+                    answer(IpSource::IfconfigMe, "203.0.113.7"),
+                ],
+                2,
+                Some(("203.0.113.7", IpSource::Icanhazip)),
+            ),
+            (
+                "a tie between two addresses",
+                vec![
+                    answer(IpSource::Ipify, "203.0.113.7"),
+                    answer(IpSource::Icanhazip, "198.51.100.1"),
+                    answer(IpSource::IfconfigMe, "203.0.113.7"),
+                    answer(IpSource::Porkbun, "198.51.100.1"),
+                ],
+                2,
+                None,
+            ),
+            (
+                "a tie with a quorum of one",
+                vec![
+                    answer(IpSource::Ipify, "203.0.113.7"),
+                    answer(IpSource::Icanhazip, "198.51.100.1"),
+                ],
+                1,
+                None,
+            ),
+            (
+                "a single source with a quorum of one",
+                vec![answer(IpSource::Stun, "203.0.113.7")],
+                1,
+                Some(("203.0.113.7", IpSource::Stun)),
+            ),
+            (
+                "a single source short of the quorum",
+                vec![
+                    answer(IpSource::Stun, "203.0.113.7"),
+                    failure(IpSource::Upnp),
+                ],
+                2,
+                None,
+            ),
+            (
+                "every source failed",
+                vec![
+                    failure(IpSource::Ipify),
+                    failure(IpSource::Icanhazip),
+                    failure(IpSource::Porkbun),
+                ],
+                1,
+                None,
+            ),
+            ("no sources", vec![], 1, None),
+        ];
+        for (case, answers, quorum, expected) in cases {
+            let agreed = tally(&answers, RecordType::A, quorum).ok();
+            let expected = expected.map(|(ip, source)| (ip.to_string(), source));
+            assert_eq!(agreed, expected, "{}", case);
+        }
+    }
 }
@@ -0,0 +1,127 @@
+// src/ip_state.rs
+
+use crate::integrity;
+use crate::porkbun::RecordType;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+const DEFAULT_STATE_FILE: &str = "porkbun-ip-state.json";
+
+/// The address and TTL a record was last seen holding, and when.
+#[derive(Debug, Serialize, Deserialize)]
+struct Known {
+    ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
+    /// Unix timestamp at which Porkbun last confirmed the record.
+    at: i64,
+}
+
+/// The address each record was last confirmed to hold, kept in `PORKBUN_IP_STATE_FILE` so a
+/// cycle whose detected address hasn't moved doesn't need to ask Porkbun at all. An empty
+/// file name turns this off.
+pub struct IpState {
+    path: Option<String>,
+    known: BTreeMap<String, Known>,
+}
+
+fn state_path() -> Option<String> {
+    let path = env::var("PORKBUN_IP_STATE_FILE").unwrap_or_else(|_| DEFAULT_STATE_FILE.to_string());
+    Some(path).filter(|path| !path.is_empty())
+}
+
+fn key(name: &str, record_type: RecordType) -> String {
+    format!("{} {}", name, record_type)
+}
+
+impl IpState {
+    /// Reads the state file. A missing file is an empty state; an unreadable one, or one that
+    /// fails its integrity check, is reported and then treated as empty, which only costs a
+    /// lookup of every record.
+    pub fn load() -> Self {
+        let path = state_path();
+        let known = match path.as_deref().map(integrity::read) {
+            Some(Ok(Some(contents))) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring unreadable IP state file '{}': {}",
+                    path.as_deref().unwrap_or_default(),
+                    e
+                );
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        IpState { path, known }
+    }
+
+    /// The address and TTL Porkbun confirmed the record to hold within the last
+    /// `max_age_seconds`, if it did.
+    pub fn recent(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        max_age_seconds: u64,
+    ) -> Option<(&str, Option<u32>)> {
+        self.known
+            .get(&key(name, record_type))
+            .filter(|known| {
+                chrono::Utc::now().timestamp().saturating_sub(known.at)
+                    < i64::try_from(max_age_seconds).unwrap_or(i64::MAX)
+            })
+            .map(|known| (known.ip.as_str(), known.ttl))
+    }
+
+    /// Records that Porkbun just confirmed the record holds `ip` with `ttl`, and saves the file.
+    pub fn remember(&mut self, name: &str, record_type: RecordType, ip: &str, ttl: Option<u32>) {
+        if self.path.is_none() {
+            return;
+        }
+        self.known.insert(
+            key(name, record_type),
+            Known {
+                ip: ip.to_string(),
+                ttl,
+                at: chrono::Utc::now().timestamp(),
+            },
+        );
+        self.save();
+    }
+
+    /// Drops what is known about one record, so the next cycle looks it up, and saves the file.
+    pub fn forget(&mut self, name: &str, record_type: RecordType) {
+        if self.known.remove(&key(name, record_type)).is_some() {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_string_pretty(&self.known)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| {
+                fs::write(path, integrity::seal(&contents)).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            warn!("Failed to write IP state file '{}': {}", path, e);
+        }
+    }
+}
+
+/// Drops everything known about the records, after a change made outside the check cycle
+/// (a scheduled change or `undo`) or when a full check is asked for, so the next cycle looks
+/// at every record again.
+pub fn forget_all() {
+    let Some(path) = state_path() else {
+        return;
+    };
+    if let Err(e) = fs::remove_file(&path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove IP state file '{}': {}", path, e);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+// src/journal.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::integrity;
+use crate::random;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The batch new writes are grouped under, so a cycle's changes can be undone together.
+static BATCH: AtomicU64 = AtomicU64::new(0);
+
+/// What a write does to its record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    #[default]
+    Edit,
+    Delete,
+}
+
+/// A record write, as intended before it is sent to Porkbun.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: u64,
+    /// Unix timestamp at which the write was started.
+    pub at: i64,
+    /// The cycle or command the write was part of.
+    #[serde(default)]
+    pub batch: u64,
+    #[serde(default)]
+    pub action: Action,
+    pub domain: String,
+    pub subdomain: String,
+    pub record_type: String,
+    /// The record being edited, or `None` when it is being created or was edited by name and
+    /// type without its ID being known.
+    pub record_id: Option<String>,
+    /// Content and TTL before the write, for rolling it back. `None` for created records.
+    pub old_content: Option<String>,
+    pub old_ttl: Option<u32>,
+    pub new_content: String,
+    pub new_ttl: u32,
+    /// Why the write was made, for manual changes given a `--reason`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// One line of the journal. Every write gets an `intent` line before it is sent and a
+/// `done` or `failed` line once Porkbun has answered. A `probe` line follows the writes of an
+/// IP change when a latency probe is configured.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+enum Line {
+    Intent(Operation),
+    Done {
+        id: u64,
+    },
+    Failed {
+        id: u64,
+        error: String,
+    },
+    /// Written once an unfinished write has been reported, so it is only reported once.
+    Interrupted {
+        id: u64,
+    },
+    /// Latency measured right after the batch's writes moved a family to a new address.
+    Probe {
+        batch: u64,
+        at: i64,
+        record_type: String,
+        ip: String,
+        probe: serde_json::Value,
+    },
+}
+
+/// Adds a line to the journal. When a crash cut the last line short, the new line starts on a
+/// line of its own rather than being joined onto the torn one.
+fn append(path: &str, line: &Line) -> Result<()> {
+    let encoded = serde_json::to_string(line)
+        .map_err(|e| DdnsError::Config(format!("Failed to encode journal entry: {}", e)))?;
+    let mut encoded = integrity::seal_line(&encoded);
+    encoded.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| {
+            if file.metadata()?.len() > 0 {
+                let mut last = [0u8];
+                file.seek(SeekFrom::End(-1))?;
+                file.read_exact(&mut last)?;
+                if last[0] != b'\n' {
+                    encoded.insert(0, '\n');
+                }
+            }
+            file.write_all(encoded.as_bytes())
+        })
+        .map_err(|e| DdnsError::file("Failed to write journal", path, e))
+}
+
+/// Starts a new batch; writes from here on are grouped under it until the next call.
+pub fn start_batch() {
+    BATCH.store(random::random_u64(), Ordering::Relaxed);
+}
+
+/// Records the intent to make a write in the journal at `path`, if there is one. Fails if the
+/// journal can't be written, so no write is ever sent without a record of it. Returns the
+/// operation's ID.
+pub fn begin(path: Option<&str>, mut operation: Operation) -> Result<u64> {
+    operation.id = random::random_u64();
+    operation.at = chrono::Utc::now().timestamp();
+    operation.batch = BATCH.load(Ordering::Relaxed);
+    let id = operation.id;
+    if let Some(path) = path {
+        append(path, &Line::Intent(operation))?;
+    }
+    Ok(id)
+}
+
+/// Marks a write as completed or failed. A failure to record this only leaves the write
+/// looking interrupted, so it is logged rather than returned.
+pub fn finish<T>(path: Option<&str>, id: u64, result: &Result<T>) {
+    let Some(path) = path else {
+        return;
+    };
+    let line = match result {
+        Ok(_) => Line::Done { id },
+        Err(e) => Line::Failed {
+            id,
+            error: e.to_string(),
+        },
+    };
+    if let Err(e) = append(path, &line) {
+        warn!("{}", e);
+    }
+}
+
+/// Records the latency measured after the current batch changed `record_type` records to
+/// `ip`. Like `finish`, a failure to record this is only logged.
+pub fn note_probe<T: Serialize>(path: &str, record_type: &str, ip: &str, probe: &T) {
+    let probe = match serde_json::to_value(probe) {
+        Ok(probe) => probe,
+        Err(e) => {
+            warn!("Failed to encode journal entry: {}", e);
+            return;
+        }
+    };
+    let line = Line::Probe {
+        batch: BATCH.load(Ordering::Relaxed),
+        at: chrono::Utc::now().timestamp(),
+        record_type: record_type.to_string(),
+        ip: ip.to_string(),
+        probe,
+    };
+    if let Err(e) = append(path, &line) {
+        warn!("{}", e);
+    }
+}
+
+/// How a journaled write ended, if it did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pending,
+    Done,
+    Failed,
+    Interrupted,
+}
+
+/// Every journaled write with its outcome, in the order they were started.
+fn read(path: &str) -> Vec<(Operation, Outcome)> {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse(path, &contents),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Parses the journal's lines. A line that can't be parsed, such as one a crash cut short, or
+/// that fails its integrity check is skipped with a warning, so it can't make the rest of the
+/// journal unusable, and an edited line is never acted on.
+fn parse(path: &str, contents: &str) -> Vec<(Operation, Outcome)> {
+    let mut operations: Vec<(Operation, Outcome)> = Vec::new();
+    let mut index = BTreeMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(line) = integrity::open_line(line) else {
+            warn!(
+                "Skipping journal '{}' line {}: it was cut short or edited",
+                path,
+                number + 1
+            );
+            continue;
+        };
+        let line: Line = match serde_json::from_str(line) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!(
+                    "Skipping unreadable journal '{}' line {}: {}",
+                    path,
+                    number + 1,
+                    e
+                );
+                continue;
+            }
+        };
+        let (id, outcome) = match line {
+            Line::Intent(operation) => {
+                index.insert(operation.id, operations.len());
+                operations.push((operation, Outcome::Pending));
+                continue;
+            }
+            Line::Done { id } => (id, Outcome::Done),
+            Line::Failed { id, .. } => (id, Outcome::Failed),
+            Line::Interrupted { id } => (id, Outcome::Interrupted),
+            Line::Probe { .. } => continue,
+        };
+        if let Some(&position) = index.get(&id) {
+            operations[position].1 = outcome;
+        }
+    }
+    operations
+}
+
+/// Writes whose intent was recorded but whose outcome never was, oldest first. These are
+/// left behind by a run that was interrupted mid-write.
+pub fn interrupted(path: &str) -> Vec<Operation> {
+    read(path)
+        .into_iter()
+        .filter(|(_, outcome)| *outcome == Outcome::Pending)
+        .map(|(operation, _)| operation)
+        .collect()
+}
+
+/// The writes of the most recent batch that made or started any, in the order they were
+/// started, each with whether it was interrupted: an interrupted write may or may not have
+/// reached Porkbun. Failed writes changed nothing and are left out.
+pub fn last_batch(path: &str) -> Vec<(Operation, bool)> {
+    let made: Vec<(Operation, bool)> = read(path)
+        .into_iter()
+        .filter(|(_, outcome)| *outcome != Outcome::Failed)
+        .map(|(operation, outcome)| (operation, outcome != Outcome::Done))
+        .collect();
+    let Some(batch) = made.last().map(|(operation, _)| operation.batch) else {
+        return Vec::new();
+    };
+    made.into_iter()
+        .filter(|(operation, _)| operation.batch == batch)
+        .collect()
+}
+
+/// Warns about writes left unfinished by an earlier run, so the zone can be inspected or the
+/// writes rolled back with `undo`, then marks them as interrupted.
+pub fn report_interrupted(path: &str) {
+    let operations = interrupted(path);
+    if operations.is_empty() {
+        return;
+    }
+    warn!(
+        "The journal lists {} write(s) that an earlier run started but never finished; their records may not match the journal (`undo` rolls back the ones that were made):",
+        operations.len()
+    );
+    for operation in &operations {
+        warn!(
+            "  {} {} record for '{}' in {}: {} -> {}",
+            operation.action,
+            operation.record_type,
+            operation.subdomain,
+            operation.domain,
+            operation.old_content.as_deref().unwrap_or("(none)"),
+            operation.new_content
+        );
+        if let Err(e) = append(path, &Line::Interrupted { id: operation.id }) {
+            warn!("{}", e);
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Create => "create",
+            Action::Edit => "edit",
+            Action::Delete => "delete",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intent(id: u64) -> String {
+        serde_json::to_string(&Line::Intent(Operation {
+            id,
+            at: 0,
+            batch: 1,
+            action: Action::Edit,
+            domain: "example.com".to_string(),
+            subdomain: "www".to_string(),
+            record_type: "A".to_string(),
+            record_id: Some("1".to_string()),
+            old_content: Some("192.0.2.1".to_string()),
+            old_ttl: Some(600),
+            new_content: "192.0.2.2".to_string(),
+            new_ttl: 600,
+            reason: None,
+        }))
+        .unwrap()
+    }
+
+    fn outcomes(contents: &str) -> Vec<(u64, Outcome)> {
+        parse("test", contents)
+            .into_iter()
+            .map(|(operation, outcome)| (operation.id, outcome))
+            .collect()
+    }
+
+    #[test]
+    fn skips_a_torn_trailing_line() {
+        let contents = format!(
+            "{}\n{{\"state\":\"done\",\"id\":1}}\n{}\n{{\"state\":\"do",
+            intent(1),
+            intent(2)
+        );
+        assert_eq!(
+            outcomes(&contents),
+            vec![(1, Outcome::Done), (2, Outcome::Pending)]
+        );
+    }
+
+    #[test]
+    fn skips_a_malformed_line_in_the_middle() {
+        let contents = format!(
+            "{}\nnot json\n{{\"state\":\"failed\",\"id\":1,\"error\":\"x\"}}\n",
+            intent(1)
+        );
+        assert_eq!(outcomes(&contents), vec![(1, Outcome::Failed)]);
+    }
+
+    #[test]
+    fn appends_after_a_torn_line_on_a_line_of_its_own() {
+        let path =
+            std::env::temp_dir().join(format!("porkbun-journal-test-{}", random::random_u64()));
+        let path = path.to_str().unwrap();
+        fs::write(path, format!("{}\n{{\"state\":\"do", intent(1))).unwrap();
+        append(path, &Line::Done { id: 1 }).unwrap();
+        append(path, &Line::Interrupted { id: 2 }).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(contents
+            .ends_with("\n{\"state\":\"done\",\"id\":1}\n{\"state\":\"interrupted\",\"id\":2}\n"));
+        assert_eq!(outcomes(&contents), vec![(1, Outcome::Done)]);
+    }
+
+    #[test]
+    fn last_batch_includes_interrupted_writes() {
+        let path =
+            std::env::temp_dir().join(format!("porkbun-journal-test-{}", random::random_u64()));
+        let path = path.to_str().unwrap();
+        let contents = [
+            intent(1),
+            r#"{"state":"done","id":1}"#.to_string(),
+            intent(2),
+            r#"{"state":"failed","id":2,"error":"x"}"#.to_string(),
+            intent(3),
+            r#"{"state":"interrupted","id":3}"#.to_string(),
+            intent(4),
+        ];
+        fs::write(path, contents.join("\n")).unwrap();
+        let batch = last_batch(path);
+        fs::remove_file(path).unwrap();
+
+        let batch: Vec<(u64, bool)> = batch
+            .into_iter()
+            .map(|(operation, interrupted)| (operation.id, interrupted))
+            .collect();
+        assert_eq!(batch, vec![(1, false), (3, true), (4, true)]);
+    }
+}
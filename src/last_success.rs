@@ -0,0 +1,55 @@
+// src/last_success.rs
+
+//! When a check cycle last synced every record, kept in `PORKBUN_LAST_SUCCESS_FILE` so
+//! `check`, which runs in a process of its own, can tell how long ago that was. An empty file
+//! name turns this off.
+
+use crate::integrity;
+use crate::summary::CycleSummary;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+
+const DEFAULT_FILE: &str = "porkbun-last-success.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LastSuccess {
+    /// Unix timestamp at which the cycle finished.
+    at: i64,
+}
+
+fn path() -> Option<String> {
+    let path = env::var("PORKBUN_LAST_SUCCESS_FILE").unwrap_or_else(|_| DEFAULT_FILE.to_string());
+    Some(path).filter(|path| !path.is_empty())
+}
+
+/// Notes a cycle that synced every record. Dry runs, which change nothing, don't count, and
+/// failures to write the file are only logged.
+pub fn record(summary: &CycleSummary) {
+    if summary.dry_run || summary.degraded() {
+        return;
+    }
+    let Some(path) = path() else {
+        return;
+    };
+    let contents = serde_json::to_string(&LastSuccess {
+        at: chrono::Utc::now().timestamp(),
+    })
+    .unwrap_or_default();
+    if let Err(e) = fs::write(&path, integrity::seal(&contents)) {
+        warn!("Failed to write last success file '{}': {}", path, e);
+    }
+}
+
+/// Seconds since a cycle last synced every record, if one ever did.
+pub fn seconds_since() -> Option<i64> {
+    let contents = integrity::read(&path()?).ok()??;
+    let last: LastSuccess = serde_json::from_str(&contents).ok()?;
+    Some(
+        chrono::Utc::now()
+            .timestamp()
+            .saturating_sub(last.at)
+            .max(0),
+    )
+}
@@ -0,0 +1,50 @@
+// lib.rs
+
+//! A client for the Porkbun DNS API, as used by the `porkbun_ddns` updater.
+//!
+//! ```no_run
+//! # async fn example() -> porkbun_ddns::Result<()> {
+//! use porkbun_ddns::{PorkbunClient, RecordType};
+//!
+//! let http = reqwest::Client::new();
+//! let client = PorkbunClient::new(&http, "pk1_...", "sk1_...", "example.com");
+//! println!("Porkbun sees us as {}", client.ping().await?);
+//! for record in client.retrieve_records().await? {
+//!     println!("{} {} {}", record.name, record.record_type, record.content);
+//! }
+//! if let Some(record) = client.get_record("www", RecordType::A).await? {
+//!     client.edit_record(&record, "www", "198.51.100.7", 600).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Writes can be recorded in a journal before they are sent, with
+//! `PorkbunClient::with_journal`; they aren't by default. What the client does can be followed
+//! as a stream of events with `events::subscribe`.
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod clock;
+pub mod errors;
+pub mod events;
+// Internals the client shares with the updater, which builds these files into the binary
+// itself and uses the parts the client doesn't: recording `--explain` output, reading the
+// journal back for `undo` and sealing it with the state key.
+#[allow(dead_code)]
+pub(crate) mod explain;
+#[allow(dead_code)]
+pub(crate) mod integrity;
+#[allow(dead_code)]
+pub(crate) mod journal;
+pub mod porkbun;
+pub(crate) mod random;
+pub mod record;
+#[allow(dead_code)]
+pub(crate) mod retry;
+
+pub use errors::{DdnsError, Result};
+pub use events::DdnsEvent;
+pub use porkbun::{DnsRecord, PorkbunClient, RecordType};
+pub use record::Record;
+pub use retry::RetryPolicy;
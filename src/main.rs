@@ -1,108 +1,1653 @@
 // main.rs
 
+mod access;
+mod aliases;
+mod app_error;
+// Only for the library's API, but the client's blocking transport refers to it.
+#[cfg(feature = "blocking")]
+#[allow(dead_code)]
+mod blocking;
+mod budget;
+mod build_info;
+mod check;
+mod cli;
+mod clock;
 mod config;
+#[cfg(feature = "config-file")]
+mod config_file;
+mod decision;
+mod discover;
+#[cfg(feature = "verify")]
+mod dns;
 mod errors;
+// Built into the binary like the rest of the client, so the library can keep its internals
+// private. Parts of the library's API, here and in `record`, go unused by the updater.
+#[allow(dead_code)]
+mod events;
+mod expand;
+mod explain;
+#[cfg(feature = "geoip")]
+mod geoip;
+mod healthcheck;
+mod hooks;
+mod integrity;
+mod interface;
+mod ip_details;
 mod ip_fetcher;
+mod ip_state;
+mod journal;
+mod last_success;
+mod planned;
 mod porkbun;
+mod port_check;
+mod probe;
+mod psl;
+mod random;
+#[allow(dead_code)]
+mod record;
+mod reload;
+#[cfg(feature = "remote-config")]
+mod remote_config;
+mod retry;
+mod router;
+mod schedule;
+#[cfg(feature = "encrypted-secrets")]
+mod secrets;
+mod settle;
+mod simulate;
+mod soft_fail;
+mod status;
+mod stun;
+mod summary;
+mod supervisor;
+mod systemd;
+mod telemetry;
+mod template;
+mod tenants;
+mod textfile;
+mod ttl_change;
+mod undo;
+mod upnp;
+mod vars;
+#[cfg(feature = "verify")]
+mod verify;
 
+use crate::budget::WriteBudget;
+use crate::cli::{Cli, Command};
+use crate::clock::SystemClock;
 use crate::config::Config;
-use crate::porkbun::PorkbunClient;
-use dotenv::dotenv;
+use crate::decision::Wanted;
+use crate::discover::DiscoverMode;
+use crate::hooks::HookContext;
+use crate::ip_details::IpDetails;
+use crate::ip_state::IpState;
+use crate::porkbun::{DnsRecord, PorkbunClient, RecordType, RetrievalStrategy, UpdateMethod, Zone};
+use crate::probe::ProbeResult;
+use crate::summary::{Change, CycleSummary, FamilyStatus};
+use clap::Parser;
 use env_logger::Builder;
-use log::{error, info};
+use log::{error, info, warn};
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::time::{sleep, Duration};
 
 // Main asynchronous function where the program execution begins.
-#[tokio::main]
-async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    // Initialize the logger, allowing RUST_LOG to override default INFO level.
-    Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+#[cfg_attr(feature = "multi-thread", tokio::main)]
+#[cfg_attr(not(feature = "multi-thread"), tokio::main(flavor = "current_thread"))]
+async fn main() -> errors::Result<()> {
+    let cli = Cli::parse();
+    // Generated without loading any settings, and without log lines around the output.
+    match &cli.command {
+        Some(Command::Completions { shell }) => {
+            cli::print_completions(*shell);
+            return Ok(());
+        }
+        Some(Command::Manpages { dir }) => return cli::write_manpages(dir),
+        _ => {}
+    }
 
-    info!("Starting Porkbun Dynamic DNS Updater...");
+    // Initialize the logger, allowing RUST_LOG to override default INFO level.
+    let mut logger = Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    // Tenants log to the same output, so each line says whose it is.
+    if let Some(tenant) = cli.tenant.clone() {
+        logger.format(move |buf, record| {
+            use std::io::Write;
+            writeln!(
+                buf,
+                "[{} {:<5} {} {}] {}",
+                buf.timestamp(),
+                record.level(),
+                tenant,
+                record.target(),
+                record.args()
+            )
+        });
+    }
+    logger.init();
 
-    dotenv().ok();
+    info!(
+        "Starting Porkbun Dynamic DNS Updater {}...",
+        build_info::get()
+    );
 
-    let config = Config::from_env().expect("Failed to load configuration from environment.");
+    reload::init(cli.config.as_deref(), cli.tenant.as_deref());
+    if cli.config.is_none() && cli.tenant.is_some() {
+        return Err(errors::DdnsError::Config(
+            "--tenant needs --config with the file that defines the tenant.".to_string(),
+        ));
+    }
+    let tenants = reload::load()?;
+    integrity::init();
+    #[cfg(not(feature = "encrypted-secrets"))]
+    if std::env::var("PORKBUN_ENCRYPTED_ENV").is_ok() {
+        warn!("PORKBUN_ENCRYPTED_ENV is set, but this build does not include the `encrypted-secrets` feature; the file will not be loaded.");
+    }
 
     // Create an HTTP client for making requests.
     let client = reqwest::Client::new();
 
+    #[cfg(feature = "remote-config")]
+    remote_config::init(&client).await?;
+    #[cfg(not(feature = "remote-config"))]
+    if std::env::var("PORKBUN_REMOTE_CONFIG_URL").is_ok() {
+        warn!("PORKBUN_REMOTE_CONFIG_URL is set, but this build does not include the `remote-config` feature; the remote configuration will not be fetched.");
+    }
+
+    // A dry run is always a single cycle.
+    let command = match cli.command {
+        Some(Command::Run) | None if cli.once || cli.dry_run || cli.explain => Command::Update,
+        Some(_) if cli.once => {
+            return Err(errors::DdnsError::Config(
+                "--once only applies to `run`.".to_string(),
+            ))
+        }
+        Some(Command::Update) => Command::Update,
+        Some(_) if cli.dry_run => {
+            return Err(errors::DdnsError::Config(
+                "--dry-run only applies to `run` and `update`.".to_string(),
+            ))
+        }
+        Some(_) if cli.explain => {
+            return Err(errors::DdnsError::Config(
+                "--explain only applies to `run` and `update`.".to_string(),
+            ))
+        }
+        command => command.unwrap_or(Command::Run),
+    };
+    if let (Some(path), None, false) = (&cli.config, &cli.tenant, tenants.is_empty()) {
+        return match command {
+            Command::Run => tenants::supervise(path, &tenants, cli.force).await,
+            _ => Err(errors::DdnsError::Config(format!(
+                "{} defines tenants ({}); choose one with --tenant.",
+                path,
+                tenants.join(", ")
+            ))),
+        };
+    }
+    if cli.json
+        && !matches!(
+            command,
+            Command::Update
+                | Command::Status { .. }
+                | Command::Check
+                | Command::Discover
+                | Command::Undo { .. }
+                | Command::Validate
+        )
+    {
+        return Err(errors::DdnsError::Config(
+            "--json applies to `update` (and `--once` or `--dry-run`), `status`, `check`, `discover`, `undo` and `validate`; `run`, `pre-change`, `post-change` and `schedule` have no JSON output.".to_string(),
+        ));
+    }
+    if let Command::Validate = command {
+        let result = Config::from_vars(&reload::current());
+        if cli.json {
+            println!(
+                "{}",
+                match &result {
+                    Ok(config) =>
+                        serde_json::json!({ "valid": true, "summary": describe_config(config) }),
+                    Err(e) => serde_json::json!({ "valid": false, "error": e.to_string() }),
+                }
+            );
+        }
+        return match result {
+            Ok(config) => {
+                if !cli.json {
+                    println!("Configuration OK: {}", describe_config(&config));
+                }
+                Ok(())
+            }
+            Err(e) => {
+                if !cli.json {
+                    println!("Configuration invalid: {}", e);
+                }
+                std::process::exit(1);
+            }
+        };
+    }
+
+    if cli.simulate {
+        simulate::start().await?;
+    }
+
+    reload::prompt_credentials()?;
+    let mut config = Config::from_vars(&reload::current())
+        .expect("Failed to load configuration from environment.");
+    if cli.force {
+        warn!("--force given: the per-cycle write limit is disabled");
+        config.max_writes_per_cycle = None;
+    }
+    if cli.dry_run {
+        info!("--dry-run given: changes will be logged but not made");
+        config.dry_run = true;
+    }
+
+    if let Some(path) = &config.journal_file {
+        journal::report_interrupted(path);
+    }
+
+    match command {
+        Command::Run => run_daemon(&client, config, cli.force).await?,
+        Command::Update => {
+            let cycle = async {
+                let mut summary = run_cycle(&client, &config, true).await;
+                // Without a daemon, a timer run is what applies scheduled changes.
+                summary.merge(run_planned_changes(&client, &config, true).await);
+                summary
+            };
+            let (summary, explanation) = if cli.explain {
+                let (summary, entries) = explain::record(cycle).await;
+                (summary, Some(entries))
+            } else {
+                (cycle.await, None)
+            };
+            if cli.json {
+                println!("{}", summary.to_json());
+            }
+            if let Some(entries) = explanation {
+                print_explanation(&entries);
+            }
+            std::process::exit(summary.outcome() as i32);
+        }
+        Command::Status { wide, no_color } => {
+            let code = status::run(
+                &client,
+                &zone_clients(&client, &config),
+                &config.record_types,
+                &config.ip_detector(),
+                status::TableStyle::new(wide, no_color),
+                cli.json,
+            )
+            .await;
+            std::process::exit(code);
+        }
+        Command::Discover => {
+            let found = discover::find(&client, &config, &zone_clients(&client, &config)).await;
+            if cli.json {
+                println!("{}", discover::to_json(&found));
+            } else {
+                discover::print(&found);
+            }
+        }
+        Command::Validate | Command::Completions { .. } | Command::Manpages { .. } => {
+            unreachable!("handled before the configuration is loaded")
+        }
+        Command::Check => {
+            let zones = zone_clients(&client, &config);
+            let code = check::run(
+                &client,
+                &zones,
+                &config.record_types,
+                config.router.as_ref(),
+                &config.ip_detector(),
+                cli.json,
+            )
+            .await;
+            std::process::exit(code);
+        }
+        Command::PreChange { ttl, reason } => {
+            let zones = zones_because(zone_clients(&client, &config), reason.as_deref());
+            journal::start_batch();
+            ttl_change::pre_change(
+                &zones,
+                &config.record_types,
+                ttl.unwrap_or(porkbun::MIN_TTL),
+            )
+            .await?;
+            // The IP state remembers the TTLs from before; the next cycle reads them again.
+            ip_state::forget_all();
+        }
+        Command::PostChange { reason } => {
+            journal::start_batch();
+            ttl_change::post_change(&zones_because(
+                zone_clients(&client, &config),
+                reason.as_deref(),
+            ))
+            .await?;
+            ip_state::forget_all();
+        }
+        Command::Schedule {
+            when,
+            host,
+            ip,
+            reason,
+        } => schedule_change(
+            &zone_clients(&client, &config),
+            &when,
+            Target {
+                host: &host,
+                ip: &ip,
+            },
+            reason,
+        )?,
+        Command::Undo { yes, reason } => {
+            let result = undo::run(
+                &zones_because(zone_clients(&client, &config), reason.as_deref()),
+                config.journal_file.as_deref(),
+                yes,
+                cli.json,
+            )
+            .await;
+            ip_state::forget_all();
+            result?
+        }
+    }
+    Ok(())
+}
+
+/// Prints the network calls of an `--explain` cycle in order, numbered, with the decisions
+/// that caused or saved calls in between, followed by the number of calls per service.
+fn print_explanation(entries: &[explain::Entry]) {
+    let mut per_service: Vec<(&str, usize)> = Vec::new();
+    println!("Network calls made by this cycle:");
+    for entry in entries {
+        match entry {
+            explain::Entry::Call { service, .. } => {
+                match per_service.iter_mut().find(|(s, _)| s == service) {
+                    Some((_, count)) => *count += 1,
+                    None => per_service.push((service, 1)),
+                }
+                let number: usize = per_service.iter().map(|(_, count)| count).sum();
+                println!("{:>4}. {}", number, entry);
+            }
+            explain::Entry::Note(_) => println!("      - {}", entry),
+        }
+    }
+    let total: usize = per_service.iter().map(|(_, count)| count).sum();
+    let breakdown: Vec<String> = per_service
+        .iter()
+        .map(|(service, count)| format!("{} {}", service, count))
+        .collect();
+    if total == 0 {
+        println!("No network calls.");
+    } else {
+        println!("{} network call(s): {}", total, breakdown.join(", "));
+    }
+}
+
+/// One line describing what a valid configuration manages, for `validate` and reloads.
+fn describe_config(config: &Config) -> String {
+    let mut domains = vec![config.domain.as_str()];
+    domains.extend(config.extra_zones.iter().map(|zone| zone.domain.as_str()));
+    if let Some(mirror) = &config.mirror {
+        domains.push(&mirror.domain);
+    }
+    let hosts = config.subdomains.len()
+        + config
+            .extra_zones
+            .iter()
+            .map(|zone| zone.subdomains.len())
+            .sum::<usize>();
+    let record_types: Vec<&str> = config.record_types.iter().map(|t| t.as_str()).collect();
+    format!(
+        "{} host(s) in {} ({} records)",
+        hosts,
+        domains.join(", "),
+        record_types.join("/")
+    )
+}
+
+/// Checks the API keys, then runs the update loop until the process is stopped.
+async fn run_daemon(
+    client: &reqwest::Client,
+    mut config: Config,
+    force: bool,
+) -> errors::Result<()> {
+    // SIGUSR2 wakes the loop early, e.g. from a PPPoE reconnect script.
+    let mut check_now = signal(SignalKind::user_defined2())?;
+    // SIGUSR1 does the same but looks every record up again, ignoring the IP state file.
+    let mut full_check = signal(SignalKind::user_defined1())?;
+    // SIGHUP reloads the configuration, e.g. after adding a subdomain to .env.
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    {
+        let zones = zone_clients(client, &config);
+        access::check_credentials(&zones).await?;
+        if config.probe_access {
+            access::report(&zones).await;
+        }
+    }
+    discover_hosts(client, &mut config).await;
+    systemd::ready(&format!("Managing {}", describe_config(&config)));
+
+    if config.on_demand {
+        info!("On-demand mode: waiting for SIGUSR2 before each check");
+        loop {
+            tokio::select! {
+                received = check_now.recv() => {
+                    if received.is_none() {
+                        break;
+                    }
+                    info!("--- Received SIGUSR2, starting check cycle ---");
+                    reload_remote_config(client, &mut config, force).await;
+                    run_cycle(client, &config, false).await;
+                    info!("--- Check cycle finished. Waiting for the next SIGUSR2 ---");
+                }
+                _ = full_check.recv() => {
+                    info!("--- Received SIGUSR1, starting a full check cycle ---");
+                    ip_state::forget_all();
+                    reload_remote_config(client, &mut config, force).await;
+                    run_cycle(client, &config, false).await;
+                    info!("--- Check cycle finished. Waiting for the next SIGUSR2 ---");
+                }
+                _ = hangup.recv() => {
+                    if reload_local_config(client, &mut config, force).await {
+                        run_cycle(client, &config, false).await;
+                    }
+                }
+                _ = planned::wait_until_due() => {
+                    run_planned_changes(client, &config, false).await;
+                }
+                _ = systemd::keep_alive() => {}
+            }
+        }
+        return Ok(());
+    }
+
+    // Stagger the first cycle so many instances restarting together don't hit the APIs at once.
+    let startup_wait = config.startup_delay_seconds + random::up_to(config.startup_splay_seconds);
+    if startup_wait > 0 {
+        info!("Waiting {} seconds before the first check...", startup_wait);
+        tokio::select! {
+            _ = sleep(Duration::from_secs(startup_wait)) => {}
+            _ = systemd::keep_alive() => {}
+        }
+    }
+
     loop {
         info!("--- Starting new check cycle ---");
-        perform_ddns_update(&client, &config).await;
+        reload_remote_config(client, &mut config, force).await;
+        run_cycle(client, &config, false).await;
         info!(
             "--- Check cycle finished. Sleeping for {} seconds ---",
             config.check_interval_seconds
         );
-        sleep(Duration::from_secs(config.check_interval_seconds)).await;
+        let next_check =
+            schedule::wait_for_next_check(Duration::from_secs(config.check_interval_seconds));
+        tokio::pin!(next_check);
+        loop {
+            tokio::select! {
+                _ = &mut next_check => break,
+                _ = check_now.recv() => {
+                    info!("Received SIGUSR2, starting an immediate check");
+                    break;
+                }
+                _ = full_check.recv() => {
+                    info!("Received SIGUSR1, starting an immediate full check");
+                    ip_state::forget_all();
+                    break;
+                }
+                _ = hangup.recv() => {
+                    if reload_local_config(client, &mut config, force).await {
+                        break;
+                    }
+                }
+                _ = planned::wait_until_due() => {
+                    run_planned_changes(client, &config, false).await;
+                }
+                _ = systemd::keep_alive() => {}
+            }
+        }
     }
 }
 
-async fn perform_ddns_update(client: &reqwest::Client, config: &Config) {
-    match ip_fetcher::get_current_ipv4(client).await {
-        Ok(current_ip) => {
-            let porkbun_client = PorkbunClient::new(
-                client,
-                &config.api_key,
-                &config.secret_api_key,
-                &config.domain,
+/// Re-reads `.env` and the `--config` file after SIGHUP and switches to the new settings,
+/// logging the hosts that were added or dropped. Returns whether it switched; an invalid
+/// configuration leaves the current settings in use. Records of dropped hosts are left as
+/// they are.
+async fn reload_local_config(client: &reqwest::Client, config: &mut Config, force: bool) -> bool {
+    info!("Received SIGHUP, reloading the configuration");
+    let mut new_config = match reload::reread() {
+        Ok(new_config) => new_config,
+        Err(e) => {
+            error!(
+                "Reloaded configuration is invalid, keeping the current one: {}",
+                e
             );
+            return false;
+        }
+    };
+    if force {
+        new_config.max_writes_per_cycle = None;
+    }
+    if new_config.on_demand != config.on_demand {
+        warn!("PORKBUN_ON_DEMAND changed; switching between on-demand and scheduled checks needs a restart");
+        new_config.on_demand = config.on_demand;
+    }
+    discover_hosts(client, &mut new_config).await;
 
-            for subdomain in &config.subdomains {
-                info!(
-                    "Processing subdomain: '{}'",
-                    if subdomain.is_empty() {
-                        &config.domain
-                    } else {
-                        &subdomain
-                    }
-                );
+    let managed_hosts = |config: &Config| -> Vec<String> {
+        zone_clients(client, config)
+            .iter()
+            .flat_map(|zone| {
+                zone.subdomains
+                    .iter()
+                    .map(|subdomain| zone.client.full_name(subdomain))
+            })
+            .collect()
+    };
+    let (old_hosts, new_hosts) = (managed_hosts(config), managed_hosts(&new_config));
+    for host in new_hosts.iter().filter(|host| !old_hosts.contains(host)) {
+        info!("Now managing {}", host);
+    }
+    for host in old_hosts.iter().filter(|host| !new_hosts.contains(host)) {
+        info!(
+            "No longer managing {}; its records are left as they are",
+            host
+        );
+    }
+
+    info!(
+        "Switched to the reloaded configuration: {}",
+        describe_config(&new_config)
+    );
+    *config = new_config;
+    true
+}
+
+/// Looks for unmanaged records pointing at this connection when `PORKBUN_DISCOVER` asks for
+/// it, logging them or adding them to the managed hosts. Runs whenever the daemon loads a
+/// configuration, so adopted hosts survive a reload.
+async fn discover_hosts(client: &reqwest::Client, config: &mut Config) {
+    let mode = config.discover;
+    if mode == DiscoverMode::Off {
+        return;
+    }
+    let found = discover::find(client, config, &zone_clients(client, config)).await;
+    match mode {
+        DiscoverMode::Adopt => discover::adopt(config, &found),
+        _ => discover::propose(&found),
+    }
+}
 
-                if let Err(e) = process_subdomain(&porkbun_client, subdomain, &current_ip).await {
-                    error!("Error processing subdomain '{}': {}", subdomain, e);
+/// Polls the remote configuration when it is due and switches to the new settings if they
+/// changed. A file that doesn't produce a valid configuration is rolled back and the current
+/// settings are kept.
+#[cfg(feature = "remote-config")]
+async fn reload_remote_config(client: &reqwest::Client, config: &mut Config, force: bool) {
+    let Some(remote) = remote_config::configured().filter(|remote| remote.due()) else {
+        return;
+    };
+    let previous = remote.applied_vars();
+    match remote.fetch(client).await {
+        Ok(false) => {}
+        Ok(true) => match Config::from_vars(&reload::current()) {
+            Ok(mut new_config) => {
+                if force {
+                    new_config.max_writes_per_cycle = None;
                 }
+                discover_hosts(client, &mut new_config).await;
+                *config = new_config;
+                info!("Switched to the new remote configuration");
+            }
+            Err(e) => {
+                error!("Remote configuration is invalid: {}", e);
+                remote.roll_back(&previous);
             }
+        },
+        Err(e) => warn!("Failed to poll remote configuration: {}", e),
+    }
+}
+
+#[cfg(not(feature = "remote-config"))]
+async fn reload_remote_config(_client: &reqwest::Client, _config: &mut Config, _force: bool) {}
+
+/// The host and address a planned change is for, as given on the command line.
+struct Target<'a> {
+    host: &'a str,
+    ip: &'a str,
+}
+
+/// Adds a planned change for the running daemon to apply at `when`.
+fn schedule_change(
+    zones: &[Zone<'_>],
+    when: &str,
+    Target { host, ip }: Target<'_>,
+    reason: Option<String>,
+) -> errors::Result<()> {
+    let at = planned::parse_when(when)?;
+    let ip = ip
+        .parse::<std::net::IpAddr>()
+        .map_err(|_| errors::DdnsError::Config(format!("'{}' is not an IP address.", ip)))?
+        .to_string();
+    let (zone, subdomain) = zone_for_host(zones, host).ok_or_else(|| {
+        let domains: Vec<&str> = zones.iter().map(|zone| zone.client.domain).collect();
+        errors::DdnsError::Config(format!(
+            "{} is not in a configured zone ({}).",
+            host,
+            domains.join(", ")
+        ))
+    })?;
+    warn_if_managed(zone, &subdomain, host);
+
+    let change = planned::PlannedChange {
+        at: at.timestamp(),
+        host: host.to_lowercase(),
+        ip,
+        reason,
+        attempts: 0,
+        failed_at: None,
+    };
+    info!(
+        "Scheduled {} -> {} at {}",
+        change.host,
+        change.ip,
+        change.local_time()
+    );
+    planned::add(change)
+}
+
+/// `zones` with their writes journaled with `reason`, for manual changes given a `--reason`.
+fn zones_because<'a>(zones: Vec<Zone<'a>>, reason: Option<&'a str>) -> Vec<Zone<'a>> {
+    zones
+        .into_iter()
+        .map(|zone| Zone {
+            client: zone.client.with_reason(reason),
+            ..zone
+        })
+        .collect()
+}
+
+/// The primary zone, the mirror zone if configured, and any extra zones from
+/// `PORKBUN_DOMAINS`, each with the subdomains managed in it.
+fn zone_clients<'a>(client: &'a reqwest::Client, config: &'a Config) -> Vec<Zone<'a>> {
+    let new_client = |api_key: &'a str, secret_api_key: &'a str, domain: &'a str| {
+        let client = PorkbunClient::new(client, api_key, secret_api_key, domain)
+            .with_parsing(config.api_parsing, &config.api_capture_file)
+            .with_ttl(config.ttl)
+            .with_host_ttls(&config.host_ttls)
+            .with_held_ttls(ttl_change::lowered_hosts())
+            .with_update_method(config.update_method)
+            .with_retry(config.retry);
+        let client = match &config.journal_file {
+            Some(path) => client.with_journal(path),
+            None => client,
+        };
+        match simulate::base_url() {
+            Some(base_url) => client.with_base_url(base_url),
+            None => client,
         }
+    };
+
+    let mut zones = vec![Zone {
+        client: new_client(&config.api_key, &config.secret_api_key, &config.domain),
+        subdomains: &config.subdomains,
+        mirror: false,
+    }];
+    if let Some(mirror) = &config.mirror {
+        zones.push(Zone {
+            client: new_client(&mirror.api_key, &mirror.secret_api_key, &mirror.domain),
+            subdomains: &config.subdomains,
+            mirror: true,
+        });
+    }
+    for extra in &config.extra_zones {
+        zones.push(Zone {
+            client: new_client(&config.api_key, &config.secret_api_key, &extra.domain),
+            subdomains: &extra.subdomains,
+            mirror: false,
+        });
+    }
+    zones
+}
+
+/// Finds the zone a fully qualified host belongs to, along with its subdomain part.
+fn zone_for_host<'z, 'a>(zones: &'z [Zone<'a>], host: &str) -> Option<(&'z Zone<'a>, String)> {
+    let host = host.trim_end_matches('.').to_lowercase();
+    zones.iter().find_map(|zone| {
+        let domain = zone.client.domain.to_lowercase();
+        if host == domain {
+            Some((zone, String::new()))
+        } else {
+            host.strip_suffix(&format!(".{}", domain))
+                .map(|subdomain| (zone, subdomain.to_string()))
+        }
+    })
+}
+
+fn warn_if_managed(zone: &Zone<'_>, subdomain: &str, host: &str) {
+    if zone
+        .subdomains
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(subdomain))
+    {
+        warn!(
+            "{} is also a managed host, so the next check cycle will set it back to the detected IP. Remove it from the list before the planned change.",
+            host
+        );
+    }
+}
+
+/// Applies every planned change that is due, running hooks and verification as for a
+/// regular update. A `one_shot` run waits for its verifications as `run_cycle` does.
+async fn run_planned_changes(
+    client: &reqwest::Client,
+    config: &Config,
+    one_shot: bool,
+) -> CycleSummary {
+    let mut summary = CycleSummary {
+        dry_run: config.dry_run,
+        tenant: config.tenant.clone(),
+        ..CycleSummary::default()
+    };
+    let changes = match planned::due() {
+        Ok(changes) => changes,
         Err(e) => {
-            error!("Error getting current public IPv4 address: {}", e);
+            error!("Error reading planned changes: {}", e);
+            summary.record_failure("scheduled changes", e);
+            return summary;
+        }
+    };
+
+    let zones = zone_clients(client, config);
+    let budget = WriteBudget::new(config.max_writes_per_cycle);
+    journal::start_batch();
+    for change in changes {
+        info!(
+            "Applying planned change: {} -> {} (scheduled for {})",
+            change.host,
+            change.ip,
+            change.local_time()
+        );
+        let Some((zone, subdomain)) = zone_for_host(&zones, &change.host) else {
+            error!(
+                "Planned change for {} skipped: not in a configured zone",
+                change.host
+            );
+            summary.record_failure(&change.host, "not in a configured zone");
+            settle_planned(config, &change, false);
+            continue;
+        };
+        warn_if_managed(zone, &subdomain, &change.host);
+
+        explain::note(format!(
+            "Scheduled change of {} to {} is due",
+            change.host, change.ip
+        ));
+        if let Some(reason) = &change.reason {
+            info!("Reason for the change to {}: {}", change.host, reason);
+        }
+        let record_type = RecordType::for_ip(&change.ip);
+        let porkbun_client = zone.client.clone().with_reason(change.reason.as_deref());
+        let outcome = supervisor::catch_panic(process_subdomain(
+            client,
+            config,
+            &budget,
+            &porkbun_client,
+            Existing::Lookup,
+            &subdomain,
+            Wanted {
+                record_type,
+                ip: &change.ip,
+                source: None,
+                cached_ip: None,
+            },
+        ))
+        .await;
+        let succeeded = matches!(outcome, Ok(Ok(_)));
+        match outcome {
+            Ok(Ok(Synced {
+                replaced_ttl: None,
+                retimed,
+                ..
+            })) => {
+                if retimed {
+                    summary.updated.push(change.host.clone());
+                } else {
+                    summary.unchanged.push(change.host.clone());
+                }
+            }
+            Ok(Ok(Synced {
+                replaced_ttl: Some(old_ttl),
+                old_ip,
+                ..
+            })) => {
+                if !config.dry_run {
+                    info!("Planned change for {} applied", change.host);
+                    // The next cycle has to look at the host again to notice the change.
+                    ip_state::forget_all();
+                    verify_host(
+                        client,
+                        config,
+                        &mut summary,
+                        &change.host,
+                        &change.ip,
+                        old_ttl,
+                    )
+                    .await;
+                }
+                summary.changes.push(Change {
+                    name: change.host.clone(),
+                    record_type,
+                    old_ip,
+                    new_ip: change.ip.clone(),
+                });
+                summary.updated.push(change.host.clone());
+            }
+            Ok(Err(e)) => {
+                error!("Planned change for {} failed: {}", change.host, e);
+                summary.record_failure(&change.host, e);
+            }
+            Err(panic) => {
+                error!(
+                    "Panic while applying planned change for {}: {}",
+                    change.host, panic
+                );
+                summary.record_failure(&change.host, format!("panic: {}", panic));
+            }
+        }
+        settle_planned(config, &change, succeeded);
+    }
+    if one_shot {
+        add_recheck_failures(&mut summary, true).await;
+    }
+    hooks::run_digest(&config.hooks, &summary).await;
+    hooks::run_failure(&config.hooks, &summary).await;
+    summary
+}
+
+/// Removes a planned change from the plan once it has been applied, or records the failed
+/// attempt so it is tried again later. A dry run leaves the plan alone.
+fn settle_planned(config: &Config, change: &planned::PlannedChange, succeeded: bool) {
+    if config.dry_run {
+        return;
+    }
+    if succeeded {
+        if let Err(e) = planned::complete(change) {
+            error!(
+                "Error removing the applied change for {} from the plan: {}",
+                change.host, e
+            );
+        }
+        return;
+    }
+    match planned::fail(change) {
+        Ok(true) => warn!(
+            "Planned change for {} stays scheduled and will be tried again",
+            change.host
+        ),
+        Ok(false) => error!(
+            "Giving up on the planned change for {} after repeated failures; it was removed from the plan",
+            change.host
+        ),
+        Err(e) => error!(
+            "Error recording the failed change for {} in the plan: {}",
+            change.host, e
+        ),
+    }
+}
+
+/// Runs one check cycle, logging rather than propagating any panic so the loop keeps going.
+/// A panic is returned as a summary with a single failure. A `one_shot` run waits for its
+/// verifications after the old TTL, as there is no later cycle to report them.
+async fn run_cycle(client: &reqwest::Client, config: &Config, one_shot: bool) -> CycleSummary {
+    hooks::run_overflow(&config.hooks).await;
+    let mut summary = match supervisor::catch_panic(perform_ddns_update(client, config)).await {
+        Ok(summary) => summary,
+        Err(panic) => {
+            error!(
+                "Panic during check cycle: {} ({} panics caught since start)",
+                panic,
+                supervisor::panic_count()
+            );
+            let mut summary = CycleSummary::default();
+            summary.record_failure("check cycle", format!("panic: {}", panic));
+            summary
+        }
+    };
+    add_recheck_failures(&mut summary, one_shot).await;
+    telemetry::report_if_due(client, config).await;
+    textfile::write(config, &summary);
+    last_success::record(&summary);
+    healthcheck::ping(client, config.healthcheck_url.as_deref(), &summary).await;
+    hooks::run_digest(&config.hooks, &summary).await;
+    hooks::run_failure(&config.hooks, &summary).await;
+    systemd::watchdog();
+    summary
+}
+
+async fn perform_ddns_update(client: &reqwest::Client, config: &Config) -> CycleSummary {
+    journal::start_batch();
+    simulate::start_cycle();
+    let mut summary = CycleSummary {
+        dry_run: config.dry_run,
+        tenant: config.tenant.clone(),
+        ..CycleSummary::default()
+    };
+    let mut addresses = Vec::with_capacity(config.record_types.len());
+    for &record_type in &config.record_types {
+        if config.soft_fail && !soft_fail::due(&SystemClock, record_type) {
+            continue;
+        }
+        let detected_ip = match ip_fetcher::detect(client, &config.ip_detector(), record_type).await
+        {
+            Ok((ip, source)) => {
+                if config.soft_fail {
+                    soft_fail::detected(record_type);
+                }
+                summary.ip_sources.push((record_type, source));
+                ip
+            }
+            // Left out of the summary, so the cycle isn't counted as degraded.
+            Err(e) if config.soft_fail => {
+                soft_fail::failed(&SystemClock, record_type, &e);
+                continue;
+            }
+            Err(e) => {
+                error!(
+                    "Error getting current public {} address, skipping {} records this cycle: {}",
+                    record_type.family(),
+                    record_type,
+                    e
+                );
+                summary
+                    .families
+                    .push((record_type, FamilyStatus::Unavailable));
+                continue;
+            }
+        };
+        let (current_ip, flaps) = settle::settle(
+            client,
+            config.settle_seconds,
+            &config.ip_detector(),
+            record_type,
+            detected_ip,
+        )
+        .await;
+        summary.flaps += flaps;
+        if record_type == RecordType::A {
+            if let Some(router) = &config.router {
+                router::cross_check(client, router, &current_ip).await;
+            }
+        }
+        #[cfg(feature = "geoip")]
+        if !network_allowed(config, &current_ip) {
+            summary
+                .families
+                .push((record_type, FamilyStatus::Refused(current_ip)));
+            continue;
+        }
+        summary
+            .families
+            .push((record_type, FamilyStatus::Synced(current_ip.clone())));
+        addresses.push((record_type, current_ip));
+    }
+    if !addresses.is_empty() {
+        let budget = WriteBudget::new(config.max_writes_per_cycle);
+        let mut known = IpState::load();
+        for zone in &zone_clients(client, config) {
+            if zone.mirror {
+                info!("Mirroring records to secondary zone {}", zone.client.domain);
+            }
+            sync_zone(
+                client,
+                config,
+                &budget,
+                zone,
+                &addresses,
+                &mut known,
+                &mut summary,
+            )
+            .await;
+        }
+    }
+    summary.log();
+    telemetry::record_cycle(&summary);
+    summary
+}
+
+/// Checks the detected address against the expected networks, guarding against a misbehaving
+/// IP echo service. Returns whether the cycle should go ahead.
+#[cfg(feature = "geoip")]
+fn network_allowed(config: &Config, current_ip: &str) -> bool {
+    let (Some(policy), Some(geoip)) = (&config.network_policy, &config.ip_details.geoip) else {
+        return true;
+    };
+    let Ok(addr) = current_ip.parse() else {
+        error!("Detected IP '{}' is not a valid address", current_ip);
+        return false;
+    };
+
+    let info = geoip.lookup(addr);
+    match policy.check(&info) {
+        None => true,
+        Some(reason) => match policy.action {
+            geoip::MismatchAction::Refuse => {
+                error!(
+                    "Refusing to update records: detected IP {} is outside the expected networks ({})",
+                    current_ip, reason
+                );
+                false
+            }
+            geoip::MismatchAction::Warn => {
+                warn!(
+                    "Detected IP {} is outside the expected networks ({}); updating anyway",
+                    current_ip, reason
+                );
+                true
+            }
+        },
+    }
+}
+
+/// Processes every subdomain managed in one Porkbun zone, bringing each record type
+/// in `addresses` in line with its detected address and adding each outcome to `summary`.
+/// Records that `known` says already hold their address are not looked up; when that is
+/// all of them, Porkbun isn't asked at all, aliases included.
+async fn sync_zone(
+    client: &reqwest::Client,
+    config: &Config,
+    budget: &WriteBudget,
+    zone: &Zone<'_>,
+    addresses: &[(RecordType, String)],
+    known: &mut IpState,
+    summary: &mut CycleSummary,
+) {
+    let porkbun_client = &zone.client;
+    let sources: Vec<(RecordType, String)> = summary
+        .ip_sources
+        .iter()
+        .map(|(record_type, source)| (*record_type, source.as_str().to_string()))
+        .collect();
+    let source_of = |record_type: RecordType| {
+        sources
+            .iter()
+            .find(|(t, _)| *t == record_type)
+            .map(|(_, source)| source.as_str())
+    };
+    let mut pending = Vec::new();
+    let mut moved = Vec::new();
+    for subdomain in zone.subdomains {
+        for (record_type, current_ip) in addresses {
+            let host = porkbun_client.full_name(subdomain);
+            let recent = known
+                .recent(&host, *record_type, config.ip_state_max_age_seconds)
+                .filter(|_| !config.dry_run);
+            if let Some((old_ip, ttl)) = recent.filter(|(ip, _)| ip != current_ip) {
+                if porkbun_client.update_method() == UpdateMethod::ByNameType {
+                    // The record still held the previous address when last confirmed, and
+                    // editByNameType needs no ID, so it can be changed without a lookup.
+                    let record = DnsRecord {
+                        id: String::new(),
+                        name: host.clone(),
+                        record_type: record_type.as_str().to_string(),
+                        content: old_ip.to_string(),
+                        ttl,
+                    };
+                    moved.push((subdomain, *record_type, current_ip, record));
+                    continue;
+                }
+            }
+            // An entry whose TTL isn't the configured one is looked up, so the TTL is put right.
+            let drift = recent.and_then(|(_, ttl)| porkbun_client.ttl_drift(ttl, subdomain));
+            let name = record_type.label(&host);
+            match (recent, drift) {
+                (Some((ip, _)), None) if ip == current_ip => {
+                    info!(
+                        "{} was recently confirmed to point to {}; not asking Porkbun",
+                        name, current_ip
+                    );
+                    explain::note(format!(
+                        "{} matches the IP state file; no lookup needed",
+                        name
+                    ));
+                    summary.unchanged.push(name);
+                    continue;
+                }
+                (Some((ip, ttl)), Some(configured)) if ip == current_ip => {
+                    explain::note(format!(
+                        "{} matches the IP state file, but its TTL {} isn't the configured {}",
+                        name,
+                        ttl.unwrap_or_default(),
+                        configured
+                    ));
+                }
+                _ => {}
+            }
+            let cached_ip = recent.map(|(ip, _)| ip.to_string());
+            pending.push((subdomain, *record_type, current_ip, cached_ip));
+        }
+    }
+    for (subdomain, record_type, current_ip, record) in moved {
+        let host = porkbun_client.full_name(subdomain);
+        let name = record_type.label(&host);
+        info!("Processing subdomain: '{}'", name);
+        let cached_ip = record.content.clone();
+        let outcome = supervisor::catch_panic(process_subdomain(
+            client,
+            config,
+            budget,
+            porkbun_client,
+            Existing::Known(record),
+            subdomain,
+            Wanted {
+                record_type,
+                ip: current_ip,
+                source: source_of(record_type),
+                cached_ip: Some(&cached_ip),
+            },
+        ))
+        .await;
+        if !matches!(outcome, Ok(Ok(_))) {
+            // The record may have been changed or removed since; look it up next cycle.
+            known.forget(&host, record_type);
         }
+        record_outcome(
+            client,
+            config,
+            known,
+            summary,
+            Attempt {
+                host: &host,
+                record_type,
+                ip: current_ip,
+            },
+            outcome,
+        )
+        .await;
     }
+    // Aliases are kept in sync below even when every record was settled from the IP state.
+    let records = pending.len();
+    let snapshot = match config.retrieval_strategy {
+        _ if pending.is_empty() => None,
+        RetrievalStrategy::PerRecord => {
+            if records > 1 {
+                explain::note(format!(
+                    "Per-record strategy: the {} records of {} are looked up one by one; PORKBUN_RETRIEVAL_STRATEGY=zone-snapshot would read the zone once",
+                    records, porkbun_client.domain
+                ));
+            }
+            None
+        }
+        RetrievalStrategy::ZoneSnapshot => {
+            let reason = format!(
+                "zone-snapshot strategy: one retrieval covers the {} record(s) of {}",
+                records, porkbun_client.domain
+            );
+            match explain::because(reason, porkbun_client.retrieve_records()).await {
+                Ok(records) => Some(records),
+                Err(e) => {
+                    error!(
+                        "Error retrieving records for zone {}: {}",
+                        porkbun_client.domain, e
+                    );
+                    for (subdomain, record_type, _, _) in &pending {
+                        let name = record_type.label(&porkbun_client.full_name(subdomain));
+                        summary.record_failure(&name, &e);
+                    }
+                    pending.clear();
+                    None
+                }
+            }
+        }
+    };
+
+    for (subdomain, record_type, current_ip, cached_ip) in pending {
+        let host = porkbun_client.full_name(subdomain);
+        let name = record_type.label(&host);
+        info!("Processing subdomain: '{}'", name);
+
+        let existing = match &snapshot {
+            Some(records) => Existing::Snapshot(records),
+            None => Existing::Lookup,
+        };
+        let outcome = supervisor::catch_panic(process_subdomain(
+            client,
+            config,
+            budget,
+            porkbun_client,
+            existing,
+            subdomain,
+            Wanted {
+                record_type,
+                ip: current_ip,
+                source: source_of(record_type),
+                cached_ip: cached_ip.as_deref(),
+            },
+        ))
+        .await;
+        record_outcome(
+            client,
+            config,
+            known,
+            summary,
+            Attempt {
+                host: &host,
+                record_type,
+                ip: current_ip,
+            },
+            outcome,
+        )
+        .await;
+    }
+    aliases::sync(config, budget, zone, snapshot.as_deref(), summary).await;
+}
+
+/// The record a sync was attempted for, and the address it was meant to hold.
+struct Attempt<'a> {
+    host: &'a str,
+    record_type: RecordType,
+    ip: &'a str,
+}
+
+/// Adds the outcome of syncing one record to the summary, remembering what the record now
+/// holds and verifying it when it was changed.
+async fn record_outcome(
+    client: &reqwest::Client,
+    config: &Config,
+    known: &mut IpState,
+    summary: &mut CycleSummary,
+    Attempt {
+        host,
+        record_type,
+        ip: current_ip,
+    }: Attempt<'_>,
+    outcome: Result<errors::Result<Synced>, String>,
+) {
+    let name = record_type.label(host);
+    match outcome {
+        Ok(Ok(synced)) => {
+            if !config.dry_run {
+                known.remember(host, record_type, current_ip, synced.ttl);
+                if let Some(old_ttl) = synced.replaced_ttl {
+                    verify_host(client, config, summary, host, current_ip, old_ttl).await;
+                }
+            }
+            match synced.replaced_ttl {
+                Some(_) => {
+                    summary.changes.push(Change {
+                        name: host.to_string(),
+                        record_type,
+                        old_ip: synced.old_ip,
+                        new_ip: current_ip.to_string(),
+                    });
+                    summary.updated.push(name);
+                }
+                None if synced.retimed => summary.updated.push(name),
+                None => summary.unchanged.push(name),
+            }
+        }
+        Ok(Err(e)) => {
+            error!("Error processing subdomain '{}': {}", name, e);
+            summary.record_failure(&name, e);
+        }
+        Err(panic) => {
+            error!(
+                "Panic while processing subdomain '{}': {} ({} panics caught since start)",
+                name,
+                panic,
+                supervisor::panic_count()
+            );
+            summary.record_failure(&name, format!("panic: {}", panic));
+        }
+    }
+}
+
+/// Checks that a changed record is visible, if verification is enabled, and that the host's
+/// forwarded ports are reachable on the new address, if any are configured. `old_ttl` is how
+/// long resolvers may keep serving the record's previous answer. A failed verification is
+/// added to the summary.
+#[cfg_attr(not(feature = "verify"), allow(unused_variables))]
+async fn verify_host(
+    client: &reqwest::Client,
+    config: &Config,
+    summary: &mut CycleSummary,
+    name: &str,
+    current_ip: &str,
+    old_ttl: u32,
+) {
+    #[cfg(feature = "verify")]
+    if let Some(verify) = &config.verify {
+        if let Some(failure) =
+            verify::verify_record(client, verify, name, current_ip, old_ttl).await
+        {
+            summary.record_failure(name, failure);
+        }
+    }
+    for failure in
+        port_check::after_change(client, config.port_checks.as_ref(), name, current_ip).await
+    {
+        summary.record_failure(name, failure);
+    }
+}
+
+/// Adds the verifications finished in the background since the last cycle that found a record
+/// still not resolving to its new address. With `wait`, for a one-shot run that nothing would
+/// report them after, it waits for the ones still running.
+#[cfg_attr(not(feature = "verify"), allow(unused_variables))]
+async fn add_recheck_failures(summary: &mut CycleSummary, wait: bool) {
+    #[cfg(feature = "verify")]
+    for (name, failure) in verify::recheck_failures(wait).await {
+        summary.record_failure(&name, failure);
+    }
+}
+
+/// Where `process_subdomain` learns what a record holds now.
+enum Existing<'a> {
+    /// Looked up with its own API call.
+    Lookup,
+    /// Picked out of the zone retrieved up front.
+    Snapshot(&'a [DnsRecord]),
+    /// Taken from the IP state file, without an ID; only for updates by name and type.
+    Known(DnsRecord),
+}
+
+/// What `process_subdomain` left a record holding.
+struct Synced {
+    /// The record's TTL, when known.
+    ttl: Option<u32>,
+    /// Set when the record was changed: the TTL of the answer it replaced, which resolvers may
+    /// keep serving until it expires. A resolver that asked before a created record existed may
+    /// hold on to that answer for Porkbun's minimum TTL.
+    replaced_ttl: Option<u32>,
+    /// The address the record held before it was changed; `None` when it was created or left
+    /// alone.
+    old_ip: Option<String>,
+    /// Set when only the record's TTL was changed, to the configured one.
+    retimed: bool,
 }
 
+/// Brings one subdomain's A or AAAA record in line with the current IP, running any configured hooks
+/// around the change and describing the old and new addresses to them. The existing record
+/// comes from `existing`. Every write is taken from `budget`, and the decision behind it is
+/// logged.
 async fn process_subdomain(
+    client: &reqwest::Client,
+    config: &Config,
+    budget: &WriteBudget,
     porkbun_client: &PorkbunClient<'_>,
+    existing: Existing<'_>,
     subdomain: &str,
-    current_ip: &str,
-) -> errors::Result<()> {
+    wanted: Wanted<'_>,
+) -> errors::Result<Synced> {
+    let Wanted {
+        record_type,
+        ip: current_ip,
+        ..
+    } = wanted;
     let domain = porkbun_client.domain; // for logging
-    match porkbun_client.get_a_record(subdomain).await {
+    let name = porkbun_client.full_name(subdomain);
+    let reason = porkbun_client.reason();
+    let label = record_type.label(&name);
+    let remote_from = match existing {
+        Existing::Lookup => "lookup",
+        Existing::Snapshot(_) => "zone-snapshot",
+        Existing::Known(_) => "ip-state",
+    };
+    let decide = |remote: Option<&DnsRecord>| {
+        decision::log(&decision::Decision {
+            host: &name,
+            record_type: record_type.as_str(),
+            detected_ip: current_ip,
+            source: wanted.source.unwrap_or("scheduled"),
+            cached_ip: wanted.cached_ip,
+            remote_ip: remote.map(|r| r.content.as_str()),
+            remote_ttl: remote.and_then(|r| r.ttl),
+            remote_from,
+            ttl: match remote {
+                Some(r) => porkbun_client.update_ttl(r, subdomain),
+                None => porkbun_client.create_ttl(subdomain),
+            },
+            comparison: match remote {
+                Some(r) if r.has_content(current_ip) => "ttl-differs",
+                Some(_) => "differs",
+                None => "missing",
+            },
+            action: if remote.is_some() { "update" } else { "create" },
+            policy: decision::Policy::new(config, reason),
+        })
+    };
+    let existing = match existing {
+        Existing::Snapshot(records) => {
+            explain::note(format!("{}: read from the zone snapshot", label));
+            Ok(porkbun_client.find_record(records, subdomain, record_type))
+        }
+        Existing::Known(record) => {
+            explain::note(format!(
+                "{} held {} according to the IP state file; no lookup needed",
+                label, record.content
+            ));
+            Ok(Some(record))
+        }
+        Existing::Lookup => {
+            let reason = format!("looking up the current record of {}", label);
+            explain::because(reason, porkbun_client.get_record(subdomain, record_type)).await
+        }
+    };
+    match existing {
         Ok(Some(record)) => {
-            // Logic for EXISTING Record (Update if IP has changed)
-            if record.content == current_ip {
+            // Logic for EXISTING Record (Update if IP or configured TTL has changed)
+            if record.has_content(current_ip) {
+                let Some(ttl) = porkbun_client.ttl_drift(record.ttl, subdomain) else {
+                    explain::note(format!(
+                        "{} already points to {}; nothing written",
+                        label, current_ip
+                    ));
+                    info!(
+                        "Current IP ({}) matches existing Porkbun {} record for {}.{}. No update needed.",
+                        current_ip, record_type, subdomain, domain
+                    );
+                    return Ok(Synced {
+                        ttl: record.ttl,
+                        replaced_ttl: None,
+                        old_ip: None,
+                        retimed: false,
+                    });
+                };
+                let old_ttl = record.ttl.unwrap_or_default();
                 info!(
-                    "Current IP ({}) matches existing Porkbun A record for {}.{}. No update needed.",
-                    current_ip, subdomain, domain
+                    "{} points to {} but has TTL {} instead of the configured {}; updating its TTL",
+                    label, current_ip, old_ttl, ttl
                 );
-            } else {
+                let retimed = Synced {
+                    ttl: Some(ttl),
+                    replaced_ttl: None,
+                    old_ip: None,
+                    retimed: true,
+                };
+                decide(Some(&record));
+                if config.dry_run {
+                    info!(
+                        "Dry run: would change the TTL of {} record {} (ID {}) from {} to {}",
+                        record_type, name, record.id, old_ttl, ttl
+                    );
+                    return Ok(retimed);
+                }
+                budget.spend()?;
+                let because = format!(
+                    "{} has TTL {}, changing it to the configured {}",
+                    label, old_ttl, ttl
+                );
+                let update =
+                    porkbun_client.update_record(&record, subdomain, record_type, current_ip);
+                explain::because(because, update).await?;
+                return Ok(retimed);
+            }
+
+            let old_details =
+                ip_details::describe(client, &config.ip_details, &record.content).await;
+            let new_details = ip_details::describe(client, &config.ip_details, current_ip).await;
+            let old_ttl = record.ttl.unwrap_or(porkbun::DEFAULT_TTL);
+            let propagation_by = propagation_by(old_ttl);
+            info!(
+                "IP change detected for {}.{}! Old IP: {}, New IP: {}",
+                subdomain,
+                domain,
+                annotate(&record.content, &old_details),
+                annotate(current_ip, &new_details)
+            );
+            let ctx = HookContext {
+                domain,
+                subdomain,
+                name: &name,
+                record_type,
+                old_ip: Some(&record.content),
+                new_ip: current_ip,
+                old_details: Some(&old_details),
+                new_details: &new_details,
+                reason,
+                probe: None,
+                propagation_by: &propagation_by,
+            };
+            decide(Some(&record));
+            if config.dry_run {
                 info!(
-                    "IP change detected for {}.{}! Old IP: {}, New IP: {}",
-                    subdomain, domain, record.content, current_ip
+                    "Dry run: would update {} record {} (ID {}) from {} to {} with TTL {} (currently {})",
+                    record_type,
+                    name,
+                    record.id,
+                    record.content,
+                    current_ip,
+                    porkbun_client.update_ttl(&record, subdomain),
+                    record.ttl.map_or("unknown".to_string(), |ttl| ttl.to_string())
                 );
-                porkbun_client
-                    .update_a_record(&record.id, subdomain, current_ip)
-                    .await?;
+                return Ok(Synced {
+                    ttl: Some(porkbun_client.update_ttl(&record, subdomain)),
+                    replaced_ttl: Some(old_ttl),
+                    old_ip: Some(record.content),
+                    retimed: false,
+                });
             }
+            hooks::run_pre_update(&config.hooks, &ctx).await?;
+            budget.spend()?;
+            let because = format!(
+                "{} points to {}, moving it to {}",
+                label, record.content, current_ip
+            );
+            let update = porkbun_client.update_record(&record, subdomain, record_type, current_ip);
+            explain::because(because, update).await?;
+            let probe = probe_change(client, config, record_type, current_ip).await;
+            let ctx = HookContext {
+                probe: probe.as_ref(),
+                ..ctx
+            };
+            hooks::run_post_update(&config.hooks, &ctx).await;
+            Ok(Synced {
+                ttl: Some(porkbun_client.update_ttl(&record, subdomain)),
+                replaced_ttl: Some(old_ttl),
+                old_ip: Some(record.content),
+                retimed: false,
+            })
         }
         Ok(None) => {
-            // Logic for NON-EXISTENT Record (Create it)
-            porkbun_client
-                .create_a_record(subdomain, current_ip)
-                .await?;
+            // Logic for NON-EXISTENT Record (Create it, unless disabled)
+            if !config.auto_create {
+                warn!(
+                    "No {} record for {} and PORKBUN_AUTO_CREATE is disabled; not creating it",
+                    record_type, name
+                );
+                return Err(errors::DdnsError::Config(format!(
+                    "no {} record exists and auto-create is disabled",
+                    record_type
+                )));
+            }
+            let new_details = ip_details::describe(client, &config.ip_details, current_ip).await;
+            // There was no answer to replace, but a resolver that asked before may have cached
+            // its absence for up to Porkbun's minimum TTL.
+            let old_ttl = porkbun::MIN_TTL;
+            let propagation_by = propagation_by(old_ttl);
+            let ctx = HookContext {
+                domain,
+                subdomain,
+                name: &name,
+                record_type,
+                old_ip: None,
+                new_ip: current_ip,
+                old_details: None,
+                new_details: &new_details,
+                reason,
+                probe: None,
+                propagation_by: &propagation_by,
+            };
+            decide(None);
+            if config.dry_run {
+                info!(
+                    "Dry run: would create {} record {} pointing to {} with TTL {}",
+                    record_type,
+                    name,
+                    current_ip,
+                    porkbun_client.create_ttl(subdomain)
+                );
+                return Ok(Synced {
+                    ttl: Some(porkbun_client.create_ttl(subdomain)),
+                    replaced_ttl: Some(old_ttl),
+                    old_ip: None,
+                    retimed: false,
+                });
+            }
+            hooks::run_pre_update(&config.hooks, &ctx).await?;
+            budget.spend()?;
+            let because = format!("{} does not exist yet, creating it", label);
+            let create = porkbun_client.create_record(subdomain, record_type, current_ip);
+            explain::because(because, create).await?;
+            let probe = probe_change(client, config, record_type, current_ip).await;
+            let ctx = HookContext {
+                probe: probe.as_ref(),
+                ..ctx
+            };
+            hooks::run_post_update(&config.hooks, &ctx).await;
+            Ok(Synced {
+                ttl: Some(porkbun_client.create_ttl(subdomain)),
+                replaced_ttl: Some(old_ttl),
+                old_ip: None,
+                retimed: false,
+            })
         }
         Err(e) => {
             // Propagate the error up
-            return Err(e);
+            Err(e)
         }
     }
-    Ok(())
+}
+
+/// When resolvers should stop serving an answer cached with `ttl` seconds left, as passed to
+/// hooks.
+fn propagation_by(ttl: u32) -> String {
+    (chrono::Local::now() + chrono::Duration::seconds(i64::from(ttl))).to_rfc3339()
+}
+
+/// Measures latency after a record was moved to `ip`, if a probe is configured, and records a
+/// new measurement in the journal next to the change.
+async fn probe_change(
+    client: &reqwest::Client,
+    config: &Config,
+    record_type: RecordType,
+    ip: &str,
+) -> Option<ProbeResult> {
+    let (result, fresh) =
+        probe::after_change(client, config.probe.as_ref(), record_type, ip).await?;
+    if let Some(path) = config.journal_file.as_deref().filter(|_| fresh) {
+        journal::note_probe(path, record_type.as_str(), ip, &result);
+    }
+    Some(result)
+}
+
+/// Formats an address followed by its details in parentheses, when there are any.
+fn annotate(ip: &str, details: &IpDetails) -> String {
+    let details = details.to_string();
+    if details.is_empty() {
+        ip.to_string()
+    } else {
+        format!("{} ({})", ip, details)
+    }
 }
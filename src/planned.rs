@@ -0,0 +1,292 @@
+// src/planned.rs
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::errors::{DdnsError, Result};
+use crate::integrity;
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDateTime, NaiveTime, TimeZone};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use tokio::time::Duration;
+
+const DEFAULT_PLAN_FILE: &str = "porkbun-planned-changes.json";
+
+/// How often the plan file is re-read while waiting, so changes scheduled from the command
+/// line are picked up by a running daemon.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Seconds before a change that failed to apply is tried again.
+const RETRY_DELAY_SECONDS: i64 = 300;
+
+/// Failed attempts after which a change is given up on and dropped from the plan.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// A one-time change of a host to a fixed IP at a given time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedChange {
+    /// Unix timestamp at which to apply the change.
+    pub at: i64,
+    pub host: String,
+    pub ip: String,
+    /// Why the change was scheduled, from `--reason`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Failed attempts to apply the change so far.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub attempts: u32,
+    /// Unix timestamp of the last failed attempt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_at: Option<i64>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+impl PlannedChange {
+    /// When the change is next due: at its scheduled time, or a while after its last failed
+    /// attempt.
+    fn due_at(&self) -> i64 {
+        match self.failed_at {
+            Some(failed_at) => self.at.max(failed_at + RETRY_DELAY_SECONDS),
+            None => self.at,
+        }
+    }
+
+    /// Whether `other` is this change, as read from the plan file again.
+    fn is(&self, other: &PlannedChange) -> bool {
+        self.at == other.at && self.host == other.host && self.ip == other.ip
+    }
+
+    pub fn local_time(&self) -> String {
+        Local
+            .timestamp_opt(self.at, 0)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M %Z").to_string())
+            .unwrap_or_else(|| self.at.to_string())
+    }
+}
+
+fn plan_path() -> String {
+    env::var("PORKBUN_PLAN_FILE").unwrap_or_else(|_| DEFAULT_PLAN_FILE.to_string())
+}
+
+/// Reads the plan. A plan that fails its integrity check is set aside, leaving nothing
+/// scheduled, rather than applying changes nobody made.
+fn load(path: &str) -> Result<Vec<PlannedChange>> {
+    match integrity::read(path) {
+        Ok(Some(contents)) => serde_json::from_str(&contents)
+            .map_err(|e| DdnsError::Config(format!("Failed to parse plan file '{}': {}", path, e))),
+        Ok(None) | Err(_) => Ok(Vec::new()),
+    }
+}
+
+fn save(path: &str, changes: &[PlannedChange]) -> Result<()> {
+    let contents = serde_json::to_string_pretty(changes)
+        .map_err(|e| DdnsError::Config(format!("Failed to encode plan file: {}", e)))?;
+    fs::write(path, integrity::seal(&contents))
+        .map_err(|e| DdnsError::file("Failed to write plan file", path, e))
+}
+
+/// Parses a local time such as `02:00` (the next occurrence), `2026-11-01 02:00`, or an
+/// RFC 3339 timestamp.
+pub fn parse_when(value: &str) -> Result<DateTime<Local>> {
+    let value = value.trim();
+    let invalid = || {
+        DdnsError::Config(format!(
+            "Invalid time '{}'. Use HH:MM, 'YYYY-MM-DD HH:MM' or an RFC 3339 timestamp.",
+            value
+        ))
+    };
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Local));
+    }
+    let naive = if let Ok(time) = NaiveTime::parse_from_str(value, "%H:%M") {
+        let now = Local::now();
+        let today = now.date_naive().and_time(time);
+        match Local.from_local_datetime(&today).earliest() {
+            Some(at) if at > now => today,
+            _ => today + ChronoDuration::days(1),
+        }
+    } else {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+            .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+            .map_err(|_| invalid())?
+    };
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(invalid)
+}
+
+/// Adds a change to the plan file.
+pub fn add(change: PlannedChange) -> Result<()> {
+    let path = plan_path();
+    let mut changes = load(&path)?;
+    changes.push(change);
+    changes.sort_by_key(|c| c.at);
+    save(&path, &changes)
+}
+
+/// Waits until at least one planned change is due, re-reading the plan file periodically.
+/// Sleeps are capped at the poll interval, so a wall-clock correction is picked up within
+/// one poll, and nothing is due while the wall clock looks unset.
+pub async fn wait_until_due() {
+    wait_until_due_on(&SystemClock, &plan_path()).await
+}
+
+async fn wait_until_due_on(clock: &impl Clock, path: &str) {
+    loop {
+        if !clock::wall_clock_plausible(clock) {
+            clock.sleep(POLL_INTERVAL).await;
+            continue;
+        }
+        let next = match load(path) {
+            Ok(changes) => changes.iter().map(PlannedChange::due_at).min(),
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        };
+        let now = clock.timestamp();
+        match next {
+            Some(at) if at <= now => return,
+            Some(at) => {
+                clock
+                    .sleep(POLL_INTERVAL.min(Duration::from_secs((at - now) as u64)))
+                    .await
+            }
+            None => clock.sleep(POLL_INTERVAL).await,
+        }
+    }
+}
+
+/// Returns every change that is due. They stay in the plan file until `complete` or `fail`
+/// records how applying them went, so a change that can't be applied isn't lost.
+pub fn due() -> Result<Vec<PlannedChange>> {
+    due_on(&SystemClock, &plan_path())
+}
+
+fn due_on(clock: &impl Clock, path: &str) -> Result<Vec<PlannedChange>> {
+    if !clock::wall_clock_plausible(clock) {
+        return Ok(Vec::new());
+    }
+    let now = clock.timestamp();
+    Ok(load(path)?
+        .into_iter()
+        .filter(|c| c.due_at() <= now)
+        .collect())
+}
+
+/// Removes a change from the plan file once it has been applied.
+pub fn complete(change: &PlannedChange) -> Result<()> {
+    let path = plan_path();
+    let mut changes = load(&path)?;
+    changes.retain(|c| !c.is(change));
+    save(&path, &changes)
+}
+
+/// Records a failed attempt at a change, so it is tried again after a delay. After
+/// `MAX_ATTEMPTS` failures the change is dropped from the plan instead. Returns whether it
+/// will be tried again.
+pub fn fail(change: &PlannedChange) -> Result<bool> {
+    fail_on(&SystemClock, &plan_path(), change)
+}
+
+fn fail_on(clock: &impl Clock, path: &str, change: &PlannedChange) -> Result<bool> {
+    let mut changes = load(path)?;
+    let mut retried = false;
+    changes.retain_mut(|c| {
+        if !c.is(change) {
+            return true;
+        }
+        c.attempts += 1;
+        c.failed_at = Some(clock.timestamp());
+        retried = c.attempts < MAX_ATTEMPTS;
+        retried
+    });
+    save(path, &changes)?;
+    Ok(retried)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    fn plan(name: &str, changes: &[PlannedChange]) -> String {
+        let path = env::temp_dir()
+            .join(format!(
+                "porkbun-planned-{}-{}.json",
+                name,
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .into_owned();
+        save(&path, changes).unwrap();
+        path
+    }
+
+    fn change(at: i64) -> PlannedChange {
+        PlannedChange {
+            at,
+            host: "www.example.com".to_string(),
+            ip: "198.51.100.7".to_string(),
+            reason: None,
+            attempts: 0,
+            failed_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_in_polls_until_the_change_is_due() {
+        let clock = MockClock::new();
+        let path = plan("wait", &[change(clock.timestamp() + 75)]);
+        wait_until_due_on(&clock, &path).await;
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(
+            clock.sleeps(),
+            [30, 30, 15].map(Duration::from_secs),
+            "sleeps are capped at the poll interval"
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_changes_wait_for_the_retry_delay_and_are_dropped_in_the_end() {
+        let clock = MockClock::new();
+        let planned = change(clock.timestamp());
+        let path = plan("fail", std::slice::from_ref(&planned));
+
+        assert_eq!(due_on(&clock, &path).unwrap().len(), 1, "due at its time");
+        for attempt in 1..MAX_ATTEMPTS {
+            assert!(
+                fail_on(&clock, &path, &planned).unwrap(),
+                "attempt {}",
+                attempt
+            );
+            assert!(
+                due_on(&clock, &path).unwrap().is_empty(),
+                "not due right after attempt {}",
+                attempt
+            );
+            clock
+                .sleep(Duration::from_secs(RETRY_DELAY_SECONDS as u64))
+                .await;
+            assert_eq!(
+                due_on(&clock, &path).unwrap().len(),
+                1,
+                "due again after attempt {}",
+                attempt
+            );
+        }
+        assert!(!fail_on(&clock, &path, &planned).unwrap());
+        assert!(
+            load(&path).unwrap().is_empty(),
+            "dropped after the last attempt"
+        );
+        let _ = fs::remove_file(&path);
+    }
+}
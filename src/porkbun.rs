@@ -1,12 +1,248 @@
 // src/porkbun.rs
 
+#[cfg(feature = "blocking")]
+use crate::blocking::ThreadClock;
 use crate::errors::{DdnsError, Result};
+use crate::events::{self, DdnsEvent};
+use crate::explain;
+use crate::journal;
+use crate::record::Record;
+use crate::retry::{self, RetryPolicy};
 use log::{error, info, warn};
-use reqwest::Client;
+use reqwest::{Client, StatusCode, Url};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::Write;
+use std::net::IpAddr;
 
-const API_BASE_URL: &str = "https://api.porkbun.com/api/json/v3/dns";
-const DEFAULT_TTL: u32 = 600;
+/// The Porkbun API endpoint used unless another is set with `PorkbunClient::with_base_url`.
+pub const API_BASE_URL: &str = "https://api.porkbun.com/api/json/v3";
+
+/// The TTL written to created and updated records unless another is configured.
+pub const DEFAULT_TTL: u32 = 600;
+
+/// The lowest TTL Porkbun accepts.
+pub const MIN_TTL: u32 = 600;
+
+/// How existing records are looked up each cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetrievalStrategy {
+    /// One `retrieveByNameType` call per managed name.
+    PerRecord,
+    /// One `retrieve` call per zone, with each name looked up in the local snapshot.
+    ZoneSnapshot,
+}
+
+impl RetrievalStrategy {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "per-record" => Ok(RetrievalStrategy::PerRecord),
+            "zone-snapshot" => Ok(RetrievalStrategy::ZoneSnapshot),
+            other => Err(DdnsError::Config(format!(
+                "Unknown retrieval strategy '{}'. Expected one of: per-record, zone-snapshot.",
+                other
+            ))),
+        }
+    }
+}
+
+/// How `update_record` writes a changed address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateMethod {
+    /// `editByNameType`, which needs no record ID, so a record whose previous address is
+    /// already known can be changed without looking it up first. It changes every record of
+    /// the name and type.
+    ByNameType,
+    /// `edit` with the record's ID, which changes only that record.
+    ById,
+}
+
+impl UpdateMethod {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "by-name-type" => Ok(UpdateMethod::ByNameType),
+            "by-id" => Ok(UpdateMethod::ById),
+            other => Err(DdnsError::Config(format!(
+                "Unknown update method '{}'. Expected one of: by-name-type, by-id.",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpdateMethod::ByNameType => "by-name-type",
+            UpdateMethod::ById => "by-id",
+        }
+    }
+}
+
+/// Whether an API key may do something in a zone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Permission {
+    Allowed,
+    /// Porkbun refused, with its reason.
+    Denied(String),
+    /// The probe itself failed, e.g. on a network error, so nothing is known.
+    Unknown(String),
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::Allowed => write!(f, "allowed"),
+            Permission::Denied(reason) => write!(f, "DENIED ({})", reason),
+            Permission::Unknown(e) => write!(f, "unknown ({})", e),
+        }
+    }
+}
+
+/// What an API key may do in a zone, as found by `PorkbunClient::probe_access`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneAccess {
+    pub read: Permission,
+    pub write: Permission,
+}
+
+/// The error codes with which Porkbun refuses the key or the domain, as opposed to the request
+/// itself.
+const ACCESS_ERROR_CODES: &[&str] = &[
+    "INVALID_API_KEY",
+    "INVALID_SECRET_API_KEY",
+    "API_ACCESS_DISABLED",
+    "DOMAIN_NOT_OPTED_IN",
+    "UNAUTHORIZED",
+];
+
+/// The address record types this client manages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RecordType {
+    /// An IPv4 address record.
+    A,
+    /// An IPv6 address record.
+    Aaaa,
+}
+
+impl RecordType {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "A" => Ok(RecordType::A),
+            "AAAA" => Ok(RecordType::Aaaa),
+            other => Err(DdnsError::Config(format!(
+                "Unknown record type '{}'. Expected one of: A, AAAA.",
+                other
+            ))),
+        }
+    }
+
+    /// The record type for an address, so an IPv6 address is written to an AAAA record.
+    pub fn for_ip(ip: &str) -> Self {
+        if ip.contains(':') {
+            RecordType::Aaaa
+        } else {
+            RecordType::A
+        }
+    }
+
+    /// The address family this record type holds.
+    pub fn family(self) -> &'static str {
+        match self {
+            RecordType::A => "IPv4",
+            RecordType::Aaaa => "IPv6",
+        }
+    }
+
+    /// Labels a name in logs and summaries. A records keep the bare name, as they always have.
+    pub fn label(self, name: &str) -> String {
+        match self {
+            RecordType::A => name.to_string(),
+            RecordType::Aaaa => format!("{} (AAAA)", name),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How responses that don't match the expected shape are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParsingMode {
+    /// Fail the call, so API changes are noticed straight away.
+    Strict,
+    /// Log a warning, capture the raw body and carry on with whatever could be parsed.
+    Lenient,
+}
+
+impl ParsingMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "strict" => Ok(ParsingMode::Strict),
+            "lenient" => Ok(ParsingMode::Lenient),
+            other => Err(DdnsError::Config(format!(
+                "Unknown API parsing mode '{}'. Expected one of: strict, lenient.",
+                other
+            ))),
+        }
+    }
+}
+
+// Fields the API is known to return. Anything else counts as an unexpected shape.
+const RETRIEVE_FIELDS: &[&str] = &["status", "code", "message", "records", "cloudflare"];
+const API_RESPONSE_FIELDS: &[&str] = &["status", "code", "message", "id"];
+const PING_FIELDS: &[&str] = &["status", "code", "message", "yourIp", "xForwardedFor"];
+const RECORD_FIELDS: &[&str] = &["id", "name", "type", "content", "ttl", "prio", "notes"];
+
+// Lists the ways a response body differs from what the API is documented to return.
+fn shape_problems(value: &Value, fields: &[&str]) -> Vec<String> {
+    let Some(object) = value.as_object() else {
+        return vec!["response is not a JSON object".to_string()];
+    };
+
+    let mut problems: Vec<String> = object
+        .keys()
+        .filter(|key| !fields.contains(&key.as_str()))
+        .map(|key| format!("unexpected field '{}'", key))
+        .collect();
+    if !object.get("status").is_some_and(Value::is_string) {
+        problems.push("missing 'status'".to_string());
+    }
+
+    match object.get("records") {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(records)) => {
+            for (index, record) in records.iter().enumerate() {
+                if let Some(record) = record.as_object() {
+                    problems.extend(
+                        record
+                            .keys()
+                            .filter(|key| !RECORD_FIELDS.contains(&key.as_str()))
+                            .map(|key| format!("unexpected field '{}' in record {}", key, index)),
+                    );
+                }
+                if let Err(e) = serde_json::from_value::<DnsRecord>(record.clone()) {
+                    problems.push(format!("record {} is unparseable: {}", index, e));
+                }
+            }
+        }
+        Some(_) => problems.push("'records' is not an array".to_string()),
+    }
+    problems
+}
 
 // Helper function to deserialize a field that might be an integer or a string into an Option<String>
 fn optional_string_from_int_or_string<'de, D>(
@@ -29,7 +265,52 @@ where
     }
 }
 
-// Structs for Porkbun API request and response bodies.
+// Helper function to deserialize a TTL that Porkbun returns as a string (e.g. "600") or a number.
+// Unparseable values become `None` so a single odd record doesn't fail the whole response.
+fn optional_ttl<'de, D>(deserializer: D) -> std::result::Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(
+        optional_string_from_int_or_string(deserializer)?.and_then(|ttl| {
+            let parsed = ttl.trim().parse::<u32>().ok();
+            if parsed.is_none() {
+                warn!("Ignoring unparseable TTL '{}' in Porkbun response", ttl);
+            }
+            parsed
+        }),
+    )
+}
+
+// Helper function to deserialize a required ID that might be an integer or a string.
+fn string_from_int_or_string<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(optional_string_from_int_or_string(deserializer)?.unwrap_or_default())
+}
+
+// Helper function to deserialize a list of records that may be missing, null or empty, skipping
+// individual records that don't parse instead of failing the whole response.
+fn lenient_records<'de, D>(deserializer: D) -> std::result::Result<Vec<DnsRecord>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let values = Option::<Vec<serde_json::Value>>::deserialize(deserializer)?.unwrap_or_default();
+    Ok(values
+        .into_iter()
+        .filter_map(|value| match serde_json::from_value::<DnsRecord>(value) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                warn!("Skipping unparseable record in Porkbun response: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+// Structs for Porkbun API request and response bodies. Unknown fields are ignored and
+// missing ones fall back to defaults, so small API changes don't break every cycle.
 
 #[derive(Serialize)]
 struct AuthPayload<'a> {
@@ -46,6 +327,18 @@ struct UpdateRecordPayload<'a> {
     record_type: &'a str,
     content: &'a str,
     ttl: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prio: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notes: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct EditByNameTypePayload<'a> {
+    #[serde(flatten)]
+    auth: AuthPayload<'a>,
+    content: &'a str,
+    ttl: u32,
 }
 
 #[derive(Serialize)]
@@ -57,40 +350,125 @@ struct CreateRecordPayload<'a> {
     record_type: &'a str,
     content: &'a str,
     ttl: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prio: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notes: Option<&'a str>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct DnsRecord {
-    #[serde(rename = "type")]
+    #[serde(rename = "type", alias = "record_type", alias = "recordType")]
     pub record_type: String,
     pub name: String,
+    #[serde(default)]
     pub content: String,
-    #[allow(dead_code)]
-    ttl: String,
+    #[serde(default, deserialize_with = "optional_ttl")]
+    pub ttl: Option<u32>,
+    #[serde(default, deserialize_with = "string_from_int_or_string")]
     pub id: String,
 }
 
+impl DnsRecord {
+    /// Whether the record already holds `content`, compared in normalized form so equivalent
+    /// spellings don't trigger an edit every cycle.
+    pub fn has_content(&self, content: &str) -> bool {
+        normalize_content(&self.record_type, &self.content)
+            == normalize_content(&self.record_type, content)
+    }
+}
+
+/// Normalizes record content for comparison. Addresses are parsed, so `2001:0db8:0:0::1` and
+/// `2001:db8::1` are equal. For record types that point at a hostname (optionally after a
+/// priority, weight and port), the hostname is lower-cased and any trailing dot dropped.
+pub fn normalize_content(record_type: &str, content: &str) -> String {
+    let content = content.trim();
+    match record_type.to_ascii_uppercase().as_str() {
+        "A" | "AAAA" => content
+            .parse::<IpAddr>()
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|_| content.to_string()),
+        "CNAME" | "ALIAS" | "NS" | "MX" | "SRV" => {
+            let mut fields: Vec<&str> = content.split_whitespace().collect();
+            let target = fields
+                .pop()
+                .map(|target| target.trim_end_matches('.').to_ascii_lowercase())
+                .unwrap_or_default();
+            fields.push(&target);
+            fields.join(" ")
+        }
+        _ => content.to_string(),
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct RetrieveRecordsResponse {
+    #[serde(default)]
     status: String,
-    records: Option<Vec<DnsRecord>>,
+    #[serde(default, deserialize_with = "lenient_records")]
+    records: Vec<DnsRecord>,
+    code: Option<String>,
     message: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ApiResponse {
+    #[serde(default)]
     status: String,
+    code: Option<String>,
     message: Option<String>,
     #[serde(default, deserialize_with = "optional_string_from_int_or_string")]
     id: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct PingResponse {
+    #[serde(default)]
+    status: String,
+    code: Option<String>,
+    message: Option<String>,
+    #[serde(rename = "yourIp")]
+    your_ip: Option<String>,
+}
+
+fn is_success(status: &str) -> bool {
+    status.trim().eq_ignore_ascii_case("SUCCESS")
+}
+
+/// A zone's client together with the subdomains managed in it.
+pub struct Zone<'a> {
+    pub client: PorkbunClient<'a>,
+    pub subdomains: &'a [String],
+    /// Whether this zone mirrors the primary zone's records.
+    pub mirror: bool,
+}
+
+/// The HTTP client requests are sent with.
+#[derive(Clone, Copy)]
+enum Http<'a> {
+    Async(&'a Client),
+    /// Sends each request on the calling thread, for `blocking::PorkbunClient`.
+    #[cfg(feature = "blocking")]
+    Blocking(&'a reqwest::blocking::Client),
+}
+
 /// A client for interacting with the Porkbun API.
+#[derive(Clone)]
 pub struct PorkbunClient<'a> {
-    client: &'a Client,
+    client: Http<'a>,
+    base_url: &'a str,
     api_key: &'a str,
     secret_api_key: &'a str,
     pub domain: &'a str,
+    parsing: ParsingMode,
+    capture_file: Option<&'a str>,
+    ttl: Option<u32>,
+    host_ttls: Option<&'a HashMap<String, u32>>,
+    held_ttls: HashSet<String>,
+    update_method: UpdateMethod,
+    retry: RetryPolicy,
+    journal: Option<&'a str>,
+    reason: Option<&'a str>,
 }
 
 impl<'a> PorkbunClient<'a> {
@@ -100,15 +478,146 @@ impl<'a> PorkbunClient<'a> {
         secret_api_key: &'a str,
         domain: &'a str,
     ) -> Self {
-        // No change here, this is just context
+        Self::with_http(Http::Async(client), api_key, secret_api_key, domain)
+    }
+
+    /// A client whose requests block the calling thread, for `blocking::PorkbunClient`.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn new_blocking(
+        client: &'a reqwest::blocking::Client,
+        api_key: &'a str,
+        secret_api_key: &'a str,
+        domain: &'a str,
+    ) -> Self {
+        Self::with_http(Http::Blocking(client), api_key, secret_api_key, domain)
+    }
+
+    fn with_http(
+        client: Http<'a>,
+        api_key: &'a str,
+        secret_api_key: &'a str,
+        domain: &'a str,
+    ) -> Self {
         Self {
             client,
+            base_url: API_BASE_URL,
             api_key,
             secret_api_key,
             domain,
+            parsing: ParsingMode::Lenient,
+            capture_file: None,
+            ttl: None,
+            host_ttls: None,
+            held_ttls: HashSet::new(),
+            update_method: UpdateMethod::ById,
+            retry: RetryPolicy::default(),
+            journal: None,
+            reason: None,
         }
     }
 
+    /// Sends requests to another API endpoint, such as a mock server in tests.
+    pub fn with_base_url(mut self, base_url: &'a str) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// Records every write in the journal at `path` before it is sent, so it can be reviewed or
+    /// undone later. Writes are not journaled by default.
+    pub fn with_journal(mut self, path: &'a str) -> Self {
+        self.journal = Some(path);
+        self
+    }
+
+    /// Records `reason` in the journal with every write, for manual changes given a
+    /// `--reason`.
+    pub fn with_reason(mut self, reason: Option<&'a str>) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    /// Why this client's writes are being made, if it was given a reason.
+    pub fn reason(&self) -> Option<&'a str> {
+        self.reason
+    }
+
+    /// Sets how failed requests are retried; the default is `RetryPolicy::default()`.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets the TTL written to created and updated records. Without one, updated records
+    /// keep their existing TTL and created records get `DEFAULT_TTL`.
+    pub fn with_ttl(mut self, ttl: Option<u32>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets TTLs for individual hosts, keyed by lowercase full hostname (`vpn.example.com`, or
+    /// `example.com` for the root domain), that replace the one from `with_ttl`.
+    pub fn with_host_ttls(mut self, host_ttls: &'a HashMap<String, u32>) -> Self {
+        self.host_ttls = Some(host_ttls);
+        self
+    }
+
+    /// Holds the TTLs of these hosts, given by lowercase full hostname, at whatever they are
+    /// now, such as while `pre-change` has them lowered: the configured TTL is neither written
+    /// to them nor counted as drift.
+    pub fn with_held_ttls(mut self, hosts: HashSet<String>) -> Self {
+        self.held_ttls = hosts;
+        self
+    }
+
+    /// Sets how `update_record` writes a changed address; the default is `UpdateMethod::ById`.
+    pub fn with_update_method(mut self, update_method: UpdateMethod) -> Self {
+        self.update_method = update_method;
+        self
+    }
+
+    pub fn update_method(&self) -> UpdateMethod {
+        self.update_method
+    }
+
+    /// The TTL configured for a host, if any and not held.
+    fn configured_ttl(&self, subdomain: &str) -> Option<u32> {
+        let host = self.full_name(subdomain).to_ascii_lowercase();
+        if self.held_ttls.contains(&host) {
+            return None;
+        }
+        self.host_ttls
+            .and_then(|host_ttls| host_ttls.get(&host))
+            .copied()
+            .or(self.ttl)
+    }
+
+    /// The TTL an update writes to `record`: the configured one, or else the record's own.
+    pub fn update_ttl(&self, record: &DnsRecord, subdomain: &str) -> u32 {
+        self.configured_ttl(subdomain)
+            .or(record.ttl)
+            .unwrap_or(DEFAULT_TTL)
+    }
+
+    /// The configured TTL of a host whose record holds `ttl` instead, or `None` when it
+    /// matches. A record without a known TTL, or a host without a configured one, never
+    /// drifts.
+    pub fn ttl_drift(&self, ttl: Option<u32>, subdomain: &str) -> Option<u32> {
+        let configured = self.configured_ttl(subdomain)?;
+        ttl.filter(|&ttl| ttl != configured).map(|_| configured)
+    }
+
+    /// The TTL a host's records are created with.
+    pub fn create_ttl(&self, subdomain: &str) -> u32 {
+        self.configured_ttl(subdomain).unwrap_or(DEFAULT_TTL)
+    }
+
+    /// Sets how unexpected response shapes are handled, and where lenient mode captures them.
+    pub fn with_parsing(mut self, parsing: ParsingMode, capture_file: &'a str) -> Self {
+        self.parsing = parsing;
+        self.capture_file = Some(capture_file).filter(|path| !path.is_empty());
+        self
+    }
+
     fn auth_payload(&self) -> AuthPayload<'_> {
         AuthPayload {
             apikey: self.api_key,
@@ -116,134 +625,1365 @@ impl<'a> PorkbunClient<'a> {
         }
     }
 
-    pub async fn get_a_record(&self, subdomain: &str) -> Result<Option<DnsRecord>> {
-        let full_name = if subdomain.is_empty() {
+    /// Builds an API URL from path segments, percent-encoding each one so multi-level or
+    /// wildcard subdomains (`office.vpn`, `*`) are passed through intact.
+    fn api_url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = Url::parse(self.base_url)
+            .map_err(|e| DdnsError::Config(format!("Invalid API base URL: {}", e)))?;
+        url.path_segments_mut()
+            .map_err(|_| DdnsError::Config("Invalid API base URL".to_string()))?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    /// Sends a request and returns the response body, retrying transient failures. Requests
+    /// that aren't `repeatable`, like creates, are only retried when they never reached
+    /// Porkbun, so a lost response can't turn into a duplicate write.
+    async fn post<P: Serialize>(&self, url: &Url, payload: &P, repeatable: bool) -> Result<String> {
+        let what = format!("Porkbun request to {}", url.path());
+        // Refusals come back as 4xx with a JSON body, so only server errors and rate limiting
+        // are treated as failed requests.
+        let failed = |status: StatusCode| {
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        };
+        let send = || async {
+            explain::call("porkbun", format!("POST {}", url));
+            match self.client {
+                Http::Async(client) => {
+                    let res = client.post(url.clone()).json(payload).send().await?;
+                    if failed(res.status()) {
+                        res.error_for_status_ref()?;
+                    }
+                    Ok(res.text().await?)
+                }
+                #[cfg(feature = "blocking")]
+                Http::Blocking(client) => {
+                    let res = client.post(url.clone()).json(payload).send()?;
+                    if failed(res.status()) {
+                        res.error_for_status_ref()?;
+                    }
+                    Ok(res.text()?)
+                }
+            }
+        };
+        let retryable = |e: &DdnsError| {
+            if repeatable {
+                e.is_transient()
+            } else {
+                matches!(e, DdnsError::Request(e) if e.is_connect())
+            }
+        };
+        match self.client {
+            Http::Async(_) => retry::retry_if(&self.retry, &what, retryable, send).await,
+            #[cfg(feature = "blocking")]
+            Http::Blocking(_) => {
+                retry::retry_if_on(&ThreadClock, &self.retry, &what, retryable, send).await
+            }
+        }
+    }
+
+    /// Checks a response body against the expected shape before deserializing it. Strict mode
+    /// turns any difference into an error; lenient mode warns, captures the raw body and parses
+    /// what it can.
+    fn decode<T: DeserializeOwned>(&self, url: &Url, body: &str, fields: &[&str]) -> Result<T> {
+        let problems = match serde_json::from_str::<Value>(body) {
+            Ok(value) => shape_problems(&value, fields),
+            Err(e) => vec![format!("invalid JSON: {}", e)],
+        };
+
+        if !problems.is_empty() {
+            let summary = problems.join("; ");
+            if self.parsing == ParsingMode::Strict {
+                error!("Unexpected response from {}: {}", url.path(), summary);
+                return Err(DdnsError::UnexpectedResponse(format!(
+                    "unexpected shape: {}",
+                    summary
+                )));
+            }
+            warn!("Unexpected response from {}: {}", url.path(), summary);
+            self.capture(url, body, &summary);
+        }
+
+        serde_json::from_str(body)
+            .map_err(|e| DdnsError::UnexpectedResponse(format!("unparseable JSON: {}", e)))
+    }
+
+    // Appends a raw response body to the capture file so API changes can be diagnosed later.
+    fn capture(&self, url: &Url, body: &str, summary: &str) {
+        let Some(path) = self.capture_file else {
+            return;
+        };
+        let entry = format!(
+            "--- {} {}: {}\n{}\n",
+            chrono::Local::now().to_rfc3339(),
+            url.path(),
+            summary,
+            body
+        );
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(entry.as_bytes()));
+        match written {
+            Ok(()) => info!("Captured the raw response in {}", path),
+            Err(e) => warn!("Failed to capture the raw response in {}: {}", path, e),
+        }
+    }
+
+    /// Returns the fully qualified name for a subdomain of this client's domain.
+    pub fn full_name(&self, subdomain: &str) -> String {
+        if subdomain.is_empty() {
             self.domain.to_string()
         } else {
             format!("{}.{}", subdomain, self.domain)
-        };
-        info!("Retrieving A record for {} from Porkbun...", full_name);
+        }
+    }
 
-        let url = format!(
-            "{}/retrieveByNameType/{}/A/{}",
-            API_BASE_URL, self.domain, subdomain
+    /// Picks the record of the given type for a subdomain out of a list of records returned by
+    /// the API.
+    pub fn find_record(
+        &self,
+        records: &[DnsRecord],
+        subdomain: &str,
+        record_type: RecordType,
+    ) -> Option<DnsRecord> {
+        let full_name = self.full_name(subdomain);
+        let found = records
+            .iter()
+            .find(|r| {
+                r.record_type.eq_ignore_ascii_case(record_type.as_str())
+                    && r.name.eq_ignore_ascii_case(&full_name)
+            })
+            .cloned();
+
+        if let Some(record) = &found {
+            info!(
+                "Found existing {} record for {}: {} (TTL: {})",
+                record_type,
+                full_name,
+                record.content,
+                record
+                    .ttl
+                    .map(|ttl| ttl.to_string())
+                    .unwrap_or_else(|| "unknown".to_string())
+            );
+        } else {
+            warn!("No {} record found for {}.", record_type, full_name);
+        }
+        found
+    }
+
+    pub async fn get_record(
+        &self,
+        subdomain: &str,
+        record_type: RecordType,
+    ) -> Result<Option<DnsRecord>> {
+        info!(
+            "Retrieving {} record for {} from Porkbun...",
+            record_type,
+            self.full_name(subdomain)
         );
-        let res = self
-            .client
-            .post(url)
-            .json(&self.auth_payload())
-            .send()
+
+        let url = self.api_url(&[
+            "dns",
+            "retrieveByNameType",
+            self.domain,
+            record_type.as_str(),
+            subdomain,
+        ])?;
+        let records = self
+            .retrieve(url, &format!("{} record", record_type))
             .await?;
+        Ok(self.find_record(&records, subdomain, record_type))
+    }
 
-        let response_body: RetrieveRecordsResponse = res
-            .json()
-            .await
-            .map_err(|e| DdnsError::PorkbunApi(format!("Failed to parse JSON response: {}", e)))?;
+    /// Checks the credentials, returning the address Porkbun sees the request coming from.
+    pub async fn ping(&self) -> Result<String> {
+        let url = self.api_url(&["ping"])?;
+        let result = async {
+            let body = self.post(&url, &self.auth_payload(), true).await?;
+            let response_body: PingResponse = self.decode(&url, &body, PING_FIELDS)?;
+            if is_success(&response_body.status) {
+                response_body.your_ip.ok_or_else(|| {
+                    DdnsError::UnexpectedResponse("the ping response holds no IP".to_string())
+                })
+            } else {
+                let message = response_body
+                    .message
+                    .unwrap_or_else(|| "Unknown error".to_string());
+                error!("Porkbun ping failed: {}", message);
+                Err(DdnsError::PorkbunApi {
+                    code: response_body.code,
+                    message,
+                })
+            }
+        }
+        .await;
+        events::emit(match &result {
+            Ok(address) => DdnsEvent::Detected {
+                address: address.clone(),
+            },
+            Err(e) => DdnsEvent::Error {
+                request: "ping".to_string(),
+                message: e.to_string(),
+            },
+        });
+        result
+    }
 
-        if response_body.status == "SUCCESS" {
-            let a_record = response_body.records.and_then(|records| {
-                records
-                    .into_iter()
-                    .find(|r| r.record_type == "A" && r.name == full_name)
-            });
+    /// Finds out whether the key may read and write records in this zone. Reading is tried
+    /// with a lookup of the root domain's A record. Writing is tried with an edit of a record ID
+    /// that can't exist. That is a write request, though one that can't change anything:
+    /// Porkbun checks the key and the domain's API access before the record, so a refusal with
+    /// one of `ACCESS_ERROR_CODES` means writes would be refused, and any other code that they
+    /// would be accepted. A refusal without a code leaves the answer unknown.
+    pub async fn probe_access(&self) -> ZoneAccess {
+        let read = async {
+            let url = self.api_url(&["dns", "retrieveByNameType", self.domain, "A"])?;
+            self.retrieve(url, "A record").await.map(|_| ())
+        };
+        let read = match read.await {
+            Ok(()) => Permission::Allowed,
+            Err(DdnsError::PorkbunApi { message, .. }) => Permission::Denied(message),
+            Err(e) => Permission::Unknown(e.to_string()),
+        };
 
-            if let Some(record) = &a_record {
-                info!(
-                    "Found existing A record for {}: {}",
-                    full_name, record.content
-                );
+        // Sent directly rather than through `send_edit`, whose failure is logged as an error.
+        let write = async {
+            let url = self.api_url(&["dns", "edit", self.domain, "0"])?;
+            let payload = UpdateRecordPayload {
+                auth: self.auth_payload(),
+                name: "",
+                record_type: RecordType::A.as_str(),
+                content: "192.0.2.1",
+                ttl: DEFAULT_TTL,
+                prio: None,
+                notes: None,
+            };
+            let body = self.post(&url, &payload, true).await?;
+            let response_body: ApiResponse = self.decode(&url, &body, API_RESPONSE_FIELDS)?;
+            if is_success(&response_body.status) {
+                Ok(())
             } else {
-                warn!("No A record found for {}.", full_name);
+                Err(DdnsError::PorkbunApi {
+                    code: response_body.code,
+                    message: response_body
+                        .message
+                        .unwrap_or_else(|| "Unknown error".to_string()),
+                })
             }
-            Ok(a_record)
-        } else {
-            let message = response_body
-                .message
-                .unwrap_or_else(|| "Unknown error".to_string());
-            error!("Failed to retrieve A record from Porkbun: {}", message);
-            Err(DdnsError::PorkbunApi(message))
+        };
+        let write = match write.await {
+            Ok(()) => Permission::Allowed,
+            Err(DdnsError::PorkbunApi {
+                code: Some(code),
+                message,
+            }) => {
+                if ACCESS_ERROR_CODES.contains(&code.as_str()) {
+                    Permission::Denied(message)
+                } else {
+                    Permission::Allowed
+                }
+            }
+            Err(DdnsError::PorkbunApi {
+                code: None,
+                message,
+            }) => Permission::Unknown(format!("refused without an error code: {}", message)),
+            Err(e) => Permission::Unknown(e.to_string()),
+        };
+
+        ZoneAccess { read, write }
+    }
+
+    /// Retrieves every record in the zone with a single API call.
+    pub async fn retrieve_records(&self) -> Result<Vec<DnsRecord>> {
+        info!("Retrieving all records for {} from Porkbun...", self.domain);
+
+        let url = self.api_url(&["dns", "retrieve", self.domain])?;
+        let records = self.retrieve(url, "records").await?;
+        info!("Retrieved {} records for {}", records.len(), self.domain);
+        Ok(records)
+    }
+
+    async fn retrieve(&self, url: Url, what: &str) -> Result<Vec<DnsRecord>> {
+        let result = async {
+            let body = self.post(&url, &self.auth_payload(), true).await?;
+            let response_body: RetrieveRecordsResponse =
+                self.decode(&url, &body, RETRIEVE_FIELDS)?;
+            if is_success(&response_body.status) {
+                Ok(response_body.records)
+            } else {
+                let message = response_body
+                    .message
+                    .unwrap_or_else(|| "Unknown error".to_string());
+                error!("Failed to retrieve {} from Porkbun: {}", what, message);
+                Err(DdnsError::PorkbunApi {
+                    code: response_body.code,
+                    message,
+                })
+            }
+        }
+        .await;
+        if let Err(e) = &result {
+            events::emit(DdnsEvent::Error {
+                request: format!("retrieve {} of {}", what, self.domain),
+                message: e.to_string(),
+            });
         }
+        result
     }
 
-    pub async fn update_a_record(
+    pub async fn update_record(
         &self,
-        record_id: &str,
+        record: &DnsRecord,
         subdomain: &str,
+        record_type: RecordType,
         new_ip: &str,
     ) -> Result<()> {
         info!(
-            "Updating A record for {}.{} to new IP: {}",
-            subdomain, self.domain, new_ip
+            "Updating {} record for {}.{} to new IP: {}",
+            record_type, subdomain, self.domain, new_ip
+        );
+
+        let ttl = self.update_ttl(record, subdomain);
+        match self.update_method {
+            UpdateMethod::ByNameType => {
+                self.edit_by_name_type(record, subdomain, record_type, new_ip, ttl)
+                    .await?
+            }
+            UpdateMethod::ById => self.edit_record(record, subdomain, new_ip, ttl).await?,
+        }
+        info!(
+            "Successfully updated {} record for {}.{} to {}",
+            record_type, subdomain, self.domain, new_ip
         );
+        Ok(())
+    }
 
+    /// Changes the TTL of an existing record, keeping its content.
+    pub async fn set_record_ttl(
+        &self,
+        record: &DnsRecord,
+        subdomain: &str,
+        ttl: u32,
+    ) -> Result<()> {
+        info!(
+            "Setting TTL of {} record for {} to {}",
+            record.record_type, record.name, ttl
+        );
+
+        self.edit_record(record, subdomain, &record.content, ttl)
+            .await?;
+        info!("Successfully set TTL of {} to {}", record.name, ttl);
+        Ok(())
+    }
+
+    /// Sets both the content and TTL of an existing record, journaling the write.
+    pub async fn edit_record(
+        &self,
+        record: &DnsRecord,
+        subdomain: &str,
+        content: &str,
+        ttl: u32,
+    ) -> Result<()> {
         let payload = UpdateRecordPayload {
             auth: self.auth_payload(),
             name: subdomain,
-            record_type: "A",
-            content: new_ip,
-            ttl: DEFAULT_TTL,
+            record_type: &record.record_type,
+            content,
+            ttl,
+            prio: None,
+            notes: None,
+        };
+        self.write(
+            self.operation(
+                journal::Action::Edit,
+                subdomain,
+                &record.record_type,
+                Some(record),
+                content,
+                ttl,
+            ),
+            self.send_edit(&record.id, &payload),
+        )
+        .await
+    }
+
+    /// Sets the content and TTL of every record of a name and type, journaling the write.
+    /// `record` describes what the record held before and need not carry an ID.
+    async fn edit_by_name_type(
+        &self,
+        record: &DnsRecord,
+        subdomain: &str,
+        record_type: RecordType,
+        content: &str,
+        ttl: u32,
+    ) -> Result<()> {
+        let payload = EditByNameTypePayload {
+            auth: self.auth_payload(),
+            content,
+            ttl,
         };
+        self.write(
+            self.operation(
+                journal::Action::Edit,
+                subdomain,
+                record_type.as_str(),
+                Some(record),
+                content,
+                ttl,
+            ),
+            self.send_edit_by_name_type(subdomain, record_type, &payload),
+        )
+        .await
+    }
 
-        let url = format!("{}/edit/{}/{}", API_BASE_URL, self.domain, record_id);
-        let res = self.client.post(url).json(&payload).send().await?;
+    /// Deletes an existing record, journaling the write.
+    pub async fn delete_record(&self, record: &DnsRecord, subdomain: &str) -> Result<()> {
+        warn!(
+            "Deleting {} record for {}: {}",
+            record.record_type, record.name, record.content
+        );
 
-        let response_body: ApiResponse = res
-            .json()
-            .await
-            .map_err(|e| DdnsError::PorkbunApi(format!("Failed to parse JSON response: {}", e)))?;
+        let result = self
+            .write(
+                self.operation(
+                    journal::Action::Delete,
+                    subdomain,
+                    &record.record_type,
+                    Some(record),
+                    "",
+                    0,
+                ),
+                self.send_delete(&record.id),
+            )
+            .await;
+        result?;
+        info!(
+            "Successfully deleted {} record for {}",
+            record.record_type, record.name
+        );
+        Ok(())
+    }
 
-        if response_body.status == "SUCCESS" {
-            info!(
-                "Successfully updated A record for {}.{} to {}",
-                subdomain, self.domain, new_ip
+    /// Describes a write for the journal before it is made.
+    fn operation(
+        &self,
+        action: journal::Action,
+        subdomain: &str,
+        record_type: &str,
+        existing: Option<&DnsRecord>,
+        new_content: &str,
+        new_ttl: u32,
+    ) -> journal::Operation {
+        journal::Operation {
+            id: 0,
+            at: 0,
+            batch: 0,
+            action,
+            domain: self.domain.to_string(),
+            subdomain: subdomain.to_string(),
+            record_type: record_type.to_string(),
+            record_id: existing
+                .map(|record| record.id.clone())
+                .filter(|id| !id.is_empty()),
+            old_content: existing.map(|record| record.content.clone()),
+            old_ttl: existing.and_then(|record| record.ttl),
+            new_content: new_content.to_string(),
+            new_ttl,
+            reason: self.reason.map(str::to_string),
+        }
+    }
+
+    /// Makes a write described by `operation`, journaling it around `send` and telling event
+    /// subscribers how it went.
+    async fn write<T>(
+        &self,
+        operation: journal::Operation,
+        send: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let id = journal::begin(self.journal, operation.clone())?;
+        let result = send.await;
+        journal::finish(self.journal, id, &result);
+        events::written(&operation, &result);
+        result
+    }
+
+    async fn send_delete(&self, record_id: &str) -> Result<()> {
+        let url = self.api_url(&["dns", "delete", self.domain, record_id])?;
+        let body = self.post(&url, &self.auth_payload(), false).await?;
+        let response_body: ApiResponse = self.decode(&url, &body, API_RESPONSE_FIELDS)?;
+
+        if is_success(&response_body.status) {
+            Ok(())
+        } else {
+            let message = response_body
+                .message
+                .unwrap_or_else(|| "Unknown error".to_string());
+            error!("Failed to delete record on Porkbun: {}", message);
+            Err(DdnsError::PorkbunApi {
+                code: response_body.code,
+                message,
+            })
+        }
+    }
+
+    /// Sends a create request, returning the new record's ID if Porkbun reported one.
+    async fn send_create(&self, payload: &CreateRecordPayload<'_>) -> Result<Option<String>> {
+        let url = self.api_url(&["dns", "create", self.domain])?;
+        let body = self.post(&url, payload, false).await?;
+        let response_body: ApiResponse = self.decode(&url, &body, API_RESPONSE_FIELDS)?;
+
+        if is_success(&response_body.status) {
+            Ok(response_body.id)
+        } else {
+            let message = response_body
+                .message
+                .unwrap_or_else(|| "Unknown error".to_string());
+            error!(
+                "Failed to create {} record on Porkbun: {}",
+                payload.record_type, message
             );
+            Err(DdnsError::PorkbunApi {
+                code: response_body.code,
+                message,
+            })
+        }
+    }
+
+    async fn send_edit(&self, record_id: &str, payload: &UpdateRecordPayload<'_>) -> Result<()> {
+        let url = self.api_url(&["dns", "edit", self.domain, record_id])?;
+        let body = self.post(&url, payload, true).await?;
+        let response_body: ApiResponse = self.decode(&url, &body, API_RESPONSE_FIELDS)?;
+
+        if is_success(&response_body.status) {
             Ok(())
         } else {
             let message = response_body
                 .message
                 .unwrap_or_else(|| "Unknown error".to_string());
-            error!("Failed to update A record on Porkbun: {}", message);
-            Err(DdnsError::PorkbunApi(message))
+            error!(
+                "Failed to update {} record on Porkbun: {}",
+                payload.record_type, message
+            );
+            Err(DdnsError::PorkbunApi {
+                code: response_body.code,
+                message,
+            })
         }
     }
 
-    pub async fn create_a_record(&self, subdomain: &str, new_ip: &str) -> Result<()> {
+    async fn send_edit_by_name_type(
+        &self,
+        subdomain: &str,
+        record_type: RecordType,
+        payload: &EditByNameTypePayload<'_>,
+    ) -> Result<()> {
+        let url = self.api_url(&[
+            "dns",
+            "editByNameType",
+            self.domain,
+            record_type.as_str(),
+            subdomain,
+        ])?;
+        let body = self.post(&url, payload, true).await?;
+        let response_body: ApiResponse = self.decode(&url, &body, API_RESPONSE_FIELDS)?;
+
+        if is_success(&response_body.status) {
+            Ok(())
+        } else {
+            let message = response_body
+                .message
+                .unwrap_or_else(|| "Unknown error".to_string());
+            error!(
+                "Failed to update {} record on Porkbun: {}",
+                record_type, message
+            );
+            Err(DdnsError::PorkbunApi {
+                code: response_body.code,
+                message,
+            })
+        }
+    }
+
+    pub async fn create_record(
+        &self,
+        subdomain: &str,
+        record_type: RecordType,
+        new_ip: &str,
+    ) -> Result<()> {
         warn!(
-            "Creating new A record for {}.{} with IP: {}",
-            subdomain, self.domain, new_ip
+            "Creating new {} record for {}.{} with IP: {}",
+            record_type, subdomain, self.domain, new_ip
         );
 
+        let ttl = self.create_ttl(subdomain);
         let payload = CreateRecordPayload {
             auth: self.auth_payload(),
             name: subdomain,
-            record_type: "A",
+            record_type: record_type.as_str(),
             content: new_ip,
-            ttl: DEFAULT_TTL,
+            ttl,
+            prio: None,
+            notes: None,
+        };
+
+        let result = self
+            .write(
+                self.operation(
+                    journal::Action::Create,
+                    subdomain,
+                    record_type.as_str(),
+                    None,
+                    new_ip,
+                    ttl,
+                ),
+                self.send_create(&payload),
+            )
+            .await;
+        let record_id = result?;
+        info!(
+            "Successfully created new {} record (ID: {}) for {}.{} to {}",
+            record_type,
+            record_id.unwrap_or_else(|| "N/A".to_string()),
+            subdomain,
+            self.domain,
+            new_ip
+        );
+        Ok(())
+    }
+
+    /// Creates a record built with one of `Record`'s constructors, returning its ID if Porkbun
+    /// reported one. Without a TTL of its own, the record gets the configured one.
+    pub async fn create(&self, record: impl Into<Record>) -> Result<Option<String>> {
+        let record = record.into();
+        let ttl = record
+            .ttl()
+            .unwrap_or_else(|| self.create_ttl(record.name()));
+        let payload = CreateRecordPayload {
+            auth: self.auth_payload(),
+            name: record.name(),
+            record_type: record.record_type(),
+            content: record.content(),
+            ttl,
+            prio: record.prio(),
+            notes: record.notes(),
+        };
+
+        let result = self
+            .write(
+                self.operation(
+                    journal::Action::Create,
+                    record.name(),
+                    record.record_type(),
+                    None,
+                    record.content(),
+                    ttl,
+                ),
+                self.send_create(&payload),
+            )
+            .await;
+        let record_id = result?;
+        info!(
+            "Created {} record for {}: {}",
+            record.record_type(),
+            self.full_name(record.name()),
+            record.content()
+        );
+        Ok(record_id)
+    }
+
+    /// Replaces an existing record with one built with `Record`'s constructors, which may
+    /// change its name and type too. Without a TTL of its own, the record keeps its TTL unless
+    /// one is configured.
+    pub async fn edit(&self, existing: &DnsRecord, record: impl Into<Record>) -> Result<()> {
+        let record = record.into();
+        let ttl = record
+            .ttl()
+            .unwrap_or_else(|| self.update_ttl(existing, record.name()));
+        let payload = UpdateRecordPayload {
+            auth: self.auth_payload(),
+            name: record.name(),
+            record_type: record.record_type(),
+            content: record.content(),
+            ttl,
+            prio: record.prio(),
+            notes: record.notes(),
         };
 
-        let url = format!("{}/create/{}", API_BASE_URL, self.domain);
-        let res = self.client.post(url).json(&payload).send().await?;
+        let result = self
+            .write(
+                self.operation(
+                    journal::Action::Edit,
+                    record.name(),
+                    record.record_type(),
+                    Some(existing),
+                    record.content(),
+                    ttl,
+                ),
+                self.send_edit(&existing.id, &payload),
+            )
+            .await;
+        result?;
+        info!(
+            "Updated {} record for {}: {}",
+            record.record_type(),
+            self.full_name(record.name()),
+            record.content()
+        );
+        Ok(())
+    }
+}
 
-        let response_body: ApiResponse = res
-            .json()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// The path and JSON body of every request a mock server received.
+    type Requests = Arc<Mutex<Vec<(String, Value)>>>;
+
+    /// Serves a canned JSON response per path on a local port, answering anything else with
+    /// a Porkbun-style error. Returns the API base URL to point a client at.
+    async fn mock_server(routes: Vec<(&'static str, &'static str)>) -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/api/json/v3", listener.local_addr().unwrap());
+        let requests = Requests::default();
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let (path, body) = read_request(&mut stream).await;
+                let response = routes
+                    .iter()
+                    .find(|(route, _)| path == format!("/api/json/v3/{}", route))
+                    .map_or(r#"{"status":"ERROR","message":"Unknown route"}"#, |r| r.1);
+                seen.lock().unwrap().push((path, body));
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        (base_url, requests)
+    }
+
+    async fn read_request(stream: &mut TcpStream) -> (String, Value) {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed mid-request");
+            buf.extend_from_slice(&chunk[..n]);
+            let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let head = String::from_utf8_lossy(&buf[..end]).to_string();
+            let length = head
+                .to_ascii_lowercase()
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:").map(str::to_string))
+                .map_or(0, |value| value.trim().parse().unwrap());
+            if buf.len() >= end + 4 + length {
+                let path = head.split_whitespace().nth(1).unwrap().to_string();
+                let body =
+                    serde_json::from_slice(&buf[end + 4..end + 4 + length]).unwrap_or(Value::Null);
+                return (path, body);
+            }
+        }
+    }
+
+    fn client<'a>(http: &'a Client, base_url: &'a str) -> PorkbunClient<'a> {
+        PorkbunClient::new(http, "pk1_test", "sk1_test", "example.com").with_base_url(base_url)
+    }
+
+    const RECORDS: &str = r#"{"status":"SUCCESS","records":[
+        {"id":"101","name":"www.example.com","type":"A","content":"198.51.100.1","ttl":"3600","prio":"0","notes":""},
+        {"id":102,"name":"example.com","type":"AAAA","content":"2001:db8::1","ttl":600,"prio":null,"notes":null}
+    ]}"#;
+
+    #[test]
+    fn tolerates_missing_null_and_odd_fields() {
+        // The ID and TTL of each record that parsed.
+        type Parsed = &'static [(&'static str, Option<u32>)];
+        let cases: [(&str, &str, Parsed); 7] = [
+            ("records missing", r#"{"status":"SUCCESS"}"#, &[]),
+            (
+                "records null",
+                r#"{"status":"SUCCESS","records":null}"#,
+                &[],
+            ),
+            ("records empty", r#"{"status":"SUCCESS","records":[]}"#, &[]),
+            (
+                "string and numeric fields",
+                r#"{"status":"SUCCESS","records":[
+                    {"id":"101","name":"www.example.com","type":"A","content":"198.51.100.1","ttl":"600"},
+                    {"id":102,"name":"example.com","type":"A","content":"198.51.100.1","ttl":3600}
+                ]}"#,
+                &[("101", Some(600)), ("102", Some(3600))],
+            ),
+            (
+                "missing, null and unparseable TTLs",
+                r#"{"status":"SUCCESS","records":[
+                    {"id":"1","name":"a.example.com","type":"A","content":"198.51.100.1"},
+                    {"id":"2","name":"b.example.com","type":"A","content":"198.51.100.1","ttl":null},
+                    {"id":"3","name":"c.example.com","type":"A","content":"198.51.100.1","ttl":"soon"}
+                ]}"#,
+                &[("1", None), ("2", None), ("3", None)],
+            ),
+            (
+                "renamed and extra fields",
+                r#"{"status":"SUCCESS","cloudflare":"enabled","records":[
+                    {"id":"7","name":"www.example.com","recordType":"A","content":"198.51.100.1","ttl":"600","proxied":false}
+                ]}"#,
+                &[("7", Some(600))],
+            ),
+            (
+                "unparseable records are skipped",
+                r#"{"status":"SUCCESS","records":[
+                    {"id":"8","type":"A"},
+                    "not a record",
+                    {"id":"9","name":"www.example.com","type":"A"}
+                ]}"#,
+                &[("9", None)],
+            ),
+        ];
+        for (case, body, expected) in cases {
+            let response: RetrieveRecordsResponse = serde_json::from_str(body).unwrap();
+            assert!(is_success(&response.status), "{}", case);
+            let records: Vec<(&str, Option<u32>)> = response
+                .records
+                .iter()
+                .map(|record| (record.id.as_str(), record.ttl))
+                .collect();
+            assert_eq!(records, expected, "{}", case);
+        }
+    }
+
+    #[test]
+    fn lists_shape_problems() {
+        let cases: [(&str, &[&str]); 5] = [
+            (r#"{"status":"SUCCESS","records":[]}"#, &[]),
+            (r#"{"status":"SUCCESS","records":null}"#, &[]),
+            (
+                r#"{"status":"SUCCESS","records":[{"id":"1","name":"a","type":"A","extra":1}]}"#,
+                &["unexpected field 'extra' in record 0"],
+            ),
+            (
+                r#"{"records":{},"paging":1}"#,
+                &[
+                    "unexpected field 'paging'",
+                    "missing 'status'",
+                    "'records' is not an array",
+                ],
+            ),
+            (r#"[]"#, &["response is not a JSON object"]),
+        ];
+        for (body, expected) in cases {
+            let value: Value = serde_json::from_str(body).unwrap();
+            assert_eq!(
+                shape_problems(&value, RETRIEVE_FIELDS),
+                expected,
+                "{}",
+                body
+            );
+        }
+    }
+
+    #[test]
+    fn normalizes_content_for_comparison() {
+        let cases = [
+            ("A", " 203.0.113.7 ", "203.0.113.7"),
+            ("AAAA", "2001:0DB8:0:0::1", "2001:db8::1"),
+            ("aaaa", "2001:db8:0:0:0:0:0:1", "2001:db8::1"),
+            ("A", "not an address", "not an address"),
+            ("CNAME", "Home.Example.com.", "home.example.com"),
+            ("ALIAS", "home.example.com", "home.example.com"),
+            ("MX", "10   Mail.Example.com.", "10 mail.example.com"),
+            (
+                "SRV",
+                "5 0 5060 SIP.example.com.",
+                "5 0 5060 sip.example.com",
+            ),
+            ("CNAME", "", ""),
+            ("TXT", " Keep Case. ", "Keep Case."),
+        ];
+        for (record_type, content, expected) in cases {
+            assert_eq!(
+                normalize_content(record_type, content),
+                expected,
+                "{} {}",
+                record_type,
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn host_ttls_are_keyed_by_full_hostname() {
+        let http = Client::new();
+        let host_ttls = HashMap::from([
+            ("vpn.example.com".to_string(), 900),
+            ("example.com".to_string(), 3600),
+        ]);
+        let zone = |domain| {
+            PorkbunClient::new(&http, "pk1_test", "sk1_test", domain)
+                .with_ttl(Some(1200))
+                .with_host_ttls(&host_ttls)
+        };
+        let cases = [
+            ("example.com", "vpn", 900),
+            ("Example.com", "VPN", 900),
+            ("example.com", "", 3600),
+            ("example.com", "www", 1200),
+            ("other.net", "vpn", 1200),
+            ("other.net", "", 1200),
+        ];
+        for (domain, subdomain, ttl) in cases {
+            assert_eq!(
+                zone(domain).create_ttl(subdomain),
+                ttl,
+                "{} in {}",
+                subdomain,
+                domain
+            );
+        }
+    }
+
+    #[test]
+    fn ttl_drift_compares_with_the_configured_ttl() {
+        let http = Client::new();
+        let host_ttls = HashMap::from([("vpn.example.com".to_string(), 900)]);
+        let configured = PorkbunClient::new(&http, "pk1_test", "sk1_test", "example.com")
+            .with_ttl(Some(600))
+            .with_host_ttls(&host_ttls);
+        let unconfigured = PorkbunClient::new(&http, "pk1_test", "sk1_test", "example.com");
+        let held = configured
+            .clone()
+            .with_held_ttls(HashSet::from(["vpn.example.com".to_string()]));
+        let cases = [
+            (&configured, Some(600), "www", None),
+            (&configured, Some(3600), "www", Some(600)),
+            (&configured, Some(600), "vpn", Some(900)),
+            (&configured, Some(900), "vpn", None),
+            (&configured, None, "www", None),
+            (&unconfigured, Some(3600), "www", None),
+            (&held, Some(600), "vpn", None),
+            (&held, Some(3600), "www", Some(600)),
+        ];
+        for (client, ttl, subdomain, expected) in cases {
+            assert_eq!(
+                client.ttl_drift(ttl, subdomain),
+                expected,
+                "{:?} on {}",
+                ttl,
+                subdomain
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn ping_returns_the_seen_address_and_sends_credentials() {
+        let (base_url, requests) = mock_server(vec![(
+            "ping",
+            r#"{"status":"SUCCESS","yourIp":"203.0.113.9"}"#,
+        )])
+        .await;
+        let http = Client::new();
+
+        let ip = client(&http, &base_url).ping().await.unwrap();
+
+        assert_eq!(ip, "203.0.113.9");
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].0, "/api/json/v3/ping");
+        assert_eq!(requests[0].1["apikey"], "pk1_test");
+        assert_eq!(requests[0].1["secretapikey"], "sk1_test");
+    }
+
+    #[tokio::test]
+    async fn retrieve_records_parses_string_and_numeric_fields() {
+        let (base_url, _) = mock_server(vec![("dns/retrieve/example.com", RECORDS)]).await;
+        let http = Client::new();
+
+        let records = client(&http, &base_url).retrieve_records().await.unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id, "101");
+        assert_eq!(records[0].ttl, Some(3600));
+        assert_eq!(records[1].id, "102");
+        assert_eq!(records[1].record_type, "AAAA");
+        assert!(records[1].has_content("2001:0db8:0:0::1"));
+    }
+
+    #[tokio::test]
+    async fn get_record_looks_up_by_name_and_type() {
+        let (base_url, requests) =
+            mock_server(vec![("dns/retrieveByNameType/example.com/A/www", RECORDS)]).await;
+        let http = Client::new();
+
+        let record = client(&http, &base_url)
+            .get_record("www", RecordType::A)
             .await
-            .map_err(|e| DdnsError::PorkbunApi(format!("Failed to parse JSON response: {}", e)))?;
+            .unwrap()
+            .unwrap();
 
-        if response_body.status == "SUCCESS" {
-            info!(
-                "Successfully created new A record (ID: {}) for {}.{} to {}",
-                response_body.id.unwrap_or_else(|| "N/A".to_string()),
+        assert_eq!(record.content, "198.51.100.1");
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn edit_record_sends_the_new_content_and_ttl() {
+        let (base_url, requests) = mock_server(vec![(
+            "dns/edit/example.com/101",
+            r#"{"status":"SUCCESS"}"#,
+        )])
+        .await;
+        let http = Client::new();
+        let record: DnsRecord = serde_json::from_str(
+            r#"{"id":"101","name":"www.example.com","type":"A","content":"198.51.100.1","ttl":"3600"}"#,
+        )
+        .unwrap();
+
+        client(&http, &base_url)
+            .edit_record(&record, "www", "203.0.113.9", 900)
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let body = &requests[0].1;
+        assert_eq!(body["name"], "www");
+        assert_eq!(body["type"], "A");
+        assert_eq!(body["content"], "203.0.113.9");
+        assert_eq!(body["ttl"], 900);
+    }
+
+    #[test]
+    fn api_url_keeps_each_subdomain_in_one_segment() {
+        let http = Client::new();
+        let cases = [
+            (
+                "www",
+                "/api/json/v3/dns/retrieveByNameType/example.com/A/www",
+            ),
+            (
+                "office.vpn",
+                "/api/json/v3/dns/retrieveByNameType/example.com/A/office.vpn",
+            ),
+            (
+                "a.b.office.vpn",
+                "/api/json/v3/dns/retrieveByNameType/example.com/A/a.b.office.vpn",
+            ),
+            ("", "/api/json/v3/dns/retrieveByNameType/example.com/A/"),
+            (
+                "odd/name?",
+                "/api/json/v3/dns/retrieveByNameType/example.com/A/odd%2Fname%3F",
+            ),
+        ];
+        for (subdomain, expected) in cases {
+            let url = client(&http, "http://127.0.0.1:1/api/json/v3")
+                .api_url(&["dns", "retrieveByNameType", "example.com", "A", subdomain])
+                .unwrap();
+            assert_eq!(url.path(), expected, "{:?}", subdomain);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_record_finds_multi_level_subdomains() {
+        let (base_url, requests) = mock_server(vec![(
+            "dns/retrieveByNameType/example.com/A/office.vpn",
+            r#"{"status":"SUCCESS","records":[
+                {"id":"103","name":"Office.VPN.example.com","type":"A","content":"198.51.100.3","ttl":"600"}
+            ]}"#,
+        )])
+        .await;
+        let http = Client::new();
+
+        let record = client(&http, &base_url)
+            .get_record("office.vpn", RecordType::A)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(record.id, "103");
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn find_record_matches_the_whole_multi_level_name() {
+        let http = Client::new();
+        let porkbun = client(&http, "http://127.0.0.1:1/api/json/v3");
+        let records: Vec<DnsRecord> = serde_json::from_str(
+            r#"[
+                {"id":"1","name":"vpn.example.com","type":"A","content":"198.51.100.1"},
+                {"id":"2","name":"office.vpn.example.com","type":"A","content":"198.51.100.2"},
+                {"id":"3","name":"home.office.vpn.example.com","type":"A","content":"198.51.100.3"},
+                {"id":"4","name":"office.vpn.example.com","type":"AAAA","content":"2001:db8::4"}
+            ]"#,
+        )
+        .unwrap();
+        let cases = [
+            ("vpn", RecordType::A, Some("1")),
+            ("office.vpn", RecordType::A, Some("2")),
+            ("OFFICE.vpn", RecordType::A, Some("2")),
+            ("home.office.vpn", RecordType::A, Some("3")),
+            ("office.vpn", RecordType::Aaaa, Some("4")),
+            ("office", RecordType::A, None),
+            ("office.vpn.example", RecordType::A, None),
+        ];
+        for (subdomain, record_type, expected) in cases {
+            let found = porkbun.find_record(&records, subdomain, record_type);
+            assert_eq!(
+                found.as_ref().map(|r| r.id.as_str()),
+                expected,
+                "{} {}",
                 subdomain,
-                self.domain,
-                new_ip
+                record_type
             );
-            Ok(())
-        } else {
-            let message = response_body
-                .message
-                .unwrap_or_else(|| "Unknown error".to_string());
-            error!("Failed to create A record on Porkbun: {}", message);
-            Err(DdnsError::PorkbunApi(message))
         }
     }
+
+    #[tokio::test]
+    async fn edit_record_sends_multi_level_names_relative_to_the_domain() {
+        let (base_url, requests) = mock_server(vec![(
+            "dns/edit/example.com/103",
+            r#"{"status":"SUCCESS"}"#,
+        )])
+        .await;
+        let http = Client::new();
+        let record: DnsRecord = serde_json::from_str(
+            r#"{"id":"103","name":"office.vpn.example.com","type":"A","content":"198.51.100.3","ttl":"600"}"#,
+        )
+        .unwrap();
+
+        client(&http, &base_url)
+            .edit_record(&record, "office.vpn", "203.0.113.9", 600)
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].1["name"], "office.vpn");
+    }
+
+    #[tokio::test]
+    async fn updates_by_name_type_keep_the_known_ttl_without_an_id() {
+        let (base_url, requests) = mock_server(vec![(
+            "dns/editByNameType/example.com/A/www",
+            r#"{"status":"SUCCESS"}"#,
+        )])
+        .await;
+        let http = Client::new();
+        let record: DnsRecord = serde_json::from_str(
+            r#"{"id":"","name":"www.example.com","type":"A","content":"198.51.100.1","ttl":"3600"}"#,
+        )
+        .unwrap();
+
+        client(&http, &base_url)
+            .with_update_method(UpdateMethod::ByNameType)
+            .update_record(&record, "www", RecordType::A, "203.0.113.9")
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let body = &requests[0].1;
+        assert_eq!(body["content"], "203.0.113.9");
+        assert_eq!(body["ttl"], 3600);
+        assert!(body.get("name").is_none());
+    }
+
+    #[tokio::test]
+    async fn access_probes_tell_refused_domains_from_missing_records_by_code() {
+        let http = Client::new();
+        let refusal = r#"{"status":"ERROR","code":"DOMAIN_NOT_OPTED_IN","message":"Domain is not opted in to API access."}"#;
+        let cases = [
+            (
+                RECORDS,
+                r#"{"status":"ERROR","code":"INVALID_RECORD_ID","message":"Invalid record ID."}"#,
+                Permission::Allowed,
+                Permission::Allowed,
+            ),
+            (
+                refusal,
+                refusal,
+                Permission::Denied("Domain is not opted in to API access.".to_string()),
+                Permission::Denied("Domain is not opted in to API access.".to_string()),
+            ),
+            (
+                // Without a code, the wording isn't guessed at.
+                RECORDS,
+                r#"{"status":"ERROR","message":"Domain is not opted in to API access."}"#,
+                Permission::Allowed,
+                Permission::Unknown(
+                    "refused without an error code: Domain is not opted in to API access."
+                        .to_string(),
+                ),
+            ),
+        ];
+        for (read_response, write_response, read, write) in cases {
+            let (base_url, requests) = mock_server(vec![
+                ("dns/retrieveByNameType/example.com/A", read_response),
+                ("dns/edit/example.com/0", write_response),
+            ])
+            .await;
+            let access = client(&http, &base_url).probe_access().await;
+            assert_eq!(access.read, read, "write answered with {}", write_response);
+            assert_eq!(
+                access.write, write,
+                "write answered with {}",
+                write_response
+            );
+            assert_eq!(requests.lock().unwrap().len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn create_record_uses_the_configured_ttl() {
+        let (base_url, requests) = mock_server(vec![(
+            "dns/create/example.com",
+            r#"{"status":"SUCCESS","id":200}"#,
+        )])
+        .await;
+        let http = Client::new();
+
+        client(&http, &base_url)
+            .with_ttl(Some(1200))
+            .create_record("vpn", RecordType::Aaaa, "2001:db8::7")
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let body = &requests[0].1;
+        assert_eq!(body["name"], "vpn");
+        assert_eq!(body["type"], "AAAA");
+        assert_eq!(body["content"], "2001:db8::7");
+        assert_eq!(body["ttl"], 1200);
+    }
+
+    #[tokio::test]
+    async fn typed_records_send_every_field() {
+        let (base_url, requests) = mock_server(vec![(
+            "dns/create/example.com",
+            r#"{"status":"SUCCESS","id":201}"#,
+        )])
+        .await;
+        let http = Client::new();
+
+        let id = client(&http, &base_url)
+            .create(
+                Record::mx("@", 10)
+                    .content("mail.example.com")
+                    .ttl(3600)
+                    .notes("primary"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(id.as_deref(), Some("201"));
+        let requests = requests.lock().unwrap();
+        let body = &requests[0].1;
+        assert_eq!(body["name"], "");
+        assert_eq!(body["type"], "MX");
+        assert_eq!(body["content"], "mail.example.com");
+        assert_eq!(body["ttl"], 3600);
+        assert_eq!(body["prio"], 10);
+        assert_eq!(body["notes"], "primary");
+    }
+
+    #[tokio::test]
+    async fn typed_edits_keep_the_existing_ttl() {
+        let (base_url, requests) = mock_server(vec![(
+            "dns/edit/example.com/101",
+            r#"{"status":"SUCCESS"}"#,
+        )])
+        .await;
+        let http = Client::new();
+        let existing = DnsRecord {
+            record_type: "A".to_string(),
+            name: "www.example.com".to_string(),
+            content: "198.51.100.1".to_string(),
+            ttl: Some(3600),
+            id: "101".to_string(),
+        };
+
+        client(&http, &base_url)
+            .edit(
+                &existing,
+                Record::aaaa("www").content("2001:db8::7".parse::<std::net::Ipv6Addr>().unwrap()),
+            )
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let body = &requests[0].1;
+        assert_eq!(body["type"], "AAAA");
+        assert_eq!(body["content"], "2001:db8::7");
+        assert_eq!(body["ttl"], 3600);
+        assert!(body.get("prio").is_none());
+    }
+
+    #[tokio::test]
+    async fn api_errors_carry_porkbuns_message() {
+        let (base_url, _) = mock_server(vec![(
+            "dns/retrieve/example.com",
+            r#"{"status":"ERROR","message":"Invalid API key."}"#,
+        )])
+        .await;
+        let http = Client::new();
+
+        let err = client(&http, &base_url)
+            .retrieve_records()
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            DdnsError::PorkbunApi { code: None, message } if message == "Invalid API key."
+        ));
+    }
+
+    #[tokio::test]
+    async fn api_error_codes_are_kept() {
+        let (base_url, _) = mock_server(vec![(
+            "dns/create/example.com",
+            r#"{"status":"ERROR","code":"INVALID_TYPE","message":"Invalid type."}"#,
+        )])
+        .await;
+        let http = Client::new();
+
+        let err = client(&http, &base_url)
+            .create_record("www", RecordType::A, "203.0.113.9")
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Porkbun API error: Invalid type. (INVALID_TYPE)"
+        );
+        assert!(!err.is_transient());
+    }
+
+    #[tokio::test]
+    async fn strict_parsing_rejects_unexpected_fields() {
+        let (base_url, _) = mock_server(vec![(
+            "ping",
+            r#"{"status":"SUCCESS","yourIp":"203.0.113.9","extra":true}"#,
+        )])
+        .await;
+        let http = Client::new();
+        let strict = client(&http, &base_url).with_parsing(ParsingMode::Strict, "");
+
+        assert!(matches!(
+            strict.ping().await,
+            Err(DdnsError::UnexpectedResponse(_))
+        ));
+        assert_eq!(
+            client(&http, &base_url).ping().await.unwrap(),
+            "203.0.113.9"
+        );
+    }
 }
@@ -0,0 +1,168 @@
+// src/port_check.rs
+
+use crate::explain;
+use log::{error, info};
+use std::net::Ipv6Addr;
+use tokio::time::Duration;
+
+/// How long the check service may take to answer for one port.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A port that should be reachable from the internet on a host's new address.
+#[derive(Debug)]
+pub struct PortCheck {
+    /// Fully qualified host name, lower-cased.
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug)]
+pub struct PortCheckConfig {
+    pub checks: Vec<PortCheck>,
+    /// The check service, with `{ip}`, `{port}` and `{host}` filled in for each check.
+    pub service_url: String,
+    /// Text the response must contain to count as reachable; otherwise any 2xx status does.
+    pub expect: Option<String>,
+}
+
+impl PortCheck {
+    /// Parses a `host:port` entry.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (host, port) = entry.trim().rsplit_once(':')?;
+        let host = host.trim().trim_end_matches('.').to_lowercase();
+        let port = port.trim().parse::<u16>().ok().filter(|&port| port > 0)?;
+        (!host.is_empty()).then_some(PortCheck { host, port })
+    }
+}
+
+/// Fills in the check service URL for one check. An IPv6 address standing for the URL's host
+/// is put in brackets, as URLs require; elsewhere, such as in the query, it is left bare.
+fn check_url(service_url: &str, check: &PortCheck, ip: &str) -> String {
+    let in_host = service_url.contains("://{ip}");
+    let ip = match ip.parse::<Ipv6Addr>() {
+        Ok(ip) if in_host => format!("[{}]", ip),
+        _ => ip.to_string(),
+    };
+    service_url
+        .replace("{ip}", &ip)
+        .replace("{port}", &check.port.to_string())
+        .replace("{host}", &check.host)
+}
+
+/// Asks the check service whether `ip:port` accepts connections. Checks have to come from
+/// outside: a connection from this network would usually be looped back by the router and
+/// say nothing about the forwarding.
+async fn reachable(
+    client: &reqwest::Client,
+    config: &PortCheckConfig,
+    check: &PortCheck,
+    ip: &str,
+) -> Result<bool, String> {
+    let url = check_url(&config.service_url, check, ip);
+    explain::call("port check", format!("GET {}", url));
+    let response = client
+        .get(&url)
+        .timeout(CHECK_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Ok(false);
+    }
+    match &config.expect {
+        Some(expect) => Ok(response
+            .text()
+            .await
+            .map_err(|e| e.to_string())?
+            .contains(expect.as_str())),
+        None => Ok(true),
+    }
+}
+
+/// Checks the configured ports of a host whose record just moved to `ip`, logging an error
+/// for each one that can't be reached, which usually means the router's port forwarding
+/// didn't follow the change. Returns a description of each failed check.
+pub async fn after_change(
+    client: &reqwest::Client,
+    config: Option<&PortCheckConfig>,
+    host: &str,
+    ip: &str,
+) -> Vec<String> {
+    let Some(config) = config else {
+        return Vec::new();
+    };
+    let host = host.trim_end_matches('.').to_lowercase();
+    let mut failures = Vec::new();
+    for check in config.checks.iter().filter(|check| check.host == host) {
+        let reason = format!(
+            "checking port {} of {} after its record changed (PORKBUN_PORT_CHECKS)",
+            check.port, check.host
+        );
+        match explain::because(reason, reachable(client, config, check, ip)).await {
+            Ok(true) => info!(
+                "Port {} of {} is reachable on {}",
+                check.port, check.host, ip
+            ),
+            Ok(false) => {
+                error!(
+                    "Port {} of {} is not reachable from outside on {}; check the router's port forwarding",
+                    check.port, check.host, ip
+                );
+                failures.push(format!("port {} is not reachable on {}", check.port, ip));
+            }
+            Err(e) => {
+                error!(
+                    "Could not check port {} of {} on {}: {}",
+                    check.port, check.host, ip, e
+                );
+                failures.push(format!("port {} could not be checked: {}", check.port, e));
+            }
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_in_the_service_url() {
+        let check = PortCheck::parse("vpn.example.com:51820").unwrap();
+        let cases = [
+            (
+                "https://check.example.net/?ip={ip}&port={port}",
+                "203.0.113.7",
+                "https://check.example.net/?ip=203.0.113.7&port=51820",
+            ),
+            (
+                "https://check.example.net/?ip={ip}&port={port}",
+                "2001:db8::7",
+                "https://check.example.net/?ip=2001:db8::7&port=51820",
+            ),
+            (
+                "http://{ip}:{port}/health",
+                "203.0.113.7",
+                "http://203.0.113.7:51820/health",
+            ),
+            (
+                "http://{ip}:{port}/health",
+                "2001:db8::7",
+                "http://[2001:db8::7]:51820/health",
+            ),
+            (
+                "https://check.example.net/{host}/{port}",
+                "2001:db8::7",
+                "https://check.example.net/vpn.example.com/51820",
+            ),
+        ];
+        for (service_url, ip, expected) in cases {
+            assert_eq!(
+                check_url(service_url, &check, ip),
+                expected,
+                "{}",
+                service_url
+            );
+        }
+    }
+}
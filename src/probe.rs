@@ -0,0 +1,171 @@
+// src/probe.rs
+
+use crate::explain;
+use crate::porkbun::RecordType;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration, Instant};
+
+/// How long a single probe attempt may take before it counts as lost.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a measurement is reused for further records changed to the same address.
+const REUSE_FOR: Duration = Duration::from_secs(60);
+
+/// What to measure right after an IP change.
+#[derive(Debug)]
+pub enum ProbeTarget {
+    /// An HTTP HEAD request to this URL.
+    Http(String),
+    /// A TCP connection to this `host:port`.
+    Tcp(String),
+}
+
+#[derive(Debug)]
+pub struct ProbeConfig {
+    pub target: ProbeTarget,
+    /// Attempts per measurement.
+    pub count: u32,
+}
+
+/// Round-trip times of one measurement, in milliseconds. The statistics are `None` when every
+/// attempt was lost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub target: String,
+    pub sent: u32,
+    pub received: u32,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+/// The latest measurement for each address family, with the address it was taken for.
+static LAST: Mutex<BTreeMap<RecordType, (String, Instant, ProbeResult)>> =
+    Mutex::new(BTreeMap::new());
+
+impl ProbeTarget {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Some(address) = value.strip_prefix("tcp://") {
+            address
+                .rsplit_once(':')
+                .filter(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+                .map(|_| ProbeTarget::Tcp(address.to_string()))
+        } else if value.starts_with("http://") || value.starts_with("https://") {
+            Some(ProbeTarget::Http(value.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ProbeTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeTarget::Http(url) => write!(f, "{}", url),
+            ProbeTarget::Tcp(address) => write!(f, "tcp://{}", address),
+        }
+    }
+}
+
+impl ProbeResult {
+    /// Share of attempts that were lost, in percent.
+    pub fn loss_percent(&self) -> u32 {
+        ((self.sent - self.received) * 100)
+            .checked_div(self.sent)
+            .unwrap_or(0)
+    }
+}
+
+impl fmt::Display for ProbeResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min_ms, self.avg_ms, self.max_ms) {
+            (Some(min), Some(avg), Some(max)) => write!(
+                f,
+                "{}/{} to {}, avg {:.1} ms (min {:.1}, max {:.1})",
+                self.received, self.sent, self.target, avg, min, max
+            ),
+            _ => write!(f, "0/{} to {}, all lost", self.sent, self.target),
+        }
+    }
+}
+
+async fn attempt(client: &reqwest::Client, target: &ProbeTarget) -> Option<Duration> {
+    explain::call(
+        "probe",
+        match target {
+            ProbeTarget::Http(url) => format!("HEAD {}", url),
+            ProbeTarget::Tcp(address) => format!("TCP connect to {}", address),
+        },
+    );
+    let started = Instant::now();
+    let reached = match target {
+        ProbeTarget::Http(url) => timeout(ATTEMPT_TIMEOUT, client.head(url).send())
+            .await
+            .is_ok_and(|res| res.is_ok()),
+        ProbeTarget::Tcp(address) => timeout(ATTEMPT_TIMEOUT, TcpStream::connect(address))
+            .await
+            .is_ok_and(|res| res.is_ok()),
+    };
+    reached.then(|| started.elapsed())
+}
+
+async fn measure(client: &reqwest::Client, config: &ProbeConfig) -> ProbeResult {
+    let mut samples = Vec::new();
+    for _ in 0..config.count {
+        if let Some(elapsed) = attempt(client, &config.target).await {
+            samples.push(elapsed.as_secs_f64() * 1000.0);
+        }
+    }
+    let avg = (!samples.is_empty()).then(|| samples.iter().sum::<f64>() / samples.len() as f64);
+    ProbeResult {
+        target: config.target.to_string(),
+        sent: config.count,
+        received: samples.len() as u32,
+        min_ms: samples.iter().copied().reduce(f64::min),
+        avg_ms: avg,
+        max_ms: samples.iter().copied().reduce(f64::max),
+    }
+}
+
+/// Measures latency to the configured target after a record was changed to `ip`. Further
+/// records changed to the same address shortly after reuse the measurement. Returns the
+/// result and whether it is new, so it is only recorded once.
+pub async fn after_change(
+    client: &reqwest::Client,
+    config: Option<&ProbeConfig>,
+    record_type: RecordType,
+    ip: &str,
+) -> Option<(ProbeResult, bool)> {
+    let config = config?;
+    if let Some((last_ip, at, result)) = LAST.lock().unwrap().get(&record_type) {
+        if last_ip == ip && at.elapsed() < REUSE_FOR {
+            explain::note(format!(
+                "Latency to {} was measured moments ago; the measurement is reused",
+                ip
+            ));
+            return Some((result.clone(), false));
+        }
+    }
+
+    let reason = format!(
+        "measuring latency after the change to {} (PORKBUN_PROBE_*)",
+        ip
+    );
+    let result = explain::because(reason, measure(client, config)).await;
+    if result.received == 0 {
+        warn!("Latency probe after the change to {}: {}", ip, result);
+    } else {
+        info!("Latency probe after the change to {}: {}", ip, result);
+    }
+    LAST.lock().unwrap().insert(
+        record_type,
+        (ip.to_string(), Instant::now(), result.clone()),
+    );
+    Some((result, true))
+}
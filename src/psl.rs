@@ -0,0 +1,75 @@
+// src/psl.rs
+
+/// Multi-label public suffixes from the Public Suffix List that are commonly seen with
+/// Porkbun domains. Single-label TLDs (`com`, `uk`, ...) need no entry, and further
+/// suffixes can be supplied at runtime via `PORKBUN_PUBLIC_SUFFIXES`.
+const MULTI_LABEL_SUFFIXES: &[&str] = &[
+    "ac.uk", "co.uk", "gov.uk", "ltd.uk", "me.uk", "net.uk", "org.uk", "plc.uk", "sch.uk",
+    "com.au", "net.au", "org.au", "edu.au", "gov.au", "id.au", "asn.au", "co.nz", "net.nz",
+    "org.nz", "geek.nz", "kiwi.nz", "co.jp", "ne.jp", "or.jp", "ac.jp", "go.jp", "co.kr", "or.kr",
+    "ne.kr", "co.in", "net.in", "org.in", "firm.in", "gen.in", "ind.in", "co.za", "org.za",
+    "net.za", "web.za", "com.br", "net.br", "org.br", "com.mx", "org.mx", "net.mx", "com.ar",
+    "com.co", "com.pe", "com.ve", "com.uy", "com.ec", "com.cn", "net.cn", "org.cn", "com.hk",
+    "com.tw", "com.sg", "com.my", "com.ph", "com.vn", "co.id", "co.th", "com.tr", "com.ua",
+    "co.il", "com.pl", "co.at", "or.at", "com.es", "com.pt", "com.gr", "co.ke", "co.ug", "co.tz",
+    "com.ng", "com.eg", "us.com", "uk.com", "eu.com", "de.com", "uk.net", "gb.net",
+];
+
+/// Splits a hostname into its registrable domain and the subdomain labels in front of it,
+/// using the longest matching public suffix. Returns `None` if the host is itself a suffix.
+pub fn split<'a>(host: &'a str, extra_suffixes: &[String]) -> Option<(&'a str, &'a str)> {
+    let host = host.trim_end_matches('.');
+    let labels: Vec<&str> = host.split('.').collect();
+
+    let suffix_labels = (1..=labels.len())
+        .rev()
+        .find(|&n| {
+            let candidate = labels[labels.len() - n..].join(".").to_ascii_lowercase();
+            MULTI_LABEL_SUFFIXES.contains(&candidate.as_str())
+                || extra_suffixes
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(&candidate))
+        })
+        .unwrap_or(1);
+
+    if labels.len() <= suffix_labels {
+        return None;
+    }
+
+    let subdomain_labels = labels.len() - suffix_labels - 1;
+    let subdomain_len = labels[..subdomain_labels]
+        .iter()
+        .map(|label| label.len() + 1)
+        .sum::<usize>();
+
+    Some(if subdomain_len == 0 {
+        (host, "")
+    } else {
+        (&host[subdomain_len..], &host[..subdomain_len - 1])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_at_the_registrable_domain() {
+        let extra = vec!["Dyn.Example".to_string()];
+        let cases = [
+            ("example.com", Some(("example.com", ""))),
+            ("www.example.com", Some(("example.com", "www"))),
+            ("home.lab.example.com.", Some(("example.com", "home.lab"))),
+            ("ddns.my.co.uk", Some(("my.co.uk", "ddns"))),
+            ("My.Co.UK", Some(("My.Co.UK", ""))),
+            ("a.b.example.com.au", Some(("example.com.au", "a.b"))),
+            ("host.site.dyn.example", Some(("site.dyn.example", "host"))),
+            ("co.uk", None),
+            ("com", None),
+            ("dyn.example", None),
+        ];
+        for (host, expected) in cases {
+            assert_eq!(split(host, &extra), expected, "{}", host);
+        }
+    }
+}
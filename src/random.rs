@@ -0,0 +1,26 @@
+// src/random.rs
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns a non-cryptographic random number, good enough for IDs and jitter.
+pub fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    hasher.finish()
+}
+
+/// Returns a random number in `0..=max`.
+pub fn up_to(max: u64) -> u64 {
+    if max == u64::MAX {
+        random_u64()
+    } else {
+        random_u64() % (max + 1)
+    }
+}
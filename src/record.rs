@@ -0,0 +1,211 @@
+// src/record.rs
+
+//! Typed builders for records to create or edit with
+//! [`PorkbunClient::create`](crate::porkbun::PorkbunClient::create) and
+//! [`PorkbunClient::edit`](crate::porkbun::PorkbunClient::edit). Each record type takes
+//! content of the matching kind, so an IPv6 address can't end up in an A record, and a
+//! record can't be built before its required fields are set:
+//!
+//! ```
+//! use porkbun_ddns::record::Record;
+//! use std::net::Ipv4Addr;
+//!
+//! let www = Record::a("www").content(Ipv4Addr::new(198, 51, 100, 7)).ttl(600).build();
+//! assert_eq!(www.content(), "198.51.100.7");
+//!
+//! let mail = Record::mx("@", 10).content("mail.example.com").build();
+//! assert_eq!((mail.name(), mail.prio()), ("", Some(10)));
+//! ```
+//!
+//! ```compile_fail
+//! # use porkbun_ddns::record::Record;
+//! // No content yet, so there is nothing to build.
+//! let www = Record::a("www").ttl(600).build();
+//! ```
+//!
+//! ```compile_fail
+//! # use porkbun_ddns::record::Record;
+//! # use std::net::Ipv6Addr;
+//! // An A record only takes an IPv4 address.
+//! let www = Record::a("www").content(Ipv6Addr::LOCALHOST).build();
+//! ```
+
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// A record type the builders know, with the kind of content it holds.
+pub trait Kind {
+    /// The type as Porkbun names it.
+    const TYPE: &'static str;
+    type Content: Display;
+}
+
+/// An IPv4 address record.
+pub struct A;
+/// An IPv6 address record.
+pub struct Aaaa;
+/// An alias for another hostname.
+pub struct Cname;
+/// A mail server, with its priority.
+pub struct Mx;
+/// Free-form text, e.g. for domain verification or SPF.
+pub struct Txt;
+
+impl Kind for A {
+    const TYPE: &'static str = "A";
+    type Content = Ipv4Addr;
+}
+
+impl Kind for Aaaa {
+    const TYPE: &'static str = "AAAA";
+    type Content = Ipv6Addr;
+}
+
+impl Kind for Cname {
+    const TYPE: &'static str = "CNAME";
+    type Content = String;
+}
+
+impl Kind for Mx {
+    const TYPE: &'static str = "MX";
+    type Content = String;
+}
+
+impl Kind for Txt {
+    const TYPE: &'static str = "TXT";
+    type Content = String;
+}
+
+/// The state of a builder whose content hasn't been set yet.
+pub struct NoContent;
+
+/// A record ready to be sent, built with one of `Record`'s constructors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    name: String,
+    record_type: &'static str,
+    content: String,
+    ttl: Option<u32>,
+    prio: Option<u32>,
+    notes: Option<String>,
+}
+
+/// Collects a record's fields. `build` only exists once the content is set.
+pub struct RecordBuilder<K: Kind, C = NoContent> {
+    name: String,
+    content: C,
+    ttl: Option<u32>,
+    prio: Option<u32>,
+    notes: Option<String>,
+    kind: PhantomData<K>,
+}
+
+impl Record {
+    /// An A record for a subdomain; `""` or `"@"` is the domain itself.
+    pub fn a(name: &str) -> RecordBuilder<A> {
+        RecordBuilder::new(name, None)
+    }
+
+    /// An AAAA record for a subdomain; `""` or `"@"` is the domain itself.
+    pub fn aaaa(name: &str) -> RecordBuilder<Aaaa> {
+        RecordBuilder::new(name, None)
+    }
+
+    pub fn cname(name: &str) -> RecordBuilder<Cname> {
+        RecordBuilder::new(name, None)
+    }
+
+    /// An MX record; lower priorities are tried first.
+    pub fn mx(name: &str, priority: u32) -> RecordBuilder<Mx> {
+        RecordBuilder::new(name, Some(priority))
+    }
+
+    pub fn txt(name: &str) -> RecordBuilder<Txt> {
+        RecordBuilder::new(name, None)
+    }
+
+    /// The subdomain, empty for the domain itself.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn record_type(&self) -> &'static str {
+        self.record_type
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// The TTL, if one was set. Otherwise the client's configured TTL applies.
+    pub fn ttl(&self) -> Option<u32> {
+        self.ttl
+    }
+
+    pub fn prio(&self) -> Option<u32> {
+        self.prio
+    }
+
+    pub fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+}
+
+impl<K: Kind> RecordBuilder<K> {
+    fn new(name: &str, prio: Option<u32>) -> Self {
+        Self {
+            name: if name == "@" { "" } else { name }.to_string(),
+            content: NoContent,
+            ttl: None,
+            prio,
+            notes: None,
+            kind: PhantomData,
+        }
+    }
+
+    pub fn content(self, content: impl Into<K::Content>) -> RecordBuilder<K, K::Content> {
+        RecordBuilder {
+            name: self.name,
+            content: content.into(),
+            ttl: self.ttl,
+            prio: self.prio,
+            notes: self.notes,
+            kind: PhantomData,
+        }
+    }
+}
+
+impl<K: Kind, C> RecordBuilder<K, C> {
+    /// TTL in seconds. Porkbun rejects anything below `MIN_TTL`.
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// A note shown next to the record in Porkbun's dashboard.
+    pub fn notes(mut self, notes: &str) -> Self {
+        self.notes = Some(notes.to_string());
+        self
+    }
+}
+
+impl<K: Kind> RecordBuilder<K, K::Content> {
+    pub fn build(self) -> Record {
+        Record {
+            name: self.name,
+            record_type: K::TYPE,
+            content: self.content.to_string(),
+            ttl: self.ttl,
+            prio: self.prio,
+            notes: self.notes,
+        }
+    }
+}
+
+/// Lets a finished builder be passed straight to the client, without calling `build`.
+impl<K: Kind> From<RecordBuilder<K, K::Content>> for Record {
+    fn from(builder: RecordBuilder<K, K::Content>) -> Self {
+        builder.build()
+    }
+}
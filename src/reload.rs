@@ -0,0 +1,142 @@
+// src/reload.rs
+
+//! Re-reads the local configuration when the daemon gets SIGHUP, so a host can be added or a
+//! setting changed without restarting it. The files are read into a fresh set of variables
+//! and a new configuration is built from those, leaving the process environment as startup
+//! left it.
+
+use crate::config::Config;
+use crate::errors::{DdnsError, Result};
+use crate::simulate;
+use crate::vars::Vars;
+use std::io::IsTerminal;
+use std::sync::{Mutex, OnceLock};
+
+/// Where the configuration came from at startup.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "config-file"), allow(dead_code))]
+struct Sources {
+    /// The process environment before any file was loaded.
+    environment: Vars,
+    config_file: Option<String>,
+    tenant: Option<String>,
+}
+
+static SOURCES: OnceLock<Sources> = OnceLock::new();
+
+/// The variables the local sources held when they were last loaded successfully.
+static LOCAL: Mutex<Option<Vars>> = Mutex::new(None);
+
+/// Credentials typed at the startup prompt. They fill in for variables no local source sets
+/// on every later reload, which never prompts: the daemon may have no terminal by then, and a
+/// prompt would stall its loop.
+static PROMPTED: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// The credentials asked for when no source sets them, with how the prompt describes them.
+const CREDENTIALS: [(&str, &str); 2] = [
+    ("PORKBUN_API_KEY", "Porkbun API key"),
+    ("PORKBUN_SECRET_API_KEY", "Porkbun secret API key"),
+];
+
+/// Records the process environment and the `--config` file, before `.env` or any other file
+/// is loaded.
+pub fn init(config_file: Option<&str>, tenant: Option<&str>) {
+    let _ = SOURCES.set(Sources {
+        environment: Vars::from_env(),
+        config_file: config_file.map(str::to_string),
+        tenant: tenant.map(str::to_string),
+    });
+}
+
+/// Loads the local sources at startup and puts what they set into the process environment,
+/// returning the tenants the config file defines.
+pub fn load() -> Result<Vec<String>> {
+    let (vars, tenants) = read_local()?;
+    vars.export();
+    *LOCAL.lock().unwrap() = Some(vars);
+    Ok(tenants)
+}
+
+/// Asks for each credential no source sets, with hidden input, when stdin is a terminal, e.g.
+/// for a one-off run from a workstation. Only called at startup; what is typed is kept with
+/// the local sources for reloads.
+pub fn prompt_credentials() -> Result<()> {
+    let vars = current();
+    for (var_name, description) in CREDENTIALS {
+        if vars.is_set(var_name) || !std::io::stdin().is_terminal() {
+            continue;
+        }
+        let value = rpassword::prompt_password(format!("{} ({}): ", description, var_name))
+            .map_err(|e| DdnsError::Config(format!("Failed to read {}: {}", var_name, e)))?;
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            return Err(DdnsError::Config(format!(
+                "{} must not be empty.",
+                var_name
+            )));
+        }
+        LOCAL
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Vars::from_env)
+            .set(var_name, &value);
+        PROMPTED.lock().unwrap().push((var_name.to_string(), value));
+    }
+    Ok(())
+}
+
+/// Reads the process environment as it was at startup, then `.env`, the encrypted env file
+/// and the `--config` file, in that order of precedence.
+fn read_local() -> Result<(Vars, Vec<String>)> {
+    let Some(sources) = SOURCES.get() else {
+        return Ok((Vars::from_env(), Vec::new()));
+    };
+    let mut vars = sources.environment.clone();
+    vars.load_dotenv();
+    #[cfg(feature = "encrypted-secrets")]
+    crate::secrets::load_encrypted_env(&mut vars)?;
+    let tenants = match &sources.config_file {
+        #[cfg(feature = "config-file")]
+        Some(path) => crate::config_file::load(path, sources.tenant.as_deref(), &mut vars)?,
+        #[cfg(not(feature = "config-file"))]
+        Some(path) => {
+            return Err(crate::errors::DdnsError::Config(format!(
+                "--config {} was given, but this build does not include the `config-file` feature.",
+                path
+            )))
+        }
+        None => Vec::new(),
+    };
+    Ok((vars, tenants))
+}
+
+/// Adds what doesn't come from local files: the remote values last fetched, which local
+/// settings win over, and the simulation's settings.
+fn layered(mut vars: Vars) -> Vars {
+    #[cfg(feature = "remote-config")]
+    crate::remote_config::overlay(&mut vars);
+    if simulate::active() {
+        simulate::overrides(&mut vars);
+    }
+    vars
+}
+
+/// The variables the configuration is built from: the local sources as last loaded, with the
+/// remote values and the simulation's settings on top.
+pub fn current() -> Vars {
+    let local = LOCAL.lock().unwrap().clone();
+    layered(local.unwrap_or_else(Vars::from_env))
+}
+
+/// Loads the configuration again the way startup did. An edited file therefore replaces what
+/// it set before, while variables from the process environment still win. When the result is
+/// not a valid configuration the error is returned and the previous sources stay in use.
+pub fn reread() -> Result<Config> {
+    let (mut local, _) = read_local()?;
+    for (var_name, value) in PROMPTED.lock().unwrap().iter() {
+        local.set_default(var_name, value);
+    }
+    let config = Config::from_vars(&layered(local.clone()))?;
+    *LOCAL.lock().unwrap() = Some(local);
+    Ok(config)
+}
@@ -0,0 +1,309 @@
+// src/remote_config.rs
+
+use crate::app_error::AppError;
+use crate::config_file;
+use crate::errors::{DdnsError, Result};
+use crate::integrity;
+use crate::vars::Vars;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use log::{info, warn};
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const DEFAULT_INTERVAL_SECONDS: u64 = 300;
+const DEFAULT_STATE_FILE: &str = "porkbun-remote-config-state.json";
+
+/// A central configuration file, in the `--config` layout, fetched over HTTPS. Its signature
+/// is read from the same URL with `.sig` appended.
+#[derive(Debug)]
+pub struct RemoteConfig {
+    url: Url,
+    public_key: VerifyingKey,
+    interval: Duration,
+}
+
+/// What the last applied fetch left behind: the ETag to poll with and the values it held,
+/// with the serial of their file.
+#[derive(Debug)]
+struct Applied {
+    etag: Option<String>,
+    vars: Vec<(String, String)>,
+    /// The serial of the file `vars` came from, once one was applied by this process.
+    serial: Option<u64>,
+    /// The highest serial ever applied, kept across restarts; older files are refused.
+    newest_serial: Option<u64>,
+    fetched_at: Option<Instant>,
+}
+
+/// The newest serial applied, as kept in `PORKBUN_REMOTE_CONFIG_STATE_FILE`.
+#[derive(Debug, Serialize, Deserialize)]
+struct State {
+    serial: u64,
+}
+
+static REMOTE: OnceLock<RemoteConfig> = OnceLock::new();
+
+static APPLIED: Mutex<Applied> = Mutex::new(Applied {
+    etag: None,
+    vars: Vec::new(),
+    serial: None,
+    newest_serial: None,
+    fetched_at: None,
+});
+
+/// Reads the remote configuration settings and fetches the file once, before the rest of the
+/// configuration is loaded. A failed fetch is only logged, leaving the local settings in use.
+pub async fn init(client: &reqwest::Client) -> Result<()> {
+    let Some(remote) = RemoteConfig::from_env()? else {
+        return Ok(());
+    };
+    APPLIED.lock().unwrap().newest_serial = load_serial();
+    if let Err(e) = remote.fetch(client).await {
+        warn!(
+            "Failed to fetch remote configuration {}, using the local settings: {}",
+            remote.url, e
+        );
+    }
+    let _ = REMOTE.set(remote);
+    Ok(())
+}
+
+/// The remote configuration, if `init` found one.
+pub fn configured() -> Option<&'static RemoteConfig> {
+    REMOTE.get()
+}
+
+/// Adds the remote values last applied to `vars`, where the local settings win over them.
+pub fn overlay(vars: &mut Vars) {
+    for (key, value) in &APPLIED.lock().unwrap().vars {
+        vars.set_default(key, value);
+    }
+}
+
+/// Whether a remote file may not set `key`. Hooks and commands run shell commands, so a
+/// leaked signing key must not be enough to run code on every device. File paths (the state
+/// files, the state key and the metrics file) would let it overwrite files on the device, or
+/// swap in state it wrote itself. And the remote settings themselves only ever come from the
+/// local environment.
+fn local_only(key: &str) -> bool {
+    key.ends_with("_HOOK")
+        || key.ends_with("_HOOKS")
+        || key.ends_with("_COMMAND")
+        || key.ends_with("_FILE")
+        || key.ends_with("_TEXTFILE")
+        || key.starts_with("PORKBUN_REMOTE_CONFIG_")
+}
+
+fn state_path() -> Option<String> {
+    let path = env::var("PORKBUN_REMOTE_CONFIG_STATE_FILE")
+        .unwrap_or_else(|_| DEFAULT_STATE_FILE.to_string());
+    Some(path).filter(|path| !path.is_empty())
+}
+
+/// Reads the newest serial applied before this process started. A missing or unreadable state
+/// file, or one that fails its integrity check, accepts any serial; the signature still has to
+/// hold.
+fn load_serial() -> Option<u64> {
+    let contents = integrity::read(&state_path()?).ok()??;
+    match serde_json::from_str::<State>(&contents) {
+        Ok(state) => Some(state.serial),
+        Err(e) => {
+            warn!("Ignoring unreadable remote configuration state file: {}", e);
+            None
+        }
+    }
+}
+
+fn save_serial(serial: u64) {
+    let Some(path) = state_path() else {
+        return;
+    };
+    let contents = serde_json::to_string(&State { serial }).unwrap_or_default();
+    if let Err(e) = fs::write(&path, integrity::seal(&contents)) {
+        warn!(
+            "Failed to write remote configuration state file '{}': {}",
+            path, e
+        );
+    }
+}
+
+impl RemoteConfig {
+    /// Reads the remote configuration settings; remote configuration is off unless
+    /// `PORKBUN_REMOTE_CONFIG_URL` is set. These settings only ever come from the local
+    /// environment, never from the fetched file.
+    fn from_env() -> Result<Option<Self>> {
+        let Ok(url) = env::var("PORKBUN_REMOTE_CONFIG_URL") else {
+            return Ok(None);
+        };
+        let url = Url::parse(&url)
+            .ok()
+            .filter(|url| url.scheme() == "https")
+            .ok_or_else(|| {
+                DdnsError::Config("PORKBUN_REMOTE_CONFIG_URL must be an https:// URL.".to_string())
+            })?;
+
+        let public_key = env::var("PORKBUN_REMOTE_CONFIG_PUBLIC_KEY").map_err(|_| {
+            DdnsError::Config(
+                "PORKBUN_REMOTE_CONFIG_PUBLIC_KEY must be set to verify the remote configuration."
+                    .to_string(),
+            )
+        })?;
+        let public_key = BASE64
+            .decode(public_key.trim())
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| {
+                DdnsError::Config(
+                    "PORKBUN_REMOTE_CONFIG_PUBLIC_KEY must be a base64-encoded Ed25519 public key."
+                        .to_string(),
+                )
+            })?;
+
+        let interval_seconds = match env::var("PORKBUN_REMOTE_CONFIG_INTERVAL_SECONDS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| {
+                    DdnsError::Config(
+                        "PORKBUN_REMOTE_CONFIG_INTERVAL_SECONDS must be a positive number."
+                            .to_string(),
+                    )
+                })?,
+            Err(_) => DEFAULT_INTERVAL_SECONDS,
+        };
+
+        Ok(Some(Self {
+            url,
+            public_key,
+            interval: Duration::from_secs(interval_seconds),
+        }))
+    }
+
+    /// Whether the poll interval has passed since the last fetch.
+    pub fn due(&self) -> bool {
+        APPLIED
+            .lock()
+            .unwrap()
+            .fetched_at
+            .is_none_or(|at| at.elapsed() >= self.interval)
+    }
+
+    /// Fetches the file and, if it changed since the last fetch, its signature is valid and
+    /// its serial is not older than the newest applied, keeps its values in place of the
+    /// previous remote values. Local settings still take precedence. Returns whether anything
+    /// was applied.
+    pub async fn fetch(&self, client: &reqwest::Client) -> Result<bool> {
+        let etag = {
+            let mut applied = APPLIED.lock().unwrap();
+            applied.fetched_at = Some(Instant::now());
+            applied.etag.clone()
+        };
+
+        let mut request = client.get(self.url.clone());
+        if let Some(etag) = &etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let res = request.send().await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            return Ok(false);
+        }
+        let res = res.error_for_status()?;
+        let new_etag = res
+            .headers()
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = res.bytes().await?;
+
+        self.verify(client, &body).await?;
+        let contents = std::str::from_utf8(&body)
+            .map_err(|_| AppError::RemoteConfig("the file is not valid UTF-8".to_string()))?;
+        let config_file::RemoteFile { serial, vars } =
+            config_file::remote(self.url.path(), contents)?;
+        let serial = serial.ok_or_else(|| {
+            AppError::RemoteConfig(
+                "the file has no serial; give it a top-level `serial` that grows with every change"
+                    .to_string(),
+            )
+        })?;
+        if let Some((key, _)) = vars.iter().find(|(key, _)| local_only(key)) {
+            return Err(AppError::RemoteConfig(format!(
+                "the file sets {}, which can only be set locally",
+                key
+            ))
+            .into());
+        }
+
+        let mut applied = APPLIED.lock().unwrap();
+        applied.etag = new_etag;
+        if let Some(newest) = applied.newest_serial.filter(|&newest| serial < newest) {
+            return Err(AppError::RemoteConfig(format!(
+                "serial {} is older than serial {} already applied; an old file may have been served again",
+                serial, newest
+            )).into());
+        }
+        if applied.serial == Some(serial) {
+            if vars != applied.vars {
+                warn!(
+                    "Remote configuration {} changed but kept serial {}; raise the serial to apply it",
+                    self.url, serial
+                );
+            }
+            return Ok(false);
+        }
+        applied.vars = vars.clone();
+        applied.serial = Some(serial);
+        applied.newest_serial = Some(serial);
+        save_serial(serial);
+        info!(
+            "Applied {} setting(s) from remote configuration {} (serial {})",
+            vars.len(),
+            self.url,
+            serial
+        );
+        Ok(true)
+    }
+
+    /// Puts the previously applied remote values back after a fetched file turned out to be
+    /// unusable. The ETag and serial are kept, so the same file is not applied again.
+    pub fn roll_back(&self, previous: &[(String, String)]) {
+        APPLIED.lock().unwrap().vars = previous.to_vec();
+        warn!("Kept the previous remote configuration");
+    }
+
+    /// The remote values currently applied, to roll back to.
+    pub fn applied_vars(&self) -> Vec<(String, String)> {
+        APPLIED.lock().unwrap().vars.clone()
+    }
+
+    async fn verify(&self, client: &reqwest::Client, body: &[u8]) -> Result<()> {
+        let mut sig_url = self.url.clone();
+        sig_url.set_path(&format!("{}.sig", self.url.path()));
+        let encoded = client
+            .get(sig_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let signature = BASE64
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| {
+                AppError::RemoteConfig("the signature is not a base64 Ed25519 signature".into())
+            })?;
+        self.public_key
+            .verify_strict(body, &signature)
+            .map_err(|_| AppError::RemoteConfig(format!("bad signature for {}", self.url)).into())
+    }
+}
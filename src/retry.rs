@@ -0,0 +1,209 @@
+// src/retry.rs
+
+use crate::clock::{Clock, SystemClock};
+use crate::errors::{DdnsError, Result};
+use crate::explain;
+use crate::random;
+use log::warn;
+use std::future::Future;
+use tokio::time::Duration;
+
+/// How often, and how far apart, a failed request is tried again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first; 1 disables retrying.
+    pub max_attempts: u32,
+    /// The wait before the first retry. Each further retry waits twice as long as the one
+    /// before, up to `max_delay`.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Tries everything exactly once.
+    pub const NONE: Self = Self {
+        max_attempts: 1,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    /// The wait after the given failed attempt (counting from 1): the exponential delay with
+    /// random jitter over its upper half, so many instances that failed together don't all
+    /// retry at the same moment.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        let half = exponential.as_millis() as u64 / 2;
+        Duration::from_millis(half + random::up_to(half))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails with an error that isn't transient, or runs out of
+/// attempts.
+pub async fn retry<T, F, Fut>(policy: &RetryPolicy, what: &str, attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    retry_if(policy, what, DdnsError::is_transient, attempt).await
+}
+
+/// Like `retry`, but with the caller deciding which errors are worth another attempt, e.g.
+/// only those where the request was never sent, for writes that must not be repeated.
+pub async fn retry_if<T, F, Fut>(
+    policy: &RetryPolicy,
+    what: &str,
+    retryable: impl Fn(&DdnsError) -> bool,
+    attempt: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    retry_if_on(&SystemClock, policy, what, retryable, attempt).await
+}
+
+/// `retry_if`, waiting between attempts on the given clock.
+pub async fn retry_if_on<T, F, Fut>(
+    clock: &impl Clock,
+    policy: &RetryPolicy,
+    what: &str,
+    retryable: impl Fn(&DdnsError) -> bool,
+    mut attempt: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match attempt().await {
+            Err(e) if attempts < policy.max_attempts && retryable(&e) => {
+                let delay = policy.delay(attempts);
+                warn!(
+                    "{} failed (attempt {} of {}), retrying in {:.1}s: {}",
+                    what,
+                    attempts,
+                    policy.max_attempts,
+                    delay.as_secs_f64(),
+                    e
+                );
+                explain::note(format!(
+                    "{} failed (attempt {} of {}), retried: {}",
+                    what, attempts, policy.max_attempts, e
+                ));
+                clock.sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::cell::Cell;
+
+    const QUICK: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    #[test]
+    fn delays_double_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        for (attempt, full) in [(1, 100), (2, 200), (3, 400), (4, 800), (5, 1000), (9, 1000)] {
+            let delay = policy.delay(attempt).as_millis();
+            assert!(
+                (full / 2..=full).contains(&delay),
+                "attempt {}: {}ms",
+                attempt,
+                delay
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried_until_attempts_run_out() {
+        let calls = Cell::new(0);
+        let result: Result<()> = retry(&QUICK, "test", || async {
+            calls.set(calls.get() + 1);
+            Err(DdnsError::IpDetection("unreachable".to_string()))
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn other_errors_and_successes_are_not_retried() {
+        let calls = Cell::new(0);
+        let result: Result<()> = retry(&QUICK, "test", || async {
+            calls.set(calls.get() + 1);
+            Err(DdnsError::Config("bad".to_string()))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+
+        let calls = Cell::new(0);
+        let result = retry(&QUICK, "test", || async {
+            calls.set(calls.get() + 1);
+            if calls.get() < 2 {
+                Err(DdnsError::IpDetection("unreachable".to_string()))
+            } else {
+                Ok(calls.get())
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_back_off_on_the_clock() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(30),
+        };
+        let clock = MockClock::new();
+        let result: Result<()> =
+            retry_if_on(&clock, &policy, "test", DdnsError::is_transient, || async {
+                Err(DdnsError::IpDetection("unreachable".to_string()))
+            })
+            .await;
+
+        assert!(result.is_err());
+        let sleeps = clock.sleeps();
+        assert_eq!(sleeps.len(), 3, "no wait after the last attempt");
+        for (sleep, full) in sleeps.iter().zip([10, 20, 30]) {
+            let full = Duration::from_secs(full);
+            assert!(
+                (full / 2..=full).contains(sleep),
+                "{:?} outside the jitter range below {:?}",
+                sleep,
+                full
+            );
+        }
+    }
+}
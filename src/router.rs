@@ -0,0 +1,119 @@
+// src/router.rs
+
+use crate::app_error::AppError;
+use crate::errors::Result;
+use crate::explain;
+use crate::systemd;
+use log::{error, info, warn};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use tokio::process::Command;
+
+/// Where the router reports its WAN address, without needing SNMP.
+#[derive(Debug)]
+pub enum RouterSource {
+    /// A URL on the router (or a small script behind it) whose body is the WAN address.
+    Url(String),
+    /// A shell command that prints the WAN address, e.g. over SSH to the router.
+    Command(String),
+}
+
+/// A second opinion on the public address, checked against the HTTP source every cycle.
+#[derive(Debug)]
+pub struct RouterConfig {
+    pub source: RouterSource,
+    /// Consecutive disagreeing cycles before the mismatch is reported as an error.
+    pub mismatch_cycles: u32,
+}
+
+/// Whether the last cross-check found the router itself behind another NAT.
+static BEHIND_NAT: AtomicBool = AtomicBool::new(false);
+
+/// Consecutive cycles in which the router and the HTTP source disagreed.
+static MISMATCHES: AtomicU32 = AtomicU32::new(0);
+
+/// Asks the router for its WAN address.
+pub async fn router_ip(client: &reqwest::Client, source: &RouterSource) -> Result<Ipv4Addr> {
+    let output = match source {
+        RouterSource::Url(url) => {
+            explain::call("router", format!("GET {}", url));
+            client.get(url).send().await?.text().await?
+        }
+        RouterSource::Command(command) => {
+            let output = systemd::isolate(Command::new("sh").arg("-c").arg(command))
+                .output()
+                .await
+                .map_err(|e| AppError::Router(format!("Failed to start '{}': {}", command, e)))?;
+            if !output.status.success() {
+                return Err(AppError::Router(format!(
+                    "'{}' exited with {}",
+                    command, output.status
+                ))
+                .into());
+            }
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+    };
+    output
+        .trim()
+        .parse()
+        .map_err(|_| AppError::Router(format!("'{}' is not an IPv4 address", output.trim())).into())
+}
+
+/// Describes the NAT in front of the router when its WAN address is not public, in which case
+/// DDNS still "works" but port forwards on the router can never be reached from outside.
+pub fn upstream_nat(router_ip: Ipv4Addr) -> Option<&'static str> {
+    let [a, b, ..] = router_ip.octets();
+    if router_ip.is_private() {
+        Some("double NAT")
+    } else if a == 100 && (64..128).contains(&b) {
+        Some("carrier-grade NAT")
+    } else {
+        None
+    }
+}
+
+/// Compares the router's WAN address with the externally observed one. A single mismatch is
+/// only a warning, since either side may lag a reconnect; a mismatch lasting
+/// `mismatch_cycles` cycles is reported as an error. Updates go ahead either way.
+pub async fn cross_check(client: &reqwest::Client, config: &RouterConfig, observed_ip: &str) {
+    let reason = "cross-checking the detected IPv4 with the router (PORKBUN_ROUTER_*)";
+    let router_ip = match explain::because(reason, router_ip(client, &config.source)).await {
+        Ok(ip) => ip,
+        Err(e) => {
+            warn!("Could not read the WAN address from the router: {}", e);
+            return;
+        }
+    };
+
+    let nat = upstream_nat(router_ip);
+    let was_behind_nat = BEHIND_NAT.swap(nat.is_some(), Ordering::Relaxed);
+    match nat {
+        Some(kind) if !was_behind_nat => warn!(
+            "Router WAN address {} is not public: this network is behind {}. DNS will point at {}, but port forwards on your router will not be reachable from the internet.",
+            router_ip, kind, observed_ip
+        ),
+        None if was_behind_nat => info!("Router WAN address {} is public again", router_ip),
+        _ => {}
+    }
+
+    if router_ip.to_string() == observed_ip {
+        if MISMATCHES.swap(0, Ordering::Relaxed) > 0 {
+            info!("Router and HTTP source agree again on {}", observed_ip);
+        }
+        return;
+    }
+
+    let count = MISMATCHES.fetch_add(1, Ordering::Relaxed) + 1;
+    if count >= config.mismatch_cycles {
+        error!(
+            "Router reports WAN address {} but the HTTP source sees {} ({} cycles in a row). This usually means double NAT or the wrong interface.",
+            router_ip, observed_ip, count
+        );
+    } else {
+        warn!(
+            "Router reports WAN address {} but the HTTP source sees {}",
+            router_ip, observed_ip
+        );
+    }
+}
@@ -0,0 +1,138 @@
+// src/schedule.rs
+
+//! Waiting for the next check cycle.
+
+use crate::clock::{Clock, SystemClock};
+use log::{info, warn};
+use std::time::Duration;
+
+/// How long each monotonic sleep step lasts while waiting for the next check.
+const STEP: Duration = Duration::from_secs(15);
+
+/// How far the boot or wall clock may run ahead of a sleep step before it is treated as a
+/// suspend or a clock step.
+const JUMP_TOLERANCE: Duration = Duration::from_secs(30);
+
+/// Waits until the next check is due.
+///
+/// The schedule always follows the monotonic clock, so NTP corrections and a badly set RTC
+/// never stretch or shorten the interval. The monotonic clock does stop while the machine is
+/// suspended, though, so a single long sleep would resume where it left off and delay the
+/// first check after wake by the rest of the interval. Sleeping in short steps and comparing
+/// against the time since boot, which keeps counting through a suspend, lets a suspend be
+/// noticed on wake and the check run straight away. Where the time since boot is unavailable
+/// the wall clock is used instead, at the cost of also treating a forward clock correction as
+/// a suspend.
+pub async fn wait_for_next_check(interval: Duration) {
+    wait_for_next_check_on(&SystemClock, interval).await
+}
+
+/// `wait_for_next_check` on the given clocks.
+pub async fn wait_for_next_check_on(clock: &impl Clock, interval: Duration) {
+    let deadline = clock.now() + interval;
+
+    loop {
+        let now = clock.now();
+        if now >= deadline {
+            return;
+        }
+        let step = STEP.min(deadline - now);
+
+        let boot_before = clock.since_boot();
+        let wall_before = clock.wall();
+        clock.sleep(step).await;
+        let wall_elapsed = clock.wall().duration_since(wall_before);
+
+        match (boot_before, clock.since_boot()) {
+            (Some(before), Some(after)) => {
+                let elapsed = after.saturating_sub(before);
+                if elapsed > step + JUMP_TOLERANCE {
+                    warn!(
+                        "{} seconds passed during a {} second sleep; assuming the system was suspended and checking now",
+                        elapsed.as_secs(),
+                        step.as_secs()
+                    );
+                    return;
+                }
+                log_clock_step(elapsed, wall_elapsed);
+            }
+            _ => match wall_elapsed {
+                Ok(elapsed) if elapsed > step + JUMP_TOLERANCE => {
+                    warn!(
+                        "Wall clock advanced {} seconds during a {} second sleep; assuming the system was suspended and checking now",
+                        elapsed.as_secs(),
+                        step.as_secs()
+                    );
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => warn!(
+                    "System clock moved backwards by {} seconds; keeping the current schedule",
+                    e.duration().as_secs()
+                ),
+            },
+        }
+    }
+}
+
+/// Notes a wall-clock step that happened while the machine was awake, such as an NTP
+/// correction. The schedule is unaffected, but wall-clock times in the log jump.
+fn log_clock_step(elapsed: Duration, wall_elapsed: Result<Duration, std::time::SystemTimeError>) {
+    match wall_elapsed {
+        Ok(wall) if wall > elapsed + JUMP_TOLERANCE => info!(
+            "System clock stepped forward by {} seconds; keeping the current schedule",
+            (wall - elapsed).as_secs()
+        ),
+        Ok(wall) if wall + JUMP_TOLERANCE < elapsed => info!(
+            "System clock stepped backwards by {} seconds; keeping the current schedule",
+            (elapsed - wall).as_secs()
+        ),
+        Ok(_) => {}
+        Err(e) => info!(
+            "System clock stepped backwards by {} seconds; keeping the current schedule",
+            (e.duration() + elapsed).as_secs()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Jump, MockClock};
+
+    #[tokio::test]
+    async fn waits_the_whole_interval_in_short_steps() {
+        let clock = MockClock::new();
+        wait_for_next_check_on(&clock, Duration::from_secs(40)).await;
+        assert_eq!(
+            clock.sleeps(),
+            [15, 15, 10].map(Duration::from_secs).to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn a_suspend_ends_the_wait_early() {
+        let clock = MockClock::new();
+        clock.jump_during(2, Jump::Suspend(Duration::from_secs(3600)));
+        wait_for_next_check_on(&clock, Duration::from_secs(300)).await;
+        assert_eq!(clock.elapsed(), Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn wall_clock_steps_keep_the_schedule() {
+        let clock = MockClock::new();
+        clock.jump_during(1, Jump::WallForward(Duration::from_secs(3600)));
+        clock.jump_during(2, Jump::WallBackward(Duration::from_secs(3600)));
+        wait_for_next_check_on(&clock, Duration::from_secs(60)).await;
+        assert_eq!(clock.elapsed(), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn without_the_boot_clock_a_forward_step_counts_as_a_suspend() {
+        let clock = MockClock::new().without_boot_clock();
+        clock.jump_during(1, Jump::WallBackward(Duration::from_secs(3600)));
+        clock.jump_during(2, Jump::WallForward(Duration::from_secs(3600)));
+        wait_for_next_check_on(&clock, Duration::from_secs(60)).await;
+        assert_eq!(clock.elapsed(), Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,118 @@
+// src/secrets.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::vars::Vars;
+use age::armor::ArmoredReader;
+use age::secrecy::Secret;
+use age::{Decryptor, IdentityFile, IdentityFileEntry};
+use log::info;
+use std::fs::File;
+use std::io::{IsTerminal, Read};
+use std::sync::Mutex;
+
+/// The passphrase typed at the startup prompt, reused when the file is read again on a reload
+/// rather than prompting from the running daemon.
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Decrypts the age-encrypted env file named by `PORKBUN_ENCRYPTED_ENV`, if any, and sets each
+/// `KEY=value` line in `vars`. Variables that are already set win, matching how `.env` files
+/// are loaded.
+///
+/// Files encrypted to a recipient are opened with the identity file in `PORKBUN_AGE_IDENTITY`.
+/// Passphrase-encrypted files use `PORKBUN_AGE_PASSPHRASE`, or prompt for it on a terminal
+/// the first time.
+pub fn load_encrypted_env(vars: &mut Vars) -> Result<()> {
+    let Ok(path) = vars.var("PORKBUN_ENCRYPTED_ENV") else {
+        return Ok(());
+    };
+    let secret_error = |e: &dyn std::fmt::Display| {
+        DdnsError::Config(format!("Failed to decrypt '{}': {}", path, e))
+    };
+
+    let file = File::open(&path).map_err(|e| secret_error(&e))?;
+    let decryptor = Decryptor::new(ArmoredReader::new(file)).map_err(|e| secret_error(&e))?;
+    let mut reader = match decryptor {
+        Decryptor::Recipients(decryptor) => {
+            let identity_path = vars.var("PORKBUN_AGE_IDENTITY").map_err(|_| {
+                DdnsError::Config(format!(
+                    "'{}' is encrypted to a recipient; set PORKBUN_AGE_IDENTITY to the identity file.",
+                    path
+                ))
+            })?;
+            let identities = IdentityFile::from_file(identity_path.clone())
+                .map_err(|e| {
+                    DdnsError::Config(format!(
+                        "Failed to read age identity '{}': {}",
+                        identity_path, e
+                    ))
+                })?
+                .into_identities();
+            decryptor
+                .decrypt(identities.iter().filter_map(|entry| match entry {
+                    IdentityFileEntry::Native(identity) => Some(identity as &dyn age::Identity),
+                    #[allow(unreachable_patterns)]
+                    _ => None,
+                }))
+                .map_err(|e| secret_error(&e))?
+        }
+        Decryptor::Passphrase(decryptor) => {
+            let typed = PASSPHRASE.lock().unwrap().clone();
+            let passphrase = match (vars.var("PORKBUN_AGE_PASSPHRASE"), typed) {
+                (Ok(passphrase), _) | (Err(_), Some(passphrase)) => passphrase,
+                (Err(_), None) if std::io::stdin().is_terminal() => {
+                    let passphrase =
+                        rpassword::prompt_password(format!("Passphrase for {}: ", path))
+                            .map_err(|e| secret_error(&e))?;
+                    *PASSPHRASE.lock().unwrap() = Some(passphrase.clone());
+                    passphrase
+                }
+                (Err(_), None) => {
+                    return Err(DdnsError::Config(format!(
+                        "'{}' is passphrase-encrypted; set PORKBUN_AGE_PASSPHRASE or run on a terminal.",
+                        path
+                    )))
+                }
+            };
+            decryptor
+                .decrypt(&Secret::new(passphrase), None)
+                .map_err(|e| secret_error(&e))?
+        }
+    };
+
+    let mut contents = String::new();
+    reader
+        .read_to_string(&mut contents)
+        .map_err(|e| secret_error(&e))?;
+
+    let mut loaded = 0;
+    for (key, value) in parse_env(&contents) {
+        if vars.set_default(key, value) {
+            loaded += 1;
+        }
+    }
+    info!("Loaded {} variable(s) from encrypted file {}", loaded, path);
+    Ok(())
+}
+
+/// Parses `KEY=value` lines, skipping blanks and `#` comments and stripping matching quotes
+/// and an optional `export ` prefix.
+fn parse_env(contents: &str) -> impl Iterator<Item = (&str, &str)> {
+    contents.lines().filter_map(|line| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        let key = key.trim().trim_start_matches("export ").trim();
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|&quote| {
+                value
+                    .strip_prefix(quote)
+                    .and_then(|v| v.strip_suffix(quote))
+            })
+            .unwrap_or(value);
+        Some((key, value))
+    })
+}
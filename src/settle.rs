@@ -0,0 +1,171 @@
+// src/settle.rs
+
+use crate::clock::{Clock, SystemClock};
+use crate::errors::Result;
+use crate::explain;
+use crate::ip_fetcher::{self, IpDetector};
+use crate::porkbun::RecordType;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::time::Duration;
+
+/// Settle rounds before giving up and using whatever address was seen last.
+const MAX_ROUNDS: u32 = 10;
+
+/// The address of each type seen by the previous cycle.
+static LAST_IP: Mutex<BTreeMap<RecordType, String>> = Mutex::new(BTreeMap::new());
+
+/// Number of flaps coalesced since the process started.
+static FLAPS: AtomicU64 = AtomicU64::new(0);
+
+/// Waits for a newly detected address to hold steady for `settle_seconds` before it is used,
+/// so a flapping link produces one update with the final address instead of several edits
+/// back-to-back. Returns the address to use and the number of flaps seen while settling.
+///
+/// Nothing is delayed when the address is unchanged since the previous cycle, or on the
+/// first cycle, since there is nothing to compare with.
+pub async fn settle(
+    client: &reqwest::Client,
+    settle_seconds: u64,
+    detector: &IpDetector<'_>,
+    record_type: RecordType,
+    detected: String,
+) -> (String, u32) {
+    let previous = LAST_IP.lock().unwrap().get(&record_type).cloned();
+    let (current, flaps) = match previous {
+        Some(ip) if settle_seconds > 0 && ip != detected => {
+            wait_for_steady(
+                &SystemClock,
+                Duration::from_secs(settle_seconds),
+                record_type,
+                detected,
+                || ip_fetcher::get_current_ip(client, detector, record_type),
+            )
+            .await
+        }
+        _ => (detected, 0),
+    };
+    LAST_IP.lock().unwrap().insert(record_type, current.clone());
+    (current, flaps)
+}
+
+/// Asks for the address again every `interval` until the same answer comes back twice in a
+/// row, for at most `MAX_ROUNDS` rounds.
+async fn wait_for_steady<F, Fut>(
+    clock: &impl Clock,
+    interval: Duration,
+    record_type: RecordType,
+    detected: String,
+    mut recheck: F,
+) -> (String, u32)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let mut current = detected;
+    let mut flaps = 0;
+    info!(
+        "IP changed to {}, waiting {}s for it to settle",
+        current,
+        interval.as_secs()
+    );
+    explain::note(format!(
+        "The {} changed to {}; asking again every {}s until it holds steady",
+        record_type.family(),
+        current,
+        interval.as_secs()
+    ));
+    for _ in 0..MAX_ROUNDS {
+        clock.sleep(interval).await;
+        match recheck().await {
+            Ok(ip) if ip == current => break,
+            Ok(ip) => {
+                flaps += 1;
+                info!("IP changed again to {}, waiting for it to settle", ip);
+                current = ip;
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to re-check the IP while settling, using {}: {}",
+                    current, e
+                );
+                break;
+            }
+        }
+    }
+    if flaps > 0 {
+        FLAPS.fetch_add(u64::from(flaps), Ordering::Relaxed);
+        info!("IP settled on {} after {} flap(s)", current, flaps);
+    }
+    (current, flaps)
+}
+
+/// Total number of flaps coalesced so far.
+pub fn flap_count() -> u64 {
+    FLAPS.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::errors::DdnsError;
+    use std::cell::RefCell;
+
+    #[tokio::test]
+    async fn waits_until_the_address_holds_steady() {
+        let interval = Duration::from_secs(20);
+        let cases: [(&[&str], &str, u32, usize); 4] = [
+            // (answers to the re-checks, address used, flaps, waits)
+            (&["b"], "b", 0, 1),
+            (&["c", "c"], "c", 1, 2),
+            (&["c", "d", "d"], "d", 2, 3),
+            (&["c", "error"], "c", 1, 2),
+        ];
+        for (answers, used, flaps, waits) in cases {
+            let clock = MockClock::new();
+            let remaining = RefCell::new(answers.iter());
+            let result = wait_for_steady(&clock, interval, RecordType::A, "b".to_string(), || {
+                let answer = remaining.borrow_mut().next().copied();
+                async move {
+                    match answer {
+                        Some("error") | None => Err(DdnsError::IpDetection("down".to_string())),
+                        Some(ip) => Ok(ip.to_string()),
+                    }
+                }
+            })
+            .await;
+            assert_eq!(result, (used.to_string(), flaps), "answers {:?}", answers);
+            assert_eq!(
+                clock.sleeps(),
+                vec![interval; waits],
+                "answers {:?}",
+                answers
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_round() {
+        let clock = MockClock::new();
+        let mut n = 0;
+        let (ip, flaps) = wait_for_steady(
+            &clock,
+            Duration::from_secs(5),
+            RecordType::Aaaa,
+            "2001:db8::".to_string(),
+            || {
+                n += 1;
+                let ip = format!("2001:db8::{}", n);
+                async move { Ok(ip) }
+            },
+        )
+        .await;
+        assert_eq!(ip, format!("2001:db8::{}", MAX_ROUNDS));
+        assert_eq!(flaps, MAX_ROUNDS);
+        assert_eq!(clock.elapsed(), Duration::from_secs(5) * MAX_ROUNDS);
+    }
+}
@@ -0,0 +1,391 @@
+// src/simulate.rs
+
+//! `--simulate`: the Porkbun API and the IP services are replaced by fakes held in memory, so
+//! hooks, notifications and settings can be tried out without touching real records.
+
+use crate::errors::{DdnsError, Result};
+use crate::porkbun::RecordType;
+use crate::vars::Vars;
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const DEFAULT_IP_CHANGE_EVERY: u64 = 3;
+
+/// State files used instead of the real ones, so a simulation never consumes scheduled
+/// changes or leaves fake writes for `undo` to revert.
+const STATE_FILES: [(&str, &str); 5] = [
+    ("PORKBUN_JOURNAL_FILE", "porkbun-simulate-journal.jsonl"),
+    ("PORKBUN_IP_STATE_FILE", "porkbun-simulate-ip-state.json"),
+    ("PORKBUN_PLAN_FILE", "porkbun-simulate-plan.json"),
+    (
+        "PORKBUN_TTL_BACKUP_FILE",
+        "porkbun-simulate-ttl-backup.json",
+    ),
+    (
+        "PORKBUN_LAST_SUCCESS_FILE",
+        "porkbun-simulate-last-success.json",
+    ),
+];
+
+/// Where the Prometheus metrics of simulated cycles go, so they don't replace the real ones.
+const TEXTFILE: &str = "porkbun-simulate-metrics.prom";
+
+/// Settings that would tell real services about simulated cycles: a dead-man monitor would
+/// hear that fake updates succeeded, and the probe would measure addresses nobody moved to.
+const REAL_SERVICES: [&str; 2] = ["PORKBUN_HEALTHCHECK_URL", "PORKBUN_PROBE_TARGET"];
+
+/// How the fakes behave; every `*_every` value of 0 turns that behavior off.
+#[derive(Debug)]
+struct Simulation {
+    /// The fake API's base URL, on a loopback port.
+    base_url: String,
+    /// Cycles between changes of the public address; 0 keeps it fixed.
+    ip_change_every: u64,
+    /// Every Nth API request is refused.
+    api_error_every: u64,
+    /// Every Nth IP detection fails.
+    ip_error_every: u64,
+}
+
+#[derive(Debug)]
+struct FakeRecord {
+    id: u64,
+    name: String,
+    record_type: String,
+    content: String,
+    ttl: u64,
+}
+
+impl Simulation {
+    /// The simulated public address of a family in the given check cycle, counted from 1,
+    /// drawn from the documentation ranges.
+    fn address(&self, record_type: RecordType, cycle: u64) -> String {
+        let generation = cycle
+            .saturating_sub(1)
+            .checked_div(self.ip_change_every)
+            .unwrap_or(0);
+        let host = 10 + generation % 200;
+        match record_type {
+            RecordType::A => format!("203.0.113.{}", host),
+            RecordType::Aaaa => format!("2001:db8::{:x}", host),
+        }
+    }
+}
+
+static SIMULATION: OnceLock<Simulation> = OnceLock::new();
+
+static RECORDS: Mutex<Vec<FakeRecord>> = Mutex::new(Vec::new());
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+static CYCLES: AtomicU64 = AtomicU64::new(0);
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+static DETECTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Starts the fake API and points the configuration at it. Called before the configuration is
+/// loaded: credentials and a domain are filled in when missing, and the state files are
+/// replaced by simulation-only ones.
+pub async fn start() -> Result<()> {
+    let simulation_setting = |var_name: &str, default: u64| match env::var(var_name) {
+        Ok(value) => value
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| DdnsError::Config(format!("{} must be a valid number.", var_name))),
+        Err(_) => Ok(default),
+    };
+    let ip_change_every =
+        simulation_setting("PORKBUN_SIMULATE_IP_CHANGE_EVERY", DEFAULT_IP_CHANGE_EVERY)?;
+    let api_error_every = simulation_setting("PORKBUN_SIMULATE_API_ERROR_EVERY", 0)?;
+    let ip_error_every = simulation_setting("PORKBUN_SIMULATE_IP_ERROR_EVERY", 0)?;
+
+    let mut vars = Vars::from_env();
+    overrides(&mut vars);
+    vars.export();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let base_url = format!("http://{}/api/json/v3", listener.local_addr()?);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve(stream));
+        }
+    });
+
+    warn!(
+        "Simulation mode: Porkbun and the IP services are fakes on {}; no real records are changed",
+        base_url
+    );
+    info!(
+        "Simulated address changes every {} cycle(s), API errors every {} request(s), IP detection failures every {} attempt(s) (0 = never)",
+        ip_change_every, api_error_every, ip_error_every
+    );
+    let _ = SIMULATION.set(Simulation {
+        base_url,
+        ip_change_every,
+        api_error_every,
+        ip_error_every,
+    });
+    Ok(())
+}
+
+/// Fills in credentials, a domain and a short check interval when they are missing, points
+/// the state files and metrics at the simulation's own, and drops the health check and probe.
+/// Applied again whenever the configuration is rebuilt, so a reload can't switch a simulation
+/// to the real files or services.
+pub fn overrides(vars: &mut Vars) {
+    for (var_name, default) in [
+        ("PORKBUN_API_KEY", "pk1_simulated"),
+        ("PORKBUN_SECRET_API_KEY", "sk1_simulated"),
+        ("PORKBUN_CHECK_INTERVAL_SECONDS", "10"),
+    ] {
+        vars.set_default(var_name, default);
+    }
+    if !vars.is_set("PORKBUN_DOMAIN") && !vars.is_set("PORKBUN_DOMAINS") {
+        vars.set("PORKBUN_DOMAIN", "example.com");
+    }
+    for (var_name, path) in STATE_FILES {
+        vars.set(var_name, path);
+    }
+    if vars.is_set("PORKBUN_PROMETHEUS_TEXTFILE") {
+        vars.set("PORKBUN_PROMETHEUS_TEXTFILE", TEXTFILE);
+    }
+    for var_name in REAL_SERVICES {
+        vars.remove(var_name);
+    }
+}
+
+/// Whether `--simulate` is in effect.
+pub fn active() -> bool {
+    SIMULATION.get().is_some()
+}
+
+/// The fake API's base URL, when simulating.
+pub fn base_url() -> Option<&'static str> {
+    SIMULATION
+        .get()
+        .map(|simulation| simulation.base_url.as_str())
+}
+
+/// Counts a check cycle, which is what the simulated address changes with.
+pub fn start_cycle() {
+    CYCLES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The simulated public address of a family for the current cycle.
+pub fn address(record_type: RecordType) -> Result<String> {
+    let Some(simulation) = SIMULATION.get() else {
+        return Err(DdnsError::IpDetection(
+            "the simulated IP source is only available with --simulate".to_string(),
+        ));
+    };
+    let detection = DETECTIONS.fetch_add(1, Ordering::Relaxed) + 1;
+    if every(simulation.ip_error_every, detection) {
+        return Err(DdnsError::IpDetection(
+            "simulated IP service outage".to_string(),
+        ));
+    }
+    Ok(simulation.address(record_type, CYCLES.load(Ordering::Relaxed)))
+}
+
+fn every(n: u64, count: u64) -> bool {
+    n > 0 && count.is_multiple_of(n)
+}
+
+/// Answers one request on a connection, then closes it.
+async fn serve(mut stream: TcpStream) {
+    let Some((path, body)) = read_request(&mut stream).await else {
+        return;
+    };
+    let (status, response) = respond(&path, &body);
+    let response = response.to_string();
+    let reply = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        response.len(),
+        response
+    );
+    let _ = stream.write_all(reply.as_bytes()).await;
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<(String, Value)> {
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let n = stream.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+        request.extend_from_slice(&chunk[..n]);
+    };
+    let head = String::from_utf8_lossy(&request[..head_end]).to_string();
+    let length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().ok())
+                .flatten()
+        })
+        .unwrap_or(0);
+    while request.len() < head_end + length {
+        let n = stream.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+        request.extend_from_slice(&chunk[..n]);
+    }
+    let path = head.split_whitespace().nth(1)?.to_string();
+    let body = serde_json::from_slice(&request[head_end..head_end + length]).unwrap_or(Value::Null);
+    Some((path, body))
+}
+
+fn error(status: &'static str, message: &str) -> (&'static str, Value) {
+    (status, json!({"status": "ERROR", "message": message}))
+}
+
+/// Handles one API call against the in-memory zone.
+fn respond(path: &str, body: &Value) -> (&'static str, Value) {
+    let request = REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+    if SIMULATION
+        .get()
+        .is_some_and(|simulation| every(simulation.api_error_every, request))
+    {
+        return error("400 Bad Request", "Simulated API error");
+    }
+
+    let segments: Vec<&str> = path
+        .trim_start_matches("/api/json/v3/")
+        .split('/')
+        .collect();
+    let mut records = RECORDS.lock().unwrap();
+    let full_name = |domain: &str, subdomain: &str| {
+        if subdomain.is_empty() {
+            domain.to_string()
+        } else {
+            format!("{}.{}", subdomain, domain)
+        }
+    };
+    let field = |name: &str| body[name].as_str().unwrap_or_default().to_string();
+    let ttl = body["ttl"].as_u64().unwrap_or(600);
+    let listed = |records: Vec<&FakeRecord>| {
+        let records: Vec<Value> = records
+            .into_iter()
+            .map(|r| {
+                json!({
+                    "id": r.id.to_string(),
+                    "name": r.name,
+                    "type": r.record_type,
+                    "content": r.content,
+                    "ttl": r.ttl.to_string(),
+                })
+            })
+            .collect();
+        ("200 OK", json!({"status": "SUCCESS", "records": records}))
+    };
+
+    match segments.as_slice() {
+        ["ping"] => (
+            "200 OK",
+            json!({"status": "SUCCESS", "yourIp": address(RecordType::A).unwrap_or_default()}),
+        ),
+        ["dns", "retrieve", domain] => listed(
+            records
+                .iter()
+                .filter(|r| r.name == *domain || r.name.ends_with(&format!(".{}", domain)))
+                .collect(),
+        ),
+        ["dns", "retrieveByNameType", domain, record_type, rest @ ..] => {
+            let name = full_name(domain, rest.first().copied().unwrap_or_default());
+            listed(
+                records
+                    .iter()
+                    .filter(|r| r.name == name && r.record_type == *record_type)
+                    .collect(),
+            )
+        }
+        ["dns", "create", domain] => {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            records.push(FakeRecord {
+                id,
+                name: full_name(domain, &field("name")),
+                record_type: field("type"),
+                content: field("content"),
+                ttl,
+            });
+            ("200 OK", json!({"status": "SUCCESS", "id": id}))
+        }
+        ["dns", "edit", domain, id] => match records.iter_mut().find(|r| r.id.to_string() == *id) {
+            Some(record) => {
+                record.name = full_name(domain, &field("name"));
+                record.record_type = field("type");
+                record.content = field("content");
+                record.ttl = ttl;
+                ("200 OK", json!({"status": "SUCCESS"}))
+            }
+            None => error("400 Bad Request", "Invalid record ID."),
+        },
+        ["dns", "editByNameType", domain, record_type, rest @ ..] => {
+            let name = full_name(domain, rest.first().copied().unwrap_or_default());
+            let mut edited = 0;
+            for record in records
+                .iter_mut()
+                .filter(|r| r.name == name && r.record_type == *record_type)
+            {
+                record.content = field("content");
+                record.ttl = ttl;
+                edited += 1;
+            }
+            if edited > 0 {
+                ("200 OK", json!({"status": "SUCCESS"}))
+            } else {
+                error("400 Bad Request", "Edit error: No records found.")
+            }
+        }
+        ["dns", "delete", _, id] => {
+            let before = records.len();
+            records.retain(|r| r.id.to_string() != *id);
+            if records.len() < before {
+                ("200 OK", json!({"status": "SUCCESS"}))
+            } else {
+                error("400 Bad Request", "Invalid record ID.")
+            }
+        }
+        _ => error("404 Not Found", "Unknown API endpoint."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_changes_every_n_cycles_and_stays_fixed_for_zero() {
+        let simulation = |ip_change_every| Simulation {
+            base_url: String::new(),
+            ip_change_every,
+            api_error_every: 0,
+            ip_error_every: 0,
+        };
+        let cases = [
+            (3, 1, "203.0.113.10"),
+            (3, 3, "203.0.113.10"),
+            (3, 4, "203.0.113.11"),
+            (1, 2, "203.0.113.11"),
+            (0, 1, "203.0.113.10"),
+            (0, 100, "203.0.113.10"),
+        ];
+        for (ip_change_every, cycle, expected) in cases {
+            assert_eq!(
+                simulation(ip_change_every).address(RecordType::A, cycle),
+                expected,
+                "every {} in cycle {}",
+                ip_change_every,
+                cycle
+            );
+        }
+        assert_eq!(simulation(0).address(RecordType::Aaaa, 7), "2001:db8::a");
+    }
+}
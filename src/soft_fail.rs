@@ -0,0 +1,110 @@
+// src/soft_fail.rs
+
+//! In soft-fail mode, an address family that can't be detected, such as IPv6 on a box with
+//! only IPv4, is warned about once and then left out of the cycles, instead of failing every
+//! one of them. Detection is tried again hourly, and the family is back once it succeeds.
+
+use crate::clock::Clock;
+use crate::errors::DdnsError;
+use crate::porkbun::RecordType;
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// How long a family is left out after its detection failed.
+const RETRY_AFTER: Duration = Duration::from_secs(3600);
+
+/// The families left out, with when their detection last failed.
+#[derive(Debug, Default)]
+struct LeftOut(BTreeMap<RecordType, Instant>);
+
+static LEFT_OUT: Mutex<LeftOut> = Mutex::new(LeftOut(BTreeMap::new()));
+
+impl LeftOut {
+    /// Whether the family's address should be detected at `now`: unless its detection failed
+    /// less than `RETRY_AFTER` ago.
+    fn due(&self, record_type: RecordType, now: Instant) -> bool {
+        self.0
+            .get(&record_type)
+            .is_none_or(|&failed| now.duration_since(failed) >= RETRY_AFTER)
+    }
+
+    /// Leaves the family out from `now`. Returns whether it is newly left out.
+    fn failed(&mut self, record_type: RecordType, now: Instant) -> bool {
+        self.0.insert(record_type, now).is_none()
+    }
+
+    /// Takes the family back in. Returns whether it had been left out.
+    fn detected(&mut self, record_type: RecordType) -> bool {
+        self.0.remove(&record_type).is_some()
+    }
+}
+
+/// Whether the family's address should be detected this cycle.
+pub fn due(clock: &impl Clock, record_type: RecordType) -> bool {
+    LEFT_OUT.lock().unwrap().due(record_type, clock.now())
+}
+
+/// Leaves a family whose address couldn't be detected out of the cycles for a while, warning
+/// the first time.
+pub fn failed(clock: &impl Clock, record_type: RecordType, error: &DdnsError) {
+    if LEFT_OUT.lock().unwrap().failed(record_type, clock.now()) {
+        warn!(
+            "Can't detect the public {} address ({}); leaving {} records alone until it can be detected, trying again hourly (soft-fail mode).",
+            record_type.family(),
+            error,
+            record_type
+        );
+    } else {
+        debug!(
+            "Public {} address still unavailable: {}",
+            record_type.family(),
+            error
+        );
+    }
+}
+
+/// Takes a family back in once its address was detected.
+pub fn detected(record_type: RecordType) {
+    if LEFT_OUT.lock().unwrap().detected(record_type) {
+        info!(
+            "The public {} address can be detected again; managing {} records again",
+            record_type.family(),
+            record_type
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[tokio::test]
+    async fn failed_families_are_left_out_until_detected() {
+        let clock = MockClock::new();
+        let mut left_out = LeftOut::default();
+        assert!(left_out.due(RecordType::Aaaa, clock.now()));
+
+        assert!(left_out.failed(RecordType::Aaaa, clock.now()), "warned");
+        assert!(!left_out.due(RecordType::Aaaa, clock.now()));
+        assert!(left_out.due(RecordType::A, clock.now()), "other family");
+
+        clock.sleep(RETRY_AFTER).await;
+        assert!(left_out.due(RecordType::Aaaa, clock.now()), "tried hourly");
+        assert!(
+            !left_out.failed(RecordType::Aaaa, clock.now()),
+            "only warned once"
+        );
+        assert!(!left_out.due(RecordType::Aaaa, clock.now()));
+
+        assert!(left_out.detected(RecordType::Aaaa));
+        assert!(left_out.due(RecordType::Aaaa, clock.now()));
+        assert!(!left_out.detected(RecordType::Aaaa), "already back");
+        assert!(
+            left_out.failed(RecordType::Aaaa, clock.now()),
+            "warned again after coming back"
+        );
+    }
+}
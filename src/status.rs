@@ -0,0 +1,306 @@
+// src/status.rs
+
+use crate::build_info;
+use crate::ip_fetcher::{self, IpDetector};
+use crate::porkbun::{RecordType, Zone};
+use serde_json::{json, Value};
+use std::env;
+use std::fs::File;
+use std::io::IsTerminal;
+use std::process::Command;
+
+/// How the record table is printed.
+#[derive(Debug, Clone, Copy)]
+pub struct TableStyle {
+    /// Print every cell in full even when the table is wider than the terminal.
+    pub wide: bool,
+    /// Color the STATE column.
+    pub color: bool,
+}
+
+impl TableStyle {
+    /// Colors only go to a terminal, and not when `--no-color` or `NO_COLOR` asks otherwise.
+    pub fn new(wide: bool, no_color: bool) -> Self {
+        let color = !no_color
+            && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            && std::io::stdout().is_terminal();
+        TableStyle { wide, color }
+    }
+}
+
+/// The width of the terminal stdout goes to, from `COLUMNS` or else `stty`; `None` when it
+/// isn't a terminal or the width can't be told.
+fn terminal_width() -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    if let Some(columns) = env::var("COLUMNS").ok().and_then(|v| v.trim().parse().ok()) {
+        return Some(columns);
+    }
+    let tty = File::open("/dev/tty").ok()?;
+    let output = Command::new("stty").arg("size").stdin(tty).output().ok()?;
+    let size = String::from_utf8(output.stdout).ok()?;
+    size.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Shortens `cell` to `width` characters, marking the cut with `…`.
+fn truncate(cell: &str, width: usize) -> String {
+    if cell.chars().count() <= width {
+        return cell.to_string();
+    }
+    let kept: String = cell.chars().take(width.saturating_sub(1)).collect();
+    format!("{}…", kept)
+}
+
+/// The ANSI color of a record state: green when current, yellow when stale or its TTL isn't the
+/// configured one, red when missing or failed, dim when unknown.
+fn state_color(state: &str) -> &'static str {
+    match state {
+        "current" => "32",
+        "stale" | "ttl-drift" => "33",
+        "unknown" => "2",
+        _ => "31",
+    }
+}
+
+/// One managed record as `status` found it.
+struct Row {
+    host: String,
+    record_type: RecordType,
+    content: String,
+    ttl: Option<u32>,
+    /// The configured TTL, when the record holds another one.
+    want_ttl: Option<u32>,
+    /// `current`, `stale`, `ttl-drift`, `unknown`, `missing` or `error`.
+    state: &'static str,
+    error: Option<String>,
+}
+
+impl Row {
+    fn ttl_cell(&self) -> String {
+        match (self.ttl, self.want_ttl) {
+            (Some(ttl), Some(want)) => format!("{} (want {})", ttl, want),
+            (ttl, _) => ttl.map(|ttl| ttl.to_string()).unwrap_or_default(),
+        }
+    }
+
+    fn state_cell(&self) -> String {
+        match &self.error {
+            Some(e) => format!("{}: {}", self.state, e),
+            None => self.state.to_string(),
+        }
+    }
+}
+
+/// Finds the build that is running, the public addresses and every managed record (including
+/// mirrored ones) with its content, TTL and whether it matches the current public IP and
+/// configured TTL. Prints them as a table with a count per state, or with `json` as one JSON
+/// object:
+///
+/// ```json
+/// {"build": "0.1.0 (...)",
+///  "addresses": [{"type": "A", "address": "203.0.113.4", "source": "ipify", "error": null}],
+///  "records": [{"host": "www.example.com", "type": "A", "content": "203.0.113.4",
+///               "ttl": 600, "configured_ttl": null, "state": "current", "error": null}],
+///  "current": true}
+/// ```
+///
+/// Returns 0 when every record is current and 1 otherwise, so scripts can test the result.
+pub async fn run(
+    client: &reqwest::Client,
+    zones: &[Zone<'_>],
+    record_types: &[RecordType],
+    detector: &IpDetector<'_>,
+    style: TableStyle,
+    json: bool,
+) -> i32 {
+    let build = build_info::get().to_string();
+    let mut addresses = Vec::with_capacity(record_types.len());
+    for &record_type in record_types {
+        let detected = ip_fetcher::detect(client, detector, record_type)
+            .await
+            .map(|(ip, source)| (ip, source.to_string()))
+            .map_err(|e| e.to_string());
+        addresses.push((record_type, detected));
+    }
+
+    let mut rows = Vec::new();
+    for zone in zones {
+        for subdomain in zone.subdomains {
+            for (record_type, detected) in &addresses {
+                let current_ip = detected.as_ref().ok().map(|(ip, _)| ip);
+                let mut row = Row {
+                    host: zone.client.full_name(subdomain),
+                    record_type: *record_type,
+                    content: String::new(),
+                    ttl: None,
+                    want_ttl: None,
+                    state: "missing",
+                    error: None,
+                };
+                match zone.client.get_record(subdomain, *record_type).await {
+                    Ok(Some(record)) => {
+                        row.want_ttl = zone.client.ttl_drift(record.ttl, subdomain);
+                        row.state = match current_ip {
+                            Some(ip) if !record.has_content(ip) => "stale",
+                            Some(_) if row.want_ttl.is_some() => "ttl-drift",
+                            Some(_) => "current",
+                            None => "unknown",
+                        };
+                        row.ttl = record.ttl;
+                        row.content = record.content;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        row.state = "error";
+                        row.error = Some(e.to_string());
+                    }
+                }
+                rows.push(row);
+            }
+        }
+    }
+    let all_current = rows.iter().all(|row| row.state == "current");
+
+    if json {
+        print_json(&build, &addresses, &rows, all_current);
+    } else {
+        print_table(&build, &addresses, &rows, style);
+    }
+    i32::from(!all_current)
+}
+
+type Detected = (RecordType, std::result::Result<(String, String), String>);
+
+fn print_json(build: &str, addresses: &[Detected], rows: &[Row], all_current: bool) {
+    let addresses: Vec<Value> = addresses
+        .iter()
+        .map(|(record_type, detected)| {
+            let (address, source, error) = match detected {
+                Ok((ip, source)) => (Some(ip), Some(source), None),
+                Err(e) => (None, None, Some(e)),
+            };
+            json!({
+                "type": record_type.as_str(),
+                "address": address,
+                "source": source,
+                "error": error,
+            })
+        })
+        .collect();
+    let records: Vec<Value> = rows
+        .iter()
+        .map(|row| {
+            json!({
+                "host": row.host,
+                "type": row.record_type.as_str(),
+                "content": Some(&row.content).filter(|content| !content.is_empty()),
+                "ttl": row.ttl,
+                "configured_ttl": row.want_ttl,
+                "state": row.state,
+                "error": row.error,
+            })
+        })
+        .collect();
+    println!(
+        "{}",
+        json!({
+            "build": build,
+            "addresses": addresses,
+            "records": records,
+            "current": all_current,
+        })
+    );
+}
+
+fn print_table(build: &str, addresses: &[Detected], rows: &[Row], style: TableStyle) {
+    println!("Build: {}", build);
+    for (record_type, detected) in addresses {
+        match detected {
+            Ok((ip, source)) => {
+                println!("Public {}: {} (from {})", record_type.family(), ip, source)
+            }
+            Err(e) => println!("Public {}: unavailable ({})", record_type.family(), e),
+        }
+    }
+    println!();
+
+    let mut cells = vec![[
+        "HOST".to_string(),
+        "TYPE".to_string(),
+        "CONTENT".to_string(),
+        "TTL".to_string(),
+        "STATE".to_string(),
+    ]];
+    cells.extend(rows.iter().map(|row| {
+        [
+            row.host.clone(),
+            row.record_type.to_string(),
+            row.content.clone(),
+            row.ttl_cell(),
+            row.state_cell(),
+        ]
+    }));
+    let rows = cells;
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    // Past the terminal's width, long error states, then addresses, then hostnames are cut
+    // short, each down to a floor that keeps it recognizable.
+    if let Some(columns) = terminal_width().filter(|_| !style.wide) {
+        let gaps = 2 * (widths.len() - 1);
+        for (column, floor) in [(4, 12), (2, 15), (0, 20)] {
+            let total = widths.iter().sum::<usize>() + gaps;
+            if total <= columns {
+                break;
+            }
+            widths[column] = widths[column]
+                .saturating_sub(total - columns)
+                .max(floor.min(widths[column]));
+        }
+    }
+
+    for (i, row) in rows.iter().enumerate() {
+        let state = truncate(&row[4], widths[4]);
+        let state = match (style.color, i) {
+            (true, 1..) => {
+                let kind = row[4].split(':').next().unwrap_or_default();
+                format!("\x1b[{}m{}\x1b[0m", state_color(kind), state)
+            }
+            _ => state,
+        };
+        println!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {:>w3$}  {}",
+            truncate(&row[0], widths[0]),
+            row[1],
+            truncate(&row[2], widths[2]),
+            row[3],
+            state,
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3]
+        );
+    }
+
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for row in rows.iter().skip(1) {
+        let kind = row[4].split(':').next().unwrap_or_default();
+        match counts.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((kind, 1)),
+        }
+    }
+    if !counts.is_empty() {
+        let counts: Vec<String> = counts
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+        println!();
+        println!("{}", counts.join(", "));
+    }
+}
@@ -0,0 +1,228 @@
+// src/stun.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::explain;
+use crate::porkbun::RecordType;
+use crate::random;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::{timeout, Duration};
+
+/// The STUN server used unless `PORKBUN_STUN_SERVER` names another.
+pub const DEFAULT_SERVER: &str = "stun.l.google.com:19302";
+
+/// UDP gives no delivery guarantee, so a request is sent this many times before giving up.
+const ATTEMPTS: u32 = 3;
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(2);
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+/// Asks a STUN server (RFC 5389) which address our UDP packets arrive from, over the address
+/// family of the record type. This is the public address as seen from outside, like the
+/// HTTP echo services, but a single round trip and unaffected by HTTP egress filtering.
+pub async fn mapped_address(server: &str, record_type: RecordType) -> Result<IpAddr> {
+    let stun_error =
+        |message: String| DdnsError::IpDetection(format!("STUN {}: {}", server, message));
+
+    let remote = lookup_host(server)
+        .await
+        .map_err(|e| stun_error(e.to_string()))?
+        .find(|addr| match record_type {
+            RecordType::A => addr.is_ipv4(),
+            RecordType::Aaaa => addr.is_ipv6(),
+        })
+        .ok_or_else(|| stun_error(format!("no {} address", record_type.family())))?;
+    let local: SocketAddr = match record_type {
+        RecordType::A => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        RecordType::Aaaa => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    explain::call("stun", format!("binding request to {}", remote));
+    let socket = UdpSocket::bind(local)
+        .await
+        .map_err(|e| stun_error(e.to_string()))?;
+    socket
+        .connect(remote)
+        .await
+        .map_err(|e| stun_error(e.to_string()))?;
+
+    let mut transaction_id = [0u8; 12];
+    transaction_id[..8].copy_from_slice(&random::random_u64().to_be_bytes());
+    transaction_id[8..].copy_from_slice(&(random::random_u64() as u32).to_be_bytes());
+    let mut request = Vec::with_capacity(20);
+    request.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+    request.extend_from_slice(&0u16.to_be_bytes());
+    request.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request.extend_from_slice(&transaction_id);
+
+    let mut buf = [0u8; 1024];
+    for _ in 0..ATTEMPTS {
+        socket
+            .send(&request)
+            .await
+            .map_err(|e| stun_error(e.to_string()))?;
+        match timeout(ATTEMPT_TIMEOUT, socket.recv(&mut buf)).await {
+            Ok(Ok(len)) => {
+                return parse_response(&buf[..len], &transaction_id)
+                    .ok_or_else(|| stun_error("unusable response".to_string()))
+            }
+            Ok(Err(e)) => return Err(stun_error(e.to_string())),
+            Err(_) => continue,
+        }
+    }
+    Err(stun_error("no response".to_string()))
+}
+
+/// Picks the mapped address out of a binding success response, preferring the XOR-mapped
+/// form that NAT devices can't mangle.
+fn parse_response(response: &[u8], transaction_id: &[u8; 12]) -> Option<IpAddr> {
+    let u16_at = |at: usize| {
+        Some(u16::from_be_bytes(
+            response.get(at..at + 2)?.try_into().ok()?,
+        ))
+    };
+    if u16_at(0)? != BINDING_SUCCESS
+        || response.get(4..8)? != MAGIC_COOKIE.to_be_bytes()
+        || response.get(8..20)? != transaction_id
+    {
+        return None;
+    }
+    let end = 20 + usize::from(u16_at(2)?);
+
+    let mut mapped = None;
+    let mut at = 20;
+    while at + 4 <= end.min(response.len()) {
+        let attr_type = u16_at(at)?;
+        let len = usize::from(u16_at(at + 2)?);
+        let value = response.get(at + 4..at + 4 + len)?;
+        match attr_type {
+            ATTR_XOR_MAPPED_ADDRESS => return decode_address(value, Some(transaction_id)),
+            ATTR_MAPPED_ADDRESS => mapped = decode_address(value, None),
+            _ => {}
+        }
+        // Attributes are padded to a multiple of four bytes.
+        at += 4 + len.div_ceil(4) * 4;
+    }
+    mapped
+}
+
+/// Decodes a (XOR-)MAPPED-ADDRESS value: reserved byte, family, port, then the address,
+/// XORed with the magic cookie and transaction ID in the XOR form.
+fn decode_address(value: &[u8], xor_with: Option<&[u8; 12]>) -> Option<IpAddr> {
+    let mut mask = [0u8; 16];
+    if let Some(transaction_id) = xor_with {
+        mask[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        mask[4..].copy_from_slice(transaction_id);
+    }
+    let unmask = |bytes: &[u8]| -> Vec<u8> { bytes.iter().zip(mask).map(|(b, m)| b ^ m).collect() };
+    match value.get(1)? {
+        0x01 => {
+            let octets: [u8; 4] = unmask(value.get(4..8)?).try_into().ok()?;
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        0x02 => {
+            let octets: [u8; 16] = unmask(value.get(4..20)?).try_into().ok()?;
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The transaction ID of the sample responses in RFC 5769.
+    const TRANSACTION_ID: [u8; 12] = [
+        0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    ];
+
+    /// RFC 5769 section 2.2: a response carrying 192.0.2.1 port 32853.
+    const IPV4_RESPONSE: [u8; 80] = [
+        0x01, 0x01, 0x00, 0x3c, 0x21, 0x12, 0xa4, 0x42, 0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6,
+        0x86, 0xfa, 0x87, 0xdf, 0xae, 0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74, 0x20, 0x76,
+        0x65, 0x63, 0x74, 0x6f, 0x72, 0x20, 0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0xa1, 0x47, 0xe1,
+        0x12, 0xa6, 0x43, 0x00, 0x08, 0x00, 0x14, 0x2b, 0x91, 0xf5, 0x99, 0xfd, 0x9e, 0x90, 0xc3,
+        0x8c, 0x74, 0x89, 0xf9, 0x2a, 0xf9, 0xba, 0x53, 0xf0, 0x6b, 0xe7, 0xd7, 0x80, 0x28, 0x00,
+        0x04, 0xc0, 0x7d, 0x4c, 0x96,
+    ];
+
+    /// RFC 5769 section 2.3: a response carrying 2001:db8:1234:5678:11:2233:4455:6677 port
+    /// 32853.
+    const IPV6_RESPONSE: [u8; 92] = [
+        0x01, 0x01, 0x00, 0x48, 0x21, 0x12, 0xa4, 0x42, 0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6,
+        0x86, 0xfa, 0x87, 0xdf, 0xae, 0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74, 0x20, 0x76,
+        0x65, 0x63, 0x74, 0x6f, 0x72, 0x20, 0x00, 0x20, 0x00, 0x14, 0x00, 0x02, 0xa1, 0x47, 0x01,
+        0x13, 0xa9, 0xfa, 0xa5, 0xd3, 0xf1, 0x79, 0xbc, 0x25, 0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9,
+        0x00, 0x08, 0x00, 0x14, 0xa3, 0x82, 0x95, 0x4e, 0x4b, 0xe6, 0x7b, 0xf1, 0x17, 0x84, 0xc9,
+        0x7c, 0x82, 0x92, 0xc2, 0x75, 0xbf, 0xe3, 0xed, 0x41, 0x80, 0x28, 0x00, 0x04, 0xc8, 0xfb,
+        0x0b, 0x4c,
+    ];
+
+    #[test]
+    fn parses_the_rfc_5769_sample_responses() {
+        let cases: [(&str, &[u8], &str); 2] = [
+            ("IPv4", &IPV4_RESPONSE, "192.0.2.1"),
+            (
+                "IPv6",
+                &IPV6_RESPONSE,
+                "2001:db8:1234:5678:11:2233:4455:6677",
+            ),
+        ];
+        for (case, response, expected) in cases {
+            assert_eq!(
+                parse_response(response, &TRANSACTION_ID),
+                Some(expected.parse().unwrap()),
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_plain_mapped_address() {
+        let mut response = IPV4_RESPONSE[..20].to_vec();
+        response[3] = 12;
+        response.extend_from_slice(&[0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x80, 0x55]);
+        response.extend_from_slice(&[192, 0, 2, 1]);
+        assert_eq!(
+            parse_response(&response, &TRANSACTION_ID),
+            Some("192.0.2.1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn rejects_responses_to_another_request() {
+        let mut transaction_id = TRANSACTION_ID;
+        transaction_id[11] ^= 0x01;
+        assert_eq!(parse_response(&IPV4_RESPONSE, &transaction_id), None);
+        assert_eq!(parse_response(&IPV6_RESPONSE, &transaction_id), None);
+    }
+
+    #[test]
+    fn rejects_truncated_responses() {
+        let cases = [
+            ("empty", 0),
+            ("cut in the header", 12),
+            ("header only", 20),
+            ("cut in the XOR-MAPPED-ADDRESS header", 38),
+            ("cut in the XOR-MAPPED-ADDRESS value", 46),
+        ];
+        for (case, len) in cases {
+            assert_eq!(
+                parse_response(&IPV4_RESPONSE[..len], &TRANSACTION_ID),
+                None,
+                "{}",
+                case
+            );
+        }
+        // An IPv6 family with only an IPv4-sized address.
+        assert_eq!(
+            decode_address(&IPV6_RESPONSE[40..48], Some(&TRANSACTION_ID)),
+            None
+        );
+    }
+}
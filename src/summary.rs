@@ -0,0 +1,297 @@
+// src/summary.rs
+
+use crate::ip_fetcher::IpSource;
+use crate::porkbun::RecordType;
+use crate::settle;
+use log::{info, warn};
+use std::fmt;
+
+/// What happened to one address family in a cycle.
+#[derive(Debug)]
+pub enum FamilyStatus {
+    /// The address was detected and the family's records were synced to it.
+    Synced(String),
+    /// The address could not be detected, so the family's records were left alone.
+    Unavailable,
+    /// The address was detected but refused by the expected-network checks.
+    #[cfg(feature = "geoip")]
+    Refused(String),
+}
+
+impl fmt::Display for FamilyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FamilyStatus::Synced(ip) => write!(f, "{}", ip),
+            FamilyStatus::Unavailable => write!(f, "unavailable"),
+            #[cfg(feature = "geoip")]
+            FamilyStatus::Refused(ip) => write!(f, "{} refused", ip),
+        }
+    }
+}
+
+/// How a one-shot run went as a whole, with the process exit code for each outcome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every needed change was made.
+    Applied = 0,
+    /// Nothing could be done: no address was usable or every needed change failed.
+    Failed = 1,
+    /// Some changes were made and others failed, or an address family was skipped while the
+    /// others were synced.
+    Partial = 2,
+    /// Every record already matched.
+    NothingToDo = 3,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Applied => "applied",
+            Outcome::Failed => "failed",
+            Outcome::Partial => "partial",
+            Outcome::NothingToDo => "nothing-to-do",
+        }
+    }
+}
+
+/// A record moved to a new address, or created with one.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub name: String,
+    pub record_type: RecordType,
+    /// `None` when the record was created.
+    pub old_ip: Option<String>,
+    pub new_ip: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} -> {}",
+            self.name,
+            self.record_type,
+            self.old_ip.as_deref().unwrap_or("(new)"),
+            self.new_ip
+        )
+    }
+}
+
+/// Per-host outcomes collected over one check cycle.
+#[derive(Debug, Default)]
+pub struct CycleSummary {
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub failed: Vec<(String, String)>,
+    /// What each record in `updated` was changed from and to.
+    pub changes: Vec<Change>,
+    /// The outcome for each configured address family, in configuration order.
+    pub families: Vec<(RecordType, FamilyStatus)>,
+    /// The service that reported each detected address.
+    pub ip_sources: Vec<(RecordType, IpSource)>,
+    /// IP changes coalesced while waiting for the address to settle.
+    pub flaps: u32,
+    /// Whether `updated` lists writes that were only logged.
+    pub dry_run: bool,
+    /// The tenant the cycle ran for, when several share a config file.
+    pub tenant: Option<String>,
+}
+
+impl CycleSummary {
+    pub fn record_failure(&mut self, name: &str, reason: impl ToString) {
+        self.failed.push((name.to_string(), reason.to_string()));
+    }
+
+    /// Whether any host failed or any address family was not synced.
+    pub fn degraded(&self) -> bool {
+        !self.failed.is_empty()
+            || self
+                .families
+                .iter()
+                .any(|(_, status)| !matches!(status, FamilyStatus::Synced(_)))
+    }
+
+    /// Folds another summary, such as that of the scheduled changes applied after a cycle,
+    /// into this one.
+    pub fn merge(&mut self, other: CycleSummary) {
+        self.updated.extend(other.updated);
+        self.unchanged.extend(other.unchanged);
+        self.failed.extend(other.failed);
+        self.changes.extend(other.changes);
+    }
+
+    /// Whether at least one address family was synced, or none were tracked.
+    fn any_family_synced(&self) -> bool {
+        self.families.is_empty()
+            || self
+                .families
+                .iter()
+                .any(|(_, status)| matches!(status, FamilyStatus::Synced(_)))
+    }
+
+    /// Sums the run up. Anything `degraded` is `Partial` or `Failed`, so the exit code agrees
+    /// with the healthcheck ping.
+    pub fn outcome(&self) -> Outcome {
+        if !self.degraded() {
+            return if self.updated.is_empty() {
+                Outcome::NothingToDo
+            } else {
+                Outcome::Applied
+            };
+        }
+        if !self.updated.is_empty() {
+            return Outcome::Partial;
+        }
+        if !self.failed.is_empty() || !self.any_family_synced() {
+            return Outcome::Failed;
+        }
+        // Nothing needed changing in the families that were synced, but one was skipped.
+        Outcome::Partial
+    }
+
+    /// The run as one JSON document: the outcome, the changes made and every change that was
+    /// not, for CI pipelines and scripts to act on.
+    pub fn to_json(&self) -> serde_json::Value {
+        let skipped: Vec<serde_json::Value> = self
+            .families
+            .iter()
+            .filter(|(_, status)| !matches!(status, FamilyStatus::Synced(_)))
+            .map(|(record_type, status)| {
+                serde_json::json!({
+                    "record_type": record_type.as_str(),
+                    "family": record_type.family(),
+                    "status": status.to_string(),
+                })
+            })
+            .collect();
+        let unapplied: Vec<serde_json::Value> = self
+            .failed
+            .iter()
+            .map(|(name, reason)| serde_json::json!({ "name": name, "error": reason }))
+            .collect();
+        let ip_sources: serde_json::Map<String, serde_json::Value> = self
+            .ip_sources
+            .iter()
+            .map(|(record_type, source)| (record_type.as_str().to_string(), source.as_str().into()))
+            .collect();
+        serde_json::json!({
+            "tenant": self.tenant,
+            "outcome": self.outcome().as_str(),
+            "dry_run": self.dry_run,
+            "applied": self.updated,
+            "unchanged": self.unchanged.len(),
+            "unapplied": unapplied,
+            "skipped_families": skipped,
+            "ip_sources": ip_sources,
+        })
+    }
+
+    /// Logs a single end-of-cycle line, followed by one line per failed host. In dual-stack
+    /// setups the line also shows what happened to each address family.
+    pub fn log(&self) {
+        let mut line = format!(
+            "{}: {} {}, {} unchanged, {} failed",
+            if self.dry_run {
+                "Dry-run cycle summary"
+            } else {
+                "Cycle summary"
+            },
+            self.updated.len(),
+            if self.dry_run { "to update" } else { "updated" },
+            self.unchanged.len(),
+            self.failed.len()
+        );
+        if self.flaps > 0 {
+            line.push_str(&format!(
+                ", {} flap(s) coalesced ({} since start)",
+                self.flaps,
+                settle::flap_count()
+            ));
+        }
+        if self.families.len() > 1 {
+            let families: Vec<String> = self
+                .families
+                .iter()
+                .map(|(record_type, status)| format!("{} {}", record_type.family(), status))
+                .collect();
+            line.push_str(&format!(" [{}]", families.join(", ")));
+        }
+        if !self.degraded() {
+            info!("{}", line);
+        } else {
+            warn!("{}", line);
+            for (name, reason) in &self.failed {
+                warn!("  {} failed: {}", name, reason);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(
+        updated: usize,
+        unchanged: usize,
+        failed: usize,
+        families: &[(RecordType, bool)],
+    ) -> CycleSummary {
+        CycleSummary {
+            updated: vec!["www.example.com".to_string(); updated],
+            unchanged: vec!["example.com".to_string(); unchanged],
+            failed: vec![("api.example.com".to_string(), "refused".to_string()); failed],
+            families: families
+                .iter()
+                .map(|&(record_type, synced)| {
+                    let status = if synced {
+                        FamilyStatus::Synced("192.0.2.1".to_string())
+                    } else {
+                        FamilyStatus::Unavailable
+                    };
+                    (record_type, status)
+                })
+                .collect(),
+            ..CycleSummary::default()
+        }
+    }
+
+    #[test]
+    fn outcomes() {
+        use RecordType::{Aaaa, A};
+        for (updated, unchanged, failed, families, outcome) in [
+            (1, 0, 0, &[(A, true)][..], Outcome::Applied),
+            (0, 1, 0, &[(A, true)][..], Outcome::NothingToDo),
+            (
+                0,
+                1,
+                0,
+                &[(A, true), (Aaaa, true)][..],
+                Outcome::NothingToDo,
+            ),
+            (1, 0, 1, &[(A, true)][..], Outcome::Partial),
+            (1, 0, 0, &[(A, true), (Aaaa, false)][..], Outcome::Partial),
+            (0, 1, 0, &[(A, true), (Aaaa, false)][..], Outcome::Partial),
+            (0, 0, 1, &[(A, true)][..], Outcome::Failed),
+            (0, 1, 1, &[(A, true)][..], Outcome::Failed),
+            (0, 0, 0, &[(A, false)][..], Outcome::Failed),
+            (0, 0, 0, &[(A, false), (Aaaa, false)][..], Outcome::Failed),
+            (0, 0, 0, &[][..], Outcome::NothingToDo),
+        ] {
+            let summary = summary(updated, unchanged, failed, families);
+            assert_eq!(
+                summary.outcome(),
+                outcome,
+                "{} updated, {} unchanged, {} failed, families {:?}",
+                updated,
+                unchanged,
+                failed,
+                families
+            );
+            assert_eq!(
+                summary.degraded(),
+                matches!(outcome, Outcome::Partial | Outcome::Failed)
+            );
+        }
+    }
+}
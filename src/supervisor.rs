@@ -0,0 +1,43 @@
+// src/supervisor.rs
+
+use std::any::Any;
+use std::future::{poll_fn, Future};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::Poll;
+
+/// Number of panics caught since the process started.
+static PANICS: AtomicU64 = AtomicU64::new(0);
+
+/// Runs a future to completion, turning a panic inside it into an error carrying the panic
+/// message so one bad host or cycle cannot take down the daemon loop.
+pub async fn catch_panic<F: Future>(future: F) -> std::result::Result<F::Output, String> {
+    let mut future = pin!(future);
+    poll_fn(
+        |cx| match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => {
+                PANICS.fetch_add(1, Ordering::Relaxed);
+                Poll::Ready(Err(panic_message(payload.as_ref())))
+            }
+        },
+    )
+    .await
+}
+
+/// Total number of panics caught so far.
+pub fn panic_count() -> u64 {
+    PANICS.load(Ordering::Relaxed)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
@@ -0,0 +1,94 @@
+// src/systemd.rs
+
+//! Readiness and watchdog notifications for running as a `Type=notify` systemd service. They
+//! go to the socket systemd passes in `NOTIFY_SOCKET`; without it nothing is sent.
+
+use log::warn;
+use std::env;
+use std::io;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+use tokio::process::Command;
+
+/// The variables systemd hands this service for notifications.
+const VARIABLES: [&str; 3] = ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"];
+
+/// Keeps a child process from notifying systemd or feeding the watchdog on this service's
+/// behalf, which would hide a hang of the service itself.
+pub fn isolate(command: &mut Command) -> &mut Command {
+    for key in VARIABLES {
+        command.env_remove(key);
+    }
+    command
+}
+
+/// Sends a state change to systemd. Failures are logged and otherwise ignored, as the updater
+/// works the same without them.
+fn notify(state: &str) {
+    let Ok(path) = env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&path, state) {
+        warn!("Failed to notify systemd at {}: {}", path, e);
+    }
+}
+
+fn send(path: &str, state: &str) -> io::Result<()> {
+    let address = match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            SocketAddr::from_abstract_name(name)?
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets are only available on Linux",
+            ))
+        }
+        None => SocketAddr::from_pathname(path)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+/// Tells systemd that startup has finished, with a status line for `systemctl status`.
+pub fn ready(status: &str) {
+    notify(&format!("READY=1\nSTATUS={}", status));
+}
+
+/// The watchdog timeout set with `WatchdogSec=`, if the watchdog is on for this process.
+fn watchdog_timeout() -> Option<Duration> {
+    let micros = env::var("WATCHDOG_USEC")
+        .ok()?
+        .parse::<u64>()
+        .ok()
+        .filter(|&n| n > 0)?;
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    Some(Duration::from_micros(micros))
+}
+
+/// Pings the watchdog, once a cycle has finished.
+pub fn watchdog() {
+    if watchdog_timeout().is_some() {
+        notify("WATCHDOG=1");
+    }
+}
+
+/// Pings the watchdog every half timeout for as long as it is polled, and never completes.
+/// Polled alongside the waits between cycles, so the interval can be longer than the timeout
+/// while a cycle that hangs for the whole timeout still gets the service restarted.
+pub async fn keep_alive() {
+    let Some(timeout) = watchdog_timeout() else {
+        return std::future::pending().await;
+    };
+    loop {
+        tokio::time::sleep(timeout / 2).await;
+        notify("WATCHDOG=1");
+    }
+}
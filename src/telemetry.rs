@@ -0,0 +1,205 @@
+// src/telemetry.rs
+
+use crate::build_info::{self, BuildInfo};
+use crate::config::Config;
+use crate::explain;
+use crate::summary::{CycleSummary, FamilyStatus};
+use crate::supervisor;
+use log::{info, warn};
+use serde::Serialize;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Seconds between reports unless configured otherwise.
+pub const DEFAULT_INTERVAL_SECONDS: u64 = 3600;
+
+/// Where and how often to report this instance to a self-hosted inventory endpoint.
+#[derive(Debug)]
+pub struct TelemetryConfig {
+    pub url: String,
+    /// Sent as a bearer token when set.
+    pub token: Option<String>,
+    pub interval_seconds: u64,
+    /// Identifies this instance in the inventory; defaults to the hostname.
+    pub instance: String,
+}
+
+/// The outcome of the most recent check cycle, as reported.
+#[derive(Debug, Clone, Serialize)]
+struct Health {
+    status: &'static str,
+    last_cycle_at: i64,
+    updated: usize,
+    unchanged: usize,
+    failed: usize,
+}
+
+/// One report. Nothing here identifies the Porkbun account: credentials and hostnames are left
+/// out, and the configuration is only sent as a hash.
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    instance: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<&'a str>,
+    version: &'static str,
+    build: BuildInfo,
+    config_hash: String,
+    managed_hosts: usize,
+    record_types: usize,
+    uptime_seconds: u64,
+    panics: u64,
+    health: Option<Health>,
+}
+
+/// When the first report was due and when the last one was sent, on the monotonic clock so
+/// clock corrections don't skip or repeat reports.
+static STARTED_AT: Mutex<Option<Instant>> = Mutex::new(None);
+
+static LAST_REPORT: Mutex<Option<Instant>> = Mutex::new(None);
+
+static LAST_CYCLE: Mutex<Option<Health>> = Mutex::new(None);
+
+/// The name to report when `PORKBUN_INSTANCE_NAME` is unset.
+pub fn default_instance() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Notes the outcome of a finished check cycle for the next report. Dry runs, which change
+/// nothing, are not counted.
+pub fn record_cycle(summary: &CycleSummary) {
+    if summary.dry_run {
+        return;
+    }
+    let synced = summary
+        .families
+        .iter()
+        .any(|(_, status)| matches!(status, FamilyStatus::Synced(_)));
+    let status = if !synced {
+        "failing"
+    } else if summary.degraded() {
+        "degraded"
+    } else {
+        "ok"
+    };
+    *LAST_CYCLE.lock().unwrap() = Some(Health {
+        status,
+        last_cycle_at: chrono::Utc::now().timestamp(),
+        updated: summary.updated.len(),
+        unchanged: summary.unchanged.len(),
+        failed: summary.failed.len(),
+    });
+}
+
+/// A stable hash of the settings that shape what the instance manages, so instances that
+/// drifted from the fleet's configuration stand out. Secrets are not part of it.
+fn config_hash(config: &Config) -> String {
+    let mut settings = vec![
+        format!("domain={}", config.domain),
+        format!("subdomains={}", config.subdomains.join(",")),
+    ];
+    for zone in &config.extra_zones {
+        settings.push(format!(
+            "zone={}:{}",
+            zone.domain,
+            zone.subdomains.join(",")
+        ));
+    }
+    if let Some(mirror) = &config.mirror {
+        settings.push(format!("mirror={}", mirror.domain));
+    }
+    let record_types: Vec<&str> = config.record_types.iter().map(|t| t.as_str()).collect();
+    settings.push(format!("record_types={}", record_types.join(",")));
+    settings.push(format!(
+        "ttl={}",
+        config.ttl.map_or("keep".to_string(), |ttl| ttl.to_string())
+    ));
+    if !config.host_ttls.is_empty() {
+        let mut host_ttls: Vec<String> = config
+            .host_ttls
+            .iter()
+            .map(|(host, ttl)| format!("{}={}", host, ttl))
+            .collect();
+        host_ttls.sort();
+        settings.push(format!("host_ttls={}", host_ttls.join(";")));
+    }
+    settings.push(format!("auto_create={}", config.auto_create));
+    settings.push(format!("max_writes={:?}", config.max_writes_per_cycle));
+    settings.push(format!("interval={}", config.check_interval_seconds));
+    settings.push(format!("on_demand={}", config.on_demand));
+
+    // FNV-1a, which unlike the std hashers is the same across builds and platforms.
+    let hash = settings
+        .join("\n")
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+fn managed_hosts(config: &Config) -> usize {
+    let mirrored = if config.mirror.is_some() { 2 } else { 1 };
+    config.subdomains.len() * mirrored
+        + config
+            .extra_zones
+            .iter()
+            .map(|zone| zone.subdomains.len())
+            .sum::<usize>()
+}
+
+/// Sends a report if one is due. Dry runs don't report. Failures are only logged; reporting
+/// never affects updates.
+pub async fn report_if_due(client: &reqwest::Client, config: &Config) {
+    let Some(telemetry) = config.telemetry.as_ref().filter(|_| !config.dry_run) else {
+        return;
+    };
+    let started_at = *STARTED_AT.lock().unwrap().get_or_insert_with(Instant::now);
+    {
+        let mut last = LAST_REPORT.lock().unwrap();
+        if last.is_some_and(|at| at.elapsed() < Duration::from_secs(telemetry.interval_seconds)) {
+            explain::note("Instance report not due yet; nothing sent");
+            return;
+        }
+        *last = Some(Instant::now());
+    }
+
+    let report = Report {
+        instance: &telemetry.instance,
+        tenant: config.tenant.as_deref(),
+        version: env!("CARGO_PKG_VERSION"),
+        build: build_info::get(),
+        config_hash: config_hash(config),
+        managed_hosts: managed_hosts(config),
+        record_types: config.record_types.len(),
+        uptime_seconds: started_at.elapsed().as_secs(),
+        panics: supervisor::panic_count(),
+        health: LAST_CYCLE.lock().unwrap().clone(),
+    };
+
+    let reason = format!(
+        "instance report, sent at most every {}s (PORKBUN_TELEMETRY_*)",
+        telemetry.interval_seconds
+    );
+    let sent = explain::because(reason, async {
+        explain::call("telemetry", format!("POST {}", telemetry.url));
+        let mut request = client.post(&telemetry.url).json(&report);
+        if let Some(token) = &telemetry.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await
+    })
+    .await;
+    match sent.and_then(|res| res.error_for_status()) {
+        Ok(_) => info!("Reported instance status to {}", telemetry.url),
+        Err(e) => warn!(
+            "Failed to report instance status to {}: {}",
+            telemetry.url, e
+        ),
+    }
+}
@@ -0,0 +1,110 @@
+// src/template.rs
+
+//! Site variables, substituted for `{name}` in the settings that hold hostnames, so one
+//! configuration can be deployed to several sites with only a variable changed. A variable is
+//! set as `PORKBUN_VAR_<NAME>`, or under `[vars]` in a config file.
+
+use crate::vars::Vars;
+use std::collections::BTreeMap;
+use std::env;
+
+const PREFIX: &str = "PORKBUN_VAR_";
+
+/// The defined variables, keyed by lower-cased name.
+fn variables(vars: &Vars) -> BTreeMap<String, String> {
+    vars.iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(PREFIX)
+                .filter(|name| !name.is_empty())
+                .map(|name| (name.to_ascii_lowercase(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Replaces every `{name}` naming a defined variable with its value; names match
+/// case-insensitively. Other braces, such as the `{www,api}` and `{01..04}` patterns of host
+/// lists, are left alone.
+fn substitute(value: &str, variables: &BTreeMap<String, String>) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(open) = rest.find('{') {
+        result.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let replaced = after.find('}').and_then(|close| {
+            let value = variables.get(&after[..close].to_ascii_lowercase())?;
+            Some((value, close))
+        });
+        match replaced {
+            Some((value, close)) => {
+                result.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Reads a variable with site variables substituted into its value.
+pub fn var(vars: &Vars, key: &str) -> Result<String, env::VarError> {
+    vars.var(key)
+        .map(|value| substitute(&value, &variables(vars)))
+}
+
+/// Whether a brace group's contents could be a variable name, for pointing out undefined
+/// variables in errors.
+pub fn is_name(inner: &str) -> bool {
+    !inner.is_empty() && inner.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The environment variable that defines the variable `name`.
+pub fn key(name: &str) -> String {
+    format!("{}{}", PREFIX, name.to_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_defined_variables_only() {
+        let variables = BTreeMap::from([
+            ("site".to_string(), "ams".to_string()),
+            ("zone".to_string(), "example.com".to_string()),
+        ]);
+        let cases = [
+            ("www.{site}", "www.ams"),
+            ("{SITE}.{Zone}", "ams.example.com"),
+            ("{site}{site}", "amsams"),
+            ("web{01..04}.{site}", "web{01..04}.ams"),
+            ("{www,api}.{site}", "{www,api}.ams"),
+            ("{undefined}.{site}", "{undefined}.ams"),
+            ("{{site}}", "{ams}"),
+            ("{site", "{site"),
+            ("site}", "site}"),
+            ("{}", "{}"),
+            ("", ""),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(substitute(value, &variables), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn recognises_variable_names() {
+        let cases = [
+            ("site", true),
+            ("site_2", true),
+            ("01..04", false),
+            ("www,api", false),
+            ("", false),
+        ];
+        for (inner, expected) in cases {
+            assert_eq!(is_name(inner), expected, "{}", inner);
+        }
+    }
+}
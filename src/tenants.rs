@@ -0,0 +1,147 @@
+// src/tenants.rs
+
+//! Runs every tenant of a config file in a process of its own, so tenants share nothing but
+//! the binary: not credentials, state files, write budgets nor a crash.
+
+use crate::errors::{DdnsError, Result};
+use crate::systemd;
+use log::{error, info, warn};
+use std::process::Stdio;
+use tokio::process::{Child, Command};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout, Duration};
+
+/// Wait before restarting a tenant whose process exited, so a crashing tenant can't spin.
+const RESTART_DELAY: Duration = Duration::from_secs(30);
+
+/// How long a tenant gets to finish its cycle after SIGTERM before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Validates every tenant's configuration, then keeps one `run` process per tenant going
+/// until this process is stopped. Any invalid tenant stops the start-up, like an invalid
+/// configuration does for a single instance.
+pub async fn supervise(config_path: &str, tenants: &[String], force: bool) -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let tenant_command = |tenant: &str, command: &str| {
+        let mut cmd = Command::new(&exe);
+        cmd.args(["--config", config_path, "--tenant", tenant, command])
+            .stdin(Stdio::null());
+        // Only the supervisor talks to systemd; a tenant's own readiness or watchdog pings
+        // would say nothing about the supervisor.
+        systemd::isolate(&mut cmd);
+        if force {
+            cmd.arg("--force");
+        }
+        cmd
+    };
+
+    let mut invalid = Vec::new();
+    for tenant in tenants {
+        let output = tenant_command(tenant, "validate")
+            .stderr(Stdio::null())
+            .output()
+            .await?;
+        let result = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() {
+            info!("Tenant {}: {}", tenant, result);
+        } else {
+            error!("Tenant {}: {}", tenant, result);
+            invalid.push(tenant.as_str());
+        }
+    }
+    if !invalid.is_empty() {
+        return Err(DdnsError::Config(format!(
+            "invalid configuration for tenant(s): {}",
+            invalid.join(", ")
+        )));
+    }
+
+    let (stop, stopping) = watch::channel(false);
+    let mut running = JoinSet::new();
+    for tenant in tenants {
+        let mut cmd = tenant_command(tenant, "run");
+        // Only a last resort, should the supervisor go away without stopping the tenant.
+        cmd.kill_on_drop(true);
+        let tenant = tenant.clone();
+        let mut stopping = stopping.clone();
+        running.spawn(async move {
+            loop {
+                let mut child = match cmd.spawn() {
+                    Ok(child) => child,
+                    Err(e) => {
+                        error!("Could not run tenant {}: {}", tenant, e);
+                        tokio::select! {
+                            _ = sleep(RESTART_DELAY) => continue,
+                            _ = stopping.changed() => return,
+                        }
+                    }
+                };
+                info!(
+                    "Started tenant {} (pid {})",
+                    tenant,
+                    child.id().unwrap_or_default()
+                );
+                tokio::select! {
+                    status = child.wait() => match status {
+                        Ok(status) => error!("Tenant {} exited ({})", tenant, status),
+                        Err(e) => error!("Could not wait for tenant {}: {}", tenant, e),
+                    },
+                    _ = stopping.changed() => {
+                        stop_tenant(&tenant, &mut child).await;
+                        return;
+                    }
+                }
+                info!(
+                    "Restarting tenant {} in {} seconds",
+                    tenant,
+                    RESTART_DELAY.as_secs()
+                );
+                tokio::select! {
+                    _ = sleep(RESTART_DELAY) => {}
+                    _ = stopping.changed() => return,
+                }
+            }
+        });
+    }
+
+    systemd::ready(&format!("Supervising {} tenant(s)", tenants.len()));
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+        _ = systemd::keep_alive() => {}
+    }
+    info!("Stopping {} tenant(s)", tenants.len());
+    let _ = stop.send(true);
+    while running.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// Passes SIGTERM on to a tenant so it can finish its cycle, and kills it if it hasn't
+/// exited within `STOP_TIMEOUT`.
+async fn stop_tenant(tenant: &str, child: &mut Child) {
+    if let Some(pid) = child.id() {
+        // SAFETY: kill only sends a signal; the pid is our own child's, which can't have been
+        // reaped and reused as it hasn't been waited for.
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+    match timeout(STOP_TIMEOUT, child.wait()).await {
+        Ok(Ok(status)) => info!("Tenant {} stopped ({})", tenant, status),
+        Ok(Err(e)) => error!("Could not wait for tenant {}: {}", tenant, e),
+        Err(_) => {
+            warn!(
+                "Tenant {} did not stop within {} seconds; killing it",
+                tenant,
+                STOP_TIMEOUT.as_secs()
+            );
+            if let Err(e) = child.kill().await {
+                error!("Could not kill tenant {}: {}", tenant, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+// src/textfile.rs
+
+use crate::build_info;
+use crate::config::Config;
+use crate::summary::{CycleSummary, FamilyStatus};
+use crate::supervisor;
+use log::warn;
+use std::fmt::Write;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Totals since start, which the per-cycle summary doesn't keep.
+static CYCLES: AtomicU64 = AtomicU64::new(0);
+
+static UPDATES: AtomicU64 = AtomicU64::new(0);
+
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Escapes a label value as the exposition format requires.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Writes the outcome of a cycle to `PORKBUN_PROMETHEUS_TEXTFILE` in the Prometheus text
+/// format, for node_exporter's textfile collector. The file is written next to its final name
+/// and renamed into place, so the collector never reads half a file. Failures are only logged,
+/// and dry runs, which change nothing, are not written.
+pub fn write(config: &Config, summary: &CycleSummary) {
+    if summary.dry_run {
+        return;
+    }
+    let cycles = CYCLES.fetch_add(1, Ordering::Relaxed) + 1;
+    let updates = UPDATES.fetch_add(summary.updated.len() as u64, Ordering::Relaxed)
+        + summary.updated.len() as u64;
+    let failures = FAILURES.fetch_add(summary.failed.len() as u64, Ordering::Relaxed)
+        + summary.failed.len() as u64;
+    let Some(path) = &config.textfile else {
+        return;
+    };
+
+    // Every series carries the tenant, when there is one, so tenants sharing a collector
+    // directory stay apart.
+    let tenant = config
+        .tenant
+        .as_deref()
+        .map(|tenant| format!("tenant=\"{}\"", escape(tenant)));
+    let labels = |pairs: &[(&str, &str)]| {
+        let labels: Vec<String> = tenant
+            .iter()
+            .cloned()
+            .chain(
+                pairs
+                    .iter()
+                    .map(|(name, value)| format!("{}=\"{}\"", name, escape(value))),
+            )
+            .collect();
+        if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels.join(","))
+        }
+    };
+
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+        let _ = writeln!(out, "# HELP porkbun_ddns_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE porkbun_ddns_{} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "porkbun_ddns_{}{} {}", name, labels, value);
+        }
+    };
+
+    let build = build_info::get();
+    metric(
+        "build_info",
+        "gauge",
+        "The running build; always 1.",
+        &[(
+            labels(&[
+                ("version", build.version),
+                ("git_hash", build.git_hash),
+                ("target", build.target),
+                ("rustc", build.rustc),
+                ("features", &build.features.join(",")),
+            ]),
+            "1".to_string(),
+        )],
+    );
+    metric(
+        "last_cycle_timestamp_seconds",
+        "gauge",
+        "When the last check cycle finished.",
+        &[(labels(&[]), chrono::Utc::now().timestamp().to_string())],
+    );
+    metric(
+        "last_cycle_success",
+        "gauge",
+        "Whether the last check cycle synced every record (1) or not (0).",
+        &[(labels(&[]), u8::from(!summary.degraded()).to_string())],
+    );
+    let families: Vec<(String, String)> = summary
+        .families
+        .iter()
+        .map(|(record_type, status)| {
+            let (ip, synced) = match status {
+                FamilyStatus::Synced(ip) => (ip.as_str(), 1),
+                FamilyStatus::Unavailable => ("", 0),
+                #[cfg(feature = "geoip")]
+                FamilyStatus::Refused(ip) => (ip.as_str(), 0),
+            };
+            let source = summary
+                .ip_sources
+                .iter()
+                .find(|(t, _)| t == record_type)
+                .map_or("", |(_, source)| source.as_str());
+            (
+                labels(&[
+                    ("family", record_type.family()),
+                    ("ip", ip),
+                    ("source", source),
+                ]),
+                synced.to_string(),
+            )
+        })
+        .collect();
+    metric(
+        "public_ip_info",
+        "gauge",
+        "The detected public address of each family; 1 when records were synced to it.",
+        &families,
+    );
+    let hosts: Vec<(String, String)> = [
+        ("updated", &summary.updated),
+        ("unchanged", &summary.unchanged),
+    ]
+    .into_iter()
+    .flat_map(|(state, names)| {
+        names
+            .iter()
+            .map(move |name| (labels(&[("name", name), ("state", state)]), "1".to_string()))
+    })
+    .chain(summary.failed.iter().map(|(name, _)| {
+        (
+            labels(&[("name", name), ("state", "failed")]),
+            "1".to_string(),
+        )
+    }))
+    .collect();
+    metric(
+        "record_state",
+        "gauge",
+        "What the last check cycle did to each managed record.",
+        &hosts,
+    );
+    metric(
+        "cycles_total",
+        "counter",
+        "Check cycles since start.",
+        &[(labels(&[]), cycles.to_string())],
+    );
+    metric(
+        "record_updates_total",
+        "counter",
+        "Records created or updated since start.",
+        &[(labels(&[]), updates.to_string())],
+    );
+    metric(
+        "record_failures_total",
+        "counter",
+        "Records that failed to sync since start.",
+        &[(labels(&[]), failures.to_string())],
+    );
+    metric(
+        "panics_total",
+        "counter",
+        "Panics caught since start.",
+        &[(labels(&[]), supervisor::panic_count().to_string())],
+    );
+
+    let temp_path = format!("{}.tmp", path);
+    if let Err(e) = fs::write(&temp_path, out).and_then(|_| fs::rename(&temp_path, path)) {
+        warn!("Failed to write metrics to {}: {}", path, e);
+    }
+}
@@ -0,0 +1,242 @@
+// src/ttl_change.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::integrity;
+use crate::porkbun::{RecordType, Zone, MIN_TTL};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs;
+
+const DEFAULT_BACKUP_FILE: &str = "porkbun-ttl-backup.json";
+
+/// A record's TTL as it was before `pre-change` lowered it.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedTtl {
+    domain: String,
+    subdomain: String,
+    // Backups written before AAAA support only held A records.
+    #[serde(default = "default_record_type")]
+    record_type: String,
+    ttl: u32,
+}
+
+fn default_record_type() -> String {
+    RecordType::A.to_string()
+}
+
+fn backup_path() -> String {
+    env::var("PORKBUN_TTL_BACKUP_FILE").unwrap_or_else(|_| DEFAULT_BACKUP_FILE.to_string())
+}
+
+/// The lowercase full hostnames whose TTL `pre-change` lowered and `post-change` hasn't
+/// restored yet. Their TTL is held while the backup exists, so the configured TTL doesn't
+/// undo the lowering before the move.
+pub fn lowered_hosts() -> HashSet<String> {
+    lowered_in(&backup_path())
+}
+
+fn lowered_in(path: &str) -> HashSet<String> {
+    let Ok(Some(contents)) = integrity::read(path) else {
+        return HashSet::new();
+    };
+    let saved: BTreeMap<String, SavedTtl> = match serde_json::from_str(&contents) {
+        Ok(saved) => saved,
+        Err(e) => {
+            warn!("Ignoring unreadable TTL backup '{}': {}", path, e);
+            return HashSet::new();
+        }
+    };
+    saved
+        .values()
+        .map(|entry| match entry.subdomain.as_str() {
+            "" => entry.domain.to_ascii_lowercase(),
+            subdomain => format!("{}.{}", subdomain, entry.domain).to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+/// Lowers the TTL of every managed record ahead of a planned IP move, saving the original
+/// TTLs so `post_change` can put them back.
+pub async fn pre_change(zones: &[Zone<'_>], record_types: &[RecordType], ttl: u32) -> Result<()> {
+    if ttl < MIN_TTL {
+        return Err(DdnsError::Config(format!(
+            "TTL {} is below Porkbun's minimum of {} seconds.",
+            ttl, MIN_TTL
+        )));
+    }
+
+    let path = backup_path();
+    let mut saved: BTreeMap<String, SavedTtl> = match integrity::read(&path) {
+        // Keep TTLs saved by an earlier run, so running pre-change twice can't overwrite the
+        // originals with the already lowered values.
+        Ok(Some(contents)) => serde_json::from_str(&contents).map_err(|e| {
+            DdnsError::Config(format!("Failed to parse TTL backup '{}': {}", path, e))
+        })?,
+        Ok(None) | Err(_) => BTreeMap::new(),
+    };
+
+    for zone in zones {
+        let porkbun_client = &zone.client;
+        for subdomain in zone.subdomains {
+            for &record_type in record_types {
+                let name = record_type.label(&porkbun_client.full_name(subdomain));
+                let Some(record) = porkbun_client.get_record(subdomain, record_type).await? else {
+                    continue;
+                };
+                let current = record.ttl.unwrap_or(MIN_TTL);
+                if current <= ttl {
+                    info!("TTL of {} is already {}, leaving it", name, current);
+                    continue;
+                }
+
+                if let Entry::Vacant(entry) = saved.entry(name) {
+                    entry.insert(SavedTtl {
+                        domain: porkbun_client.domain.to_string(),
+                        subdomain: subdomain.clone(),
+                        record_type: record_type.to_string(),
+                        ttl: current,
+                    });
+                    // Saved before the TTL is lowered, so a failure partway through leaves
+                    // every lowered record in the backup for post-change to restore.
+                    save(&path, &saved)?;
+                }
+                porkbun_client
+                    .set_record_ttl(&record, subdomain, ttl)
+                    .await?;
+            }
+        }
+    }
+
+    info!(
+        "Saved original TTLs of {} record(s) to {}",
+        saved.len(),
+        path
+    );
+    Ok(())
+}
+
+fn save(path: &str, saved: &BTreeMap<String, SavedTtl>) -> Result<()> {
+    let contents = serde_json::to_string_pretty(saved)
+        .map_err(|e| DdnsError::Config(format!("Failed to encode TTL backup: {}", e)))?;
+    fs::write(path, integrity::seal(&contents))
+        .map_err(|e| DdnsError::file("Failed to write TTL backup", path, e))
+}
+
+/// Restores the TTLs saved by `pre_change` and removes the backup file.
+pub async fn post_change(zones: &[Zone<'_>]) -> Result<()> {
+    let path = backup_path();
+    let contents = integrity::read(&path)
+        .map_err(|e| DdnsError::file("Failed to read TTL backup (did pre-change run?)", &path, e))?
+        .ok_or_else(|| {
+            DdnsError::Config(format!(
+                "TTL backup '{}' failed its integrity check and was set aside, so its TTLs can't be trusted. The running daemon puts back the configured TTLs; restore any others by hand.",
+                path
+            ))
+        })?;
+    let saved: BTreeMap<String, SavedTtl> = serde_json::from_str(&contents)
+        .map_err(|e| DdnsError::Config(format!("Failed to parse TTL backup '{}': {}", path, e)))?;
+
+    let mut failed = 0;
+    for (name, entry) in &saved {
+        let Some(porkbun_client) = zones
+            .iter()
+            .map(|zone| &zone.client)
+            .find(|c| c.domain == entry.domain)
+        else {
+            warn!(
+                "Zone {} for {} is no longer configured; skipping",
+                entry.domain, name
+            );
+            failed += 1;
+            continue;
+        };
+        let result = match RecordType::parse(&entry.record_type) {
+            Ok(record_type) => match porkbun_client
+                .get_record(&entry.subdomain, record_type)
+                .await
+            {
+                Ok(Some(record)) => {
+                    porkbun_client
+                        .set_record_ttl(&record, &entry.subdomain, entry.ttl)
+                        .await
+                }
+                Ok(None) => Err(DdnsError::Config(format!("{} no longer exists", name))),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Failed to restore TTL of {}: {}", name, e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(DdnsError::Config(format!(
+            "{} of {} TTL(s) could not be restored; {} was kept",
+            failed,
+            saved.len(),
+            path
+        )));
+    }
+    fs::remove_file(&path).map_err(|e| DdnsError::file("Failed to remove TTL backup", &path, e))?;
+    info!("Restored original TTLs of {} record(s)", saved.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::porkbun::{DnsRecord, PorkbunClient};
+    use std::collections::HashMap;
+
+    #[test]
+    fn lowered_hosts_keep_their_ttl_until_restored() {
+        let path = env::temp_dir()
+            .join(format!("porkbun-ttl-backup-{}.json", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let saved = BTreeMap::from([(
+            "VPN.example.com".to_string(),
+            SavedTtl {
+                domain: "example.com".to_string(),
+                subdomain: "VPN".to_string(),
+                record_type: "A".to_string(),
+                ttl: 3600,
+            },
+        )]);
+        save(&path, &saved).unwrap();
+        let lowered = lowered_in(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(lowered, HashSet::from(["vpn.example.com".to_string()]));
+
+        let http = reqwest::Client::new();
+        let host_ttls = HashMap::new();
+        let client = PorkbunClient::new(&http, "pk1_test", "sk1_test", "example.com")
+            .with_ttl(Some(3600))
+            .with_host_ttls(&host_ttls)
+            .with_held_ttls(lowered);
+        let record: DnsRecord = serde_json::from_str(
+            r#"{"id":"101","name":"vpn.example.com","type":"A","content":"198.51.100.1","ttl":"600"}"#,
+        )
+        .unwrap();
+        for (subdomain, drift, update_ttl) in [("vpn", None, 600), ("www", Some(3600), 3600)] {
+            assert_eq!(
+                client.ttl_drift(record.ttl, subdomain),
+                drift,
+                "{}",
+                subdomain
+            );
+            assert_eq!(
+                client.update_ttl(&record, subdomain),
+                update_ttl,
+                "{}",
+                subdomain
+            );
+        }
+        assert!(lowered_in(&path).is_empty());
+    }
+}
@@ -0,0 +1,531 @@
+// src/undo.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::journal::{self, Action, Operation};
+use crate::porkbun::{DnsRecord, Zone, DEFAULT_TTL};
+use crate::record::Record;
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Describes what undoing one write will do.
+fn describe(operation: &Operation) -> String {
+    let host = host(operation);
+    match operation.action {
+        Action::Create => format!(
+            "delete {} record {} ({})",
+            operation.record_type, host, operation.new_content
+        ),
+        Action::Edit => format!(
+            "restore {} record {}: {} (TTL {}) -> {} (TTL {})",
+            operation.record_type,
+            host,
+            operation.new_content,
+            operation.new_ttl,
+            operation.old_content.as_deref().unwrap_or("(none)"),
+            operation.old_ttl.unwrap_or(DEFAULT_TTL)
+        ),
+        Action::Delete => format!(
+            "recreate {} record {} ({})",
+            operation.record_type,
+            host,
+            operation.old_content.as_deref().unwrap_or("(none)")
+        ),
+    }
+}
+
+fn host(operation: &Operation) -> String {
+    if operation.subdomain.is_empty() {
+        operation.domain.clone()
+    } else {
+        format!("{}.{}", operation.subdomain, operation.domain)
+    }
+}
+
+/// Asks for confirmation on the terminal. Anything but "y" or "yes" declines.
+fn confirm() -> bool {
+    print!("Apply these changes? [y/N] ");
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// What undoing one write takes, worked out before anything is changed.
+enum Revert {
+    Delete(DnsRecord),
+    Restore {
+        record: DnsRecord,
+        content: String,
+        ttl: u32,
+    },
+    Recreate(Record),
+    /// An interrupted write that never reached the zone, so there is nothing to revert.
+    Nothing,
+}
+
+/// The record a write of `record_type` with `content` would create, for the types the
+/// updater writes: A and AAAA records and the CNAMEs of aliases.
+fn recreated(operation: &Operation, content: &str) -> Result<Record> {
+    let invalid = || {
+        DdnsError::Config(format!(
+            "'{}' is not valid content for a {} record",
+            content, operation.record_type
+        ))
+    };
+    let subdomain = operation.subdomain.as_str();
+    // The record comes back with the TTL it had, not the one new records get.
+    let ttl = operation.old_ttl.unwrap_or(DEFAULT_TTL);
+    Ok(match operation.record_type.to_ascii_uppercase().as_str() {
+        "A" => Record::a(subdomain)
+            .content(content.parse::<Ipv4Addr>().map_err(|_| invalid())?)
+            .ttl(ttl)
+            .build(),
+        "AAAA" => Record::aaaa(subdomain)
+            .content(content.parse::<Ipv6Addr>().map_err(|_| invalid())?)
+            .ttl(ttl)
+            .build(),
+        "CNAME" => Record::cname(subdomain)
+            .content(content.to_string())
+            .ttl(ttl)
+            .build(),
+        other => {
+            return Err(DdnsError::Config(format!(
+                "undoing writes to {} records is not supported",
+                other
+            )))
+        }
+    })
+}
+
+/// Works out how to revert one write to the record `name` from the zone's records as they
+/// are now. Fails if the record has changed since, so nothing is reverted over a later change.
+/// An `interrupted` write may never have been made; if the zone still shows the record as it
+/// was before, there is nothing to revert.
+fn plan(
+    name: &str,
+    records: &[DnsRecord],
+    operation: &Operation,
+    interrupted: bool,
+) -> Result<Revert> {
+    let current: Vec<&DnsRecord> = records
+        .iter()
+        .filter(|record| {
+            record.name.eq_ignore_ascii_case(name)
+                && record
+                    .record_type
+                    .eq_ignore_ascii_case(&operation.record_type)
+        })
+        .collect();
+    let holding = |content: &str| {
+        current
+            .iter()
+            .find(|record| record.has_content(content))
+            .copied()
+    };
+    let never_made = |old_content: &str| interrupted && holding(old_content).is_some();
+    let changed = || DdnsError::Config("the record has changed since".to_string());
+    match operation.action {
+        Action::Create => match holding(&operation.new_content) {
+            Some(record) => Ok(Revert::Delete(record.clone())),
+            None if interrupted => Ok(Revert::Nothing),
+            None => Err(changed()),
+        },
+        Action::Edit => {
+            let Some(old_content) = &operation.old_content else {
+                return Err(DdnsError::Config(
+                    "the journal does not hold the previous content".to_string(),
+                ));
+            };
+            // Checked now so an unsupported type fails before anything is changed.
+            recreated(operation, old_content)?;
+            let Some(record) = holding(&operation.new_content) else {
+                return if never_made(old_content) {
+                    Ok(Revert::Nothing)
+                } else {
+                    Err(changed())
+                };
+            };
+            Ok(Revert::Restore {
+                record: record.clone(),
+                content: old_content.clone(),
+                ttl: operation.old_ttl.unwrap_or(DEFAULT_TTL),
+            })
+        }
+        Action::Delete => {
+            let Some(old_content) = &operation.old_content else {
+                return Err(DdnsError::Config(
+                    "the journal does not hold the deleted content".to_string(),
+                ));
+            };
+            if !current.is_empty() {
+                return if never_made(old_content) {
+                    Ok(Revert::Nothing)
+                } else {
+                    Err(changed())
+                };
+            }
+            Ok(Revert::Recreate(recreated(operation, old_content)?))
+        }
+    }
+}
+
+/// Carries out a planned revert.
+async fn revert(zone: &Zone<'_>, operation: &Operation, revert: Revert) -> Result<()> {
+    match revert {
+        Revert::Delete(record) => {
+            zone.client
+                .delete_record(&record, &operation.subdomain)
+                .await
+        }
+        Revert::Restore {
+            record,
+            content,
+            ttl,
+        } => {
+            zone.client
+                .edit_record(&record, &operation.subdomain, &content, ttl)
+                .await
+        }
+        Revert::Recreate(record) => zone.client.create(record).await.map(|_| ()),
+        Revert::Nothing => Ok(()),
+    }
+}
+
+/// Plans the revert of every write, reading each zone involved once. Returns the plans in
+/// the order the writes are undone, or every problem found.
+async fn plan_all<'z, 'c>(
+    zones: &'z [Zone<'c>],
+    operations: &[(Operation, bool)],
+) -> std::result::Result<Vec<(&'z Zone<'c>, Revert)>, Vec<String>> {
+    let mut records: Vec<(&str, Result<Vec<DnsRecord>>)> = Vec::new();
+    let mut plans = Vec::new();
+    let mut problems = Vec::new();
+    for (operation, interrupted) in operations.iter().rev() {
+        let Some(zone) = zones
+            .iter()
+            .find(|zone| zone.client.domain.eq_ignore_ascii_case(&operation.domain))
+        else {
+            problems.push(format!(
+                "Can't {}: {} is no longer a configured zone",
+                describe(operation),
+                operation.domain
+            ));
+            continue;
+        };
+        let position = match records
+            .iter()
+            .position(|(domain, _)| *domain == zone.client.domain)
+        {
+            Some(position) => position,
+            None => {
+                records.push((zone.client.domain, zone.client.retrieve_records().await));
+                records.len() - 1
+            }
+        };
+        let zone_records = match &records[position].1 {
+            Ok(zone_records) => zone_records,
+            Err(e) => {
+                problems.push(format!("Can't {}: {}", describe(operation), e));
+                continue;
+            }
+        };
+        let name = zone.client.full_name(&operation.subdomain);
+        match plan(&name, zone_records, operation, *interrupted) {
+            Ok(revert) => plans.push((zone, revert)),
+            Err(e) => problems.push(format!("Can't {}: {}", describe(operation), e)),
+        }
+    }
+    if problems.is_empty() {
+        Ok(plans)
+    } else {
+        Err(problems)
+    }
+}
+
+/// The outcome of `undo --json`, with the writes in the order they are undone:
+///
+/// ```json
+/// {"writes": [{"undo": "restore A record www.example.com: ...", "interrupted": false,
+///              "reason": null}],
+///  "problems": [], "applied": true, "failed": []}
+/// ```
+fn report(
+    operations: &[(Operation, bool)],
+    problems: &[String],
+    applied: bool,
+    failed: &[String],
+) -> Value {
+    let writes: Vec<_> = operations
+        .iter()
+        .rev()
+        .map(|(operation, interrupted)| {
+            json!({
+                "undo": describe(operation),
+                "interrupted": interrupted,
+                "reason": operation.reason,
+            })
+        })
+        .collect();
+    json!({
+        "writes": writes,
+        "problems": problems,
+        "applied": applied,
+        "failed": failed,
+    })
+}
+
+/// Reverts the most recent journaled change set: edited records get their previous content
+/// and TTL back, created records are deleted and deleted records are recreated. Every write
+/// is checked against the zone first; if any record has changed again since, nothing is
+/// reverted. Writes an earlier run was interrupted in are reverted if the zone shows they were
+/// made, and skipped if it shows they weren't.
+///
+/// The plan is always printed first. Without `confirmed` the changes are applied only after
+/// a yes on the terminal, and never when stdin isn't one. With `json`, the plan and outcome
+/// are printed as JSON at the end instead, and only `confirmed` applies the changes.
+pub async fn run(
+    zones: &[Zone<'_>],
+    journal_file: Option<&str>,
+    confirmed: bool,
+    json: bool,
+) -> Result<()> {
+    let operations = match journal_file {
+        Some(journal_file) => journal::last_batch(journal_file),
+        None if json => Vec::new(),
+        None => {
+            println!(
+                "Journaling is off (PORKBUN_JOURNAL_FILE is empty), so there is nothing to undo."
+            );
+            return Ok(());
+        }
+    };
+    if operations.is_empty() {
+        if json {
+            println!("{}", report(&[], &[], false, &[]));
+        } else {
+            println!("The journal holds no changes to undo.");
+        }
+        return Ok(());
+    }
+
+    if !json {
+        println!(
+            "Undoing the last change set ({} write(s)):",
+            operations.len()
+        );
+        for (operation, interrupted) in operations.iter().rev() {
+            let interrupted = if *interrupted {
+                " (interrupted, so it may not have been made)"
+            } else {
+                ""
+            };
+            match &operation.reason {
+                Some(reason) => println!(
+                    "  {}{} (made because: {})",
+                    describe(operation),
+                    interrupted,
+                    reason
+                ),
+                None => println!("  {}{}", describe(operation), interrupted),
+            }
+        }
+    }
+
+    let plans = match plan_all(zones, &operations).await {
+        Ok(plans) => plans,
+        Err(problems) => {
+            if json {
+                println!("{}", report(&operations, &problems, false, &[]));
+            } else {
+                for problem in &problems {
+                    println!("{}", problem);
+                }
+            }
+            return Err(DdnsError::Config(format!(
+                "{} of {} change(s) can't be undone, so nothing was changed",
+                problems.len(),
+                operations.len()
+            )));
+        }
+    };
+
+    if !confirmed {
+        if json {
+            println!("{}", report(&operations, &[], false, &[]));
+            return Ok(());
+        }
+        if !io::stdin().is_terminal() {
+            println!("Run again with --yes to apply.");
+            return Ok(());
+        }
+        if !confirm() {
+            println!("Nothing changed.");
+            return Ok(());
+        }
+    }
+
+    // The undo is a change set of its own, so it can be undone in turn.
+    journal::start_batch();
+    let mut failed = Vec::new();
+    for ((operation, _), (zone, plan)) in operations.iter().rev().zip(plans) {
+        if let Revert::Nothing = plan {
+            info!(
+                "Nothing to undo: the interrupted write was never made ({})",
+                describe(operation)
+            );
+            continue;
+        }
+        match revert(zone, operation, plan).await {
+            Ok(()) => info!("Undone: {}", describe(operation)),
+            Err(e) => {
+                warn!("Could not {}: {}", describe(operation), e);
+                failed.push(format!("Could not {}: {}", describe(operation), e));
+            }
+        }
+    }
+
+    if json {
+        println!("{}", report(&operations, &[], true, &failed));
+    }
+    if !failed.is_empty() {
+        return Err(DdnsError::Config(format!(
+            "{} of {} change(s) could not be undone",
+            failed.len(),
+            operations.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation(action: Action, record_type: &str, old: Option<&str>, new: &str) -> Operation {
+        Operation {
+            id: 1,
+            at: 0,
+            batch: 1,
+            action,
+            domain: "example.com".to_string(),
+            subdomain: "www".to_string(),
+            record_type: record_type.to_string(),
+            record_id: Some("101".to_string()),
+            old_content: old.map(str::to_string),
+            old_ttl: Some(600),
+            new_content: new.to_string(),
+            new_ttl: 600,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn recreated_records_keep_their_old_ttl() {
+        let cases = [
+            ("A", "198.51.100.7", Some(3600), 3600),
+            ("AAAA", "2001:db8::7", Some(86400), 86400),
+            ("CNAME", "home.example.com", Some(1200), 1200),
+            ("A", "198.51.100.7", None, DEFAULT_TTL),
+        ];
+        for (record_type, content, old_ttl, ttl) in cases {
+            let operation = Operation {
+                old_ttl,
+                ..operation(Action::Delete, record_type, Some(content), "")
+            };
+            let record = recreated(&operation, content).unwrap();
+            assert_eq!(record.content(), content, "{}", record_type);
+            assert_eq!(
+                record.ttl(),
+                Some(ttl),
+                "{} with {:?}",
+                record_type,
+                old_ttl
+            );
+        }
+    }
+
+    #[test]
+    fn interrupted_writes_that_never_landed_need_no_revert() {
+        let zone = |content: Option<&str>| -> Vec<DnsRecord> {
+            content
+                .into_iter()
+                .map(|content| DnsRecord {
+                    record_type: "A".to_string(),
+                    name: "www.example.com".to_string(),
+                    content: content.to_string(),
+                    ttl: Some(600),
+                    id: "101".to_string(),
+                })
+                .collect()
+        };
+        let old = "198.51.100.7";
+        let new = "203.0.113.9";
+        let other = "192.0.2.1";
+        let create = operation(Action::Create, "A", None, new);
+        let edit = operation(Action::Edit, "A", Some(old), new);
+        let delete = operation(Action::Delete, "A", Some(old), "");
+        let cases = [
+            // (operation, zone content, interrupted, expected revert or None for an error)
+            (&create, None, true, Some("nothing")),
+            (&create, Some(new), true, Some("delete")),
+            (&create, None, false, None),
+            (&edit, Some(old), true, Some("nothing")),
+            (&edit, Some(new), true, Some("restore")),
+            (&edit, Some(old), false, None),
+            (&edit, Some(other), true, None),
+            (&delete, Some(old), true, Some("nothing")),
+            (&delete, None, true, Some("recreate")),
+            (&delete, Some(old), false, None),
+            (&delete, Some(other), true, None),
+        ];
+        for (operation, content, interrupted, expected) in cases {
+            let planned = plan("www.example.com", &zone(content), operation, interrupted)
+                .ok()
+                .map(|revert| match revert {
+                    Revert::Delete(_) => "delete",
+                    Revert::Restore { .. } => "restore",
+                    Revert::Recreate(_) => "recreate",
+                    Revert::Nothing => "nothing",
+                });
+            assert_eq!(
+                planned, expected,
+                "{:?} with {:?}, interrupted: {}",
+                operation.action, content, interrupted
+            );
+        }
+    }
+
+    #[test]
+    fn json_report_lists_writes_in_undo_order() {
+        let created = operation(Action::Create, "A", None, "203.0.113.9");
+        let edited = Operation {
+            reason: Some("moving hosts".to_string()),
+            ..operation(Action::Edit, "A", Some("198.51.100.7"), "203.0.113.9")
+        };
+        let report = report(&[(created, false), (edited, true)], &[], true, &[]);
+        assert_eq!(
+            report,
+            json!({
+                "writes": [
+                    {
+                        "undo": "restore A record www.example.com: 203.0.113.9 (TTL 600) -> 198.51.100.7 (TTL 600)",
+                        "interrupted": true,
+                        "reason": "moving hosts",
+                    },
+                    {
+                        "undo": "delete A record www.example.com (203.0.113.9)",
+                        "interrupted": false,
+                        "reason": null,
+                    },
+                ],
+                "problems": [],
+                "applied": true,
+                "failed": [],
+            })
+        );
+    }
+}
@@ -0,0 +1,306 @@
+// src/upnp.rs
+
+use crate::errors::{DdnsError, Result};
+use crate::explain;
+use crate::ip_fetcher::is_public;
+use crate::porkbun::RecordType;
+use log::debug;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Mutex;
+use tokio::net::UdpSocket;
+use tokio::time::{timeout_at, Duration, Instant};
+
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+
+/// How long to wait for gateways to answer the discovery request.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Gateways announce themselves as either version of the IGD device.
+const SEARCH_TARGETS: [&str; 2] = [
+    "urn:schemas-upnp-org:device:InternetGatewayDevice:1",
+    "urn:schemas-upnp-org:device:InternetGatewayDevice:2",
+];
+
+/// The services that answer `GetExternalIPAddress`: cable/DSL routers use the first, PPPoE
+/// links usually the second.
+const WAN_SERVICES: [&str; 2] = ["WANIPConnection", "WANPPPConnection"];
+
+/// The gateway's control URL and service type, found once and reused until a request fails.
+static GATEWAY: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+/// Asks the router for its external IPv4 address via UPnP IGD `GetExternalIPAddress`. No
+/// outside service is involved, but the router must have UPnP enabled, and behind a second
+/// NAT (e.g. carrier-grade NAT) it only knows a private address, which is rejected.
+pub async fn external_address(
+    client: &reqwest::Client,
+    record_type: RecordType,
+) -> Result<Ipv4Addr> {
+    if record_type == RecordType::Aaaa {
+        return Err(upnp_error("gateways only report an IPv4 address"));
+    }
+    let cached = GATEWAY.lock().unwrap().clone();
+    let (control_url, service_type) = match cached {
+        Some(gateway) => {
+            explain::note("UPnP gateway known from an earlier cycle; discovery skipped");
+            gateway
+        }
+        None => {
+            explain::note("No UPnP gateway known yet; discovering it");
+            discover(client).await?
+        }
+    };
+    match query(client, &control_url, &service_type).await {
+        Ok(ip) => {
+            *GATEWAY.lock().unwrap() = Some((control_url, service_type));
+            Ok(ip)
+        }
+        Err(e) => {
+            // The router may have restarted on another port; discover it again next time.
+            *GATEWAY.lock().unwrap() = None;
+            Err(e)
+        }
+    }
+}
+
+fn upnp_error(message: &str) -> DdnsError {
+    DdnsError::IpDetection(format!("UPnP: {}", message))
+}
+
+/// Finds the gateway with SSDP and reads its device description for the WAN service's control
+/// URL.
+async fn discover(client: &reqwest::Client) -> Result<(String, String)> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| upnp_error(&e.to_string()))?;
+    socket
+        .set_multicast_ttl_v4(2)
+        .map_err(|e| upnp_error(&e.to_string()))?;
+    let ssdp_addr: SocketAddr = SSDP_ADDR.parse().expect("valid SSDP address");
+    explain::call("upnp", format!("SSDP search to {}", SSDP_ADDR));
+    for target in SEARCH_TARGETS {
+        let search = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+            SSDP_ADDR, target
+        );
+        socket
+            .send_to(search.as_bytes(), ssdp_addr)
+            .await
+            .map_err(|e| upnp_error(&e.to_string()))?;
+    }
+
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    let mut buf = [0u8; 2048];
+    while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, from) = received.map_err(|e| upnp_error(&e.to_string()))?;
+        let response = String::from_utf8_lossy(&buf[..len]);
+        let Some(location) = response.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("location")
+                .then(|| value.trim().to_string())
+        }) else {
+            continue;
+        };
+        debug!("UPnP gateway at {} describes itself at {}", from, location);
+        match wan_service(client, &location).await {
+            Some(gateway) => return Ok(gateway),
+            None => debug!("{} offers no WAN connection service", location),
+        }
+    }
+    Err(upnp_error(
+        "no gateway with a WAN connection service answered",
+    ))
+}
+
+/// Reads a device description and returns the absolute control URL and type of its first WAN
+/// connection service.
+async fn wan_service(client: &reqwest::Client, location: &str) -> Option<(String, String)> {
+    explain::call("upnp", format!("GET {}", location));
+    let description = client
+        .get(location)
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+    wan_service_in(&description, location)
+}
+
+/// Picks the first WAN connection service out of a device description fetched from
+/// `location`, with its control URL made absolute.
+fn wan_service_in(description: &str, location: &str) -> Option<(String, String)> {
+    description.split("<service>").skip(1).find_map(|service| {
+        let service_type = element(service, "serviceType")?;
+        let control_url = element(service, "controlURL")?;
+        if !WAN_SERVICES.iter().any(|wan| service_type.contains(wan)) {
+            return None;
+        }
+        let control_url = if control_url.starts_with("http://") {
+            control_url.to_string()
+        } else {
+            // Relative to the description's origin.
+            let origin_end = location
+                .find("://")
+                .and_then(|scheme| location[scheme + 3..].find('/').map(|i| scheme + 3 + i))
+                .unwrap_or(location.len());
+            format!(
+                "{}/{}",
+                &location[..origin_end],
+                control_url.trim_start_matches('/')
+            )
+        };
+        Some((control_url, service_type.to_string()))
+    })
+}
+
+/// Sends the `GetExternalIPAddress` SOAP action and checks the answer is a public address.
+async fn query(
+    client: &reqwest::Client,
+    control_url: &str,
+    service_type: &str,
+) -> Result<Ipv4Addr> {
+    let body = format!(
+        "<?xml version=\"1.0\"?>\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body><u:GetExternalIPAddress xmlns:u=\"{}\"/></s:Body></s:Envelope>",
+        service_type
+    );
+    explain::call(
+        "upnp",
+        format!("POST {} (GetExternalIPAddress)", control_url),
+    );
+    let response = client
+        .post(control_url)
+        .header("Content-Type", "text/xml; charset=\"utf-8\"")
+        .header(
+            "SOAPAction",
+            format!("\"{}#GetExternalIPAddress\"", service_type),
+        )
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let ip = element(&response, "NewExternalIPAddress")
+        .and_then(|ip| ip.trim().parse::<Ipv4Addr>().ok())
+        .ok_or_else(|| upnp_error("the gateway did not report an external address"))?;
+    if !is_public(IpAddr::V4(ip)) {
+        return Err(upnp_error(&format!(
+            "the gateway's external address {} is not public; it is probably behind another NAT",
+            ip
+        )));
+    }
+    Ok(ip)
+}
+
+/// The text of the first element with this name, with or without a namespace prefix.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("{}>", name);
+    let mut from = 0;
+    while let Some(found) = xml[from..].find(&open) {
+        let at = from + found;
+        from = at + open.len();
+        // Skip closing tags and longer names that end the same way.
+        if !matches!(xml[..at].chars().last(), Some('<' | ':')) {
+            continue;
+        }
+        let text = &xml[from..];
+        return Some(text[..text.find('<').unwrap_or(text.len())].trim());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trimmed device description of an IGD v1 router, whose WAN connection service sits
+    /// below the common interface config service.
+    const DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:WANCommonInterfaceConfig:1</serviceType>
+        <controlURL>/ctl/CmnIfCfg</controlURL>
+      </service>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+        <SCPDURL>/WANIPCn.xml</SCPDURL>
+        <controlURL>/ctl/IPConn</controlURL>
+      </service>
+    </serviceList>
+  </device>
+</root>"#;
+
+    #[test]
+    fn finds_element_text() {
+        let cases = [
+            ("<ip>203.0.113.7</ip>", Some("203.0.113.7")),
+            ("<u:ip> 203.0.113.7 </u:ip>", Some("203.0.113.7")),
+            (
+                "<wan_ip>10.0.0.1</wan_ip><ip>203.0.113.7</ip>",
+                Some("203.0.113.7"),
+            ),
+            ("<ip></ip>", Some("")),
+            ("<ip>203.0.113.7", Some("203.0.113.7")),
+            ("</ip>", None),
+            ("<wan_ip>10.0.0.1</wan_ip>", None),
+        ];
+        for (xml, expected) in cases {
+            assert_eq!(element(xml, "ip"), expected, "{}", xml);
+        }
+    }
+
+    #[test]
+    fn finds_the_wan_connection_service() {
+        let ppp = DESCRIPTION
+            .replace("WANIPConnection", "WANPPPConnection")
+            .replace("/ctl/IPConn", "http://192.168.1.1:5000/ctl/PPPConn");
+        let cases = [
+            (
+                DESCRIPTION.to_string(),
+                "http://192.168.1.1:49152/rootDesc.xml",
+                Some((
+                    "http://192.168.1.1:49152/ctl/IPConn",
+                    "urn:schemas-upnp-org:service:WANIPConnection:1",
+                )),
+            ),
+            (
+                DESCRIPTION.replace("/ctl/IPConn", "ctl/IPConn"),
+                "http://192.168.1.1:49152",
+                Some((
+                    "http://192.168.1.1:49152/ctl/IPConn",
+                    "urn:schemas-upnp-org:service:WANIPConnection:1",
+                )),
+            ),
+            (
+                ppp,
+                "http://192.168.1.1:49152/rootDesc.xml",
+                Some((
+                    "http://192.168.1.1:5000/ctl/PPPConn",
+                    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+                )),
+            ),
+            (
+                DESCRIPTION.replace("WANIPConnection", "Layer3Forwarding"),
+                "http://192.168.1.1:49152/rootDesc.xml",
+                None,
+            ),
+        ];
+        for (description, location, expected) in cases {
+            let expected = expected.map(|(url, service)| (url.to_string(), service.to_string()));
+            assert_eq!(
+                wan_service_in(&description, location),
+                expected,
+                "{}",
+                location
+            );
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// src/vars.rs
+
+//! The variables the configuration is read from. At startup they are the process
+//! environment; a reload or a remote configuration builds a new set from the same sources
+//! instead of changing the environment, which other threads and child processes read
+//! without any locking.
+
+use std::collections::BTreeMap;
+use std::env;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Vars(BTreeMap<String, String>);
+
+impl Vars {
+    /// The process environment, leaving out variables that aren't valid UTF-8 as
+    /// `env::var` would.
+    pub fn from_env() -> Self {
+        Vars(
+            env::vars_os()
+                .filter_map(|(key, value)| {
+                    Some((key.into_string().ok()?, value.into_string().ok()?))
+                })
+                .collect(),
+        )
+    }
+
+    /// Looks a variable up, with the same result as `env::var` on the environment these
+    /// variables stand for.
+    pub fn var(&self, key: &str) -> Result<String, env::VarError> {
+        self.0.get(key).cloned().ok_or(env::VarError::NotPresent)
+    }
+
+    pub fn is_set(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.0.insert(key.to_string(), value.to_string());
+    }
+
+    /// Sets a variable unless it is set already, the way `.env` and the other files are
+    /// loaded. Returns whether it was set.
+    pub fn set_default(&mut self, key: &str, value: &str) -> bool {
+        if self.is_set(key) {
+            return false;
+        }
+        self.set(key, value);
+        true
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.0.remove(key);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Loads `.env` from the working directory or one of its parents. A missing file is
+    /// fine, and loading stops at the first line that can't be parsed, as with
+    /// `dotenv::dotenv`.
+    pub fn load_dotenv(&mut self) {
+        // Deprecated only in favour of loading straight into the process environment, which is
+        // what this avoids.
+        #[allow(deprecated)]
+        let Ok(lines) = dotenv::dotenv_iter() else {
+            return;
+        };
+        for (key, value) in lines.map_while(|line| line.ok()) {
+            self.set_default(&key, &value);
+        }
+    }
+
+    /// Makes the process environment hold exactly these variables. Only called once at
+    /// startup, before any thread or child process could be reading the environment, so that
+    /// state file locations, hooks and tenant processes see what the files set.
+    pub fn export(&self) {
+        for (key, _) in Self::from_env().iter() {
+            if !self.is_set(key) {
+                env::remove_var(key);
+            }
+        }
+        for (key, value) in self.iter() {
+            if env::var(key).ok().as_deref() != Some(value) {
+                env::set_var(key, value);
+            }
+        }
+    }
+}
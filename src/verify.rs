@@ -0,0 +1,239 @@
+// src/verify.rs
+
+use crate::config::VerifyConfig;
+use crate::dns::{self, Resolver};
+use crate::explain;
+use crate::porkbun::{self, RecordType};
+use chrono::Local;
+use log::{error, info, warn};
+use std::fmt;
+use std::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration, Instant};
+
+/// What a single resolver returned for the verified name.
+#[derive(Debug)]
+pub enum Outcome {
+    Propagated,
+    Stale(Vec<String>),
+    Failed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Propagated => write!(f, "propagated"),
+            Outcome::Stale(answers) if answers.is_empty() => write!(f, "no answer"),
+            Outcome::Stale(answers) => write!(f, "stale ({})", answers.join(", ")),
+            Outcome::Failed(e) => write!(f, "error ({})", e),
+        }
+    }
+}
+
+/// The verification outcome for one resolver.
+#[derive(Debug)]
+pub struct ResolverResult {
+    pub endpoint: String,
+    pub outcome: Outcome,
+}
+
+/// How long after the old TTL ran out the last check is made, so resolvers that fetched the
+/// record just before the change have certainly let it expire.
+const RECHECK_MARGIN: Duration = Duration::from_secs(30);
+
+/// A check after the TTL, ending with the name checked and why it failed, if it did.
+type Recheck = JoinHandle<(String, Option<String>)>;
+
+/// The checks after the TTL still to be collected.
+static RECHECKS: Mutex<Vec<Recheck>> = Mutex::new(Vec::new());
+
+/// Checks that `name` now resolves to `expected_ip` on every configured resolver, asking for
+/// the A or AAAA record depending on the address family.
+///
+/// Recursive resolvers may keep serving a cached answer until the old TTL (`old_ttl`
+/// seconds) expires. A resolver still serving the old answer before then is reported as
+/// pending, with the time propagation is expected by, and checked again once that time has
+/// passed; only then is a mismatch reported as a failure. The check after the TTL runs in the
+/// background, so the cycle doesn't wait for it; `recheck_failures` collects its result.
+/// Returns why verification failed, if it already has.
+pub async fn verify_record(
+    client: &reqwest::Client,
+    config: &VerifyConfig,
+    name: &str,
+    expected_ip: &str,
+    old_ttl: u32,
+) -> Option<String> {
+    let changed_at = Instant::now();
+    let expected_by = Local::now() + chrono::Duration::seconds(i64::from(old_ttl));
+    if config.delay_seconds > 0 {
+        info!(
+            "Waiting {} seconds before verifying {}...",
+            config.delay_seconds, name
+        );
+        sleep(Duration::from_secs(config.delay_seconds)).await;
+    }
+
+    let reason = format!(
+        "verifying that {} now resolves to {} (PORKBUN_VERIFY_*)",
+        name, expected_ip
+    );
+    let results = explain::because(
+        reason,
+        query_all(client, &config.resolvers, name, expected_ip),
+    )
+    .await;
+    let (propagated, details) = tally(&results);
+    let ttl_left = Duration::from_secs(u64::from(old_ttl)).saturating_sub(changed_at.elapsed());
+
+    if propagated == results.len() {
+        info!(
+            "Verified {} resolves to {} on all {} resolver(s): {}",
+            name,
+            expected_ip,
+            results.len(),
+            details
+        );
+    } else if ttl_left.is_zero() {
+        error!(
+            "{} resolves to {} on only {}/{} resolver(s), although its old TTL of {}s has expired: {}",
+            name,
+            expected_ip,
+            propagated,
+            results.len(),
+            old_ttl,
+            details
+        );
+        return Some(format!(
+            "resolves to {} on only {}/{} resolver(s) after its old TTL: {}",
+            expected_ip,
+            propagated,
+            results.len(),
+            details
+        ));
+    } else {
+        info!(
+            "{} resolves to {} on {}/{} resolver(s) so far ({}); the old answer may be cached for up to {}s, so propagation is expected by {}",
+            name,
+            expected_ip,
+            propagated,
+            results.len(),
+            details,
+            old_ttl,
+            expected_by.format("%H:%M:%S")
+        );
+        let client = client.clone();
+        let resolvers = config.resolvers.clone();
+        let name = name.to_string();
+        let expected_ip = expected_ip.to_string();
+        let recheck = tokio::spawn(async move {
+            sleep(ttl_left + RECHECK_MARGIN).await;
+            let results = query_all(&client, &resolvers, &name, &expected_ip).await;
+            let (propagated, details) = tally(&results);
+            if propagated == results.len() {
+                info!(
+                    "Verified {} resolves to {} on all {} resolver(s) after the old TTL: {}",
+                    name,
+                    expected_ip,
+                    results.len(),
+                    details
+                );
+                return (name, None);
+            }
+            error!(
+                "{} resolves to {} on only {}/{} resolver(s), although its old TTL expired at {}: {}",
+                name,
+                expected_ip,
+                propagated,
+                results.len(),
+                expected_by.format("%H:%M:%S"),
+                details
+            );
+            let failure = format!(
+                "resolves to {} on only {}/{} resolver(s) after its old TTL: {}",
+                expected_ip,
+                propagated,
+                results.len(),
+                details
+            );
+            (name, Some(failure))
+        });
+        RECHECKS.lock().unwrap().push(recheck);
+    }
+    None
+}
+
+/// Collects the checks after the TTL that have finished, or with `wait` all of them, waiting
+/// for the ones still running. Returns each checked name that failed, with why.
+pub async fn recheck_failures(wait: bool) -> Vec<(String, String)> {
+    let finished: Vec<_> = {
+        let mut rechecks = RECHECKS.lock().unwrap();
+        let (finished, running) = rechecks
+            .drain(..)
+            .partition(|recheck| wait || recheck.is_finished());
+        *rechecks = running;
+        finished
+    };
+    if wait && !finished.is_empty() {
+        info!(
+            "Waiting for {} check(s) after the old TTL to finish...",
+            finished.len()
+        );
+    }
+    let mut failures = Vec::new();
+    for recheck in finished {
+        match recheck.await {
+            Ok((_, None)) => {}
+            Ok((name, Some(failure))) => failures.push((name, failure)),
+            Err(e) => warn!("A check after the old TTL did not finish: {}", e),
+        }
+    }
+    failures
+}
+
+/// Asks each resolver for the record and compares its answer with `expected_ip`.
+async fn query_all(
+    client: &reqwest::Client,
+    resolvers: &[Resolver],
+    name: &str,
+    expected_ip: &str,
+) -> Vec<ResolverResult> {
+    let record_type = RecordType::for_ip(expected_ip);
+    let expected = porkbun::normalize_content(record_type.as_str(), expected_ip);
+    let qtype = match record_type {
+        RecordType::A => dns::TYPE_A,
+        RecordType::Aaaa => dns::TYPE_AAAA,
+    };
+    let mut results = Vec::with_capacity(resolvers.len());
+    for resolver in resolvers {
+        let outcome = match resolver.query(client, name, qtype).await {
+            Ok(answers)
+                if answers
+                    .iter()
+                    .any(|a| porkbun::normalize_content(record_type.as_str(), a) == expected) =>
+            {
+                Outcome::Propagated
+            }
+            Ok(answers) => Outcome::Stale(answers),
+            Err(e) => Outcome::Failed(e.to_string()),
+        };
+        results.push(ResolverResult {
+            endpoint: resolver.endpoint.clone(),
+            outcome,
+        });
+    }
+    results
+}
+
+/// The number of resolvers that see the new address, and every resolver's outcome.
+fn tally(results: &[ResolverResult]) -> (usize, String) {
+    let propagated = results
+        .iter()
+        .filter(|r| matches!(r.outcome, Outcome::Propagated))
+        .count();
+    let details = results
+        .iter()
+        .map(|r| format!("{}: {}", r.endpoint, r.outcome))
+        .collect::<Vec<_>>()
+        .join("; ");
+    (propagated, details)
+}